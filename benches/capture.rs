@@ -0,0 +1,161 @@
+//! Throughput of tokenizing and parsing tshark lines and of creating bursts, run with
+//! `cargo bench`.
+
+use std::{fmt::Write, hint::black_box, net::IpAddr};
+
+use burstshark::{
+    bench,
+    capture::{
+        self, CaptureBackend, CaptureType, CommonOptions, Fields, IpOptions, SyntheticTraffic,
+    },
+    BurstStream, BurstsharkError,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Lines as tshark prints them for TCP packets of 64 flows, sending bursts of 10 packets
+fn tshark_lines(packets: u32) -> String {
+    let mut lines = String::new();
+    for i in 0..packets {
+        let flow = i % 64;
+        let time = (i / 640) as f64 + (i % 640) as f64 * 1e-4;
+        writeln!(
+            lines,
+            "{time:.6}\t10.0.{}.{}\t192.168.1.1\t\t{}\t\t443\t\t\t1400",
+            flow / 256,
+            flow % 256,
+            40000 + flow
+        )
+        .unwrap();
+    }
+    lines
+}
+
+/// Time, addresses, ports and length of a line split on whitespace, as lines were split before
+/// they were tokenized on tabs
+fn split_whitespace(line: &str) -> (f64, IpAddr, IpAddr, u16, u16, u32) {
+    let mut fields = line.split_whitespace();
+    (
+        fields.next().unwrap().parse().unwrap(),
+        fields.next().unwrap().parse().unwrap(),
+        fields.next().unwrap().parse().unwrap(),
+        fields.next().unwrap().parse().unwrap(),
+        fields.next().unwrap().parse().unwrap(),
+        fields.next().unwrap().parse().unwrap(),
+    )
+}
+
+/// The same fields of a line tokenized on tabs, with the empty fields of UDP or TCP in place
+fn tokenize(line: &str) -> Result<(f64, IpAddr, IpAddr, u16, u16, u32), BurstsharkError> {
+    let mut fields = Fields::new(line);
+    Ok((
+        fields.field()?.parse()?,
+        fields.field()?.parse()?,
+        fields.field()?.parse()?,
+        fields.first_present(2)?.parse()?,
+        fields.first_present(2)?.parse()?,
+        fields.first_present(3)?.parse()?,
+    ))
+}
+
+fn tokenize_lines(c: &mut Criterion) {
+    let packets = 100_000;
+    let lines = tshark_lines(packets);
+    let ip = IpOptions::default();
+    for line in lines.lines() {
+        assert_eq!(split_whitespace(line), tokenize(line).unwrap());
+    }
+
+    // Lines per second are reported as elements per second
+    let mut group = c.benchmark_group("tokenize_lines");
+    group.throughput(Throughput::Elements(packets as u64));
+    group.bench_function("split_whitespace", |b| {
+        b.iter(|| {
+            lines.lines().map(split_whitespace).for_each(|fields| {
+                black_box(fields);
+            })
+        })
+    });
+    group.bench_function("fields", |b| {
+        b.iter(|| {
+            lines.lines().map(tokenize).for_each(|fields| {
+                black_box(fields.unwrap());
+            })
+        })
+    });
+    group.bench_function("from_tshark", |b| {
+        b.iter(|| {
+            for line in lines.lines() {
+                capture::parse_ip_line(black_box(line), &ip).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn parse_lines(c: &mut Criterion) {
+    let packets = 100_000;
+    let lines = tshark_lines(packets);
+    let mut group = c.benchmark_group("parse_lines");
+    group.throughput(Throughput::Elements(packets as u64));
+    group.sample_size(10);
+    for workers in [1, 4] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workers),
+            &workers,
+            |b, &workers| {
+                b.iter(|| {
+                    let lines = lines.clone();
+                    let mut stream = BurstStream::start(|tx| {
+                        let mut opts = CommonOptions::new(CaptureBackend::Lines { lines }, tx);
+                        opts.inactive_time = 0.05;
+                        opts.workers = workers;
+                        CaptureType::IPCapture {
+                            opts,
+                            ip: IpOptions::default(),
+                        }
+                    });
+                    let bursts = stream.by_ref().count();
+                    stream.finish().unwrap();
+                    bursts
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn synthetic_bursts(c: &mut Criterion) {
+    let traffic = SyntheticTraffic {
+        flows: 1000,
+        packet_rate: 1000.0,
+        burst_packets: 10,
+        burst_gap: 1.0,
+        packet_size: 1400,
+        duration: 10.0,
+    };
+    let mut group = c.benchmark_group("synthetic_bursts");
+    group.throughput(Throughput::Elements(traffic.packets()));
+    group.sample_size(10);
+    for workers in [1, 4] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workers),
+            &workers,
+            |b, &workers| {
+                b.iter(|| {
+                    let report =
+                        bench::run(&traffic, 0.1, workers, |opts| CaptureType::IPCapture {
+                            opts,
+                            ip: IpOptions::default(),
+                        })
+                        .unwrap();
+                    assert!(report.passed());
+                    report.bursts
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tokenize_lines, parse_lines, synthetic_bursts);
+criterion_main!(benches);
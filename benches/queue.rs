@@ -0,0 +1,145 @@
+//! The burst queue against the per-packet FIFO it replaced, for a single flow at a high rate,
+//! run with `cargo bench`.
+
+use burstshark::capture::BurstQueue;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// Packets of the flow, one every microsecond
+const PACKETS: u32 = 1_000_000;
+/// Seconds without packets for a burst to complete
+const GAP: f64 = 0.5;
+
+/// Times of the packets, in bursts of 0.8 seconds separated by pauses of 1 second, so the FIFO
+/// holds the packets of most of a burst
+fn packet_times() -> Vec<f64> {
+    (0..PACKETS)
+        .map(|i| {
+            let time = i as f64 * 1e-6;
+            time + (time / 0.8).floor() * 1.0
+        })
+        .collect()
+}
+
+/// Bursts completed by queueing the end of the burst of the flow once per flow
+fn heap_bursts(times: &[f64]) -> u32 {
+    let mut queue = BurstQueue::new(GAP, false);
+    let (mut end, mut bursts) = (None, 0);
+    for &time in times {
+        while let Some((_, queued)) = queue.dequeue_complete(time) {
+            bursts += u32::from(end == Some(queued));
+            end = None;
+        }
+        end = Some(time);
+        queue.enqueue(0u32, time, GAP);
+    }
+    bursts + u32::from(queue.dequeue().is_some())
+}
+
+/// Bursts completed by queueing every packet, skipping those no longer last of the flow, as the
+/// burst threads did before the burst queue
+fn fifo_bursts(times: &[f64]) -> (u32, usize) {
+    let mut fifo = fifo::Fifo::new();
+    let (mut end, mut bursts, mut peak) = (None, 0, 0);
+    for &time in times {
+        while let Some(&(_, queued)) = fifo.peek() {
+            if time - queued < GAP {
+                break;
+            }
+            fifo.dequeue();
+            if end == Some(queued) {
+                bursts += 1;
+                end = None;
+            }
+        }
+        end = Some(time);
+        fifo.enqueue((0u32, time));
+        peak = peak.max(fifo.len());
+    }
+    (bursts + u32::from(end.is_some()), peak)
+}
+
+fn single_flow(c: &mut Criterion) {
+    let times = packet_times();
+    let (bursts, peak) = fifo_bursts(&times);
+    assert_eq!(heap_bursts(&times), bursts);
+    println!("queued entries of the flow at most: fifo {peak}, heap 1");
+
+    let mut group = c.benchmark_group("single_flow");
+    group.throughput(Throughput::Elements(PACKETS as u64));
+    group.sample_size(10);
+    group.bench_function("fifo", |b| b.iter(|| fifo_bursts(&times)));
+    group.bench_function("heap", |b| b.iter(|| heap_bursts(&times)));
+    group.finish();
+}
+
+criterion_group!(benches, single_flow);
+criterion_main!(benches);
+
+/// The ring buffer the burst threads queued packets in before the burst queue
+mod fifo {
+    pub struct Fifo<T: Sized + Clone> {
+        /// A circular ring buffer, which can grow if full
+        buffer: Vec<T>,
+
+        /// The number of items in the queue
+        size: usize,
+
+        /// The index used for the next dequeue
+        head: usize,
+
+        /// The index for the next enqueue
+        tail: usize,
+    }
+
+    impl<T: Sized + Clone> Fifo<T> {
+        pub fn new() -> Self {
+            Self {
+                buffer: Vec::with_capacity(512),
+                size: 0,
+                head: 0,
+                tail: 0,
+            }
+        }
+
+        pub fn len(&self) -> usize {
+            self.size
+        }
+
+        pub fn enqueue(&mut self, item: T) {
+            if self.tail == self.head && self.size > 0 {
+                // The vector is full, so we have to re-allocate it to a new one
+                let mut old_buffer =
+                    std::mem::replace(&mut self.buffer, Vec::with_capacity(self.size * 2));
+                self.buffer.extend(old_buffer.drain(self.tail..));
+                self.buffer.append(&mut old_buffer);
+
+                self.head = 0;
+                self.size += 1;
+                self.tail = self.size;
+                self.buffer.push(item);
+            } else if self.buffer.len() < self.buffer.capacity() {
+                self.buffer.push(item);
+                self.tail = (self.tail + 1) % self.buffer.capacity();
+                self.size += 1;
+            } else {
+                self.buffer[self.tail] = item;
+                self.tail = (self.tail + 1) % self.buffer.capacity();
+                self.size += 1;
+            }
+        }
+
+        pub fn peek(&self) -> Option<&T> {
+            (self.size > 0).then(|| &self.buffer[self.head])
+        }
+
+        pub fn dequeue(&mut self) -> Option<T> {
+            if self.size == 0 {
+                return None;
+            }
+            let item = self.buffer[self.head].clone();
+            self.head = (self.head + 1) % self.buffer.capacity();
+            self.size -= 1;
+            Some(item)
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! Conditions on bursts to raise alerts for, such as `size>10MB && duration<1s`.
+//!
+//! A condition compares fields of bursts with values, and combines the comparisons with `&&`, `||`,
+//! `!` and parentheses. Sizes can be given in B, kB, MB and GB or KiB, MiB and GiB, and times in
+//! ns, us, ms, s, m and h. Text fields are compared with `==` and `!=` to words or quoted strings,
+//! such as `label==video-stream`. Comparisons with fields a burst lacks, such as the label of a
+//! burst matching no signature, are false.
+
+use std::{borrow::Cow, cmp::Ordering, fmt, str::FromStr};
+
+use crate::{capture::Burst, BurstsharkError};
+
+/// What the value of a field is measured in, which decides the units and operators allowed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Bytes,
+    Seconds,
+    Number,
+    Text,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Size,
+    Packets,
+    Duration,
+    SrcPort,
+    DstPort,
+    /// Either port of the flow
+    Port,
+    Dscp,
+    Airtime,
+    RetransmittedBytes,
+    LostPackets,
+    Deviation,
+    ResponseLatency,
+    ClassConfidence,
+    Src,
+    Dst,
+    Interface,
+    Service,
+    Class,
+    Label,
+    SrcCountry,
+    DstCountry,
+}
+
+/// Names of the fields conditions can compare
+const FIELDS: &[(&str, Field, Kind)] = &[
+    ("size", Field::Size, Kind::Bytes),
+    ("packets", Field::Packets, Kind::Number),
+    ("duration", Field::Duration, Kind::Seconds),
+    ("src_port", Field::SrcPort, Kind::Number),
+    ("dst_port", Field::DstPort, Kind::Number),
+    ("port", Field::Port, Kind::Number),
+    ("dscp", Field::Dscp, Kind::Number),
+    ("airtime", Field::Airtime, Kind::Seconds),
+    (
+        "retransmitted_bytes",
+        Field::RetransmittedBytes,
+        Kind::Bytes,
+    ),
+    ("lost_packets", Field::LostPackets, Kind::Number),
+    ("deviation", Field::Deviation, Kind::Number),
+    ("response_latency", Field::ResponseLatency, Kind::Seconds),
+    ("class_confidence", Field::ClassConfidence, Kind::Number),
+    ("src", Field::Src, Kind::Text),
+    ("dst", Field::Dst, Kind::Text),
+    ("interface", Field::Interface, Kind::Text),
+    ("service", Field::Service, Kind::Text),
+    ("class", Field::Class, Kind::Text),
+    ("label", Field::Label, Kind::Text),
+    ("src_country", Field::SrcCountry, Kind::Text),
+    ("dst_country", Field::DstCountry, Kind::Text),
+];
+
+const BYTE_UNITS: &[(&str, f64)] = &[
+    ("KiB", 1024.0),
+    ("MiB", 1024.0 * 1024.0),
+    ("GiB", 1024.0 * 1024.0 * 1024.0),
+    ("kB", 1e3),
+    ("KB", 1e3),
+    ("MB", 1e6),
+    ("GB", 1e9),
+    ("B", 1.0),
+];
+
+/// Ordered so that no unit is tried before the longer units ending with it
+const TIME_UNITS: &[(&str, f64)] = &[
+    ("ns", 1e-9),
+    ("us", 1e-6),
+    ("ms", 1e-3),
+    ("s", 1.0),
+    ("m", 60.0),
+    ("h", 3600.0),
+];
+
+impl Field {
+    /// The numeric values of the field, of which there are two for the port of either end
+    fn numbers(self, burst: &Burst) -> [Option<f64>; 2] {
+        let value = match self {
+            Field::Size => Some(burst.size as f64),
+            Field::Packets => Some(burst.num_packets as f64),
+            Field::Duration => Some(burst.end - burst.start),
+            Field::SrcPort => burst.src_port.map(f64::from),
+            Field::DstPort => burst.dst_port.map(f64::from),
+            Field::Port => return [burst.src_port.map(f64::from), burst.dst_port.map(f64::from)],
+            Field::Dscp => burst.dscp.map(f64::from),
+            Field::Airtime => burst.airtime,
+            Field::RetransmittedBytes => burst.retransmitted_bytes.map(f64::from),
+            Field::LostPackets => burst.lost_packets.map(f64::from),
+            Field::Deviation => burst.deviation,
+            Field::ResponseLatency => burst.response_latency,
+            Field::ClassConfidence => burst.class_confidence,
+            _ => None,
+        };
+        [value, None]
+    }
+
+    fn text(self, burst: &Burst) -> Option<Cow<'_, str>> {
+        let text = match self {
+            Field::Src => &burst.src,
+            Field::Dst => &burst.dst,
+            Field::Interface => burst.interface.as_ref()?,
+            Field::Service => burst.service.as_ref()?,
+            Field::Class => return burst.class.map(|class| Cow::Owned(class.to_string())),
+            Field::Label => burst.label.as_ref()?,
+            Field::SrcCountry => burst.src_country.as_ref()?,
+            Field::DstCountry => burst.dst_country.as_ref()?,
+            _ => return None,
+        };
+        Some(Cow::Borrowed(text))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Field, Op, Value),
+}
+
+impl Expr {
+    fn matches(&self, burst: &Burst) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(burst) && b.matches(burst),
+            Expr::Or(a, b) => a.matches(burst) || b.matches(burst),
+            Expr::Not(a) => !a.matches(burst),
+            Expr::Compare(field, op, Value::Number(value)) => {
+                let mut numbers = field.numbers(burst).into_iter().flatten().peekable();
+                let holds = |n: f64| n.partial_cmp(value).is_some_and(|o| op.holds(o));
+                match op {
+                    // Neither port may be the value, rather than just one of them
+                    Op::Ne => numbers.peek().is_some() && numbers.all(holds),
+                    _ => numbers.any(holds),
+                }
+            }
+            Expr::Compare(field, op, Value::Text(value)) => field
+                .text(burst)
+                .is_some_and(|text| op.holds(text.as_ref().cmp(value.as_str()))),
+        }
+    }
+}
+
+/// A condition on bursts, parsed from an expression such as `size>10MB && duration<1s`
+#[derive(Clone, Debug)]
+pub struct AlertCondition {
+    text: String,
+    expr: Expr,
+}
+
+impl AlertCondition {
+    pub fn matches(&self, burst: &Burst) -> bool {
+        self.expr.matches(burst)
+    }
+}
+
+impl fmt::Display for AlertCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl FromStr for AlertCondition {
+    type Err = BurstsharkError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| {
+            BurstsharkError::InvalidOptions(format!("Invalid alert condition '{text}': {reason}"))
+        };
+        let tokens = tokenize(text).map_err(invalid)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or().map_err(invalid)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(invalid(format!("unexpected {token}")));
+        }
+        Ok(AlertCondition {
+            text: text.to_string(),
+            expr,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    /// A quoted string, which is never taken for a field
+    Quoted(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{word}'"),
+            Token::Quoted(text) => write!(f, "\"{text}\""),
+            Token::Op(op) => f.write_str(match op {
+                Op::Eq => "'=='",
+                Op::Ne => "'!='",
+                Op::Lt => "'<'",
+                Op::Le => "'<='",
+                Op::Gt => "'>'",
+                Op::Ge => "'>='",
+            }),
+            Token::And => f.write_str("'&&'"),
+            Token::Or => f.write_str("'||'"),
+            Token::Not => f.write_str("'!'"),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | ':' | '/')
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Op(Op::Eq),
+            '!' if next_is('=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next_is('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' | '\'' => {
+                let quoted: String = chars
+                    .by_ref()
+                    .map(|(_, c)| c)
+                    .take_while(|q| *q != c)
+                    .collect();
+                if !text[start + 1..].contains(c) {
+                    return Err("unterminated string".to_string());
+                }
+                Token::Quoted(quoted)
+            }
+            c if is_word_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    end = i + c.len_utf8();
+                }
+                Token::Word(text[start..end].to_string())
+            }
+            c => return Err(format!("unexpected '{c}'")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parses tokens by precedence, from `||` over `&&` to `!` and comparisons
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn next_if(&mut self, expected: Token) -> bool {
+        let matched = self.tokens.get(self.pos) == Some(&expected);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.next_if(Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.next_if(Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(token) => Err(format!("expected ')' but found {token}")),
+                    None => Err("missing ')'".to_string()),
+                }
+            }
+            Some(Token::Word(name)) => self.comparison(&name),
+            Some(token) => Err(format!("expected a field but found {token}")),
+            None => Err("expected a field".to_string()),
+        }
+    }
+
+    fn comparison(&mut self, name: &str) -> Result<Expr, String> {
+        let (field, kind) = FIELDS
+            .iter()
+            .find(|(field, _, _)| *field == name)
+            .map(|(_, field, kind)| (*field, *kind))
+            .ok_or_else(|| format!("unknown field '{name}'"))?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            Some(token) => {
+                return Err(format!(
+                    "expected a comparison after '{name}' but found {token}"
+                ))
+            }
+            None => return Err(format!("expected a comparison after '{name}'")),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => value,
+            Some(token) => return Err(format!("expected a value but found {token}")),
+            None => return Err(format!("expected a value after {}", Token::Op(op))),
+        };
+
+        let value = match kind {
+            Kind::Text if matches!(op, Op::Eq | Op::Ne) => Value::Text(value),
+            Kind::Text => return Err(format!("'{name}' can only be compared with == and !=")),
+            Kind::Bytes => Value::Number(number(&value, BYTE_UNITS)?),
+            Kind::Seconds => Value::Number(number(&value, TIME_UNITS)?),
+            Kind::Number => Value::Number(number(&value, &[])?),
+        };
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+/// A number, optionally followed by one of the units
+fn number(value: &str, units: &[(&str, f64)]) -> Result<f64, String> {
+    let (digits, scale) = units
+        .iter()
+        .find_map(|(unit, scale)| Some((value.strip_suffix(unit)?, *scale)))
+        .unwrap_or((value, 1.0));
+    match digits.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number * scale),
+        _ => Err(format!("invalid number '{value}'")),
+    }
+}
+
+/// A number of bytes, optionally followed by a unit as in conditions, such as 1MB or 64KiB
+pub fn parse_bytes(value: &str) -> Result<f64, String> {
+    number(value, BYTE_UNITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_bytes, AlertCondition};
+    use crate::capture::Burst;
+
+    fn matches(condition: &str, burst: &Burst) -> bool {
+        condition.parse::<AlertCondition>().unwrap().matches(burst)
+    }
+
+    #[test]
+    fn sizes_and_times_are_compared_in_their_units() {
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 2_000_000);
+        assert!(matches("size>1MB && duration<1s", &burst));
+        assert!(matches("size<=2MB", &burst));
+        assert!(!matches("size>2MiB", &burst));
+        assert!(matches("duration==500ms", &burst));
+        assert!(!matches("duration>=1m", &burst));
+    }
+
+    #[test]
+    fn operators_combine_by_precedence() {
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 100);
+        assert!(matches("size>1kB || packets==1 && src_port==443", &burst));
+        assert!(!matches("(size>1kB || packets==1) && src_port==80", &burst));
+        assert!(matches("!(src_port==80)", &burst));
+    }
+
+    #[test]
+    fn ports_match_either_end() {
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 100);
+        assert!(matches("port==50000", &burst));
+        assert!(matches("port==443", &burst));
+        // Neither port may be the value
+        assert!(!matches("port!=443", &burst));
+        assert!(matches("port!=80", &burst));
+    }
+
+    #[test]
+    fn text_fields_the_burst_lacks_never_match() {
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 100);
+        assert!(matches("src=='10.0.0.1'", &burst));
+        assert!(!matches("label==video", &burst));
+        assert!(!matches("label!=video", &burst));
+        let burst = Burst {
+            label: Some("video-stream".into()),
+            ..burst
+        };
+        assert!(matches("label==video-stream", &burst));
+    }
+
+    #[test]
+    fn invalid_conditions_are_refused() {
+        for condition in [
+            "",
+            "size>",
+            "bytes>1MB",
+            "size>1XB",
+            "label<video",
+            "(size>1MB",
+            "size>1MB)",
+            "src=='10.0.0.1",
+            "size>1MB && ",
+        ] {
+            assert!(condition.parse::<AlertCondition>().is_err(), "{condition}");
+        }
+    }
+
+    #[test]
+    fn conditions_display_as_given() {
+        let condition: AlertCondition = "size>10MB && duration<1s".parse().unwrap();
+        assert_eq!(condition.to_string(), "size>10MB && duration<1s");
+    }
+
+    #[test]
+    fn bytes_are_parsed_with_units() {
+        assert_eq!(parse_bytes("64KiB"), Ok(65536.0));
+        assert_eq!(parse_bytes("1MB"), Ok(1e6));
+        assert_eq!(parse_bytes("12"), Ok(12.0));
+        assert!(parse_bytes("inf").is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Consistent anonymization of the addresses in records.
+//!
+//! By default, IP addresses are anonymized with the prefix-preserving scheme of Crypto-PAn, so that
+//! addresses sharing a prefix still do so after anonymization, while they can also be replaced by a
+//! keyed hash or stripped. MAC addresses are replaced by random locally administered ones. All are
+//! derived from a 32 byte key, which is random for each run unless it is kept in a key file.
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::unix::fs::OpenOptionsExt,
+    sync::Arc,
+};
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use clap::ValueEnum;
+use macaddr::{MacAddr, MacAddr6};
+
+use crate::{
+    capture::{Burst, Record},
+    BurstsharkError,
+};
+
+/// Bytes of a key, the AES key followed by the secret padding
+const KEY_LEN: usize = 32;
+
+/// How the addresses of records are anonymized
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum AnonymizeMode {
+    /// Crypto-PAn for IP addresses, keeping the prefixes they share, and random MAC addresses.
+    #[default]
+    PrefixPreserving,
+
+    /// A keyed hash of each IP address, hiding which share a network, and random MAC addresses.
+    Hash,
+
+    /// The unspecified IP address and the all-zero MAC address in place of every address.
+    Strip,
+}
+
+/// Anonymizes addresses consistently, so that each address is always replaced by the same one
+pub struct Anonymizer {
+    mode: AnonymizeMode,
+    cipher: Aes128,
+    /// Encrypted second half of the key, which fills the blocks after the bits of an address
+    pad: u128,
+    /// Anonymized addresses by the original, as the same addresses are seen over and over
+    cache: HashMap<Arc<str>, Arc<str>>,
+}
+
+impl Anonymizer {
+    /// Anonymize with a random key, so addresses can not be linked to those of other runs.
+    pub fn random(mode: AnonymizeMode) -> Result<Self, BurstsharkError> {
+        Ok(Anonymizer::new(mode, &random_key()?))
+    }
+
+    /// Anonymize with the key in the file, which is created with a random key if it does not
+    /// exist, so addresses are anonymized the same way by every run using the file.
+    pub fn from_key_file(path: &str, mode: AnonymizeMode) -> Result<Self, BurstsharkError> {
+        let key = match fs::read(path) {
+            Ok(key) => key.try_into().map_err(|_| BurstsharkError::InvalidFile {
+                path: path.to_string(),
+                reason: format!("anonymization keys must hold {KEY_LEN} bytes"),
+            })?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let key = random_key()?;
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(path)?
+                    .write_all(&key)?;
+                key
+            }
+            Err(source) => {
+                return Err(BurstsharkError::File {
+                    action: "read key file",
+                    path: path.to_string(),
+                    source,
+                })
+            }
+        };
+        Ok(Anonymizer::new(mode, &key))
+    }
+
+    fn new(mode: AnonymizeMode, key: &[u8; KEY_LEN]) -> Self {
+        let cipher = Aes128::new(GenericArray::from_slice(&key[..16]));
+        let mut pad = GenericArray::clone_from_slice(&key[16..]);
+        cipher.encrypt_block(&mut pad);
+        Anonymizer {
+            mode,
+            cipher,
+            pad: u128::from_be_bytes(pad.into()),
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn ip(&self, ip: IpAddr) -> IpAddr {
+        match (self.mode, ip) {
+            (AnonymizeMode::PrefixPreserving, IpAddr::V4(ip)) => {
+                let bits = self.prefix_preserving(u32::from(ip) as u128, 32);
+                IpAddr::V4(Ipv4Addr::from(bits as u32))
+            }
+            (AnonymizeMode::PrefixPreserving, IpAddr::V6(ip)) => {
+                IpAddr::V6(Ipv6Addr::from(self.prefix_preserving(u128::from(ip), 128)))
+            }
+            (AnonymizeMode::Hash, IpAddr::V4(ip)) => {
+                let bits = self.hash(u32::from(ip) as u128, 4);
+                IpAddr::V4(Ipv4Addr::from((bits >> 96) as u32))
+            }
+            (AnonymizeMode::Hash, IpAddr::V6(ip)) => {
+                IpAddr::V6(Ipv6Addr::from(self.hash(u128::from(ip), 6)))
+            }
+            (AnonymizeMode::Strip, IpAddr::V4(_)) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            (AnonymizeMode::Strip, IpAddr::V6(_)) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+
+    /// A random locally administered address, which stays multicast if the address is. The
+    /// broadcast address is kept, unless addresses are stripped.
+    pub fn mac(&self, mac: MacAddr6) -> MacAddr6 {
+        if self.mode == AnonymizeMode::Strip {
+            return MacAddr6::nil();
+        }
+        if mac.is_broadcast() {
+            return mac;
+        }
+        let original = mac.into_array();
+        let mut block = self.pad.to_be_bytes();
+        block[..6].copy_from_slice(&original);
+        // Sets these blocks apart from those of the IP addresses
+        block[15] ^= 1;
+        let encrypted = self.encrypt(u128::from_be_bytes(block)).to_be_bytes();
+
+        let mut bytes = [0; 6];
+        bytes.copy_from_slice(&encrypted[..6]);
+        bytes[0] = (bytes[0] & 0xfc) | 0x02 | (original[0] & 0x01);
+        MacAddr6::from(bytes)
+    }
+
+    /// Anonymize an address of a record, which is kept if it is neither an IP nor a MAC address
+    pub fn addr(&mut self, addr: &Arc<str>) -> Arc<str> {
+        if let Some(anonymized) = self.cache.get(addr) {
+            return anonymized.clone();
+        }
+        let anonymized: Arc<str> = match (addr.parse::<IpAddr>(), addr.parse::<MacAddr>()) {
+            (Ok(ip), _) => Arc::from(self.ip(ip).to_string()),
+            (_, Ok(MacAddr::V6(mac))) => Arc::from(self.mac(mac).to_string()),
+            _ => addr.clone(),
+        };
+        self.cache.insert(addr.clone(), anonymized.clone());
+        anonymized
+    }
+
+    /// A copy of the burst with its addresses anonymized. The flow id is left out, as it is a
+    /// hash of the original addresses.
+    pub fn burst(&mut self, burst: &Burst) -> Burst {
+        Burst {
+            src: self.addr(&burst.src),
+            dst: self.addr(&burst.dst),
+            flow_id: None,
+            src_name: None,
+            dst_name: None,
+            ..burst.clone()
+        }
+    }
+
+    /// A copy of the record with its addresses anonymized
+    pub fn record(&mut self, record: &Record) -> Record {
+        match record {
+            Record::Burst(burst) => Record::Burst(self.burst(burst)),
+            Record::FlowSurge(surge) => {
+                let mut surge = surge.clone();
+                surge.src = self.ip(surge.src);
+                Record::FlowSurge(surge)
+            }
+            Record::FlowEvent(event) => {
+                let mut event = event.clone();
+                event.src = self.addr(&event.src);
+                event.dst = self.addr(&event.dst);
+                event.flow_id = None;
+                Record::FlowEvent(event)
+            }
+            Record::Concurrency(concurrency) => {
+                let mut concurrency = concurrency.clone();
+                concurrency.host = self.ip(concurrency.host);
+                Record::Concurrency(concurrency)
+            }
+            Record::BurstDelta(delta) => {
+                let mut delta = delta.clone();
+                delta.src = self.addr(&delta.src);
+                delta.dst = self.addr(&delta.dst);
+                Record::BurstDelta(delta)
+            }
+            Record::RateSample(sample) => {
+                let mut sample = sample.clone();
+                sample.src = self.addr(&sample.src);
+                sample.dst = self.addr(&sample.dst);
+                sample.flow_id = None;
+                Record::RateSample(sample)
+            }
+            Record::FlowHistogram(histogram) => {
+                let mut histogram = histogram.clone();
+                histogram.src = self.addr(&histogram.src);
+                histogram.dst = self.addr(&histogram.dst);
+                Record::FlowHistogram(histogram)
+            }
+            // Already anonymous
+            Record::BurstHistogram(histogram) => Record::BurstHistogram(histogram.clone()),
+        }
+    }
+
+    /// Anonymize the first `len` bits of `bits`, aligned to the right, with Crypto-PAn.
+    ///
+    /// Each bit is flipped by the first bit of the encrypted bits before it, followed by the pad,
+    /// so the anonymized bits only depend on the bits up to them.
+    fn prefix_preserving(&self, bits: u128, len: u32) -> u128 {
+        let original = bits << (128 - len);
+        let mut flips = 0;
+        for pos in 0..len {
+            let prefix = u128::MAX.checked_shl(128 - pos).unwrap_or(0);
+            let block = (original & prefix) | (self.pad & !prefix);
+            flips |= (self.encrypt(block) >> 127) << (127 - pos);
+        }
+        (original ^ flips) >> (128 - len)
+    }
+
+    /// Keyed hash of the bits of an address, as the CBC-MAC of a block set apart by the domain,
+    /// followed by the bits
+    fn hash(&self, bits: u128, domain: u8) -> u128 {
+        let mut block = self.pad.to_be_bytes();
+        block[15] ^= domain;
+        self.encrypt(self.encrypt(u128::from_be_bytes(block)) ^ bits)
+    }
+
+    fn encrypt(&self, block: u128) -> u128 {
+        let mut block = GenericArray::from(block.to_be_bytes());
+        self.cipher.encrypt_block(&mut block);
+        u128::from_be_bytes(block.into())
+    }
+}
+
+fn random_key() -> Result<[u8; KEY_LEN], BurstsharkError> {
+    let mut key = [0; KEY_LEN];
+    getrandom::getrandom(&mut key)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        os::unix::fs::PermissionsExt,
+        process,
+    };
+
+    use macaddr::MacAddr6;
+
+    use super::{AnonymizeMode, Anonymizer, KEY_LEN};
+
+    /// The key of the sample trace distributed with the reference implementation of Crypto-PAn
+    const SAMPLE_KEY: [u8; KEY_LEN] = [
+        21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143, 131,
+        121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+    ];
+
+    fn v4(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    /// Bits the two addresses share before the first that differs
+    fn shared_prefix(a: IpAddr, b: IpAddr) -> u32 {
+        match (a, b) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => (u32::from(a) ^ u32::from(b)).leading_zeros(),
+            (IpAddr::V6(a), IpAddr::V6(b)) => (u128::from(a) ^ u128::from(b)).leading_zeros(),
+            _ => panic!("addresses of different versions"),
+        }
+    }
+
+    #[test]
+    fn ipv4_matches_the_reference_implementation() {
+        let anonymizer = Anonymizer::new(AnonymizeMode::PrefixPreserving, &SAMPLE_KEY);
+        for (original, anonymized) in [
+            ("128.11.68.132", "135.242.180.132"),
+            ("129.118.74.4", "134.136.186.123"),
+            ("130.132.252.244", "133.68.164.234"),
+            ("141.223.7.43", "141.167.8.160"),
+            ("192.102.249.13", "252.138.62.131"),
+            ("198.26.132.101", "249.36.123.202"),
+        ] {
+            assert_eq!(anonymizer.ip(v4(original)), v4(anonymized), "{original}");
+        }
+    }
+
+    #[test]
+    fn ipv4_shared_prefixes_are_preserved() {
+        let anonymizer = Anonymizer::new(AnonymizeMode::PrefixPreserving, &SAMPLE_KEY);
+        let base = u32::from(Ipv4Addr::new(10, 20, 30, 40));
+        for len in 0..32 {
+            // Shares exactly `len` bits with the base address
+            let other = base ^ (1 << (31 - len)) ^ 0x5a5a5a5a_u32.checked_shr(len + 1).unwrap_or(0);
+            let (a, b) = (IpAddr::V4(base.into()), IpAddr::V4(other.into()));
+            assert_eq!(shared_prefix(a, b), len);
+            assert_eq!(shared_prefix(anonymizer.ip(a), anonymizer.ip(b)), len);
+        }
+    }
+
+    #[test]
+    fn ipv6_shared_prefixes_are_preserved() {
+        let anonymizer = Anonymizer::new(AnonymizeMode::PrefixPreserving, &SAMPLE_KEY);
+        let base = u128::from("2001:db8:85a3::8a2e:370:7334".parse::<Ipv6Addr>().unwrap());
+        for len in 0..128 {
+            let other =
+                base ^ (1 << (127 - len)) ^ (u128::MAX / 3).checked_shr(len + 1).unwrap_or(0);
+            let (a, b) = (IpAddr::V6(base.into()), IpAddr::V6(other.into()));
+            assert_eq!(shared_prefix(a, b), len);
+            assert_eq!(shared_prefix(anonymizer.ip(a), anonymizer.ip(b)), len);
+        }
+    }
+
+    #[test]
+    fn key_files_anonymize_the_same_way_every_run() {
+        let path = std::env::temp_dir().join(format!("burstshark-key-{}", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let ips = [v4("192.0.2.1"), "2001:db8::1".parse().unwrap()];
+        let mut runs = Vec::new();
+        for mode in [AnonymizeMode::PrefixPreserving, AnonymizeMode::Hash] {
+            for _ in 0..2 {
+                let anonymizer = Anonymizer::from_key_file(path, mode).unwrap();
+                runs.push(ips.map(|ip| anonymizer.ip(ip)));
+            }
+        }
+        let key = fs::read(path).unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[2], runs[3]);
+        assert_ne!(runs[0], runs[2]);
+        assert_eq!(key.len(), KEY_LEN);
+        assert_eq!(mode & 0o777, 0o600);
+        // The file holds the key the addresses were anonymized with
+        let anonymizer = Anonymizer::new(AnonymizeMode::PrefixPreserving, &key.try_into().unwrap());
+        assert_eq!(ips.map(|ip| anonymizer.ip(ip)), runs[0]);
+    }
+
+    #[test]
+    fn key_files_of_the_wrong_length_are_refused() {
+        let path = std::env::temp_dir().join(format!("burstshark-short-key-{}", process::id()));
+        fs::write(&path, [0; KEY_LEN - 1]).unwrap();
+        let result = Anonymizer::from_key_file(path.to_str().unwrap(), AnonymizeMode::Hash);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn hashes_depend_on_the_key_but_not_on_the_prefix() {
+        let anonymizer = Anonymizer::new(AnonymizeMode::Hash, &SAMPLE_KEY);
+        let other_key = Anonymizer::new(AnonymizeMode::Hash, &[7; KEY_LEN]);
+        let (a, b) = (v4("10.0.0.1"), v4("10.0.0.2"));
+        assert_eq!(anonymizer.ip(a), anonymizer.ip(a));
+        assert_ne!(anonymizer.ip(a), other_key.ip(a));
+        // A single differing bit almost never leaves the first 24 in common
+        assert!(shared_prefix(anonymizer.ip(a), anonymizer.ip(b)) < 24);
+        assert!(anonymizer.ip("2001:db8::1".parse().unwrap()).is_ipv6());
+    }
+
+    #[test]
+    fn mac_addresses_are_locally_administered() {
+        let anonymizer = Anonymizer::new(AnonymizeMode::PrefixPreserving, &SAMPLE_KEY);
+        let unicast = MacAddr6::new(0x00, 0x1b, 0x63, 0x84, 0x45, 0xe6);
+        let multicast = MacAddr6::new(0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb);
+        let anonymized = anonymizer.mac(unicast);
+        assert_ne!(anonymized, unicast);
+        assert_eq!(anonymized, anonymizer.mac(unicast));
+        assert!(anonymized.is_local() && anonymized.is_unicast());
+        assert!(anonymizer.mac(multicast).is_multicast());
+        assert_eq!(anonymizer.mac(MacAddr6::broadcast()), MacAddr6::broadcast());
+    }
+
+    #[test]
+    fn stripping_replaces_every_address() {
+        let mut anonymizer = Anonymizer::new(AnonymizeMode::Strip, &SAMPLE_KEY);
+        assert_eq!(anonymizer.ip(v4("192.0.2.1")), v4("0.0.0.0"));
+        assert_eq!(&*anonymizer.addr(&"2001:db8::1".into()), "::");
+        assert_eq!(
+            &*anonymizer.addr(&"00:1b:63:84:45:e6".into()),
+            "00:00:00:00:00:00"
+        );
+        // Neither an IP nor a MAC address
+        assert_eq!(&*anonymizer.addr(&"wlan0".into()), "wlan0");
+    }
+}
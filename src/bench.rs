@@ -0,0 +1,133 @@
+//! Measuring how fast bursts are created, by running the pipeline over generated traffic whose
+//! bursts are known in advance.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{
+    capture::{CaptureBackend, CaptureType, CommonOptions, SyntheticTraffic},
+    stream::BurstStream,
+    BurstsharkError,
+};
+
+/// Throughput of the pipeline over generated traffic, and how many bursts differed from those
+/// generated
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub packets: u64,
+    pub expected_bursts: u64,
+    pub bursts: u64,
+    /// Bursts with other numbers of packets or bytes than the generated bursts
+    pub mismatched: u64,
+    #[serde(serialize_with = "seconds")]
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    /// Whether the bursts created are exactly those generated
+    pub fn passed(&self) -> bool {
+        self.bursts == self.expected_bursts && self.mismatched == 0
+    }
+
+    pub fn packets_per_sec(&self) -> f64 {
+        self.packets as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn bursts_per_sec(&self) -> f64 {
+        self.bursts as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+fn seconds<S: serde::Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(elapsed.as_secs_f64())
+}
+
+/// Run the capture created by `capture` over the generated traffic, with the inactive time and
+/// workers given, and compare its bursts with those generated.
+///
+/// Bursts are only those generated when the packets of a burst are sent closer than the inactive
+/// time, and the bursts of a flow further apart.
+pub fn run(
+    traffic: &SyntheticTraffic,
+    inactive_time: f64,
+    workers: usize,
+    capture: impl FnOnce(CommonOptions) -> CaptureType,
+) -> Result<BenchReport, BurstsharkError> {
+    if 1.0 / traffic.packet_rate >= inactive_time || traffic.burst_gap <= inactive_time {
+        return Err(BurstsharkError::InvalidOptions(format!(
+            "Generated bursts can only be told apart with packets less than the inactive time of \
+             {inactive_time} s apart, and pauses longer than it"
+        )));
+    }
+    let burst_size = traffic.burst_packets as u64 * traffic.packet_size as u64;
+    if burst_size > u32::MAX as u64 {
+        return Err(BurstsharkError::InvalidOptions(
+            "Generated bursts can be at most 4 GiB".to_string(),
+        ));
+    }
+
+    let start = Instant::now();
+    let mut stream = BurstStream::start(|tx| {
+        let mut opts = CommonOptions::new(CaptureBackend::Synthetic(traffic.clone()), tx);
+        opts.inactive_time = inactive_time;
+        opts.workers = workers;
+        capture(opts)
+    });
+
+    let (mut bursts, mut mismatched) = (0, 0);
+    for burst in stream.by_ref() {
+        bursts += 1;
+        if burst.num_packets != traffic.burst_packets || burst.size as u64 != burst_size {
+            mismatched += 1;
+        }
+    }
+    stream.finish()?;
+
+    Ok(BenchReport {
+        packets: traffic.packets(),
+        expected_bursts: traffic.bursts(),
+        bursts,
+        mismatched,
+        elapsed: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::capture::{CaptureType, IpOptions, SyntheticTraffic};
+
+    fn traffic() -> SyntheticTraffic {
+        SyntheticTraffic {
+            flows: 50,
+            packet_rate: 1000.0,
+            burst_packets: 10,
+            burst_gap: 1.0,
+            packet_size: 1200,
+            duration: 5.0,
+        }
+    }
+
+    fn ip_capture(opts: crate::capture::CommonOptions) -> CaptureType {
+        CaptureType::IPCapture {
+            opts,
+            ip: IpOptions::default(),
+        }
+    }
+
+    #[test]
+    fn the_generated_bursts_are_created() {
+        for workers in [1, 4] {
+            let report = run(&traffic(), 0.1, workers, ip_capture).unwrap();
+            assert!(report.passed(), "{report:?}");
+            assert_eq!(report.packets, report.bursts * 10);
+        }
+    }
+
+    #[test]
+    fn bursts_that_can_not_be_told_apart_are_refused() {
+        assert!(run(&traffic(), 0.0005, 1, ip_capture).is_err());
+        assert!(run(&traffic(), 2.0, 1, ip_capture).is_err());
+    }
+}
@@ -0,0 +1,407 @@
+//! Storing bursts in a SQLite database, and finding them again with simple filters.
+
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use rusqlite::{params_from_iter, types::Value, Connection, OpenFlags};
+use serde::Serialize;
+
+use crate::BurstsharkError;
+
+/// Version of the table, kept as the user version of the database. Columns are only ever added at
+/// the end, and the version is increased if any is renamed, removed or changes its type.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The table of bursts, with the names of the CSV header, and indexes for finding the bursts of a
+/// time range, optionally of a source or destination. The full burst is kept as its JSON line.
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS bursts (
+    completion_time REAL NOT NULL,
+    src TEXT NOT NULL,
+    src_port INTEGER,
+    dst TEXT NOT NULL,
+    dst_port INTEGER,
+    start REAL NOT NULL,
+    "end" REAL NOT NULL,
+    num_packets INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    proto TEXT,
+    interface TEXT,
+    probe_id TEXT,
+    record TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bursts_start ON bursts (start);
+CREATE INDEX IF NOT EXISTS bursts_src ON bursts (src, start);
+CREATE INDEX IF NOT EXISTS bursts_dst ON bursts (dst, start);
+"#;
+
+/// Inserts a burst, with the columns in the order of the table
+pub const INSERT: &str = r#"
+INSERT INTO bursts (
+    completion_time, src, src_port, dst, dst_port, start, "end", num_packets, size, proto,
+    interface, probe_id, record
+) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+"#;
+
+/// Open the database to store bursts in, creating it and its table unless they exist, so that the
+/// bursts of several captures can be kept in the same database
+pub fn create(path: &str) -> Result<Connection, BurstsharkError> {
+    let conn = Connection::open(path).map_err(|source| BurstsharkError::Database {
+        path: path.to_string(),
+        source,
+    })?;
+    // Lets queries read the database while bursts are being written
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    check_version(&conn, path)?;
+    conn.execute_batch(SCHEMA)?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(conn)
+}
+
+/// Open a database of bursts to query
+pub fn open(path: &str) -> Result<Connection, BurstsharkError> {
+    let conn =
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|source| {
+            BurstsharkError::Database {
+                path: path.to_string(),
+                source,
+            }
+        })?;
+    match check_version(&conn, path)? {
+        0 => Err(BurstsharkError::InvalidFile {
+            path: path.to_string(),
+            reason: "not a database of bursts".to_string(),
+        }),
+        _ => Ok(conn),
+    }
+}
+
+/// The version of the table of the database, which is 0 for a new database
+fn check_version(conn: &Connection, path: &str) -> Result<u32, BurstsharkError> {
+    let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    match version > SCHEMA_VERSION {
+        true => Err(BurstsharkError::InvalidFile {
+            path: path.to_string(),
+            reason: format!(
+                "bursts of a newer version of BurstShark, with schema version {version}"
+            ),
+        }),
+        false => Ok(version),
+    }
+}
+
+/// Which bursts to find, of which all given conditions must hold
+#[derive(Debug, Clone, Default)]
+pub struct BurstQuery {
+    pub src: Option<String>,
+    pub dst: Option<String>,
+    /// Address of either end
+    pub host: Option<String>,
+    /// Port of either end
+    pub port: Option<u16>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Bursts that started at or after this time
+    pub from: Option<f64>,
+    /// Bursts that ended at or before this time
+    pub to: Option<f64>,
+    /// Most bursts, or flows, to return
+    pub limit: Option<u64>,
+}
+
+impl BurstQuery {
+    /// The WHERE clause of the conditions, with their parameters
+    fn conditions(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        let mut add = |condition: &str, values: Vec<Value>| {
+            conditions.push(condition.to_string());
+            params.extend(values);
+        };
+
+        if let Some(src) = &self.src {
+            add("src = ?", vec![Value::Text(src.clone())]);
+        }
+        if let Some(dst) = &self.dst {
+            add("dst = ?", vec![Value::Text(dst.clone())]);
+        }
+        if let Some(host) = &self.host {
+            let host = Value::Text(host.clone());
+            add("(src = ? OR dst = ?)", vec![host.clone(), host]);
+        }
+        if let Some(port) = self.port {
+            let port = Value::Integer(port.into());
+            add("(src_port = ? OR dst_port = ?)", vec![port.clone(), port]);
+        }
+        if let Some(size) = self.min_size {
+            add("size >= ?", vec![Value::Integer(size as i64)]);
+        }
+        if let Some(size) = self.max_size {
+            add("size <= ?", vec![Value::Integer(size as i64)]);
+        }
+        if let Some(from) = self.from {
+            add("start >= ?", vec![Value::Real(from)]);
+        }
+        if let Some(to) = self.to {
+            add(r#""end" <= ?"#, vec![Value::Real(to)]);
+        }
+
+        let clause = match conditions.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+        (clause, params)
+    }
+
+    fn limit(&self) -> String {
+        self.limit
+            .map_or(String::new(), |limit| format!("LIMIT {limit}"))
+    }
+}
+
+/// A stored burst, with its JSON line as written by `--output-format jsonl`
+#[derive(Debug, Clone)]
+pub struct StoredBurst {
+    pub src: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst: Arc<str>,
+    pub dst_port: Option<u16>,
+    pub start: f64,
+    pub end: f64,
+    pub num_packets: u32,
+    pub size: u64,
+    pub record: String,
+}
+
+/// The bursts of a flow that matched a query
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowSummary {
+    pub src: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst: Arc<str>,
+    pub dst_port: Option<u16>,
+    pub bursts: u64,
+    pub bytes: u64,
+    /// Start of the first burst and end of the last
+    pub first: f64,
+    pub last: f64,
+}
+
+/// The bursts matching the query, in the order they started
+pub fn bursts(conn: &Connection, query: &BurstQuery) -> Result<Vec<StoredBurst>, BurstsharkError> {
+    let (conditions, params) = query.conditions();
+    let sql = format!(
+        r#"SELECT src, src_port, dst, dst_port, start, "end", num_packets, size, record
+        FROM bursts {conditions} ORDER BY start {}"#,
+        query.limit()
+    );
+    let mut statement = conn.prepare(&sql)?;
+    let rows = statement.query_map(params_from_iter(params), |row| {
+        Ok(StoredBurst {
+            src: row.get::<_, String>(0)?.into(),
+            src_port: row.get(1)?,
+            dst: row.get::<_, String>(2)?.into(),
+            dst_port: row.get(3)?,
+            start: row.get(4)?,
+            end: row.get(5)?,
+            num_packets: row.get(6)?,
+            size: row.get(7)?,
+            record: row.get(8)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// The flows with bursts matching the query, with the most bytes first
+pub fn flows(conn: &Connection, query: &BurstQuery) -> Result<Vec<FlowSummary>, BurstsharkError> {
+    let (conditions, params) = query.conditions();
+    let sql = format!(
+        r#"SELECT src, src_port, dst, dst_port, COUNT(*), SUM(size), MIN(start), MAX("end")
+        FROM bursts {conditions} GROUP BY src, src_port, dst, dst_port
+        ORDER BY SUM(size) DESC, src, src_port, dst, dst_port {}"#,
+        query.limit()
+    );
+    let mut statement = conn.prepare(&sql)?;
+    let rows = statement.query_map(params_from_iter(params), |row| {
+        Ok(FlowSummary {
+            src: row.get::<_, String>(0)?.into(),
+            src_port: row.get(1)?,
+            dst: row.get::<_, String>(2)?.into(),
+            dst_port: row.get(3)?,
+            bursts: row.get(4)?,
+            bytes: row.get(5)?,
+            first: row.get(6)?,
+            last: row.get(7)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Print a table of the bursts
+pub fn write_bursts(out: &mut impl Write, bursts: &[StoredBurst]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>17} {:>17} {:15} {:6} {:15} {:5} {:>7} {:>10}",
+        "start", "end", "src", "port", "dst", "port", "packets", "size",
+    )?;
+    for burst in bursts {
+        writeln!(
+            out,
+            "{:17.6} {:17.6} {:15} {:6} {:15} {:5} {:7} {:10}",
+            burst.start,
+            burst.end,
+            burst.src,
+            burst.src_port.map_or("".to_string(), |p| p.to_string()),
+            burst.dst,
+            burst.dst_port.map_or("".to_string(), |p| p.to_string()),
+            burst.num_packets,
+            burst.size,
+        )?;
+    }
+    Ok(())
+}
+
+/// Print a table of the flows
+pub fn write_flows(out: &mut impl Write, flows: &[FlowSummary]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:15} {:6} {:15} {:5} {:>8} {:>12} {:>17} {:>17}",
+        "src", "port", "dst", "port", "bursts", "bytes", "first", "last",
+    )?;
+    for flow in flows {
+        writeln!(
+            out,
+            "{:15} {:6} {:15} {:5} {:8} {:12} {:17.6} {:17.6}",
+            flow.src,
+            flow.src_port.map_or("".to_string(), |p| p.to_string()),
+            flow.dst,
+            flow.dst_port.map_or("".to_string(), |p| p.to_string()),
+            flow.bursts,
+            flow.bytes,
+            flow.first,
+            flow.last,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use rusqlite::{params, Connection};
+
+    use super::{bursts, create, flows, open, write_bursts, BurstQuery, INSERT, SCHEMA_VERSION};
+
+    fn db_path(test: &str) -> String {
+        let path = std::env::temp_dir().join(format!("burstshark-{test}-{}.db", process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+        path.to_string_lossy().into_owned()
+    }
+
+    /// A database of three bursts of two flows
+    fn database(test: &str) -> String {
+        let path = db_path(test);
+        let conn = create(&path).unwrap();
+        for (src, src_port, start, size) in [
+            ("10.0.0.1", 443, 1.0, 1000),
+            ("10.0.0.3", 53, 2.0, 300),
+            ("10.0.0.1", 443, 3.0, 2000),
+        ] {
+            conn.execute(
+                INSERT,
+                params![
+                    start + 0.5,
+                    src,
+                    src_port,
+                    "10.0.0.2",
+                    50000,
+                    start,
+                    start + 0.1,
+                    2,
+                    size,
+                    None::<String>,
+                    None::<String>,
+                    None::<String>,
+                    "{}"
+                ],
+            )
+            .unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn bursts_are_found_by_all_conditions_in_order() {
+        let conn = open(&database("burstdb-bursts")).unwrap();
+        let all = bursts(&conn, &BurstQuery::default()).unwrap();
+        let starts: Vec<f64> = all.iter().map(|burst| burst.start).collect();
+        assert_eq!(starts, [1.0, 2.0, 3.0]);
+
+        let query = BurstQuery {
+            host: Some("10.0.0.1".to_string()),
+            min_size: Some(1500),
+            ..BurstQuery::default()
+        };
+        let found = bursts(&conn, &query).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].size, found[0].end), (2000, 3.1));
+
+        let query = BurstQuery {
+            port: Some(50000),
+            from: Some(1.5),
+            to: Some(2.5),
+            ..BurstQuery::default()
+        };
+        assert_eq!(&*bursts(&conn, &query).unwrap()[0].src, "10.0.0.3");
+        let query = BurstQuery {
+            limit: Some(2),
+            ..BurstQuery::default()
+        };
+        assert_eq!(bursts(&conn, &query).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn flows_are_summarized_with_the_most_bytes_first() {
+        let conn = open(&database("burstdb-flows")).unwrap();
+        let found = flows(&conn, &BurstQuery::default()).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(&*found[0].src, "10.0.0.1");
+        assert_eq!((found[0].bursts, found[0].bytes), (2, 3000));
+        assert_eq!((found[0].first, found[0].last), (1.0, 3.1));
+        assert_eq!(found[1].bytes, 300);
+    }
+
+    #[test]
+    fn databases_of_other_versions_are_refused() {
+        let path = db_path("burstdb-version");
+        Connection::open(&path).unwrap();
+        // Empty databases hold no bursts to query
+        assert!(open(&path).is_err());
+
+        drop(create(&path).unwrap());
+        assert!(open(&path).is_ok());
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        assert!(open(&path).is_err());
+        assert!(create(&path).is_err());
+    }
+
+    #[test]
+    fn bursts_are_printed_as_a_table() {
+        let conn = open(&database("burstdb-table")).unwrap();
+        let mut out = Vec::new();
+        write_bursts(&mut out, &bursts(&conn, &BurstQuery::default()).unwrap()).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].trim_start().starts_with("start"));
+        assert!(lines[1].contains("10.0.0.1") && lines[1].ends_with("1000"));
+    }
+}
@@ -0,0 +1,113 @@
+//! Reading bursts back from the JSON output of earlier captures.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+
+use crate::BurstsharkError;
+
+/// A burst read from a burst log, with the fields of the output that describe its flow and size
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggedBurst {
+    pub src: Arc<str>,
+    pub dst: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub start: f64,
+    pub end: f64,
+    pub num_packets: u32,
+    pub size: u64,
+}
+
+/// A record of the output, of which all but bursts are skipped
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LoggedRecord {
+    Burst(LoggedBurst),
+    #[serde(other)]
+    Other,
+}
+
+/// Read the bursts of a log written with `--output-format jsonl` or `json`.
+///
+/// Each line holds a record, which the JSON array of the `json` format wraps in brackets and
+/// separates by commas at the start of the lines.
+pub fn read(path: &str) -> Result<Vec<LoggedBurst>, BurstsharkError> {
+    let file = File::open(path).map_err(|source| BurstsharkError::File {
+        action: "open burst log",
+        path: path.to_string(),
+        source,
+    })?;
+    let mut bursts = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let record = line
+            .trim()
+            .trim_start_matches(['[', ','])
+            .trim_end_matches(']');
+        if record.is_empty() {
+            continue;
+        }
+        match serde_json::from_str(record) {
+            Ok(LoggedRecord::Burst(burst)) => bursts.push(burst),
+            Ok(LoggedRecord::Other) => {}
+            Err(e) => {
+                return Err(BurstsharkError::InvalidFile {
+                    path: path.to_string(),
+                    reason: format!("line {}: {e}", number + 1),
+                })
+            }
+        }
+    }
+    Ok(bursts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::read;
+
+    /// Read a log of its own for the test
+    fn read_log(test: &str, log: &str) -> Result<Vec<(u16, u64)>, String> {
+        let path = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        fs::write(&path, log).unwrap();
+        let bursts = read(path.to_str().unwrap());
+        let _ = fs::remove_file(path);
+        bursts
+            .map(|bursts| {
+                bursts
+                    .iter()
+                    .map(|burst| (burst.src_port.unwrap(), burst.size))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    const BURST: &str = r#"{"type":"burst","completion_time":1.5,"src":"10.0.0.1","dst":"10.0.0.2","src_port":50000,"dst_port":443,"start":1.0,"end":1.25,"num_packets":3,"size":4000}"#;
+
+    #[test]
+    fn json_lines_and_arrays_are_read() {
+        let event = r#"{"type":"flow_event","kind":"start"}"#;
+        let jsonl = format!("{BURST}\n{event}\n{BURST}\n");
+        assert_eq!(
+            read_log("burstlog-jsonl", &jsonl),
+            Ok(vec![(50000, 4000), (50000, 4000)])
+        );
+
+        let json = format!("[\n{BURST}\n,{event}\n,{BURST}\n]\n");
+        assert_eq!(read_log("burstlog-json", &json).unwrap().len(), 2);
+        assert_eq!(read_log("burstlog-empty", "[]\n"), Ok(vec![]));
+    }
+
+    #[test]
+    fn invalid_lines_are_refused_with_their_number() {
+        let log = format!("{BURST}\n1.5 10.0.0.1 50000\n");
+        let err = read_log("burstlog-text", &log).unwrap_err();
+        assert!(err.contains("line 2"), "{err}");
+    }
+}
@@ -0,0 +1,68 @@
+/// Bytes of the MAC header of QoS data frames and their frame check sequence, which are sent along
+/// with the frame body
+const MAC_OVERHEAD: u32 = 26 + 4;
+
+/// Microseconds of the long preamble and PLCP header of DSSS and CCK frames
+const DSSS_PREAMBLE: f64 = 192.0;
+
+/// Microseconds of the preamble and SIGNAL field of OFDM frames
+const OFDM_PREAMBLE: f64 = 20.0;
+
+/// Microseconds of the mixed format preamble of HT and later frames, with a single stream
+const HT_PREAMBLE: f64 = 36.0;
+
+/// Microseconds of an OFDM symbol with the long guard interval
+const OFDM_SYMBOL: f64 = 4.0;
+
+/// Bits of the SERVICE field and tail that OFDM frames add to the data
+const OFDM_EXTRA_BITS: f64 = 16.0 + 6.0;
+
+/// Estimate the seconds a data frame with a body of `data_len` bytes takes on air at `rate` Mb/s.
+///
+/// The frame is assumed to be sent with the long preamble at DSSS and CCK rates, the legacy OFDM
+/// preamble at other rates up to 54 Mb/s, and the HT mixed format preamble above that. The time
+/// of acknowledgements and of contending for the channel is not included.
+pub(super) fn frame_airtime(data_len: u32, rate: f64) -> f64 {
+    let bits = ((data_len + MAC_OVERHEAD) * 8) as f64;
+    let micros = match rate {
+        1.0 | 2.0 | 5.5 | 11.0 => DSSS_PREAMBLE + bits / rate,
+        _ => {
+            let preamble = match rate > 54.0 {
+                true => HT_PREAMBLE,
+                false => OFDM_PREAMBLE,
+            };
+            let bits_per_symbol = rate * OFDM_SYMBOL;
+            preamble + ((OFDM_EXTRA_BITS + bits) / bits_per_symbol).ceil() * OFDM_SYMBOL
+        }
+    };
+    micros / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::frame_airtime;
+
+    /// Airtime in microseconds, rounded to cancel floating point errors
+    fn micros(data_len: u32, rate: f64) -> f64 {
+        (frame_airtime(data_len, rate) * 1e9).round() / 1e3
+    }
+
+    #[test]
+    fn dsss_frames_take_the_long_preamble_and_their_bits() {
+        // 130 bytes of header, body and frame check sequence at 1 and 2 Mb/s
+        assert_eq!(micros(100, 1.0), 192.0 + 1040.0);
+        assert_eq!(micros(100, 2.0), 192.0 + 520.0);
+    }
+
+    #[test]
+    fn ofdm_frames_take_whole_symbols() {
+        // 1062 bits in symbols of 24 and 216 bits
+        assert_eq!(micros(100, 6.0), 20.0 + 45.0 * 4.0);
+        assert_eq!(micros(100, 54.0), 20.0 + 5.0 * 4.0);
+    }
+
+    #[test]
+    fn rates_above_54_mbps_take_the_ht_preamble() {
+        assert_eq!(micros(100, 65.0), 36.0 + 5.0 * 4.0);
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+use super::fields::Fields;
+use crate::BurstsharkError;
+
+/// Shortest time between reports of lines that could not be parsed
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Counts lines of tshark output that could not be parsed, such as those with fields printing
+/// several values, and continues with the next line.
+///
+/// When logging them, a report shows the fields that were expected next to those of the line. To
+/// not flood the terminal, lines after a report are only counted until the next report is due.
+/// All of them can be kept in a file for bug reports.
+pub struct BadLines {
+    /// Names of the fields of a line, in order
+    layout: Vec<String>,
+    dump: Option<(String, BufWriter<File>)>,
+    log: bool,
+    total: u64,
+    /// Lines since the last report
+    unreported: u64,
+    last_report: Option<Instant>,
+}
+
+impl BadLines {
+    pub fn new(
+        layout: Vec<String>,
+        dump_file: Option<&str>,
+        log: bool,
+    ) -> Result<Self, BurstsharkError> {
+        let dump = match dump_file {
+            Some(path) => Some((
+                path.to_string(),
+                BufWriter::new(File::create(path).map_err(|source| BurstsharkError::File {
+                    action: "create bad lines file",
+                    path: path.to_string(),
+                    source,
+                })?),
+            )),
+            None => None,
+        };
+        Ok(BadLines {
+            layout,
+            dump,
+            log,
+            total: 0,
+            unreported: 0,
+            last_report: None,
+        })
+    }
+
+    /// The names of the `-e` arguments of tshark
+    pub fn tshark_layout(args: &[String]) -> Vec<String> {
+        args.windows(2)
+            .filter(|pair| pair[0] == "-e")
+            .map(|pair| pair[1].clone())
+            .collect()
+    }
+
+    pub fn add(&mut self, line: &str, error: &BurstsharkError) {
+        self.total += 1;
+        self.unreported += 1;
+
+        if let Some((path, file)) = &mut self.dump {
+            if let Err(e) = writeln!(file, "{line}") {
+                eprintln!("Error writing bad lines to {path}: {e}");
+                self.dump = None;
+            }
+        }
+
+        if !self.log {
+            if self.total == 1 {
+                eprintln!("Skipping lines of tshark output that could not be parsed, see them with --log-malformed");
+            }
+            return;
+        }
+
+        if self
+            .last_report
+            .is_some_and(|last| last.elapsed() < REPORT_INTERVAL)
+        {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+
+        if self.unreported > 1 {
+            eprintln!(
+                "Skipped {} more lines of tshark output that could not be parsed",
+                self.unreported - 1
+            );
+        }
+        self.unreported = 0;
+        eprintln!("Skipped a line of tshark output that could not be parsed: {error}");
+        eprintln!("{}", self.describe(line));
+    }
+
+    /// Report the lines skipped since the last report, or in total when not logging them
+    pub fn finish(mut self) {
+        if !self.log && self.total > 0 {
+            eprintln!(
+                "Skipped {} lines of tshark output that could not be parsed",
+                self.total
+            );
+        } else if self.unreported > 0 {
+            eprintln!(
+                "Skipped {} more lines of tshark output that could not be parsed",
+                self.unreported
+            );
+        }
+        if let Some((path, file)) = &mut self.dump {
+            match file.flush() {
+                Ok(()) if self.total > 0 => {
+                    eprintln!(
+                        "Wrote {} lines that could not be parsed to {path}",
+                        self.total
+                    )
+                }
+                Ok(()) => {}
+                Err(e) => eprintln!("Error writing bad lines to {path}: {e}"),
+            }
+        }
+    }
+
+    /// The fields of the line next to the names of the expected fields, one per line
+    fn describe(&self, line: &str) -> String {
+        let fields: Vec<_> = Fields::new(line).collect();
+        let mut description = match self.layout.len() {
+            0 => format!("  got {} fields", fields.len()),
+            expected => format!("  expected {expected} fields, got {}", fields.len()),
+        };
+        for (i, field) in fields.iter().enumerate() {
+            let name = self.layout.get(i).map_or("(unexpected)", String::as_str);
+            description.push_str(&format!("\n  {name:>20}: {field:?}"));
+        }
+        for name in self.layout.iter().skip(fields.len()) {
+            description.push_str(&format!("\n  {name:>20}: (missing)"));
+        }
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::BadLines;
+    use crate::BurstsharkError;
+
+    fn layout() -> Vec<String> {
+        BadLines::tshark_layout(&[
+            "-T".to_string(),
+            "fields".to_string(),
+            "-e".to_string(),
+            "frame.time_epoch".to_string(),
+            "-e".to_string(),
+            "ip.src".to_string(),
+        ])
+    }
+
+    #[test]
+    fn the_layout_is_the_fields_of_tshark() {
+        assert_eq!(layout(), ["frame.time_epoch", "ip.src"]);
+    }
+
+    #[test]
+    fn lines_are_described_by_their_fields() {
+        let bad_lines = BadLines::new(layout(), None, true).unwrap();
+        let missing = bad_lines.describe("1.5");
+        assert!(missing.starts_with("  expected 2 fields, got 1"));
+        assert!(missing.contains("frame.time_epoch: \"1.5\""));
+        assert!(missing.contains("ip.src: (missing)"));
+
+        let unexpected = bad_lines.describe("1.5\t10.0.0.1\t10.0.0.2\n");
+        assert!(unexpected.contains("(unexpected): \"10.0.0.2\""));
+    }
+
+    #[test]
+    fn lines_after_a_report_are_only_counted() {
+        let mut bad_lines = BadLines::new(layout(), None, true).unwrap();
+        let error = BurstsharkError::MissingField("1.5".to_string());
+        for _ in 0..3 {
+            bad_lines.add("1.5", &error);
+        }
+        assert_eq!((bad_lines.total, bad_lines.unreported), (3, 2));
+    }
+
+    #[test]
+    fn all_lines_are_dumped() {
+        let path = std::env::temp_dir().join(format!("burstshark-bad-lines-{}", process::id()));
+        let path = path.to_str().unwrap();
+        let mut bad_lines = BadLines::new(layout(), Some(path), false).unwrap();
+        let error = BurstsharkError::MissingField(String::new());
+        bad_lines.add("1.5", &error);
+        bad_lines.add("2.5\t10.0.0.1\textra", &error);
+        bad_lines.finish();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "1.5\n2.5\t10.0.0.1\textra\n"
+        );
+        let _ = fs::remove_file(path);
+    }
+}
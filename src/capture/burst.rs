@@ -1,191 +1,1713 @@
-use std::{collections::HashMap, error::Error, net::IpAddr, sync::mpsc, thread, time::Duration};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use macaddr::MacAddr;
+use serde::Serialize;
 
-use super::{fifo::Fifo, IpPacket, WlanPacket};
+use super::{
+    airtime::frame_airtime,
+    concurrency::{ConcurrencyTracker, HostConcurrency},
+    detail::{BurstDetail, DetailTracker},
+    flow_error::FlowError,
+    flow_id::flow_id,
+    gap::{AdaptiveGap, InterArrival},
+    group::{CastGroup, CastKind},
+    interrupt,
+    pool::PooledAddr,
+    proto::IpProto,
+    queue::BurstQueue,
+    quic::{ConnectionId, QuicFlows},
+    random_mac::MacCorrelator,
+    rate::{RateSample, RateSampler},
+    retransmit::SeqTracker,
+    sequence::{Sequence, SequenceTracker, SequenceWindow},
+    shard::{hash_hosts, Input, Progress},
+    ssh::{SshPhase, SSH_PORT},
+    state::{wall_clock_offset, FlowState, SequenceState, StateFile},
+    surge::SurgeDetector,
+    tunnel::{Tunnel, TunnelKey, TunnelMode},
+    CommonOptions, EthPacket, IpOptions, IpPacket, Record, WlanOptions, WlanPacket,
+};
+use crate::channel::{self, Receiver, RecvTimeoutError, Sender};
+use crate::classify::FlowClass;
+use crate::eve::EveAlert;
+use crate::probe::ProbeIdentity;
+use crate::profile::{self, Stage};
+use crate::BurstsharkError;
 
-pub(super) fn start_ip(
-    inactive_time: f64,
+/// Bursts are completed once they have this many packets, or this many bytes, so that further
+/// packets of flows that never pause can not overflow them. Leaves room for the lost frames guessed
+/// in monitor mode.
+const MAX_BURST_PACKETS: u16 = u16::MAX / 2;
+const MAX_BURST_SIZE: u32 = u32::MAX / 2;
+
+/// Addresses, ports, tunnel, QUIC connection id, RTP SSRC, interface id and the IP protocol of
+/// flows without ports
+type IpFlowKey = (
+    IpAddr,
+    IpAddr,
+    Option<u16>,
+    Option<u16>,
+    Option<TunnelKey>,
+    Option<ConnectionId>,
+    Option<u32>,
+    Option<u16>,
+    Option<u8>,
+);
+
+/// Addresses and TID of a WLAN flow
+type WlanFlowKey = (MacAddr, MacAddr, Option<u8>);
+
+/// Addresses, ethertype and interface id of a layer 2 flow
+type EthFlowKey = (MacAddr, MacAddr, u16, Option<u16>);
+
+/// The flow key of a packet, and the multicast or broadcast group it belongs to
+fn ip_flow_key(
+    p: &IpPacket,
     ignore_ports: bool,
-    output_tx: mpsc::Sender<Burst>,
-) -> Result<mpsc::Sender<IpPacket>, Box<dyn Error>> {
-    let (tx, rx) = mpsc::channel::<IpPacket>();
+    group_keys: bool,
+    tunnels: Option<TunnelMode>,
+) -> (IpFlowKey, Option<CastGroup>) {
+    // Tunnels are keyed on the tunnel rather than ports, which may change with NAT traversal
+    if let (Some(tunnel), Some(mode)) = (p.tunnel.filter(|tunnel| !tunnel.decapsulated()), tunnels)
+    {
+        return (
+            (
+                p.src,
+                p.dst,
+                None,
+                None,
+                Some(tunnel.key(mode)),
+                None,
+                None,
+                p.interface,
+                None,
+            ),
+            None,
+        );
+    }
 
-    thread::spawn(move || {
-        let mut key_time_queue = Fifo::new();
-        let mut flows: HashMap<(IpAddr, IpAddr, Option<u16>, Option<u16>), IpFlow> = HashMap::new();
+    let group = if group_keys {
+        CastGroup::of(p.dst, p.dst_port)
+    } else {
+        None
+    };
 
+    let cid = p.quic.map(|ids| ids.dcid);
+    // Decapsulated packets are also keyed on their tunnel, as inner addresses may be reused in
+    // different tunnels, such as by the tenants of VXLAN networks
+    let tunnel = p
+        .tunnel
+        .filter(Tunnel::decapsulated)
+        .map(|tunnel| tunnel.key(TunnelMode::Session));
+    let mut key = match group {
+        // Each source sending to a multicast group is one flow, regardless of ports
+        Some(group) if group.kind == CastKind::Multicast => (
+            p.src,
+            p.dst,
+            None,
+            None,
+            None,
+            None,
+            None,
+            p.interface,
+            None,
+        ),
+        // Separate broadcast protocols, such as DHCP, by their destination port
+        Some(_) => (
+            p.src,
+            p.dst,
+            None,
+            p.dst_port,
+            None,
+            None,
+            None,
+            p.interface,
+            None,
+        ),
+        None if ignore_ports => (p.src, p.dst, None, None, None, cid, None, p.interface, None),
+        None => (
+            p.src,
+            p.dst,
+            p.src_port,
+            p.dst_port,
+            None,
+            cid,
+            None,
+            p.interface,
+            None,
+        ),
+    };
+    // Protocols without ports, such as ICMP and ESP, are told apart by their IP protocol
+    if p.src_port.is_none() && p.dst_port.is_none() {
+        key.8 = p.proto.map(|proto| proto.0);
+    }
+    key.4 = tunnel;
+    // RTP streams sharing ports, such as the audio and video of WebRTC, are told apart by SSRC
+    key.6 = p.rtp.map(|rtp| rtp.ssrc);
+    (key, group)
+}
+
+/// Order the endpoints of a flow key, so both directions of a flow have the same key. Also returns
+/// whether the endpoints were swapped, so the packet goes from the second endpoint to the first.
+fn sort_endpoints(key: IpFlowKey) -> (IpFlowKey, bool) {
+    let (src, dst, src_port, dst_port, tunnel, cid, ssrc, interface, proto) = key;
+    match (src, src_port) <= (dst, dst_port) {
+        true => (key, false),
+        false => (
+            (
+                dst, src, dst_port, src_port, tunnel, cid, ssrc, interface, proto,
+            ),
+            true,
+        ),
+    }
+}
+
+pub(super) fn start_ip(
+    opts: &CommonOptions,
+    ip: IpOptions,
+    progress: Progress,
+    state: Option<Arc<StateFile>>,
+) -> Result<Sender<Input<IpPacket>>, BurstsharkError> {
+    let (tx, rx) = channel::channel::<Input<IpPacket>>(&opts.channels);
+    let inactive_time = opts.inactive_time;
+    let adaptive_gap = opts.adaptive_gap;
+    let flow_events = opts.flow_events;
+    let offline = opts.offline;
+    let sched = opts.worker_sched;
+    let profile = opts.profile.clone();
+    let output_tx = opts.tx.clone();
+    let mut filter = opts.filter;
+    let controls = opts.controls.clone();
+    let packet_timing = opts.packet_timing;
+    let detailed_bursts = opts.detailed_bursts;
+    let interfaces = opts.interfaces.clone();
+    let max_packets = opts
+        .max_burst_packets
+        .map_or(MAX_BURST_PACKETS, |max| max.min(MAX_BURST_PACKETS));
+    let mut activity = FlowActivity::new(opts);
+    let mut rates = opts.rate_interval.map(RateSampler::new);
+    let mut restored = restore_flows(
+        &state,
+        &progress,
+        opts.workers,
+        |flow| IpFlow::restore(flow, &ip, &interfaces),
+        |key| hash_hosts(key.0, key.1),
+    );
+
+    thread::spawn(move || -> Result<(), BurstsharkError> {
+        sched.apply_or_warn("burst");
+
+        let mut key_time_queue = BurstQueue::new(inactive_time, adaptive_gap.is_some());
+        let mut flows: HashMap<IpFlowKey, IpFlow> = HashMap::new();
+        let mut surge = ip.surge.map(SurgeDetector::new);
+        let mut quic = QuicFlows::new();
+        let mut concurrency = ip
+            .concurrency_interval
+            .map(|interval| ConcurrencyTracker::new(interval, inactive_time));
+
+        // Wake up at least as often as the shortest gap of a live capture
+        let timeout = adaptive_gap.map_or(inactive_time, |gap| gap.min);
         let mut last_time = 0.0;
+        let mut last_arrival = Instant::now();
+        let mut seen = 0;
         loop {
-            match rx.recv_timeout(Duration::from_secs_f64(inactive_time)) {
-                Ok(packet) => {
+            if let Some(settings) = controls.as_ref().and_then(|c| c.changed(&mut seen)) {
+                filter = settings.filter;
+            }
+            match recv(&rx, offline, timeout) {
+                Ok(Input::Packet(packet)) => profile::time(&profile, Stage::Burstify, || {
                     last_time = packet.time;
-                    create_bursts(
+                    last_arrival = Instant::now();
+                    add_restored(
                         packet.time,
+                        &mut restored,
+                        &mut flows,
+                        &mut key_time_queue,
+                        &mut activity,
                         inactive_time,
+                        adaptive_gap,
+                    )?;
+                    send_rates(packet.time, &mut rates, &flows, &output_tx)?;
+                    create_bursts(
+                        packet.time,
                         &mut key_time_queue,
                         &mut flows,
                         &output_tx,
-                    );
+                        &filter,
+                    )?;
+                    evict_flows(
+                        packet.time,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    if let Some(concurrency) = &mut concurrency {
+                        send_concurrency(concurrency.report(packet.time), &output_tx)?;
+                    }
+
+                    let (flow_key, group) =
+                        ip_flow_key(&packet, ip.ignore_ports, ip.group_keys, ip.tunnels);
 
-                    let flow_key = (
-                        packet.src,
-                        packet.dst,
-                        (!ignore_ports).then_some(packet.src_port),
-                        (!ignore_ports).then_some(packet.dst_port),
-                    );
+                    if let Some(error) = packet.error {
+                        // Errors are sent by either endpoint, or a router on the path
+                        let (src, dst, src_port, dst_port, tunnel, cid, ssrc, interface, proto) =
+                            flow_key;
+                        let reverse_key = (
+                            dst, src, dst_port, src_port, tunnel, cid, ssrc, interface, proto,
+                        );
+                        for key in [flow_key, reverse_key] {
+                            let key = match ip.bidirectional {
+                                true => sort_endpoints(key).0,
+                                false => key,
+                            };
+                            if let Some(flow) = flows.get_mut(&key) {
+                                flow.add_error(error);
+                            }
+                        }
+                        return Ok(());
+                    }
 
-                    flows
-                        .entry(flow_key)
-                        .and_modify(|flow| flow.add_packet(&packet))
-                        .or_insert_with(|| IpFlow::new(&packet, ignore_ports));
+                    // QUIC flows continue on the connection ids when the client migrates
+                    let flow_key = match packet.quic {
+                        Some(ids) => quic.key(flow_key, ids),
+                        None => flow_key,
+                    };
 
-                    key_time_queue.enqueue((flow_key, packet.time));
+                    // Groups are only sent to, so there is no opposite direction
+                    let (flow_key, reversed) = match ip.bidirectional && group.is_none() {
+                        true => sort_endpoints(flow_key),
+                        false => (flow_key, false),
+                    };
+
+                    let flow = match flows.entry(flow_key) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            if let Some(alert) = surge.as_mut().and_then(|surge| {
+                                surge.new_flow(
+                                    packet.time,
+                                    packet.src,
+                                    packet.dst,
+                                    packet.dst_port?,
+                                )
+                            }) {
+                                output_tx.send(Record::FlowSurge(alert))?;
+                            }
+                            let ssh = ip.ssh_phases
+                                && packet.tunnel.is_none_or(|tunnel| tunnel.decapsulated())
+                                && (packet.src_port == Some(SSH_PORT)
+                                    || packet.dst_port == Some(SSH_PORT));
+                            let interface = interface_name(&interfaces, packet.interface);
+                            let flow = entry.insert(IpFlow::new(
+                                &flow_key,
+                                packet.time,
+                                group,
+                                ssh,
+                                interface,
+                                &ip,
+                            ));
+                            if flow_events {
+                                send_event(flow, FlowEventKind::Open, packet.time, &output_tx)?;
+                            }
+                            flow
+                        }
+                    };
+
+                    // Connection attempts without payload are only captured to detect surges
+                    if surge.is_none() || packet.data_len > 0 {
+                        flow.add_packet(&packet, reversed);
+                        if let Some(max) = packet_timing {
+                            flow.add_timing(packet.time, packet.data_len, max);
+                        }
+                        if detailed_bursts {
+                            flow.add_detail(packet.time, packet.data_len);
+                        }
+                        send_if_full(flow, max_packets, packet.time, &output_tx, &filter)?;
+                        if let Some(concurrency) = &mut concurrency {
+                            let (src, dst, src_port, dst_port, tunnel, _, _, _, _) = flow_key;
+                            concurrency
+                                .add_packet(packet.time, (src, dst, src_port, dst_port, tunnel));
+                        }
+                        // Outliers do not extend the burst, which still completes after its end
+                        if let Some(end) = flow.prev_time() {
+                            let gap =
+                                adaptive_gap.map_or(inactive_time, |adaptive| flow.gap(&adaptive));
+                            key_time_queue.enqueue(flow_key, end, gap);
+                        }
+                    }
+
+                    if let Some(activity) = &mut activity {
+                        activity.touch(&flow_key, packet.time);
+                    }
+                    Ok::<_, BurstsharkError>(())
+                })?,
+                Ok(Input::Tick(time)) => {
+                    last_time = time;
+                    last_arrival = Instant::now();
+                    add_restored(
+                        time,
+                        &mut restored,
+                        &mut flows,
+                        &mut key_time_queue,
+                        &mut activity,
+                        inactive_time,
+                        adaptive_gap,
+                    )?;
+                    send_rates(time, &mut rates, &flows, &output_tx)?;
+                    create_bursts(time, &mut key_time_queue, &mut flows, &output_tx, &filter)?;
+                    evict_flows(
+                        time,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    progress.report(time);
                 }
-                Err(mpsc::RecvTimeoutError::Timeout) => {
+                Err(RecvTimeoutError::Timeout) => {
                     // timeout, check if we should send any bursts
-                    let current_time_est = last_time + inactive_time;
+                    let current_time_est = last_time + last_arrival.elapsed().as_secs_f64();
+                    send_rates(current_time_est, &mut rates, &flows, &output_tx)?;
                     create_bursts(
                         current_time_est,
-                        inactive_time,
                         &mut key_time_queue,
                         &mut flows,
                         &output_tx,
-                    );
+                        &filter,
+                    )?;
+                    evict_flows(
+                        current_time_est,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    if let Some(concurrency) = &mut concurrency {
+                        send_concurrency(concurrency.report(current_time_est), &output_tx)?;
+                    }
+                    progress.report(current_time_est);
                 }
-                Err(mpsc::RecvTimeoutError::Disconnected) => break, // No more work coming
+                Err(RecvTimeoutError::Disconnected) => break, // No more work coming
             }
         }
+
+        if offline {
+            if let Some(concurrency) = &mut concurrency {
+                send_concurrency(concurrency.flush(last_time + inactive_time), &output_tx)?;
+            }
+            flush_rates(last_time + inactive_time, &mut rates, &flows, &output_tx)?;
+        }
+        if let Some(state) = &state {
+            save_flows(
+                state,
+                end_time(offline, last_time, inactive_time, last_arrival),
+                &mut flows,
+                restored,
+                &interfaces,
+            );
+        }
+        flush_bursts(
+            end_time(offline, last_time, inactive_time, last_arrival),
+            !offline || interrupt::interrupted(),
+            &mut key_time_queue,
+            &mut flows,
+            &output_tx,
+            &filter,
+        )?;
+
+        if flow_events {
+            close_flows(&flows, last_time, &output_tx)?;
+        }
+        Ok(())
     });
 
     Ok(tx)
 }
 
 pub(super) fn start_wlan(
-    inactive_time: f64,
-    no_guess: bool,
-    max_deviation: u16,
-    output_tx: mpsc::Sender<Burst>,
-) -> Result<mpsc::Sender<WlanPacket>, Box<dyn Error>> {
-    let (tx, rx) = mpsc::channel::<WlanPacket>();
+    opts: &CommonOptions,
+    wlan: WlanOptions,
+    progress: Progress,
+    state: Option<Arc<StateFile>>,
+) -> Result<Sender<Input<WlanPacket>>, BurstsharkError> {
+    let (tx, rx) = channel::channel::<Input<WlanPacket>>(&opts.channels);
+    let inactive_time = opts.inactive_time;
+    let adaptive_gap = opts.adaptive_gap;
+    let flow_events = opts.flow_events;
+    let offline = opts.offline;
+    let sched = opts.worker_sched;
+    let profile = opts.profile.clone();
+    let output_tx = opts.tx.clone();
+    let mut filter = opts.filter;
+    let controls = opts.controls.clone();
+    let packet_timing = opts.packet_timing;
+    let detailed_bursts = opts.detailed_bursts;
+    let interfaces = opts.interfaces.clone();
+    let max_packets = opts
+        .max_burst_packets
+        .map_or(MAX_BURST_PACKETS, |max| max.min(MAX_BURST_PACKETS));
+    let mut activity = FlowActivity::new(opts);
+    let mut rates = opts.rate_interval.map(RateSampler::new);
+    let mut restored = restore_flows(
+        &state,
+        &progress,
+        opts.workers,
+        |flow| WlanFlow::restore(flow, &wlan, &interfaces),
+        |key| hash_hosts(key.0, key.1),
+    );
+
+    thread::spawn(move || -> Result<(), BurstsharkError> {
+        sched.apply_or_warn("burst");
 
-    thread::spawn(move || {
-        let mut key_time_queue = Fifo::new();
-        let mut flows: HashMap<(MacAddr, MacAddr), WlanFlow> = HashMap::new();
+        let mut key_time_queue = BurstQueue::new(inactive_time, adaptive_gap.is_some());
+        let mut flows: HashMap<WlanFlowKey, WlanFlow> = HashMap::new();
+        let mut devices = wlan.correlate_macs.then(MacCorrelator::new);
 
+        // Wake up at least as often as the shortest gap of a live capture
+        let timeout = adaptive_gap.map_or(inactive_time, |gap| gap.min);
+        let mut last_time = 0.0;
+        let mut last_arrival = Instant::now();
+        let mut seen = 0;
         loop {
-            match rx.recv_timeout(Duration::from_secs_f64(inactive_time)) {
-                Ok(packet) => {
-                    create_bursts(
+            if let Some(settings) = controls.as_ref().and_then(|c| c.changed(&mut seen)) {
+                filter = settings.filter;
+            }
+            match recv(&rx, offline, timeout) {
+                Ok(Input::Packet(packet)) => profile::time(&profile, Stage::Burstify, || {
+                    last_time = packet.time;
+                    last_arrival = Instant::now();
+                    add_restored(
                         packet.time,
+                        &mut restored,
+                        &mut flows,
+                        &mut key_time_queue,
+                        &mut activity,
                         inactive_time,
+                        adaptive_gap,
+                    )?;
+                    send_rates(packet.time, &mut rates, &flows, &output_tx)?;
+                    create_bursts(
+                        packet.time,
                         &mut key_time_queue,
                         &mut flows,
                         &output_tx,
-                    );
+                        &filter,
+                    )?;
+                    evict_flows(
+                        packet.time,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+
+                    // Stations count sequence numbers per TID, and may give each TID its own queue
+                    let tid = packet.tid.filter(|_| !wlan.no_tid);
+                    let flow_key = (packet.src, packet.dst, tid);
+                    let device = devices.as_mut().map(|devices| devices.device(&packet));
+                    let flow = match flows.entry(flow_key) {
+                        Entry::Occupied(entry) => {
+                            let flow = entry.into_mut();
+                            flow.add_packet(&packet, &interfaces);
+                            flow
+                        }
+                        Entry::Vacant(entry) => {
+                            let flow = entry.insert(WlanFlow::new(
+                                &packet,
+                                tid,
+                                device,
+                                &wlan,
+                                &interfaces,
+                            ));
+                            if flow_events {
+                                send_event(flow, FlowEventKind::Open, packet.time, &output_tx)?;
+                            }
+                            flow
+                        }
+                    };
+                    if let Some(max) = packet_timing {
+                        flow.add_timing(packet.time, packet.data_len, max);
+                    }
+                    if detailed_bursts {
+                        flow.add_detail(packet.time, packet.data_len);
+                    }
+                    send_if_full(flow, max_packets, packet.time, &output_tx, &filter)?;
 
-                    let flow_key = (packet.src, packet.dst);
-                    flows
-                        .entry(flow_key)
-                        .and_modify(|flow| flow.add_packet(&packet))
-                        .or_insert_with(|| WlanFlow::new(&packet, no_guess, max_deviation));
+                    if let Some(end) = flow.prev_time() {
+                        let gap =
+                            adaptive_gap.map_or(inactive_time, |adaptive| flow.gap(&adaptive));
+                        key_time_queue.enqueue(flow_key, end, gap);
+                    }
 
-                    key_time_queue.enqueue((flow_key, packet.time));
+                    if let Some(activity) = &mut activity {
+                        activity.touch(&flow_key, packet.time);
+                    }
+                    Ok::<_, BurstsharkError>(())
+                })?,
+                Ok(Input::Tick(time)) => {
+                    last_time = time;
+                    last_arrival = Instant::now();
+                    add_restored(
+                        time,
+                        &mut restored,
+                        &mut flows,
+                        &mut key_time_queue,
+                        &mut activity,
+                        inactive_time,
+                        adaptive_gap,
+                    )?;
+                    send_rates(time, &mut rates, &flows, &output_tx)?;
+                    create_bursts(time, &mut key_time_queue, &mut flows, &output_tx, &filter)?;
+                    evict_flows(
+                        time,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    progress.report(time);
                 }
-                Err(mpsc::RecvTimeoutError::Timeout) => {
+                Err(RecvTimeoutError::Timeout) => {
                     // timeout, check if we should send any bursts
-                    let current_time_est = 0.0 + inactive_time;
+                    let current_time_est = last_time + last_arrival.elapsed().as_secs_f64();
+                    send_rates(current_time_est, &mut rates, &flows, &output_tx)?;
                     create_bursts(
                         current_time_est,
+                        &mut key_time_queue,
+                        &mut flows,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    evict_flows(
+                        current_time_est,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    progress.report(current_time_est);
+                }
+                Err(RecvTimeoutError::Disconnected) => break, // No more work coming
+            }
+        }
+
+        if offline {
+            flush_rates(last_time + inactive_time, &mut rates, &flows, &output_tx)?;
+        }
+        if let Some(state) = &state {
+            save_flows(
+                state,
+                end_time(offline, last_time, inactive_time, last_arrival),
+                &mut flows,
+                restored,
+                &interfaces,
+            );
+        }
+        flush_bursts(
+            end_time(offline, last_time, inactive_time, last_arrival),
+            !offline || interrupt::interrupted(),
+            &mut key_time_queue,
+            &mut flows,
+            &output_tx,
+            &filter,
+        )?;
+
+        if flow_events {
+            close_flows(&flows, last_time, &output_tx)?;
+        }
+        Ok(())
+    });
+
+    Ok(tx)
+}
+
+pub(super) fn start_eth(
+    opts: &CommonOptions,
+    progress: Progress,
+    state: Option<Arc<StateFile>>,
+) -> Result<Sender<Input<EthPacket>>, BurstsharkError> {
+    let (tx, rx) = channel::channel::<Input<EthPacket>>(&opts.channels);
+    let inactive_time = opts.inactive_time;
+    let adaptive_gap = opts.adaptive_gap;
+    let flow_events = opts.flow_events;
+    let offline = opts.offline;
+    let sched = opts.worker_sched;
+    let profile = opts.profile.clone();
+    let output_tx = opts.tx.clone();
+    let mut filter = opts.filter;
+    let controls = opts.controls.clone();
+    let packet_timing = opts.packet_timing;
+    let detailed_bursts = opts.detailed_bursts;
+    let interfaces = opts.interfaces.clone();
+    let max_packets = opts
+        .max_burst_packets
+        .map_or(MAX_BURST_PACKETS, |max| max.min(MAX_BURST_PACKETS));
+    let mut activity = FlowActivity::new(opts);
+    let mut rates = opts.rate_interval.map(RateSampler::new);
+    let mut restored = restore_flows(
+        &state,
+        &progress,
+        opts.workers,
+        |flow| EthFlow::restore(flow, &interfaces),
+        |key| hash_hosts(key.0, key.1),
+    );
+
+    thread::spawn(move || -> Result<(), BurstsharkError> {
+        sched.apply_or_warn("burst");
+
+        let mut key_time_queue = BurstQueue::new(inactive_time, adaptive_gap.is_some());
+        let mut flows: HashMap<EthFlowKey, EthFlow> = HashMap::new();
+
+        // Wake up at least as often as the shortest gap of a live capture
+        let timeout = adaptive_gap.map_or(inactive_time, |gap| gap.min);
+        let mut last_time = 0.0;
+        let mut last_arrival = Instant::now();
+        let mut seen = 0;
+        loop {
+            if let Some(settings) = controls.as_ref().and_then(|c| c.changed(&mut seen)) {
+                filter = settings.filter;
+            }
+            match recv(&rx, offline, timeout) {
+                Ok(Input::Packet(packet)) => profile::time(&profile, Stage::Burstify, || {
+                    last_time = packet.time;
+                    last_arrival = Instant::now();
+                    add_restored(
+                        packet.time,
+                        &mut restored,
+                        &mut flows,
+                        &mut key_time_queue,
+                        &mut activity,
+                        inactive_time,
+                        adaptive_gap,
+                    )?;
+                    send_rates(packet.time, &mut rates, &flows, &output_tx)?;
+                    create_bursts(
+                        packet.time,
+                        &mut key_time_queue,
+                        &mut flows,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    evict_flows(
+                        packet.time,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+
+                    let flow_key = (packet.src, packet.dst, packet.ethertype, packet.interface);
+                    let flow = match flows.entry(flow_key) {
+                        Entry::Occupied(entry) => {
+                            let flow = entry.into_mut();
+                            flow.add_packet(&packet);
+                            flow
+                        }
+                        Entry::Vacant(entry) => {
+                            let interface = interface_name(&interfaces, packet.interface);
+                            let flow = entry.insert(EthFlow::new(&packet, interface));
+                            if flow_events {
+                                send_event(flow, FlowEventKind::Open, packet.time, &output_tx)?;
+                            }
+                            flow
+                        }
+                    };
+                    if let Some(max) = packet_timing {
+                        flow.add_timing(packet.time, packet.data_len, max);
+                    }
+                    if detailed_bursts {
+                        flow.add_detail(packet.time, packet.data_len);
+                    }
+                    send_if_full(flow, max_packets, packet.time, &output_tx, &filter)?;
+
+                    if let Some(end) = flow.prev_time() {
+                        let gap =
+                            adaptive_gap.map_or(inactive_time, |adaptive| flow.gap(&adaptive));
+                        key_time_queue.enqueue(flow_key, end, gap);
+                    }
+
+                    if let Some(activity) = &mut activity {
+                        activity.touch(&flow_key, packet.time);
+                    }
+                    Ok::<_, BurstsharkError>(())
+                })?,
+                Ok(Input::Tick(time)) => {
+                    last_time = time;
+                    last_arrival = Instant::now();
+                    add_restored(
+                        time,
+                        &mut restored,
+                        &mut flows,
+                        &mut key_time_queue,
+                        &mut activity,
                         inactive_time,
+                        adaptive_gap,
+                    )?;
+                    send_rates(time, &mut rates, &flows, &output_tx)?;
+                    create_bursts(time, &mut key_time_queue, &mut flows, &output_tx, &filter)?;
+                    evict_flows(
+                        time,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    progress.report(time);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // timeout, check if we should send any bursts
+                    let current_time_est = last_time + last_arrival.elapsed().as_secs_f64();
+                    send_rates(current_time_est, &mut rates, &flows, &output_tx)?;
+                    create_bursts(
+                        current_time_est,
                         &mut key_time_queue,
                         &mut flows,
                         &output_tx,
-                    );
+                        &filter,
+                    )?;
+                    evict_flows(
+                        current_time_est,
+                        &mut activity,
+                        &mut flows,
+                        flow_events,
+                        &output_tx,
+                        &filter,
+                    )?;
+                    progress.report(current_time_est);
                 }
-                Err(mpsc::RecvTimeoutError::Disconnected) => break, // No more work coming
+                Err(RecvTimeoutError::Disconnected) => break, // No more work coming
             }
         }
+
+        if offline {
+            flush_rates(last_time + inactive_time, &mut rates, &flows, &output_tx)?;
+        }
+        if let Some(state) = &state {
+            save_flows(
+                state,
+                end_time(offline, last_time, inactive_time, last_arrival),
+                &mut flows,
+                restored,
+                &interfaces,
+            );
+        }
+        flush_bursts(
+            end_time(offline, last_time, inactive_time, last_arrival),
+            !offline || interrupt::interrupted(),
+            &mut key_time_queue,
+            &mut flows,
+            &output_tx,
+            &filter,
+        )?;
+
+        if flow_events {
+            close_flows(&flows, last_time, &output_tx)?;
+        }
+        Ok(())
     });
 
     Ok(tx)
 }
 
+/// Receive the next packet. When reading a file, bursts are only completed by the times of the
+/// packets, so there is no need to wake up when no packet arrives.
+fn recv<P>(rx: &Receiver<P>, offline: bool, inactive_time: f64) -> Result<P, RecvTimeoutError> {
+    match offline {
+        true => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        false => rx.recv_timeout(Duration::from_secs_f64(inactive_time)),
+    }
+}
+
+/// Send an event with the current totals of a flow
+fn send_event<F: Flow>(
+    flow: &F,
+    kind: FlowEventKind,
+    time: f64,
+    output_tx: &Sender<Record>,
+) -> Result<(), BurstsharkError> {
+    output_tx.send(Record::FlowEvent(flow.event(kind, time)))?;
+    Ok(())
+}
+
+/// Send the concurrency of the hosts reported for an interval
+fn send_concurrency(
+    reports: Vec<HostConcurrency>,
+    output_tx: &Sender<Record>,
+) -> Result<(), BurstsharkError> {
+    for report in reports {
+        output_tx.send(Record::Concurrency(report))?;
+    }
+    Ok(())
+}
+
+/// Send the rates of the flows active during the interval, if it has ended by the current time
+fn send_rates<K: Clone + Eq + Hash, F: Flow>(
+    time: f64,
+    rates: &mut Option<RateSampler<K>>,
+    flows: &HashMap<K, F>,
+    output_tx: &Sender<Record>,
+) -> Result<(), BurstsharkError> {
+    let Some(rates) = rates else {
+        return Ok(());
+    };
+    match rates.due(time) {
+        Some((end, interval)) => send_samples(end, interval, rates, flows, output_tx),
+        None => Ok(()),
+    }
+}
+
+/// Send the rates of the flows active during the part of the interval before the capture ended
+fn flush_rates<K: Clone + Eq + Hash, F: Flow>(
+    time: f64,
+    rates: &mut Option<RateSampler<K>>,
+    flows: &HashMap<K, F>,
+    output_tx: &Sender<Record>,
+) -> Result<(), BurstsharkError> {
+    let Some(rates) = rates else {
+        return Ok(());
+    };
+    match rates.flush(time) {
+        Some((end, interval)) => send_samples(end, interval, rates, flows, output_tx),
+        None => Ok(()),
+    }
+}
+
+fn send_samples<K: Clone + Eq + Hash, F: Flow>(
+    end: f64,
+    interval: f64,
+    rates: &mut RateSampler<K>,
+    flows: &HashMap<K, F>,
+    output_tx: &Sender<Record>,
+) -> Result<(), BurstsharkError> {
+    rates.retain(|key| flows.contains_key(key));
+    for (key, flow) in flows {
+        let (addrs, totals) = flow.totals();
+        let Some((bytes_per_sec, bursts_per_sec)) =
+            rates.rates(key, totals.bytes, totals.bursts, interval)
+        else {
+            continue;
+        };
+        output_tx.send(Record::RateSample(RateSample {
+            time: end,
+            interval,
+            src: addrs.src.clone(),
+            dst: addrs.dst.clone(),
+            src_port: addrs.src_port,
+            dst_port: addrs.dst_port,
+            flow_id: addrs.flow_id.clone(),
+            interface: addrs.interface.clone(),
+            tid: addrs.tid,
+            ssrc: addrs.ssrc,
+            bytes_per_sec,
+            bursts_per_sec,
+        }))?;
+    }
+    Ok(())
+}
+
+/// Complete the burst of a flow once it is full, so flows that never pause still send bursts
+fn send_if_full<F: Flow>(
+    flow: &mut F,
+    max_packets: u16,
+    time: f64,
+    output_tx: &Sender<Record>,
+    filter: &BurstFilter,
+) -> Result<(), BurstsharkError> {
+    if flow.is_full(max_packets) {
+        flow.send_burst(output_tx, filter, time)?;
+    }
+    Ok(())
+}
+
+/// Send close events for all flows as the capture ended
+fn close_flows<K, F: Flow>(
+    flows: &HashMap<K, F>,
+    time: f64,
+    output_tx: &Sender<Record>,
+) -> Result<(), BurstsharkError> {
+    for flow in flows.values() {
+        send_event(flow, FlowEventKind::Close, time, output_tx)?;
+    }
+    Ok(())
+}
+
 /// Inspect all flows that could have spawned a new burst for the current time
-fn create_bursts<K: Clone + Eq + std::hash::Hash, F: Flow>(
+fn create_bursts<K: Clone + Eq + Hash, F: Flow>(
     current_time: f64,
+    key_time_queue: &mut BurstQueue<K>,
+    flows: &mut HashMap<K, F>,
+    output_tx: &Sender<Record>,
+    filter: &BurstFilter,
+) -> Result<(), BurstsharkError> {
+    while let Some((key, queue_time)) = key_time_queue.dequeue_complete(current_time) {
+        send_if_unmodified(key, queue_time, current_time, flows, output_tx, filter)?;
+    }
+    Ok(())
+}
+
+/// Time a capture ended at. Bursts read from a file complete as if no more packets followed.
+fn end_time(offline: bool, last_time: f64, inactive_time: f64, last_arrival: Instant) -> f64 {
+    match offline {
+        true => last_time + inactive_time,
+        false => last_time + last_arrival.elapsed().as_secs_f64(),
+    }
+}
+
+/// Complete all bursts still in progress in the order they would complete, as the capture ended.
+///
+/// With `truncated`, such as when a live capture is stopped, the bursts are flagged as cut short.
+fn flush_bursts<K: Clone + Eq + Hash, F: Flow>(
+    completion_time: f64,
+    truncated: bool,
+    key_time_queue: &mut BurstQueue<K>,
+    flows: &mut HashMap<K, F>,
+    output_tx: &Sender<Record>,
+    filter: &BurstFilter,
+) -> Result<(), BurstsharkError> {
+    if truncated {
+        flows.values_mut().for_each(Flow::truncate);
+    }
+
+    while let Some((key, queue_time)) = key_time_queue.dequeue() {
+        send_if_unmodified(key, queue_time, completion_time, flows, output_tx, filter)?;
+    }
+    Ok(())
+}
+
+/// The flows of the state file that this worker restores, which are those of its hosts, in the
+/// order their bursts complete
+fn restore_flows<K, F: Flow>(
+    state: &Option<Arc<StateFile>>,
+    progress: &Progress,
+    workers: usize,
+    restore: impl Fn(&FlowState) -> Option<(K, F)>,
+    hosts_hash: impl Fn(&K) -> u64,
+) -> Vec<(K, F)> {
+    let Some(state) = state else {
+        return Vec::new();
+    };
+    let mut flows: Vec<(K, F)> = state
+        .restored()
+        .iter()
+        .filter_map(restore)
+        .filter(|(key, _)| hosts_hash(key) % workers.max(1) as u64 == progress.worker() as u64)
+        .collect();
+    flows.sort_by(|(_, a), (_, b)| {
+        let end = |flow: &F| flow.prev_time().unwrap_or(f64::NEG_INFINITY);
+        end(a).total_cmp(&end(b))
+    });
+    flows
+}
+
+/// Add the restored flows as the first packet of the capture arrives, with their times moved from
+/// the wall clock to the clock of the capture
+fn add_restored<K: Clone + Eq + Hash, F: Flow>(
+    time: f64,
+    restored: &mut Vec<(K, F)>,
+    flows: &mut HashMap<K, F>,
+    key_time_queue: &mut BurstQueue<K>,
+    activity: &mut Option<FlowActivity<K>>,
     inactive_time: f64,
-    key_time_queue: &mut Fifo<(K, f64)>,
+    adaptive_gap: Option<AdaptiveGap>,
+) -> Result<(), BurstsharkError> {
+    if restored.is_empty() {
+        return Ok(());
+    }
+
+    let offset = wall_clock_offset(time)?;
+    for (key, mut flow) in restored.drain(..) {
+        flow.rebase(-offset);
+        if let Some(end) = flow.prev_time() {
+            let gap = adaptive_gap.map_or(inactive_time, |adaptive| flow.gap(&adaptive));
+            key_time_queue.enqueue(key.clone(), end, gap);
+            if let Some(activity) = activity {
+                activity.touch(&key, end);
+            }
+        }
+        flows.insert(key, flow);
+    }
+    Ok(())
+}
+
+/// Save the flows with bursts in progress as the capture ended, taking their bursts so they are
+/// continued by the next capture rather than completed. Flows restored without any packets since
+/// are saved again.
+fn save_flows<K, F: ResumableFlow<K>>(
+    state: &StateFile,
+    time: f64,
     flows: &mut HashMap<K, F>,
-    output_tx: &mpsc::Sender<Burst>,
+    restored: Vec<(K, F)>,
+    interfaces: &[Arc<str>],
 ) {
-    while let Some((_key, queue_time)) = key_time_queue.peek() {
-        if current_time - *queue_time < inactive_time {
-            // Not old enough to make is a separate burst
-            break;
+    let offset = match wall_clock_offset(time) {
+        Ok(offset) => offset,
+        Err(e) => {
+            eprintln!("Error saving the flow state: {e}");
+            return;
         }
+    };
+    let saved = flows
+        .iter_mut()
+        .filter_map(|(key, flow)| flow.save(key, offset, interfaces))
+        .chain(
+            restored
+                .into_iter()
+                .filter_map(|(key, mut flow)| flow.save(&key, 0.0, interfaces)),
+        )
+        .collect();
+    if let Err(e) = state.save(saved) {
+        eprintln!("Error saving the flow state: {e}");
+    }
+}
 
-        // Dequeue the pair
-        let (key, queue_time) = key_time_queue.dequeue().unwrap();
+/// Counts of the flows evicted by the burst workers, shared by all of them
+#[derive(Debug, Default)]
+pub struct FlowEvictions {
+    idle: AtomicU64,
+    over_limit: AtomicU64,
+}
 
-        // Can unwrap as it ws in the fifo queue, must be in hash-map
-        let flow = flows.get_mut(&key).unwrap();
-        if (flow.prev_time().unwrap_or(0.0) - queue_time).abs() < 0.0001 {
-            // TODO: Can we just use eq?
-            // The flow has not been modified since the time was inserted into the queue
-            // So it can be made a burst
+impl FlowEvictions {
+    /// Flows evicted as they had no packets for the flow timeout
+    pub fn idle(&self) -> u64 {
+        self.idle.load(Ordering::Relaxed)
+    }
 
-            // TODO: Should we remove the flow from the hashmap to save space?
-            // Otherwise it will continue to grow forever
-            flow.send_burst(&output_tx, current_time)
-                .expect("Could not send a burst!");
+    /// Flows evicted as the least recently active, to keep to the most flows
+    pub fn over_limit(&self) -> u64 {
+        self.over_limit.load(Ordering::Relaxed)
+    }
+}
+
+/// Flows by when they last had a packet, to evict those idle for the flow timeout and the least
+/// recently active beyond the most flows
+struct FlowActivity<K> {
+    timeout: Option<f64>,
+    max_flows: Option<usize>,
+    /// Flows in the order of their last packet, with its time
+    by_use: BTreeMap<u64, (K, f64)>,
+    last_use: HashMap<K, u64>,
+    uses: u64,
+    evictions: Arc<FlowEvictions>,
+}
+
+impl<K: Clone + Eq + Hash> FlowActivity<K> {
+    /// Tracks the activity of flows, unless they are never evicted
+    fn new(opts: &CommonOptions) -> Option<Self> {
+        (opts.flow_timeout.is_some() || opts.max_flows.is_some()).then(|| FlowActivity {
+            timeout: opts.flow_timeout,
+            max_flows: opts.max_flows,
+            by_use: BTreeMap::new(),
+            last_use: HashMap::new(),
+            uses: 0,
+            evictions: opts.evictions.clone(),
+        })
+    }
+
+    fn touch(&mut self, key: &K, time: f64) {
+        self.uses += 1;
+        if let Some(last_use) = self.last_use.insert(key.clone(), self.uses) {
+            self.by_use.remove(&last_use);
         }
+        self.by_use.insert(self.uses, (key.clone(), time));
+    }
+
+    /// The least recently active flow, if it is idle or there are too many flows
+    fn evict(&mut self, time: f64, flows: usize) -> Option<K> {
+        let (_, (_, last_time)) = self.by_use.first_key_value()?;
+        let counter = if self
+            .timeout
+            .is_some_and(|timeout| time - last_time > timeout)
+        {
+            &self.evictions.idle
+        } else if self.max_flows.is_some_and(|max| flows > max) {
+            &self.evictions.over_limit
+        } else {
+            return None;
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let (_, (key, _)) = self.by_use.pop_first()?;
+        self.last_use.remove(&key);
+        Some(key)
     }
 }
 
-#[derive(Debug, Clone)]
+/// Remove the flows that are idle or beyond the most flows, completing their bursts in progress
+fn evict_flows<K: Clone + Eq + Hash, F: Flow>(
+    time: f64,
+    activity: &mut Option<FlowActivity<K>>,
+    flows: &mut HashMap<K, F>,
+    flow_events: bool,
+    output_tx: &Sender<Record>,
+    filter: &BurstFilter,
+) -> Result<(), BurstsharkError> {
+    let Some(activity) = activity else {
+        return Ok(());
+    };
+    while let Some(key) = activity.evict(time, flows.len()) {
+        let Some(mut flow) = flows.remove(&key) else {
+            continue;
+        };
+        if flow.prev_time().is_some() {
+            flow.send_burst(output_tx, filter, time)?;
+        }
+        if flow_events {
+            send_event(&flow, FlowEventKind::Evict, time, output_tx)?;
+        }
+    }
+    Ok(())
+}
+
+/// Send the burst of a dequeued flow, unless a packet was added to it after it was queued
+fn send_if_unmodified<K: Clone + Eq + Hash, F: Flow>(
+    key: K,
+    queue_time: f64,
+    current_time: f64,
+    flows: &mut HashMap<K, F>,
+    output_tx: &Sender<Record>,
+    filter: &BurstFilter,
+) -> Result<(), BurstsharkError> {
+    // The flow may have been evicted since it was queued
+    let Some(flow) = flows.get_mut(&key) else {
+        return Ok(());
+    };
+    if flow
+        .prev_time()
+        .is_some_and(|prev_time| (prev_time - queue_time).abs() < 0.0001)
+    {
+        // TODO: Can we just use eq?
+        // The flow has not been modified since the time was inserted into the queue
+        // So it can be made a burst
+        flow.send_burst(output_tx, filter, current_time)?;
+    }
+    Ok(())
+}
+
+/// Limits on the size of bursts to output, excluding bursts at or beyond them.
+///
+/// Filtered bursts still count towards the totals of their flows.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BurstFilter {
+    pub min_bytes: Option<u32>,
+    pub max_bytes: Option<u32>,
+    pub min_packets: Option<u16>,
+    pub max_packets: Option<u16>,
+}
+
+impl BurstFilter {
+    fn allows(&self, burst: &Burst) -> bool {
+        !(self.min_bytes.is_some_and(|min| min >= burst.size)
+            || self.max_bytes.is_some_and(|max| max <= burst.size)
+            || self.min_packets.is_some_and(|min| min >= burst.num_packets)
+            || self.max_packets.is_some_and(|max| max <= burst.num_packets))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Burst {
     pub completion_time: f64,
-    pub src: String,
-    pub dst: String,
+    /// Shared with the flow, so emitting a burst does not allocate new address strings
+    pub src: Arc<str>,
+    pub dst: Arc<str>,
     pub src_port: Option<u16>,
     pub dst_port: Option<u16>,
     pub start: f64,
     pub end: f64,
     pub num_packets: u16,
     pub size: u32,
+    /// Ethernet type of the frames, only set for non-IP layer 2 captures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ethertype: Option<u16>,
+    /// IP protocol of the first packet, when annotating bursts with it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proto: Option<IpProto>,
+    /// DSCP and IPv6 flow label of the first packet, when capturing QoS markings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_label: Option<u32>,
+    /// Multicast group or broadcast the burst was sent to, when keying on groups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<CastGroup>,
+    /// Tunnel of the flow, and the bytes of the burst spent on tunneling, when detecting tunnels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel: Option<TunnelKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel_overhead: Option<u32>,
+    /// Estimated seconds the frames of the burst took on air, when estimating airtime of WLAN
+    /// bursts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub airtime: Option<f64>,
+    /// Channels the frames of the burst were received on, in ascending order, when merging WLAN
+    /// channels
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<u16>,
+    /// Bytes of TCP segments sent before, which are left out of the size, when excluding
+    /// retransmissions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retransmitted_bytes: Option<u32>,
+    /// Inferred phase of SSH sessions, when enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_phase: Option<SshPhase>,
+    /// How much the burst deviates from the learned profile of its service port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deviation: Option<f64>,
+    /// Seconds from the end of the preceding burst in the opposite direction, when pairing responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_latency: Option<f64>,
+    /// Class of traffic inferred from the recent bursts of the flow, and the confidence in it from
+    /// 0 to 1, when classifying flows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<FlowClass>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_confidence: Option<f64>,
+    /// Label of the first signature the recent bursts of the flow match, when detecting signatures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<Arc<str>>,
+    /// Direction between the sorted endpoints that most bytes of the burst were sent in, when
+    /// aggregating both directions of flows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<BurstDirection>,
+    /// Id of the flow that is the same for both directions, when enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_id: Option<Arc<str>>,
+    /// Interface the burst was captured on, when capturing on several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<Arc<str>>,
+    /// TID of the frames of WLAN bursts, unless the TIDs of flows are merged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tid: Option<u8>,
+    /// Synthetic id of the device sending WLAN bursts, which is kept as it rotates randomized
+    /// addresses, when correlating them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<u32>,
+    /// SSRC and payload type of the first packet of RTP bursts, and the packets of the burst that
+    /// were missed by the capture, as told by their sequence numbers, when keying on SSRCs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssrc: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_type: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lost_packets: Option<u16>,
+    /// Host names of the source and destination, and the service name of the port of the flow,
+    /// when resolving names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_name: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dst_name: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<Arc<str>>,
+    /// Countries and autonomous system numbers of the source and destination, when looking them
+    /// up in GeoIP databases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_country: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src_asn: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dst_country: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dst_asn: Option<u32>,
+    /// TCP resets and ICMP errors referencing the flow seen during the burst, when enabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FlowError>,
+    /// Set when the capture ended before the burst was complete, such as when interrupted
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Time and size of the first packets of the burst, when exporting packet timing
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub packets: Vec<BurstPacket>,
+    /// Inter-arrival times, jitter and peak rate of the packets of the burst, when detailing bursts
+    #[serde(flatten)]
+    pub detail: Option<BurstDetail>,
+    /// Suricata alerts of the flow during the burst
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<EveAlert>,
+    /// Id and tags of the probe that captured the burst, when set
+    #[serde(flatten)]
+    pub probe: ProbeIdentity,
+}
+
+/// A packet of a burst, at a time relative to the start of the burst
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BurstPacket {
+    pub offset: f64,
+    pub size: u32,
+}
+
+/// Direction of a burst of a bidirectional flow, from the lower endpoint (a) or the higher (b)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BurstDirection {
+    AToB,
+    BToA,
+}
+
+impl fmt::Display for BurstDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            BurstDirection::AToB => "a_to_b",
+            BurstDirection::BToA => "b_to_a",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowEventKind {
+    /// The first packet of the flow was seen
+    Open,
+
+    /// The flow is closed as the capture ended
+    Close,
+
+    /// The flow is removed as it was idle for the flow timeout, or to keep to the most flows
+    Evict,
+}
+
+/// Lifetime totals of a flow
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FlowTotals {
+    pub first_seen: f64,
+    pub last_seen: f64,
+    pub bursts: u32,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl FlowTotals {
+    fn new(time: f64) -> Self {
+        FlowTotals {
+            first_seen: time,
+            last_seen: time,
+            ..Default::default()
+        }
+    }
+
+    fn add_burst(&mut self, burst: &Burst) {
+        self.last_seen = burst.end;
+        self.bursts += 1;
+        self.packets += burst.num_packets as u64;
+        self.bytes += burst.size as u64;
+    }
+
+    /// The totals with the burst in progress, which has not been sent yet but is part of them
+    fn with_burst(&self, current_burst: &Option<Burst>) -> FlowTotals {
+        let mut totals = *self;
+        if let Some(burst) = current_burst {
+            totals.add_burst(burst);
+        }
+        totals
+    }
+}
+
+/// A flow being opened or closed, with its totals at that time
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowEvent {
+    pub kind: FlowEventKind,
+    pub time: f64,
+    pub src: Arc<str>,
+    pub dst: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_id: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tid: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssrc: Option<u32>,
+    pub totals: FlowTotals,
+}
+
+impl FlowEvent {
+    fn new(kind: FlowEventKind, time: f64, addrs: &FlowAddrs, totals: FlowTotals) -> Self {
+        FlowEvent {
+            kind,
+            time,
+            src: addrs.src.clone(),
+            dst: addrs.dst.clone(),
+            src_port: addrs.src_port,
+            dst_port: addrs.dst_port,
+            flow_id: addrs.flow_id.clone(),
+            interface: addrs.interface.clone(),
+            tid: addrs.tid,
+            ssrc: addrs.ssrc,
+            totals,
+        }
+    }
 }
 
 impl Burst {
-    fn from_ip_packet(p: &IpPacket, ignore_ports: bool) -> Self {
-        let (src_port, dst_port) = if ignore_ports {
-            (None, None)
-        } else {
-            (Some(p.src_port), Some(p.dst_port))
-        };
+    fn is_full(&self, max_packets: u16) -> bool {
+        self.num_packets >= max_packets || self.size >= MAX_BURST_SIZE
+    }
+
+    /// Record the timing of a packet, unless `max` packets are already recorded
+    fn add_timing(&mut self, time: f64, size: u32, max: usize) {
+        if self.packets.len() < max {
+            // Rounded to nanoseconds, the precision of capture times
+            self.packets.push(BurstPacket {
+                offset: ((time - self.start) * 1e9).round() / 1e9,
+                size,
+            });
+        }
+    }
+
+    fn from_ip_packet(p: &IpPacket, addrs: &FlowAddrs) -> Self {
         Burst {
             completion_time: p.time,
-            src: p.src.to_string(),
-            dst: p.dst.to_string(),
-            src_port,
-            dst_port,
+            src: addrs.src.clone(),
+            dst: addrs.dst.clone(),
+            src_port: addrs.src_port,
+            dst_port: addrs.dst_port,
             start: p.time,
             end: p.time,
             num_packets: 1,
             size: p.data_len,
+            ethertype: None,
+            proto: p.proto,
+            dscp: p.qos.map(|qos| qos.dscp),
+            flow_label: p.qos.and_then(|qos| qos.flow_label),
+            group: None,
+            tunnel: None,
+            tunnel_overhead: p.tunnel.map(|tunnel| tunnel.overhead),
+            retransmitted_bytes: None,
+            airtime: None,
+            channels: Vec::new(),
+            ssh_phase: None,
+            deviation: None,
+            response_latency: None,
+            class: None,
+            class_confidence: None,
+            label: None,
+            direction: None,
+            flow_id: addrs.flow_id.clone(),
+            interface: addrs.interface.clone(),
+            tid: None,
+            device: None,
+            ssrc: addrs.ssrc,
+            payload_type: p.rtp.map(|rtp| rtp.payload_type),
+            lost_packets: p.rtp.map(|_| 0),
+            src_name: None,
+            dst_name: None,
+            service: None,
+            src_country: None,
+            src_asn: None,
+            dst_country: None,
+            dst_asn: None,
+            errors: Vec::new(),
+            truncated: false,
+            packets: Vec::new(),
+            detail: None,
+            alerts: Vec::new(),
+            probe: ProbeIdentity::default(),
+        }
+    }
+    /// A burst of a frame, on the interface of the frame as stations may move between the channels
+    /// of several interfaces
+    fn from_wlan_packet(p: &WlanPacket, addrs: &FlowAddrs, interfaces: &[Arc<str>]) -> Self {
+        Burst {
+            completion_time: p.time,
+            src: addrs.src.clone(),
+            dst: addrs.dst.clone(),
+            src_port: None,
+            dst_port: None,
+            start: p.time,
+            end: p.time,
+            num_packets: p.msdus,
+            size: p.data_len,
+            ethertype: None,
+            proto: None,
+            dscp: None,
+            flow_label: None,
+            group: None,
+            tunnel: None,
+            tunnel_overhead: None,
+            retransmitted_bytes: None,
+            airtime: p.data_rate.map(|rate| frame_airtime(p.data_len, rate)),
+            channels: p.channel.into_iter().collect(),
+            ssh_phase: None,
+            deviation: None,
+            response_latency: None,
+            class: None,
+            class_confidence: None,
+            label: None,
+            direction: None,
+            flow_id: addrs.flow_id.clone(),
+            interface: interface_name(interfaces, p.interface),
+            tid: addrs.tid,
+            device: addrs.device,
+            ssrc: None,
+            payload_type: None,
+            lost_packets: None,
+            src_name: None,
+            dst_name: None,
+            service: None,
+            src_country: None,
+            src_asn: None,
+            dst_country: None,
+            dst_asn: None,
+            errors: Vec::new(),
+            truncated: false,
+            packets: Vec::new(),
+            detail: None,
+            alerts: Vec::new(),
+            probe: ProbeIdentity::default(),
         }
     }
-    fn from_wlan_packet(p: &WlanPacket) -> Self {
+}
+
+impl Burst {
+    fn from_eth_packet(p: &EthPacket, addrs: &FlowAddrs) -> Self {
         Burst {
             completion_time: p.time,
-            src: p.src.to_string(),
-            dst: p.dst.to_string(),
+            src: addrs.src.clone(),
+            dst: addrs.dst.clone(),
             src_port: None,
             dst_port: None,
             start: p.time,
             end: p.time,
             num_packets: 1,
             size: p.data_len,
+            ethertype: Some(p.ethertype),
+            proto: None,
+            dscp: None,
+            flow_label: None,
+            group: None,
+            tunnel: None,
+            tunnel_overhead: None,
+            retransmitted_bytes: None,
+            airtime: None,
+            channels: Vec::new(),
+            ssh_phase: None,
+            deviation: None,
+            response_latency: None,
+            class: None,
+            class_confidence: None,
+            label: None,
+            direction: None,
+            flow_id: addrs.flow_id.clone(),
+            interface: addrs.interface.clone(),
+            tid: None,
+            device: None,
+            ssrc: None,
+            payload_type: None,
+            lost_packets: None,
+            src_name: None,
+            dst_name: None,
+            service: None,
+            src_country: None,
+            src_asn: None,
+            dst_country: None,
+            dst_asn: None,
+            errors: Vec::new(),
+            truncated: false,
+            packets: Vec::new(),
+            detail: None,
+            alerts: Vec::new(),
+            probe: ProbeIdentity::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Burst {
+    /// A burst of a UDP flow between two socket addresses, for the tests of what handles bursts
+    pub(crate) fn test(src: &str, dst: &str, start: f64, end: f64, size: u32) -> Self {
+        let src: std::net::SocketAddr = src.parse().unwrap();
+        let dst: std::net::SocketAddr = dst.parse().unwrap();
+        let packet = IpPacket {
+            time: start,
+            src: src.ip(),
+            dst: dst.ip(),
+            src_port: Some(src.port()),
+            dst_port: Some(dst.port()),
+            data_len: size,
+            tunnel: None,
+            error: None,
+            quic: None,
+            rtp: None,
+            qos: None,
+            tcp_seq: None,
+            proto: None,
+            interface: None,
+        };
+        let addrs = FlowAddrs::new(src.ip(), dst.ip(), packet.src_port, packet.dst_port);
+        Burst {
+            completion_time: end,
+            end,
+            ..Burst::from_ip_packet(&packet, &addrs)
+        }
+    }
+}
+
+/// The name of an interface by its id, or the id itself if there is no name for it
+fn interface_name(interfaces: &[Arc<str>], id: Option<u16>) -> Option<Arc<str>> {
+    let id = id?;
+    Some(
+        interfaces
+            .get(id as usize)
+            .cloned()
+            .unwrap_or_else(|| Arc::from(id.to_string())),
+    )
+}
+
+/// Formatted flow addresses, shared by all bursts of the flow and with other flows of the hosts
+struct FlowAddrs {
+    src: Arc<str>,
+    dst: Arc<str>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    /// Id of the flow that is the same for both directions, when enabled
+    flow_id: Option<Arc<str>>,
+    /// Interface the flow is captured on, when capturing on several
+    interface: Option<Arc<str>>,
+    /// TID of the frames of a WLAN flow, unless the TIDs are merged
+    tid: Option<u8>,
+    /// SSRC of an RTP stream, when keying on them
+    ssrc: Option<u32>,
+    /// Device sending the frames of a WLAN flow, when correlating randomized addresses
+    device: Option<u32>,
+}
+
+impl FlowAddrs {
+    fn new(
+        src: impl PooledAddr,
+        dst: impl PooledAddr,
+        src_port: Option<u16>,
+        dst_port: Option<u16>,
+    ) -> Self {
+        FlowAddrs {
+            src: src.shared(),
+            dst: dst.shared(),
+            src_port,
+            dst_port,
+            flow_id: None,
+            interface: None,
+            tid: None,
+            ssrc: None,
+            device: None,
         }
     }
 }
@@ -194,112 +1716,498 @@ struct IpFlow {
     /// The current burst. Is None when none has started
     current_burst: Option<Burst>,
 
-    ignore_ports: bool,
+    addrs: FlowAddrs,
+    group: Option<CastGroup>,
+    tunnel: Option<TunnelKey>,
+    /// Whether the phase of bursts is inferred, as the flow is an SSH session
+    ssh: bool,
+    /// Whether packets in both directions are added, and the direction of bursts is annotated
+    bidirectional: bool,
+    /// Bytes of the current burst sent from the second endpoint to the first
+    reversed_size: u32,
+    /// Sequence numbers sent from the first endpoint and from the second, when excluding
+    /// retransmissions
+    seqs: Option<[SeqTracker; 2]>,
+    /// Sequence numbers of the RTP stream during the current burst, and the size of its last
+    /// packet to guess the sizes of missed packets from, unless disabled
+    rtp_sequence: Option<SequenceTracker>,
+    last_rtp_len: u32,
+    no_guess: bool,
+    totals: FlowTotals,
+    inter_arrival: InterArrival,
+    /// Shape of the current burst, when detailing bursts
+    detail: Option<DetailTracker>,
 }
 
 struct WlanFlow {
     /// The current burst. Is None when none has started
     current_burst: Option<Burst>,
-    expected_seq_number: u16,
+    addrs: FlowAddrs,
+    totals: FlowTotals,
+    /// Sequence numbers of each channel and TID the flow was seen on, as transmitters keep a
+    /// counter per TID, and each radio of a station its own
+    sequences: Vec<(SequenceKey, SequenceTracker)>,
+    window: SequenceWindow,
     last_packet_len: u32,
     no_guess: bool,
-    max_deviation: u16,
+    inter_arrival: InterArrival,
+    /// Shape of the current burst, when detailing bursts
+    detail: Option<DetailTracker>,
+}
+
+struct EthFlow {
+    /// The current burst. Is None when none has started
+    current_burst: Option<Burst>,
+
+    addrs: FlowAddrs,
+    totals: FlowTotals,
+    inter_arrival: InterArrival,
+    /// Shape of the current burst, when detailing bursts
+    detail: Option<DetailTracker>,
 }
 
 impl IpFlow {
-    fn new(p: &IpPacket, ignore_ports: bool) -> Self {
+    /// Create a flow without a burst, which is started by the first added packet
+    fn new(
+        key: &IpFlowKey,
+        time: f64,
+        group: Option<CastGroup>,
+        ssh: bool,
+        interface: Option<Arc<str>>,
+        ip: &IpOptions,
+    ) -> Self {
+        let mut addrs = FlowAddrs::new(key.0, key.1, key.2, key.3);
+        addrs.interface = interface;
+        let (src, dst, src_port, dst_port, tunnel, _, ssrc, _, _) = *key;
+        addrs.ssrc = ssrc;
+        addrs.flow_id = ip
+            .flow_ids
+            .map(|format| flow_id((src, dst, src_port, dst_port, tunnel), format));
+
         IpFlow {
-            ignore_ports,
-            current_burst: Some(Burst::from_ip_packet(p, ignore_ports)),
+            current_burst: None,
+            addrs,
+            group,
+            tunnel: key.4,
+            ssh,
+            bidirectional: ip.bidirectional,
+            reversed_size: 0,
+            seqs: ip.exclude_retransmissions.then(Default::default),
+            rtp_sequence: None,
+            last_rtp_len: 0,
+            no_guess: ip.no_guess,
+            totals: FlowTotals::new(time),
+            inter_arrival: InterArrival::default(),
+            detail: None,
         }
     }
 
-    fn add_packet(&mut self, p: &IpPacket) {
+    /// A flow continuing a saved burst, unless its addresses are not IP addresses
+    fn restore(
+        state: &FlowState,
+        ip: &IpOptions,
+        interfaces: &[Arc<str>],
+    ) -> Option<(IpFlowKey, Self)> {
+        let src = state.src.parse::<IpAddr>().ok()?;
+        let dst = state.dst.parse::<IpAddr>().ok()?;
+        let key = (
+            src,
+            dst,
+            state.src_port,
+            state.dst_port,
+            None,
+            None,
+            state.ssrc,
+            state.interface,
+            state
+                .proto
+                .filter(|_| state.src_port.is_none() && state.dst_port.is_none()),
+        );
+        let ssh = ip.ssh_phases && [state.src_port, state.dst_port].contains(&Some(SSH_PORT));
+        let interface = interface_name(interfaces, state.interface);
+        let mut flow = IpFlow::new(&key, state.start, None, ssh, interface, ip);
+
+        let packet = IpPacket {
+            time: state.start,
+            src,
+            dst,
+            src_port: state.src_port,
+            dst_port: state.dst_port,
+            data_len: state.size,
+            tunnel: None,
+            error: None,
+            quic: None,
+            rtp: None,
+            qos: None,
+            tcp_seq: None,
+            proto: None,
+            interface: state.interface,
+        };
+        let mut burst = Burst::from_ip_packet(&packet, &flow.addrs);
+        state.restore(&mut burst);
+        flow.current_burst = Some(burst);
+        flow.reversed_size = state.reversed_size;
+        flow.rtp_sequence = state
+            .sequences
+            .first()
+            .map(|sequence| SequenceTracker::rtp(sequence.last, SequenceWindow::default()));
+        flow.last_rtp_len = state.last_packet_len;
+        flow.inter_arrival = InterArrival::resume(state.inter_arrival);
+        Some((key, flow))
+    }
+
+    /// Flag the current burst with an error referencing the flow, if a burst is in progress
+    fn add_error(&mut self, error: FlowError) {
+        if let Some(burst) = &mut self.current_burst {
+            if !burst.errors.contains(&error) {
+                burst.errors.push(error);
+            }
+        }
+    }
+
+    /// Add a packet, sent from the second endpoint of the flow to the first if `reversed` is set
+    fn add_packet(&mut self, p: &IpPacket, reversed: bool) {
+        // Duplicated RTP packets only extend the burst, and outliers are skipped, as WLAN frames
+        let mut missed = 0;
+        if let (Some(rtp), Some(burst), Some(sequence)) =
+            (p.rtp, &mut self.current_burst, &mut self.rtp_sequence)
+        {
+            match sequence.add(rtp.seq) {
+                Sequence::New { missed: count } => missed = count,
+                Sequence::Repeated => {
+                    self.inter_arrival.add(p.time - burst.end);
+                    burst.end = p.time;
+                    return;
+                }
+                Sequence::Outlier => return,
+            }
+        }
+
+        // Retransmitted segments still extend the burst and count as packets, but not their bytes
+        let data_len = match (&mut self.seqs, p.tcp_seq) {
+            (Some(seqs), Some(seq)) => seqs[reversed as usize].add(seq, p.data_len),
+            _ => p.data_len,
+        };
+        let retransmitted = p.data_len - data_len;
+
+        if reversed {
+            self.reversed_size += data_len;
+        }
+
         if let Some(ref mut burst) = &mut self.current_burst {
+            self.inter_arrival.add(p.time - burst.end);
             burst.end = p.time;
             burst.num_packets += 1;
-            burst.size += p.data_len;
+            burst.size += data_len;
+            if let (Some(overhead), Some(tunnel)) = (&mut burst.tunnel_overhead, p.tunnel) {
+                *overhead += tunnel.overhead;
+            }
+            if let Some(bytes) = &mut burst.retransmitted_bytes {
+                *bytes += retransmitted;
+            }
+            if missed > 0 {
+                *burst.lost_packets.get_or_insert(0) += missed;
+                if !self.no_guess {
+                    let guess = (self.last_rtp_len + data_len) / 2;
+                    burst.num_packets += missed;
+                    burst.size += guess * missed as u32;
+                }
+            }
         } else {
-            self.current_burst = Some(Burst::from_ip_packet(p, self.ignore_ports));
+            let mut burst = Burst::from_ip_packet(p, &self.addrs);
+            burst.size = data_len;
+            burst.retransmitted_bytes =
+                (self.seqs.is_some() && p.tcp_seq.is_some()).then_some(retransmitted);
+            burst.group = self.group;
+            burst.tunnel = self.tunnel;
+            self.current_burst = Some(burst);
+            // Packets after the inactive time may follow any sequence number
+            self.rtp_sequence = p
+                .rtp
+                .map(|rtp| SequenceTracker::rtp(rtp.seq, SequenceWindow::default()));
         }
+        self.last_rtp_len = data_len;
     }
 }
 
+/// Channel of frames, which is None unless merging channels, and their TID
+type SequenceKey = (Option<u16>, Option<u8>);
+
 impl WlanFlow {
-    fn new(p: &WlanPacket, no_guess: bool, max_deviation: u16) -> Self {
+    fn new(
+        p: &WlanPacket,
+        tid: Option<u8>,
+        device: Option<u32>,
+        wlan: &WlanOptions,
+        interfaces: &[Arc<str>],
+    ) -> Self {
+        let mut addrs = FlowAddrs::new(p.src, p.dst, None, None);
+        addrs.tid = tid;
+        addrs.device = device;
         WlanFlow {
-            current_burst: Some(Burst::from_wlan_packet(p)),
-            expected_seq_number: p.seq_number,
+            current_burst: Some(Burst::from_wlan_packet(p, &addrs, interfaces)),
+            addrs,
+            totals: FlowTotals::new(p.time),
+            // The first frame is already part of the burst
+            sequences: vec![(
+                (p.channel, p.tid),
+                SequenceTracker::new(p.seq_number, wlan.sequence),
+            )],
+            window: wlan.sequence,
             last_packet_len: p.data_len,
-            no_guess,
-            max_deviation,
+            no_guess: wlan.no_guess,
+            inter_arrival: InterArrival::default(),
+            detail: None,
+        }
+    }
+
+    /// A flow continuing a saved burst, unless its addresses are not MAC addresses
+    fn restore(
+        state: &FlowState,
+        wlan: &WlanOptions,
+        interfaces: &[Arc<str>],
+    ) -> Option<(WlanFlowKey, Self)> {
+        let src = state.src.parse::<MacAddr>().ok()?;
+        let dst = state.dst.parse::<MacAddr>().ok()?;
+        let tid = state.tid.filter(|_| !wlan.no_tid);
+        let packet = WlanPacket {
+            time: state.start,
+            src,
+            dst,
+            data_len: state.size,
+            msdus: state.packets,
+            seq_number: 0,
+            data_rate: None,
+            tid: state.tid,
+            channel: None,
+            interface: state.interface,
+        };
+        let mut flow = WlanFlow::new(&packet, tid, None, wlan, interfaces);
+        if let Some(burst) = &mut flow.current_burst {
+            state.restore(burst);
         }
+        flow.sequences = state
+            .sequences
+            .iter()
+            .map(|sequence| {
+                let tracker = SequenceTracker::new(sequence.last, wlan.sequence);
+                ((sequence.channel, sequence.tid), tracker)
+            })
+            .collect();
+        flow.last_packet_len = state.last_packet_len;
+        flow.inter_arrival = InterArrival::resume(state.inter_arrival);
+        Some(((src, dst, tid), flow))
     }
 
-    fn add_packet(&mut self, p: &WlanPacket) {
+    /// Add a frame, starting a burst on the interface of the frame if none is in progress
+    fn add_packet(&mut self, p: &WlanPacket, interfaces: &[Arc<str>]) {
         if let Some(ref mut current_burst) = &mut self.current_burst {
-            // Packet sequence number is what we expect.
-            if p.seq_number == self.expected_seq_number {
-                self.expected_seq_number = (p.seq_number + 1) & 4095;
-                self.last_packet_len = p.data_len;
-                current_burst.end = p.time;
-                current_burst.num_packets += 1;
-                current_burst.size += p.data_len;
-                return;
-            }
+            self.inter_arrival.add(p.time - current_burst.end);
 
-            // Packet sequence number not what we expect.
-            let diff = (p.seq_number as i16 - self.expected_seq_number as i16) & 4095;
-            let signed_diff = if diff <= 2048 { diff } else { diff - 4096 };
+            // Every frame takes airtime, also retransmissions and outliers
+            if let Some(rate) = p.data_rate {
+                *current_burst.airtime.get_or_insert(0.0) += frame_airtime(p.data_len, rate);
+            }
 
-            // We already added this packet, but it is probably being retransmitted.
-            // Note: not enough to filter on the retransmission bit as the first frame might be lost.
-            if -(self.max_deviation as i16) < signed_diff && signed_diff < 0 {
-                current_burst.end = p.time;
-                return;
+            if let Some(channel) = p.channel {
+                if let Err(i) = current_burst.channels.binary_search(&channel) {
+                    current_burst.channels.insert(i, channel);
+                }
             }
 
-            // The packet has a sequence number that is further along than what we expect.
-            // Monitor mode device might have missed frames.
-            if 0 < signed_diff && signed_diff < self.max_deviation as i16 {
-                if !self.no_guess {
-                    // Guess the lengths of the lost frames
-                    let guess = (self.last_packet_len + p.data_len) / 2;
-                    current_burst.num_packets += diff as u16;
-                    current_burst.size += guess * diff as u32;
-                } else {
-                    // Accept only this
-                    current_burst.num_packets += 1;
+            let key = (p.channel, p.tid);
+            let sequence = match self.sequences.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, tracker)) => tracker.add(p.seq_number),
+                // The first frame on another channel or TID, such as after the station moved
+                None => {
+                    let tracker = SequenceTracker::new(p.seq_number, self.window);
+                    self.sequences.push((key, tracker));
+                    Sequence::New { missed: 0 }
+                }
+            };
+
+            match sequence {
+                Sequence::New { missed } => {
+                    if missed > 0 && !self.no_guess {
+                        // Monitor mode device might have missed frames. Guess their lengths, and
+                        // add this one as it is
+                        let guess = (self.last_packet_len + p.data_len) / 2;
+                        current_burst.num_packets += missed;
+                        current_burst.size += guess * missed as u32;
+                    }
+                    current_burst.num_packets += p.msdus;
                     current_burst.size += p.data_len;
+                    self.last_packet_len = p.data_len;
+                    current_burst.end = p.time;
                 }
-                // Bring the expected sequence number in line with the packet.
-                self.expected_seq_number = (p.seq_number + 1) & 4095;
-                self.last_packet_len = p.data_len;
-                current_burst.end = p.time;
-            } else {
-                // In case of a larger deviation, might be a single outlier, go to next expected.
-                self.expected_seq_number = (self.expected_seq_number + 1) & 4095;
+                // We already added this packet, but it is probably being retransmitted.
+                // Note: not enough to filter on the retransmission bit as the first frame might be lost.
+                Sequence::Repeated => current_burst.end = p.time,
+                Sequence::Outlier => {}
             }
         } else {
-            self.current_burst = Some(Burst::from_wlan_packet(p));
+            self.current_burst = Some(Burst::from_wlan_packet(p, &self.addrs, interfaces));
 
             // Accept sequence number of packet after the inactive time.
-            self.expected_seq_number = (p.seq_number + 1) & 4095;
+            self.sequences.clear();
+            self.sequences.push((
+                (p.channel, p.tid),
+                SequenceTracker::new(p.seq_number, self.window),
+            ));
             self.last_packet_len = p.data_len;
         };
     }
 }
 
+impl EthFlow {
+    fn new(p: &EthPacket, interface: Option<Arc<str>>) -> Self {
+        let mut addrs = FlowAddrs::new(p.src, p.dst, None, None);
+        addrs.interface = interface;
+        EthFlow {
+            current_burst: Some(Burst::from_eth_packet(p, &addrs)),
+            addrs,
+            totals: FlowTotals::new(p.time),
+            inter_arrival: InterArrival::default(),
+            detail: None,
+        }
+    }
+
+    /// A flow continuing a saved burst, unless its addresses are not MAC addresses
+    fn restore(state: &FlowState, interfaces: &[Arc<str>]) -> Option<(EthFlowKey, Self)> {
+        let src = state.src.parse::<MacAddr>().ok()?;
+        let dst = state.dst.parse::<MacAddr>().ok()?;
+        let ethertype = state.ethertype?;
+        let packet = EthPacket {
+            time: state.start,
+            src,
+            dst,
+            ethertype,
+            data_len: state.size,
+            interface: state.interface,
+        };
+        let mut flow = EthFlow::new(&packet, interface_name(interfaces, state.interface));
+        if let Some(burst) = &mut flow.current_burst {
+            state.restore(burst);
+        }
+        flow.inter_arrival = InterArrival::resume(state.inter_arrival);
+        Some(((src, dst, ethertype, state.interface), flow))
+    }
+
+    fn add_packet(&mut self, p: &EthPacket) {
+        if let Some(ref mut burst) = &mut self.current_burst {
+            self.inter_arrival.add(p.time - burst.end);
+            burst.end = p.time;
+            burst.num_packets += 1;
+            burst.size += p.data_len;
+        } else {
+            self.current_burst = Some(Burst::from_eth_packet(p, &self.addrs));
+        }
+    }
+}
+
 trait Flow {
     /// Gets the last time a packet was added to the flow
     fn prev_time(&self) -> Option<f64>;
 
+    /// Whether the current burst has reached the packet limit, or the largest size
+    fn is_full(&self, max_packets: u16) -> bool;
+
+    /// Flag the current burst as cut short by the end of the capture
+    fn truncate(&mut self);
+
+    /// Move the times of the flow and its current burst by the offset, to another clock
+    fn rebase(&mut self, offset: f64);
+
+    /// Record the timing of a packet just added to the current burst
+    fn add_timing(&mut self, time: f64, size: u32, max: usize);
+
+    /// Follow the shape of the current burst with a packet just added to it
+    fn add_detail(&mut self, time: f64, size: u32);
+
+    /// The gap after which the current burst is complete, adapted to the packets of the flow
+    fn gap(&self, adaptive: &AdaptiveGap) -> f64;
+
     /// Sends the current burst to outupt, and reset it
     fn send_burst(
         &mut self,
-        output_tx: &mpsc::Sender<Burst>,
+        output_tx: &Sender<Record>,
+        filter: &BurstFilter,
         time: f64,
-    ) -> Result<(), Box<dyn Error>>;
+    ) -> Result<(), BurstsharkError>;
+
+    /// The addresses of the flow, and its lifetime totals including the burst in progress
+    fn totals(&self) -> (&FlowAddrs, FlowTotals);
+
+    /// Create an event with the lifetime totals of the flow
+    fn event(&self, kind: FlowEventKind, time: f64) -> FlowEvent {
+        let (addrs, totals) = self.totals();
+        FlowEvent::new(kind, time, addrs, totals)
+    }
+}
+
+/// Flows whose bursts in progress can be saved to the state file, and continued by the next capture
+trait ResumableFlow<K>: Flow {
+    /// The state of the flow, taking its burst in progress, with its times moved to the wall clock
+    /// by the offset. None for flows without a burst in progress, or whose state is not saved.
+    fn save(&mut self, key: &K, offset: f64, interfaces: &[Arc<str>]) -> Option<FlowState>;
+}
+
+impl ResumableFlow<IpFlowKey> for IpFlow {
+    fn save(&mut self, key: &IpFlowKey, offset: f64, _: &[Arc<str>]) -> Option<FlowState> {
+        // Flows keyed on tunnels, QUIC connection ids or groups start over
+        if key.4.is_some() || key.5.is_some() || self.group.is_some() {
+            return None;
+        }
+        let burst = self.current_burst.take()?;
+        let mut state = FlowState::new(&burst, key.7, offset);
+        state.reversed_size = self.reversed_size;
+        state.sequences = self
+            .rtp_sequence
+            .iter()
+            .map(|sequence| SequenceState {
+                channel: None,
+                tid: None,
+                last: sequence.last(),
+            })
+            .collect();
+        state.last_packet_len = self.last_rtp_len;
+        state.inter_arrival = self.inter_arrival.mean();
+        Some(state)
+    }
+}
+
+impl ResumableFlow<WlanFlowKey> for WlanFlow {
+    fn save(&mut self, _: &WlanFlowKey, offset: f64, interfaces: &[Arc<str>]) -> Option<FlowState> {
+        let burst = self.current_burst.take()?;
+        let interface = burst.interface.as_ref().and_then(|name| {
+            match interfaces.iter().position(|interface| interface == name) {
+                Some(id) => Some(id as u16),
+                None => name.parse().ok(),
+            }
+        });
+        let mut state = FlowState::new(&burst, interface, offset);
+        state.sequences = self
+            .sequences
+            .iter()
+            .map(|((channel, tid), tracker)| SequenceState {
+                channel: *channel,
+                tid: *tid,
+                last: tracker.last(),
+            })
+            .collect();
+        state.last_packet_len = self.last_packet_len;
+        state.inter_arrival = self.inter_arrival.mean();
+        Some(state)
+    }
+}
+
+impl ResumableFlow<EthFlowKey> for EthFlow {
+    fn save(&mut self, key: &EthFlowKey, offset: f64, _: &[Arc<str>]) -> Option<FlowState> {
+        let burst = self.current_burst.take()?;
+        let mut state = FlowState::new(&burst, key.3, offset);
+        state.inter_arrival = self.inter_arrival.mean();
+        Some(state)
+    }
 }
 
 impl Flow for IpFlow {
@@ -307,21 +2215,80 @@ impl Flow for IpFlow {
         self.current_burst.as_ref().map(|burst| burst.end)
     }
 
+    fn is_full(&self, max_packets: u16) -> bool {
+        self.current_burst
+            .as_ref()
+            .is_some_and(|burst| burst.is_full(max_packets))
+    }
+
+    fn gap(&self, adaptive: &AdaptiveGap) -> f64 {
+        self.inter_arrival.gap(adaptive)
+    }
+
+    fn truncate(&mut self) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.truncated = true;
+        }
+    }
+
+    fn rebase(&mut self, offset: f64) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.start += offset;
+            burst.end += offset;
+            burst.completion_time += offset;
+        }
+        if let Some(detail) = &mut self.detail {
+            detail.rebase(offset);
+        }
+        self.totals.first_seen += offset;
+        self.totals.last_seen += offset;
+    }
+
+    fn add_timing(&mut self, time: f64, size: u32, max: usize) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.add_timing(time, size, max);
+        }
+    }
+
+    fn add_detail(&mut self, time: f64, size: u32) {
+        match &mut self.detail {
+            Some(detail) => detail.add(time, size),
+            None => self.detail = Some(DetailTracker::new(time, size)),
+        }
+    }
+
     fn send_burst(
         &mut self,
-        output_tx: &mpsc::Sender<Burst>,
+        output_tx: &Sender<Record>,
+        filter: &BurstFilter,
         current_time: f64,
-    ) -> Result<(), Box<dyn Error>> {
-        if let Some(mut burst) = std::mem::replace(&mut self.current_burst, None) {
+    ) -> Result<(), BurstsharkError> {
+        if let Some(mut burst) = self.current_burst.take() {
             burst.completion_time = current_time;
-            output_tx.send(burst)?;
+            burst.detail = self.detail.as_mut().and_then(DetailTracker::finish);
+            if self.ssh {
+                burst.ssh_phase = Some(SshPhase::of(&burst));
+            }
+            if self.bidirectional {
+                burst.direction = Some(match self.reversed_size > burst.size / 2 {
+                    true => BurstDirection::BToA,
+                    false => BurstDirection::AToB,
+                });
+                self.reversed_size = 0;
+            }
+            self.totals.add_burst(&burst);
+            if filter.allows(&burst) {
+                output_tx.send(Record::Burst(burst))?;
+            }
             Ok(())
         } else {
-            Err(Box::from(
-                "Internal error: Tried to transmit an empty burst",
-            ))
+            Err(BurstsharkError::EmptyBurst)
         }
     }
+
+    fn totals(&self) -> (&FlowAddrs, FlowTotals) {
+        (&self.addrs, self.totals.with_burst(&self.current_burst))
+    }
 }
 
 impl Flow for WlanFlow {
@@ -329,19 +2296,139 @@ impl Flow for WlanFlow {
         self.current_burst.as_ref().map(|burst| burst.end)
     }
 
+    fn is_full(&self, max_packets: u16) -> bool {
+        self.current_burst
+            .as_ref()
+            .is_some_and(|burst| burst.is_full(max_packets))
+    }
+
+    fn gap(&self, adaptive: &AdaptiveGap) -> f64 {
+        self.inter_arrival.gap(adaptive)
+    }
+
+    fn truncate(&mut self) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.truncated = true;
+        }
+    }
+
+    fn rebase(&mut self, offset: f64) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.start += offset;
+            burst.end += offset;
+            burst.completion_time += offset;
+        }
+        if let Some(detail) = &mut self.detail {
+            detail.rebase(offset);
+        }
+        self.totals.first_seen += offset;
+        self.totals.last_seen += offset;
+    }
+
+    fn add_timing(&mut self, time: f64, size: u32, max: usize) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.add_timing(time, size, max);
+        }
+    }
+
+    fn add_detail(&mut self, time: f64, size: u32) {
+        match &mut self.detail {
+            Some(detail) => detail.add(time, size),
+            None => self.detail = Some(DetailTracker::new(time, size)),
+        }
+    }
+
     fn send_burst(
         &mut self,
-        output_tx: &mpsc::Sender<Burst>,
+        output_tx: &Sender<Record>,
+        filter: &BurstFilter,
         current_time: f64,
-    ) -> Result<(), Box<dyn Error>> {
-        if let Some(mut burst) = std::mem::replace(&mut self.current_burst, None) {
+    ) -> Result<(), BurstsharkError> {
+        if let Some(mut burst) = self.current_burst.take() {
             burst.completion_time = current_time;
-            output_tx.send(burst)?;
+            burst.detail = self.detail.as_mut().and_then(DetailTracker::finish);
+            self.totals.add_burst(&burst);
+            if filter.allows(&burst) {
+                output_tx.send(Record::Burst(burst))?;
+            }
             Ok(())
         } else {
-            Err(Box::from(
-                "Internal error: Tried to transmit an empty burst",
-            ))
+            Err(BurstsharkError::EmptyBurst)
         }
     }
+
+    fn totals(&self) -> (&FlowAddrs, FlowTotals) {
+        (&self.addrs, self.totals.with_burst(&self.current_burst))
+    }
+}
+
+impl Flow for EthFlow {
+    fn prev_time(&self) -> Option<f64> {
+        self.current_burst.as_ref().map(|burst| burst.end)
+    }
+
+    fn is_full(&self, max_packets: u16) -> bool {
+        self.current_burst
+            .as_ref()
+            .is_some_and(|burst| burst.is_full(max_packets))
+    }
+
+    fn gap(&self, adaptive: &AdaptiveGap) -> f64 {
+        self.inter_arrival.gap(adaptive)
+    }
+
+    fn truncate(&mut self) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.truncated = true;
+        }
+    }
+
+    fn rebase(&mut self, offset: f64) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.start += offset;
+            burst.end += offset;
+            burst.completion_time += offset;
+        }
+        if let Some(detail) = &mut self.detail {
+            detail.rebase(offset);
+        }
+        self.totals.first_seen += offset;
+        self.totals.last_seen += offset;
+    }
+
+    fn add_timing(&mut self, time: f64, size: u32, max: usize) {
+        if let Some(burst) = &mut self.current_burst {
+            burst.add_timing(time, size, max);
+        }
+    }
+
+    fn add_detail(&mut self, time: f64, size: u32) {
+        match &mut self.detail {
+            Some(detail) => detail.add(time, size),
+            None => self.detail = Some(DetailTracker::new(time, size)),
+        }
+    }
+
+    fn send_burst(
+        &mut self,
+        output_tx: &Sender<Record>,
+        filter: &BurstFilter,
+        current_time: f64,
+    ) -> Result<(), BurstsharkError> {
+        if let Some(mut burst) = self.current_burst.take() {
+            burst.completion_time = current_time;
+            burst.detail = self.detail.as_mut().and_then(DetailTracker::finish);
+            self.totals.add_burst(&burst);
+            if filter.allows(&burst) {
+                output_tx.send(Record::Burst(burst))?;
+            }
+            Ok(())
+        } else {
+            Err(BurstsharkError::EmptyBurst)
+        }
+    }
+
+    fn totals(&self) -> (&FlowAddrs, FlowTotals) {
+        (&self.addrs, self.totals.with_burst(&self.current_burst))
+    }
 }
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use serde::Serialize;
+
+use super::tunnel::TunnelKey;
+
+/// A connection of a host, the same for both directions of the flow
+type Connection = (IpAddr, IpAddr, Option<u16>, Option<u16>, Option<TunnelKey>);
+
+/// Number of flows of a host with bursts in progress at the same time, during an interval
+#[derive(Debug, Clone, Serialize)]
+pub struct HostConcurrency {
+    /// End of the interval
+    pub time: f64,
+    pub host: IpAddr,
+    pub interval: f64,
+    /// Flows with bursts in progress at the end of the interval
+    pub current: usize,
+    /// Most flows with bursts in progress at any moment of the interval
+    pub peak: usize,
+}
+
+#[derive(Default)]
+struct HostBursts {
+    /// Time of the last packet of each connection with a burst in progress
+    active: HashMap<Connection, f64>,
+    peak: usize,
+}
+
+impl HostBursts {
+    /// Forget connections whose bursts have been completed by inactivity
+    fn complete(&mut self, time: f64, inactive_time: f64) {
+        self.active.retain(|_, last| time - *last < inactive_time);
+    }
+}
+
+/// Tracks the overlapping bursts of the flows of each host, and reports the peak per interval.
+///
+/// A burst is in progress from its first packet until the inactive time has passed after its last,
+/// the same as when bursts are completed.
+pub struct ConcurrencyTracker {
+    interval: f64,
+    inactive_time: f64,
+    hosts: HashMap<IpAddr, HostBursts>,
+    interval_end: Option<f64>,
+}
+
+impl ConcurrencyTracker {
+    pub fn new(interval: f64, inactive_time: f64) -> Self {
+        ConcurrencyTracker {
+            interval,
+            inactive_time,
+            hosts: HashMap::new(),
+            interval_end: None,
+        }
+    }
+
+    /// Register a packet between two hosts, of the connection with the given ports and tunnel
+    pub fn add_packet(&mut self, time: f64, (src, dst, src_port, dst_port, tunnel): Connection) {
+        // Order the endpoints, so both directions are the same connection
+        let connection = match (src, src_port) <= (dst, dst_port) {
+            true => (src, dst, src_port, dst_port, tunnel),
+            false => (dst, src, dst_port, src_port, tunnel),
+        };
+
+        for host in [src, dst] {
+            let bursts = self.hosts.entry(host).or_default();
+            if bursts.active.insert(connection, time).is_none() {
+                // Only a new burst can raise the concurrency
+                bursts.complete(time, self.inactive_time);
+                bursts.peak = bursts.peak.max(bursts.active.len());
+            }
+        }
+    }
+
+    /// Report the hosts with concurrent bursts in the interval, if it has ended by the current time
+    pub fn report(&mut self, time: f64) -> Vec<HostConcurrency> {
+        let interval_end = *self.interval_end.get_or_insert(time + self.interval);
+        if time < interval_end {
+            return Vec::new();
+        }
+
+        // Skip intervals without any packets
+        let skipped = ((time - interval_end) / self.interval).floor();
+        self.interval_end = Some(interval_end + (skipped + 1.0) * self.interval);
+
+        self.report_interval(interval_end)
+    }
+
+    /// Report the part of the interval in progress until the capture ended
+    pub fn flush(&mut self, time: f64) -> Vec<HostConcurrency> {
+        match self.interval_end {
+            Some(interval_end) => self.report_interval(interval_end.min(time)),
+            None => Vec::new(),
+        }
+    }
+
+    fn report_interval(&mut self, interval_end: f64) -> Vec<HostConcurrency> {
+        let mut reports = Vec::new();
+        for (host, bursts) in self.hosts.iter_mut() {
+            bursts.complete(interval_end, self.inactive_time);
+            if bursts.peak > 1 {
+                reports.push(HostConcurrency {
+                    time: interval_end,
+                    host: *host,
+                    interval: self.interval,
+                    current: bursts.active.len(),
+                    peak: bursts.peak,
+                });
+            }
+            bursts.peak = bursts.active.len();
+        }
+        self.hosts.retain(|_, bursts| !bursts.active.is_empty());
+        reports.sort_by_key(|report| report.host);
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{ConcurrencyTracker, Connection};
+
+    fn host(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    /// A connection from a client to port 443 of the server
+    fn connection(client: u8, client_port: u16) -> Connection {
+        (host(client), host(1), Some(client_port), Some(443), None)
+    }
+
+    fn peaks(tracker: &mut ConcurrencyTracker, time: f64) -> Vec<(IpAddr, usize, usize)> {
+        tracker
+            .report(time)
+            .into_iter()
+            .map(|report| (report.host, report.peak, report.current))
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_bursts_of_a_host_are_counted() {
+        let mut tracker = ConcurrencyTracker::new(1.0, 0.1);
+        // The first interval starts at the first report
+        assert!(peaks(&mut tracker, 0.0).is_empty());
+        tracker.add_packet(0.0, connection(2, 50000));
+        tracker.add_packet(0.05, connection(3, 50000));
+        tracker.add_packet(0.08, connection(2, 50001));
+        // The reply belongs to the same connection
+        let (client, server, client_port, server_port, tunnel) = connection(2, 50000);
+        tracker.add_packet(0.09, (server, client, server_port, client_port, tunnel));
+
+        // The server had bursts of three connections at a time, and the client of its two, all of
+        // them done by the end of the interval
+        assert_eq!(peaks(&mut tracker, 1.0), [(host(1), 3, 0), (host(2), 2, 0)]);
+        assert!(peaks(&mut tracker, 2.0).is_empty());
+    }
+
+    #[test]
+    fn completed_bursts_no_longer_count() {
+        let mut tracker = ConcurrencyTracker::new(1.0, 0.1);
+        assert!(peaks(&mut tracker, 0.0).is_empty());
+        for i in 0..5 {
+            tracker.add_packet(i as f64 * 0.15, connection(2 + i, 50000));
+        }
+        assert!(peaks(&mut tracker, 1.0).is_empty());
+    }
+
+    #[test]
+    fn the_interval_in_progress_is_flushed() {
+        let mut tracker = ConcurrencyTracker::new(10.0, 1.0);
+        tracker.add_packet(0.0, connection(2, 50000));
+        tracker.add_packet(0.1, connection(3, 50000));
+        assert!(peaks(&mut tracker, 0.2).is_empty());
+        let reports = tracker.flush(0.5);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            (reports[0].time, reports[0].peak, reports[0].current),
+            (0.5, 2, 2)
+        );
+    }
+}
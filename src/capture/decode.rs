@@ -0,0 +1,1150 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use macaddr::MacAddr;
+
+use super::{
+    flow_error::FlowError,
+    proto::IpProto,
+    qos::IpQos,
+    tunnel::{Tunnel, TunnelKind},
+    EthPacket, IpOptions, IpPacket, WlanPacket,
+};
+
+/// Link layer of captured frames, with the numbers used for them in pcap files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkType {
+    Ethernet,
+    RawIp,
+    Ieee80211,
+    LinuxSll,
+    Radiotap,
+}
+
+impl LinkType {
+    pub fn from_pcap(link_type: u32) -> Option<Self> {
+        match link_type {
+            1 => Some(LinkType::Ethernet),
+            101 | 228 | 229 => Some(LinkType::RawIp),
+            105 => Some(LinkType::Ieee80211),
+            113 => Some(LinkType::LinuxSll),
+            127 => Some(LinkType::Radiotap),
+            _ => None,
+        }
+    }
+
+    /// Link type from the ARPHRD hardware type of a Linux interface
+    pub fn from_arphrd(hardware_type: u32) -> Option<Self> {
+        match hardware_type {
+            // Loopback interfaces also have ethernet headers on Linux
+            1 | 772 => Some(LinkType::Ethernet),
+            801 => Some(LinkType::Ieee80211),
+            803 => Some(LinkType::Radiotap),
+            _ => None,
+        }
+    }
+}
+
+/// A frame captured by the native backend
+pub struct Frame<'a> {
+    pub time: f64,
+    pub link: LinkType,
+    /// Captured bytes, which may be truncated by the snapshot length
+    pub data: &'a [u8],
+    /// Length of the frame on the wire
+    pub len: u32,
+}
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+/// Transparent ethernet bridging, of ethernet frames carried in GRE
+const ETHERTYPE_TEB: u16 = 0x6558;
+
+const PROTO_ICMP: u8 = 1;
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+const PROTO_GRE: u8 = 47;
+const PROTO_ESP: u8 = 50;
+const PROTO_ICMPV6: u8 = 58;
+
+const TCP_RST: u8 = 0x04;
+
+/// UDP port of ESP encapsulated for NAT traversal.
+const NAT_T_PORT: u16 = 4500;
+
+/// UDP ports of VXLAN and of GTP-U.
+const VXLAN_PORT: u16 = 4789;
+const GTP_U_PORT: u16 = 2152;
+
+/// GTP message type of user data (G-PDU).
+const GTP_G_PDU: u8 = 0xff;
+
+fn u16_be(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u16_le(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn mac(data: &[u8], offset: usize) -> Option<MacAddr> {
+    let bytes: [u8; 6] = data.get(offset..offset + 6)?.try_into().ok()?;
+    Some(MacAddr::from(bytes))
+}
+
+/// The EtherType and payload of a frame with an ethernet or Linux cooked header, after VLAN tags
+fn network_layer<'a>(frame: &Frame<'a>) -> Option<(u16, &'a [u8])> {
+    let (mut ethertype, mut offset) = match frame.link {
+        LinkType::Ethernet => (u16_be(frame.data, 12)?, 14),
+        LinkType::LinuxSll => (u16_be(frame.data, 14)?, 16),
+        LinkType::RawIp => match frame.data.first()? >> 4 {
+            4 => (ETHERTYPE_IPV4, 0),
+            6 => (ETHERTYPE_IPV6, 0),
+            _ => return None,
+        },
+        LinkType::Ieee80211 | LinkType::Radiotap => return None,
+    };
+
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        ethertype = u16_be(frame.data, offset + 2)?;
+        offset += 4;
+    }
+
+    Some((ethertype, frame.data.get(offset..)?))
+}
+
+/// The EtherType of an ethernet frame carried in a tunnel, and the offset of its payload, after
+/// VLAN tags
+fn inner_ethernet(data: &[u8], offset: usize) -> Option<(u16, usize)> {
+    let mut ethertype = u16_be(data, offset + 12)?;
+    let mut offset = offset + 14;
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        ethertype = u16_be(data, offset + 2)?;
+        offset += 4;
+    }
+    Some((ethertype, offset))
+}
+
+/// Decode a UDP datagram or TCP segment with payload, as set by the options.
+///
+/// The size is that of the transport payload. TCP segments that only open a connection are also
+/// decoded, with a size of zero, when detecting surges. When detecting tunnels, ESP packets are
+/// also decoded, and ESP and WireGuard packets are marked with their tunnel. When decapsulating,
+/// packets in VXLAN, GRE and GTP-U tunnels are decoded from their inner IP header, and marked with
+/// the tunnel they were carried in. When detecting flow errors, TCP resets and ICMP errors are
+/// decoded as errors of the flow they reference. When capturing QoS, packets are marked with their
+/// DSCP and IPv6 flow label. TCP segments are marked with their sequence number. When annotating
+/// protocols, packets are marked with their IP protocol. When burstifying protocols without ports,
+/// packets of other protocols, such as ICMP, are also decoded without ports, with the size of
+/// their IP payload and marked with their IP protocol.
+pub fn ip(frame: &Frame, options: &IpOptions) -> Option<IpPacket> {
+    let syn_packets = options.surge.is_some();
+    let tunnels = options.tunnels.is_some();
+    let (decapsulate, errors, qos) = (options.decapsulate, options.flow_errors, options.qos);
+    let (protos, portless) = (options.protocols, options.portless);
+    let (mut ethertype, mut data) = network_layer(frame)?;
+    let (mut src, mut dst, mut protocol, mut payload, mut payload_len) = match ethertype {
+        ETHERTYPE_IPV4 => ipv4(data)?,
+        ETHERTYPE_IPV6 => ipv6(data)?,
+        _ => return None,
+    };
+
+    let mut encapsulation = None;
+    if decapsulate {
+        if let Some((tunnel, inner_ethertype, offset)) = decapsulate_tunnel(protocol, payload) {
+            data = payload.get(offset..)?;
+            ethertype = inner_ethertype;
+            (src, dst, protocol, payload, payload_len) = match ethertype {
+                ETHERTYPE_IPV4 => ipv4(data)?,
+                ETHERTYPE_IPV6 => ipv6(data)?,
+                _ => return None,
+            };
+            encapsulation = Some(tunnel);
+        }
+    }
+
+    let mut tcp_seq = None;
+    let (src_port, dst_port, data_len) = match protocol {
+        PROTO_UDP => {
+            let length = u16_be(payload, 4)?;
+            (
+                u16_be(payload, 0)?,
+                u16_be(payload, 2)?,
+                length.checked_sub(8)?,
+            )
+        }
+        PROTO_TCP => {
+            let header_len = (*payload.get(12)? as usize >> 4) * 4;
+            let flags = *payload.get(13)?;
+            if errors && flags & TCP_RST != 0 {
+                return Some(IpPacket {
+                    time: frame.time,
+                    src,
+                    dst,
+                    src_port: Some(u16_be(payload, 0)?),
+                    dst_port: Some(u16_be(payload, 2)?),
+                    data_len: 0,
+                    tunnel: None,
+                    error: Some(FlowError::TcpReset),
+                    quic: None,
+                    rtp: None,
+                    qos: None,
+                    tcp_seq: None,
+                    proto: None,
+                    interface: None,
+                });
+            }
+            let data_len = payload_len.checked_sub(header_len)? as u16;
+            // Only connection attempts (SYN without ACK) are of interest without payload
+            if data_len == 0 && !(syn_packets && flags & 0x12 == 0x02) {
+                return None;
+            }
+            tcp_seq = Some(u32_be(payload, 4)?);
+            (u16_be(payload, 0)?, u16_be(payload, 2)?, data_len)
+        }
+        PROTO_ESP if tunnels => (0, 0, payload_len as u16),
+        PROTO_ICMP | PROTO_ICMPV6 if errors => match icmp_error(frame.time, protocol, payload) {
+            Some(error) => return Some(error),
+            None if portless => (0, 0, payload_len as u16),
+            None => return None,
+        },
+        _ if portless => (0, 0, payload_len as u16),
+        _ => return None,
+    };
+    let ports = protocol == PROTO_UDP || protocol == PROTO_TCP;
+
+    let tunnel = match protocol {
+        PROTO_ESP if tunnels => Some(Tunnel::esp(u32_be(payload, 0)?, data_len as u32)),
+        PROTO_UDP if tunnels => udp_tunnel(src_port, dst_port, payload.get(8..)?, data_len),
+        _ => encapsulation,
+    };
+
+    Some(IpPacket {
+        time: frame.time,
+        src,
+        dst,
+        src_port: ports.then_some(src_port),
+        dst_port: ports.then_some(dst_port),
+        data_len: data_len as u32,
+        tunnel,
+        error: None,
+        quic: None,
+        rtp: None,
+        qos: match qos {
+            true => ip_qos(ethertype, data),
+            false => None,
+        },
+        tcp_seq,
+        proto: (protos || portless).then_some(IpProto(protocol)),
+        interface: None,
+    })
+}
+
+/// Decode an ICMP error as an error of the flow of the UDP or TCP packet it quotes
+fn icmp_error(time: f64, protocol: u8, payload: &[u8]) -> Option<IpPacket> {
+    let icmp_type = *payload.first()?;
+    let (error, quoted) = match protocol {
+        PROTO_ICMP => (FlowError::from_icmp(icmp_type)?, ipv4(payload.get(8..)?)?),
+        _ => (FlowError::from_icmpv6(icmp_type)?, ipv6(payload.get(8..)?)?),
+    };
+
+    let (src, dst, protocol, transport, _) = quoted;
+    if protocol != PROTO_TCP && protocol != PROTO_UDP {
+        return None;
+    }
+
+    Some(IpPacket {
+        time,
+        src,
+        dst,
+        src_port: Some(u16_be(transport, 0)?),
+        dst_port: Some(u16_be(transport, 2)?),
+        data_len: 0,
+        tunnel: None,
+        error: Some(error),
+        quic: None,
+        rtp: None,
+        qos: None,
+        tcp_seq: None,
+        proto: None,
+        interface: None,
+    })
+}
+
+/// Detect ESP encapsulated in UDP, or WireGuard from the sizes of its messages as tshark does.
+///
+/// WireGuard handshake initiations have no receiver index, and are not considered tunnel packets.
+fn udp_tunnel(src_port: u16, dst_port: u16, payload: &[u8], data_len: u16) -> Option<Tunnel> {
+    let data_len = data_len as u32;
+
+    // ESP packets start with a non-zero SPI, which tells them apart from IKE on the same port
+    if src_port == NAT_T_PORT || dst_port == NAT_T_PORT {
+        return match u32_be(payload, 0)? {
+            0 => None,
+            spi => Some(Tunnel::esp(spi, data_len)),
+        };
+    }
+
+    if payload.get(1..4)? != [0, 0, 0] {
+        return None;
+    }
+    let message_type = *payload.first()?;
+    let receiver_index = match (message_type, data_len) {
+        (2, 92) => u32_le(payload, 8)?,
+        (3, 64) => u32_le(payload, 4)?,
+        (4, len) if len >= 32 && len % 16 == 0 => u32_le(payload, 4)?,
+        _ => return None,
+    };
+
+    Some(Tunnel::wireguard(message_type, receiver_index, data_len))
+}
+
+/// The tunnel of a VXLAN, GRE or GTP-U packet, with the EtherType of the packet it carries and the
+/// offset of that packet in the IP payload, which is the overhead of the tunnel.
+///
+/// GRE packets without a key have a key of 0. Only GTP-U packets of user data carry a packet.
+fn decapsulate_tunnel(protocol: u8, payload: &[u8]) -> Option<(Tunnel, u16, usize)> {
+    let (kind, id, ethertype, offset) = match protocol {
+        PROTO_GRE => {
+            let flags = u16_be(payload, 0)?;
+            // Only version 0, as version 1 is PPTP
+            if flags & 0x7 != 0 {
+                return None;
+            }
+            let mut offset = 4;
+            // Checksum and reserved
+            if flags & 0x8000 != 0 {
+                offset += 4;
+            }
+            let key = match flags & 0x2000 != 0 {
+                true => {
+                    offset += 4;
+                    u32_be(payload, offset - 4)?
+                }
+                false => 0,
+            };
+            // Sequence number
+            if flags & 0x1000 != 0 {
+                offset += 4;
+            }
+            (TunnelKind::Gre, key, u16_be(payload, 2)?, offset)
+        }
+        PROTO_UDP if u16_be(payload, 2)? == VXLAN_PORT => {
+            // The VNI is valid when the I flag is set, and is followed by a reserved byte
+            if payload.get(8)? & 0x08 == 0 {
+                return None;
+            }
+            (
+                TunnelKind::Vxlan,
+                u32_be(payload, 12)? >> 8,
+                ETHERTYPE_TEB,
+                16,
+            )
+        }
+        PROTO_UDP if u16_be(payload, 2)? == GTP_U_PORT => {
+            let flags = *payload.get(8)?;
+            if flags >> 5 != 1 || *payload.get(9)? != GTP_G_PDU {
+                return None;
+            }
+            let mut offset = 16;
+            // Sequence number, N-PDU number and the type of the first extension header
+            if flags & 0x07 != 0 {
+                offset += 4;
+                let mut next_type = *payload.get(offset - 1)?;
+                while flags & 0x04 != 0 && next_type != 0 {
+                    // In units of four bytes, ending with the type of the next
+                    let len = *payload.get(offset)? as usize * 4;
+                    if len == 0 {
+                        return None;
+                    }
+                    offset += len;
+                    next_type = *payload.get(offset - 1)?;
+                }
+            }
+            let ethertype = match payload.get(offset)? >> 4 {
+                4 => ETHERTYPE_IPV4,
+                6 => ETHERTYPE_IPV6,
+                _ => return None,
+            };
+            (TunnelKind::Gtp, u32_be(payload, 12)?, ethertype, offset)
+        }
+        _ => return None,
+    };
+
+    let (ethertype, offset) = match ethertype {
+        ETHERTYPE_TEB => inner_ethernet(payload, offset)?,
+        ethertype => (ethertype, offset),
+    };
+    Some((
+        Tunnel::encapsulation(kind, id, offset as u32),
+        ethertype,
+        offset,
+    ))
+}
+
+/// Addresses, protocol, payload and payload length of an IPv4 packet.
+///
+/// Non-first fragments are skipped, as they have no transport header.
+fn ipv4(data: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8], usize)> {
+    let header_len = (*data.first()? as usize & 0xf) * 4;
+    let total_len = u16_be(data, 2)? as usize;
+    if u16_be(data, 6)? & 0x1fff != 0 {
+        return None;
+    }
+
+    let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+    let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+    // Ethernet frames can be padded past the end of the packet
+    let payload = data.get(header_len..total_len.min(data.len()))?;
+
+    Some((
+        IpAddr::V4(Ipv4Addr::from(src)),
+        IpAddr::V4(Ipv4Addr::from(dst)),
+        data[9],
+        payload,
+        total_len.checked_sub(header_len)?,
+    ))
+}
+
+/// Addresses, protocol, payload and payload length of an IPv6 packet, after any extension headers
+fn ipv6(data: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8], usize)> {
+    let payload_len = u16_be(data, 4)? as usize;
+    let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+    let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+
+    let mut next_header = *data.get(6)?;
+    let mut offset = 40;
+    loop {
+        match next_header {
+            // Hop-by-hop, routing and destination options
+            0 | 43 | 60 => {
+                next_header = *data.get(offset)?;
+                offset += (*data.get(offset + 1)? as usize + 1) * 8;
+            }
+            // Fragment
+            44 => {
+                if u16_be(data, offset + 2)? & 0xfff8 != 0 {
+                    return None;
+                }
+                next_header = *data.get(offset)?;
+                offset += 8;
+            }
+            // Authentication header
+            51 => {
+                next_header = *data.get(offset)?;
+                offset += (*data.get(offset + 1)? as usize + 2) * 4;
+            }
+            _ => break,
+        }
+    }
+
+    let payload = data.get(offset..(40 + payload_len).min(data.len()))?;
+    Some((
+        IpAddr::V6(Ipv6Addr::from(src)),
+        IpAddr::V6(Ipv6Addr::from(dst)),
+        next_header,
+        payload,
+        (40 + payload_len).checked_sub(offset)?,
+    ))
+}
+
+/// DSCP and flow label of an IP packet, of which IPv6 splits the traffic class over the first two
+/// bytes
+fn ip_qos(ethertype: u16, data: &[u8]) -> Option<IpQos> {
+    match ethertype {
+        ETHERTYPE_IPV4 => Some(IpQos {
+            dscp: data.get(1)? >> 2,
+            flow_label: None,
+        }),
+        ETHERTYPE_IPV6 => {
+            let first = u32_be(data, 0)?;
+            Some(IpQos {
+                dscp: (first >> 22 & 0x3f) as u8,
+                flow_label: Some(first & 0xfffff),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Decode a QoS data frame, with or without a radiotap header.
+///
+/// The size is that of the frame body, without the CCMP header and MIC of protected frames. With
+/// `airtime` set, frames are marked with their legacy PHY rate from the radiotap header, and with
+/// `channels` set, with the channel they were received on. With `msdus` set, unprotected A-MSDU
+/// frames count as their MSDUs, sized without the subframe headers and padding.
+pub fn wlan(frame: &Frame, airtime: bool, channels: bool, msdus: bool) -> Option<WlanPacket> {
+    let (data, radio) = match frame.link {
+        LinkType::Radiotap => radiotap(frame.data)?,
+        LinkType::Ieee80211 => (frame.data, Radio::default()),
+        _ => return None,
+    };
+
+    // Only QoS data frames, as with the tshark filter
+    let frame_control = u16_le(data, 0)?;
+    if frame_control & 0xfc != 0x88 {
+        return None;
+    }
+
+    let to_ds = frame_control & 0x0100 != 0;
+    let from_ds = frame_control & 0x0200 != 0;
+    let protected = frame_control & 0x4000 != 0;
+    let order = frame_control & 0x8000 != 0;
+
+    let (src, dst) = match (to_ds, from_ds) {
+        (false, false) => (mac(data, 10)?, mac(data, 4)?),
+        (true, false) => (mac(data, 10)?, mac(data, 16)?),
+        (false, true) => (mac(data, 16)?, mac(data, 4)?),
+        (true, true) => (mac(data, 24)?, mac(data, 16)?),
+    };
+    let seq_number = u16_le(data, 22)? >> 4;
+
+    // Addresses, sequence control and QoS control, then optional HT control
+    let qos_control = if to_ds && from_ds { 30 } else { 24 };
+    let tid = data.get(qos_control)? & 0x0f;
+    let amsdu = data.get(qos_control)? & 0x80 != 0;
+    let mut header_len = qos_control + 2;
+    if order {
+        header_len += 4;
+    }
+
+    // Length on the wire, without the radiotap header
+    let wire_len = (frame.len as usize).checked_sub(frame.data.len() - data.len())?;
+    let mut body_len = wire_len.checked_sub(header_len)?;
+    if radio.has_fcs {
+        body_len = body_len.checked_sub(4)?;
+    }
+    if protected {
+        body_len = body_len.checked_sub(16)?;
+    }
+
+    // The body of protected frames is encrypted, subframe headers and all
+    let (msdus, data_len) = match msdus && amsdu && !protected {
+        true => amsdu_subframes(data.get(header_len..)?, body_len).unwrap_or((1, body_len as u32)),
+        false => (1, body_len as u32),
+    };
+
+    Some(WlanPacket {
+        time: frame.time,
+        src,
+        dst,
+        data_len,
+        msdus,
+        seq_number,
+        data_rate: radio.rate.filter(|_| airtime),
+        tid: Some(tid),
+        channel: radio.channel.filter(|_| channels),
+        interface: None,
+    })
+}
+
+/// Number and total length of the MSDUs of an A-MSDU frame body, from the headers of its
+/// subframes. Each subframe starts with its destination and source addresses and the length of
+/// its MSDU, and all but the last are padded to a multiple of four bytes.
+fn amsdu_subframes(body: &[u8], body_len: usize) -> Option<(u16, u32)> {
+    let mut offset = 0;
+    let mut msdus = 0u16;
+    let mut len = 0;
+    while offset + 14 <= body_len {
+        let msdu_len = u16_be(body, offset + 12)? as usize;
+        msdus += 1;
+        len += msdu_len as u32;
+        offset = (offset + 14 + msdu_len).next_multiple_of(4);
+    }
+    (msdus > 0).then_some((msdus, len))
+}
+
+/// What the radiotap header tells about a frame
+#[derive(Default)]
+struct Radio {
+    /// Whether the frame ends with a frame check sequence
+    has_fcs: bool,
+    /// Legacy PHY rate in Mb/s
+    rate: Option<f64>,
+    /// Channel the frame was received on
+    channel: Option<u16>,
+}
+
+/// The 802.11 frame after a radiotap header, and what the header tells about it. The rate of HT and
+/// later frames is not decoded.
+fn radiotap(data: &[u8]) -> Option<(&[u8], Radio)> {
+    let header_len = u16_le(data, 2)? as usize;
+    let present = u32_le(data, 4)?;
+
+    // Skip extended present bitmasks
+    let mut offset = 4;
+    let mut word = present;
+    while word & (1 << 31) != 0 {
+        offset += 4;
+        word = u32_le(data, offset)?;
+    }
+    offset += 4;
+
+    // The TSFT field is 8 bytes aligned to 8, and comes before the flags
+    if present & 1 != 0 {
+        offset = offset.next_multiple_of(8) + 8;
+    }
+    let has_fcs = present & 2 != 0 && data.get(offset)? & 0x10 != 0;
+    if present & 2 != 0 {
+        offset += 1;
+    }
+    // In units of 500 kb/s
+    let rate = match present & 4 != 0 {
+        true => Some(*data.get(offset)? as f64 / 2.0).filter(|rate| *rate > 0.0),
+        false => None,
+    };
+    if present & 4 != 0 {
+        offset += 1;
+    }
+    // The frequency in MHz and flags, aligned to 2
+    let channel = match present & 8 != 0 {
+        true => channel_number(u16_le(data, offset.next_multiple_of(2))?),
+        false => None,
+    };
+
+    Some((
+        data.get(header_len..)?,
+        Radio {
+            has_fcs,
+            rate,
+            channel,
+        },
+    ))
+}
+
+/// The channel number of a frequency in MHz, in the 2.4, 5 or 6 GHz band
+fn channel_number(frequency: u16) -> Option<u16> {
+    match frequency {
+        2484 => Some(14),
+        2412..=2472 => Some((frequency - 2407) / 5),
+        5955..=7115 => Some((frequency - 5950) / 5),
+        5000..=5925 => Some((frequency - 5000) / 5),
+        _ => None,
+    }
+}
+
+/// Decode a layer 2 frame that carries neither IPv4 nor IPv6. The size is that of the whole frame.
+pub fn eth(frame: &Frame) -> Option<EthPacket> {
+    if frame.link != LinkType::Ethernet {
+        return None;
+    }
+
+    let ethertype = u16_be(frame.data, 12)?;
+    if let (ETHERTYPE_IPV4 | ETHERTYPE_IPV6, _) = network_layer(frame)? {
+        return None;
+    }
+
+    Some(EthPacket {
+        time: frame.time,
+        src: mac(frame.data, 6)?,
+        dst: mac(frame.data, 0)?,
+        ethertype,
+        data_len: frame.len,
+        interface: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{
+        channel_number, eth, ip, wlan, Frame, LinkType, PROTO_ESP, PROTO_GRE, PROTO_ICMP,
+        PROTO_TCP, PROTO_UDP, TCP_RST,
+    };
+    use crate::capture::{
+        flow_error::FlowError,
+        tunnel::{TunnelKind, TunnelMode},
+        IpOptions, IpPacket,
+    };
+
+    const TCP_SYN: u8 = 0x02;
+    const TCP_ACK: u8 = 0x10;
+
+    fn frame(link: LinkType, data: &[u8]) -> Frame<'_> {
+        Frame {
+            time: 1.5,
+            link,
+            data,
+            len: data.len() as u32,
+        }
+    }
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01];
+        frame.extend(ethertype.to_be_bytes());
+        frame.extend(payload);
+        frame
+    }
+
+    /// An IPv4 packet from 10.0.0.1 to 10.0.0.2
+    fn ipv4(protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0];
+        packet.extend((20 + payload.len() as u16).to_be_bytes());
+        packet.extend([0, 0, 0, 0, 64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend(payload);
+        packet
+    }
+
+    /// An IPv6 packet from 2001:db8::1 to 2001:db8::2
+    fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend((payload.len() as u16).to_be_bytes());
+        packet.extend([next_header, 64]);
+        for host in [1, 2] {
+            packet.extend([0x20, 0x01, 0x0d, 0xb8]);
+            packet.extend([0; 11]);
+            packet.push(host);
+        }
+        packet.extend(payload);
+        packet
+    }
+
+    fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = src_port.to_be_bytes().to_vec();
+        datagram.extend(dst_port.to_be_bytes());
+        datagram.extend((8 + payload.len() as u16).to_be_bytes());
+        datagram.extend([0, 0]);
+        datagram.extend(payload);
+        datagram
+    }
+
+    /// A TCP segment with sequence number 1000
+    fn tcp(src_port: u16, dst_port: u16, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = src_port.to_be_bytes().to_vec();
+        segment.extend(dst_port.to_be_bytes());
+        segment.extend(1000u32.to_be_bytes());
+        segment.extend([0, 0, 0, 0, 0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend(payload);
+        segment
+    }
+
+    fn decode(link: LinkType, data: &[u8], options: IpOptions) -> Option<IpPacket> {
+        ip(&frame(link, data), &options)
+    }
+
+    #[test]
+    fn udp_datagrams_are_sized_by_their_payload() {
+        let mut data = ethernet(0x0800, &ipv4(PROTO_UDP, &udp(5353, 53, &[7; 30])));
+        // Ethernet padding past the end of the packet
+        data.extend([0; 16]);
+        let packet = decode(LinkType::Ethernet, &data, IpOptions::default()).unwrap();
+        assert_eq!(packet.time, 1.5);
+        assert_eq!(packet.src, "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(packet.dst, "10.0.0.2".parse::<IpAddr>().unwrap());
+        assert_eq!((packet.src_port, packet.dst_port), (Some(5353), Some(53)));
+        assert_eq!(packet.data_len, 30);
+        assert_eq!(packet.proto, None);
+    }
+
+    #[test]
+    fn vlan_tags_and_cooked_headers_are_skipped() {
+        let packet = ipv4(PROTO_UDP, &udp(1, 2, &[0; 10]));
+        // An outer QinQ tag and an inner VLAN tag, each followed by the next EtherType
+        let mut tagged = vec![0, 10, 0x81, 0x00, 0, 20, 0x08, 0x00];
+        tagged.extend(&packet);
+        let data = ethernet(0x88a8, &tagged);
+        assert_eq!(
+            decode(LinkType::Ethernet, &data, IpOptions::default())
+                .unwrap()
+                .data_len,
+            10
+        );
+
+        let mut cooked = vec![0; 14];
+        cooked.extend([0x08, 0x00]);
+        cooked.extend(&packet);
+        assert_eq!(
+            decode(LinkType::LinuxSll, &cooked, IpOptions::default())
+                .unwrap()
+                .data_len,
+            10
+        );
+        assert_eq!(
+            decode(LinkType::RawIp, &packet, IpOptions::default())
+                .unwrap()
+                .data_len,
+            10
+        );
+    }
+
+    #[test]
+    fn tcp_segments_without_payload_are_skipped_unless_detecting_surges() {
+        let data_segment = ipv4(PROTO_TCP, &tcp(50000, 443, TCP_ACK, &[1; 100]));
+        let packet = decode(LinkType::RawIp, &data_segment, IpOptions::default()).unwrap();
+        assert_eq!((packet.data_len, packet.tcp_seq), (100, Some(1000)));
+
+        let ack = ipv4(PROTO_TCP, &tcp(50000, 443, TCP_ACK, &[]));
+        let syn = ipv4(PROTO_TCP, &tcp(50000, 443, TCP_SYN, &[]));
+        let surges = IpOptions {
+            surge: Some(crate::capture::SurgeOptions {
+                threshold: 10,
+                window: 1.0,
+            }),
+            ..IpOptions::default()
+        };
+        assert!(decode(LinkType::RawIp, &syn, IpOptions::default()).is_none());
+        assert!(decode(LinkType::RawIp, &ack, surges).is_none());
+        assert_eq!(decode(LinkType::RawIp, &syn, surges).unwrap().data_len, 0);
+    }
+
+    #[test]
+    fn ipv6_extension_headers_are_skipped() {
+        let datagram = udp(546, 547, &[0; 12]);
+        let mut hop_by_hop = vec![PROTO_UDP, 0, 0, 0, 0, 0, 0, 0];
+        hop_by_hop.extend(&datagram);
+        let packet = decode(LinkType::RawIp, &ipv6(0, &hop_by_hop), IpOptions::default()).unwrap();
+        assert_eq!(packet.src, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!((packet.dst_port, packet.data_len), (Some(547), 12));
+
+        // A fragment that is not the first has no UDP header
+        let mut fragment = vec![PROTO_UDP, 0, 0, 8, 0, 0, 0, 1];
+        fragment.extend(&datagram);
+        assert!(decode(LinkType::RawIp, &ipv6(44, &fragment), IpOptions::default()).is_none());
+    }
+
+    #[test]
+    fn ipv4_fragments_after_the_first_are_skipped() {
+        let mut packet = ipv4(PROTO_UDP, &udp(1, 2, &[0; 10]));
+        packet[7] = 0x10;
+        assert!(decode(LinkType::RawIp, &packet, IpOptions::default()).is_none());
+    }
+
+    #[test]
+    fn truncated_packets_are_skipped() {
+        let packet = ipv4(PROTO_UDP, &udp(1, 2, &[0; 10]));
+        for len in [0, 1, 19, 25] {
+            assert!(decode(LinkType::RawIp, &packet[..len], IpOptions::default()).is_none());
+        }
+    }
+
+    #[test]
+    fn only_non_ip_frames_are_layer_2_packets() {
+        let arp = ethernet(0x0806, &[0; 28]);
+        let packet = eth(&frame(LinkType::Ethernet, &arp)).unwrap();
+        assert_eq!(packet.ethertype, 0x0806);
+        assert_eq!(packet.src.to_string(), "02:00:00:00:00:01");
+        assert_eq!(packet.dst.to_string(), "02:00:00:00:00:02");
+        assert_eq!(packet.data_len, 42);
+
+        let ip = ethernet(0x0800, &ipv4(PROTO_UDP, &udp(1, 2, &[])));
+        assert!(eth(&frame(LinkType::Ethernet, &ip)).is_none());
+    }
+
+    /// A QoS data frame from a station to the access point, with sequence number 291 and TID 5
+    fn qos_data(flags: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x88, 0x01 | flags, 0, 0];
+        data.extend([0x02, 0, 0, 0, 0, 0xaa]);
+        data.extend([0x02, 0, 0, 0, 0, 0x01]);
+        data.extend([0x02, 0, 0, 0, 0, 0x02]);
+        data.extend((291u16 << 4).to_le_bytes());
+        data.extend([0x05, 0]);
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn qos_data_frames_are_sized_by_their_body() {
+        let data = qos_data(0, &[0; 100]);
+        let packet = wlan(&frame(LinkType::Ieee80211, &data), false, false, false).unwrap();
+        assert_eq!(packet.src.to_string(), "02:00:00:00:00:01");
+        assert_eq!(packet.dst.to_string(), "02:00:00:00:00:02");
+        assert_eq!((packet.seq_number, packet.tid), (291, Some(5)));
+        assert_eq!((packet.data_len, packet.msdus), (100, 1));
+
+        // Without the CCMP header and MIC
+        let protected = qos_data(0x40, &[0; 116]);
+        let packet = wlan(&frame(LinkType::Ieee80211, &protected), false, false, false).unwrap();
+        assert_eq!(packet.data_len, 100);
+
+        let mut beacon = data.clone();
+        beacon[0] = 0x80;
+        assert!(wlan(&frame(LinkType::Ieee80211, &beacon), false, false, false).is_none());
+    }
+
+    #[test]
+    fn a_msdu_frames_count_their_msdus_when_asked() {
+        // Subframes of 100 and 50 bytes, the first padded to a multiple of four
+        let mut body = Vec::new();
+        for (len, padding) in [(100u16, 2), (50, 0)] {
+            body.extend([0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01]);
+            body.extend(len.to_be_bytes());
+            body.extend(vec![0; len as usize + padding]);
+        }
+        let mut data = qos_data(0, &body);
+        data[24] |= 0x80;
+        let amsdu = frame(LinkType::Ieee80211, &data);
+        let packet = wlan(&amsdu, false, false, true).unwrap();
+        assert_eq!((packet.msdus, packet.data_len), (2, 150));
+        let packet = wlan(&amsdu, false, false, false).unwrap();
+        assert_eq!((packet.msdus, packet.data_len), (1, 180));
+
+        // Subframe headers of protected frames are encrypted
+        let mut protected = qos_data(0x40, &[0; 196]);
+        protected[24] |= 0x80;
+        let packet = wlan(&frame(LinkType::Ieee80211, &protected), false, false, true).unwrap();
+        assert_eq!((packet.msdus, packet.data_len), (1, 180));
+    }
+
+    #[test]
+    fn radiotap_headers_give_the_rate_and_frame_check_sequence() {
+        // Flags with the FCS bit, and a rate of 54 Mb/s
+        let mut data = vec![0, 0, 10, 0, 0x06, 0, 0, 0, 0x10, 108];
+        data.extend(qos_data(0, &[0; 104]));
+        let radiotap = frame(LinkType::Radiotap, &data);
+        let packet = wlan(&radiotap, true, false, false).unwrap();
+        assert_eq!((packet.data_len, packet.data_rate), (100, Some(54.0)));
+        assert_eq!(
+            wlan(&radiotap, false, false, false).unwrap().data_rate,
+            None
+        );
+    }
+
+    #[test]
+    fn radiotap_headers_give_the_channel() {
+        // Flags, then the frequency of channel 36 aligned to 2
+        let mut data = vec![0, 0, 14, 0, 0x0a, 0, 0, 0, 0, 0];
+        data.extend(5180u16.to_le_bytes());
+        data.extend([0x40, 0x01]);
+        data.extend(qos_data(0, &[0; 100]));
+        let radiotap = frame(LinkType::Radiotap, &data);
+        assert_eq!(
+            wlan(&radiotap, false, true, false).unwrap().channel,
+            Some(36)
+        );
+        assert_eq!(wlan(&radiotap, false, false, false).unwrap().channel, None);
+
+        // After the TSFT, aligned to 8
+        let mut data = vec![0, 0, 20, 0, 0x09, 0, 0, 0];
+        data.extend([0; 8]);
+        data.extend(2437u16.to_le_bytes());
+        data.extend([0xa0, 0x00]);
+        data.extend(qos_data(0, &[0; 100]));
+        let radiotap = frame(LinkType::Radiotap, &data);
+        let packet = wlan(&radiotap, false, true, false).unwrap();
+        assert_eq!((packet.channel, packet.data_len), (Some(6), 100));
+    }
+
+    #[test]
+    fn frequencies_are_numbered_by_their_band() {
+        assert_eq!(channel_number(2412), Some(1));
+        assert_eq!(channel_number(2484), Some(14));
+        assert_eq!(channel_number(5745), Some(149));
+        assert_eq!(channel_number(5955), Some(1));
+        assert_eq!(channel_number(7115), Some(233));
+        assert_eq!(channel_number(900), None);
+    }
+
+    #[test]
+    fn captured_lengths_do_not_change_sizes() {
+        let data = qos_data(0, &[0; 100]);
+        let truncated = Frame {
+            len: data.len() as u32,
+            ..frame(LinkType::Ieee80211, &data[..40])
+        };
+        assert_eq!(wlan(&truncated, false, false, false).unwrap().data_len, 100);
+    }
+
+    fn tunnels() -> IpOptions {
+        IpOptions {
+            tunnels: Some(TunnelMode::Session),
+            ..IpOptions::default()
+        }
+    }
+
+    #[test]
+    fn esp_packets_are_decoded_when_detecting_tunnels() {
+        let mut esp = 0xabcdu32.to_be_bytes().to_vec();
+        esp.extend([0; 96]);
+        let packet = ipv4(PROTO_ESP, &esp);
+        assert!(decode(LinkType::RawIp, &packet, IpOptions::default()).is_none());
+        let packet = decode(LinkType::RawIp, &packet, tunnels()).unwrap();
+        let tunnel = packet.tunnel.unwrap();
+        assert_eq!((tunnel.kind, tunnel.id), (TunnelKind::Esp, 0xabcd));
+        assert_eq!((packet.src_port, packet.data_len), (None, 100));
+
+        // ESP in UDP has a non-zero SPI, unlike IKE on the same port
+        let nat_t = ipv4(PROTO_UDP, &udp(4500, 4500, &esp));
+        let tunnel = decode(LinkType::RawIp, &nat_t, tunnels()).unwrap().tunnel;
+        assert_eq!(tunnel.map(|t| t.id), Some(0xabcd));
+        let ike = ipv4(PROTO_UDP, &udp(4500, 4500, &[0; 100]));
+        assert!(decode(LinkType::RawIp, &ike, tunnels())
+            .unwrap()
+            .tunnel
+            .is_none());
+    }
+
+    #[test]
+    fn tunneled_packets_are_decoded_from_the_inner_packet_when_decapsulating() {
+        let decapsulate = IpOptions {
+            decapsulate: true,
+            ..IpOptions::default()
+        };
+        let inner = ipv6(PROTO_UDP, &udp(1000, 2000, &[0; 50]));
+
+        // VNI 42, and an ethernet frame
+        let mut vxlan = vec![0x08, 0, 0, 0, 0, 0, 42, 0];
+        vxlan.extend(ethernet(0x86dd, &inner));
+        // Key 7, and an IPv6 packet
+        let mut gre = vec![0x20, 0, 0x86, 0xdd, 0, 0, 0, 7];
+        gre.extend(&inner);
+        // TEID 0x1234, and an extension header of four bytes after the first of its type
+        let mut gtp = vec![
+            0x34, 0xff, 0, 0, 0, 0, 0x12, 0x34, 0, 0, 0, 0x85, 1, 0, 0, 0,
+        ];
+        gtp.extend(&inner);
+
+        for (packet, kind, id, overhead) in [
+            (udp(50000, 4789, &vxlan), TunnelKind::Vxlan, 42, 30),
+            (gre, TunnelKind::Gre, 7, 8),
+            (udp(2152, 2152, &gtp), TunnelKind::Gtp, 0x1234, 24),
+        ] {
+            let proto = match kind {
+                TunnelKind::Gre => PROTO_GRE,
+                _ => PROTO_UDP,
+            };
+            let outer = ipv4(proto, &packet);
+            let packet = decode(LinkType::RawIp, &outer, decapsulate).unwrap();
+            assert_eq!(packet.src, "2001:db8::1".parse::<IpAddr>().unwrap());
+            assert_eq!((packet.src_port, packet.data_len), (Some(1000), 50));
+            let tunnel = packet.tunnel.unwrap();
+            assert_eq!(
+                (tunnel.kind, tunnel.id, tunnel.overhead),
+                (kind, id, overhead)
+            );
+        }
+
+        let outer = ipv4(PROTO_UDP, &udp(50000, 4789, &vxlan));
+        let packet = decode(LinkType::RawIp, &outer, IpOptions::default()).unwrap();
+        assert_eq!(
+            (packet.dst_port, packet.tunnel.is_none()),
+            (Some(4789), true)
+        );
+    }
+
+    #[test]
+    fn wireguard_is_told_by_the_sizes_of_its_messages() {
+        let message = |message_type: u8, len: usize| {
+            let mut message = vec![message_type, 0, 0, 0];
+            message.extend(0x01020304u32.to_le_bytes());
+            message.resize(len, 0);
+            ipv4(PROTO_UDP, &udp(51820, 51820, &message))
+        };
+        let tunnel = |packet: &[u8]| decode(LinkType::RawIp, packet, tunnels()).unwrap().tunnel;
+
+        let data = tunnel(&message(4, 96)).unwrap();
+        assert_eq!(
+            (data.kind, data.id, data.overhead),
+            (TunnelKind::WireGuard, 0x01020304, 32)
+        );
+        assert_eq!(tunnel(&message(3, 64)).map(|t| t.overhead), Some(64));
+        // Handshake initiations have no receiver index, and data is padded to 16 bytes
+        assert!(tunnel(&message(1, 148)).is_none());
+        assert!(tunnel(&message(4, 97)).is_none());
+    }
+
+    fn flow_errors() -> IpOptions {
+        IpOptions {
+            flow_errors: true,
+            ..IpOptions::default()
+        }
+    }
+
+    #[test]
+    fn resets_are_errors_of_their_flow() {
+        let reset = ipv4(PROTO_TCP, &tcp(50000, 443, TCP_RST, &[]));
+        assert!(decode(LinkType::RawIp, &reset, IpOptions::default()).is_none());
+        let packet = decode(LinkType::RawIp, &reset, flow_errors()).unwrap();
+        assert_eq!(packet.error, Some(FlowError::TcpReset));
+        assert_eq!(
+            (packet.src_port, packet.dst_port, packet.data_len),
+            (Some(50000), Some(443), 0)
+        );
+    }
+
+    #[test]
+    fn icmp_errors_are_errors_of_the_flow_they_quote() {
+        // Port unreachable, quoting the header of the datagram that was sent
+        let mut icmp = vec![3, 3, 0, 0, 0, 0, 0, 0];
+        icmp.extend(ipv4(PROTO_UDP, &udp(50000, 53, &[0; 20]))[..28].iter());
+        let packet = decode(LinkType::RawIp, &ipv4(PROTO_ICMP, &icmp), flow_errors()).unwrap();
+        assert_eq!(packet.error, Some(FlowError::IcmpUnreachable));
+        // The quoted datagram is of the flow from 10.0.0.1:50000 to 10.0.0.2:53
+        assert_eq!(packet.src, "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!((packet.src_port, packet.dst_port), (Some(50000), Some(53)));
+
+        let echo = [8, 0, 0, 0, 0, 1, 0, 1];
+        assert!(decode(LinkType::RawIp, &ipv4(PROTO_ICMP, &echo), flow_errors()).is_none());
+    }
+
+    #[test]
+    fn protocols_without_ports_are_sized_by_their_ip_payload_when_asked() {
+        let echo = ipv4(PROTO_ICMP, &[8, 0, 0, 0, 0, 1, 0, 1, 7, 7]);
+        assert!(decode(LinkType::RawIp, &echo, IpOptions::default()).is_none());
+        let portless = IpOptions {
+            portless: true,
+            ..IpOptions::default()
+        };
+        let packet = decode(LinkType::RawIp, &echo, portless).unwrap();
+        assert_eq!((packet.src_port, packet.dst_port), (None, None));
+        assert_eq!(packet.data_len, 10);
+        assert_eq!(packet.proto.map(|proto| proto.0), Some(PROTO_ICMP));
+
+        // ICMP errors are still errors of their flow, and other ICMP packets are portless
+        let errors = IpOptions {
+            flow_errors: true,
+            ..portless
+        };
+        assert!(decode(LinkType::RawIp, &echo, errors)
+            .unwrap()
+            .error
+            .is_none());
+        let packet = decode(LinkType::RawIp, &ipv4(PROTO_ESP, &[0; 16]), errors).unwrap();
+        assert_eq!((packet.src_port, packet.data_len), (None, 16));
+
+        // UDP keeps its ports
+        let datagram = ipv4(PROTO_UDP, &udp(5353, 53, &[0; 4]));
+        let packet = decode(LinkType::RawIp, &datagram, portless).unwrap();
+        assert_eq!((packet.src_port, packet.data_len), (Some(5353), 4));
+    }
+
+    #[test]
+    fn dscp_and_flow_labels_are_decoded_when_capturing_qos() {
+        let qos = IpOptions {
+            qos: true,
+            ..IpOptions::default()
+        };
+        let mut v4 = ipv4(PROTO_UDP, &udp(1, 2, &[0; 10]));
+        // Expedited forwarding, with ECN bits set
+        v4[1] = 46 << 2 | 0x01;
+        let marked = decode(LinkType::RawIp, &v4, qos).unwrap().qos.unwrap();
+        assert_eq!((marked.dscp, marked.flow_label), (46, None));
+        assert!(decode(LinkType::RawIp, &v4, IpOptions::default())
+            .unwrap()
+            .qos
+            .is_none());
+
+        let mut v6 = ipv6(PROTO_UDP, &udp(1, 2, &[0; 10]));
+        // The traffic class spans the first two bytes, followed by the flow label
+        v6[..4].copy_from_slice(&(6 << 28 | 10 << 22 | 0xabcde_u32).to_be_bytes());
+        let marked = decode(LinkType::RawIp, &v6, qos).unwrap().qos.unwrap();
+        assert_eq!((marked.dscp, marked.flow_label), (10, Some(0xabcde)));
+    }
+}
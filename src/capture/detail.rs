@@ -0,0 +1,238 @@
+use std::{collections::VecDeque, mem};
+
+use serde::Serialize;
+
+/// Seconds of the window the peak rate of a burst is measured over
+const PEAK_WINDOW: f64 = 0.01;
+
+/// Shape of a burst, from the times and sizes of its packets, when detailing bursts
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BurstDetail {
+    /// Shortest, mean and longest seconds between consecutive packets, for bursts of more than a
+    /// packet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_iat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_iat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_iat: Option<f64>,
+    /// Mean difference in seconds between consecutive inter-arrival times, as the packet delay
+    /// variation of RFC 3550 but without smoothing, for bursts of more than two packets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<f64>,
+    /// Most bytes per second within any 10 ms of the burst
+    pub peak_rate: f64,
+}
+
+impl BurstDetail {
+    /// The shape of a burst of `packets` followed `gap` seconds later by a burst of `next_packets`,
+    /// as one burst. The gap is an inter-arrival time of its own, while differences between the
+    /// inter-arrival times of the two, and windows spanning both, are not measured.
+    pub fn merge(&self, packets: u16, next: &BurstDetail, next_packets: u16, gap: f64) -> Self {
+        let iats = |packets: u16| packets.saturating_sub(1) as f64;
+        let (a, b) = (iats(packets), iats(next_packets));
+        let either = |a: Option<f64>, b: Option<f64>, f: fn(f64, f64) -> f64| match (a, b) {
+            (Some(a), Some(b)) => Some(f(a, b)),
+            (a, b) => a.or(b),
+        };
+        BurstDetail {
+            min_iat: Some(
+                either(self.min_iat, next.min_iat, f64::min).map_or(gap, |min| min.min(gap)),
+            ),
+            mean_iat: Some(
+                (self.mean_iat.unwrap_or(0.0) * a + next.mean_iat.unwrap_or(0.0) * b + gap)
+                    / (a + b + 1.0),
+            ),
+            max_iat: Some(
+                either(self.max_iat, next.max_iat, f64::max).map_or(gap, |max| max.max(gap)),
+            ),
+            jitter: match (self.jitter, next.jitter) {
+                (Some(x), Some(y)) => Some((x * (a - 1.0) + y * (b - 1.0)) / (a + b - 2.0)),
+                (x, y) => x.or(y),
+            },
+            peak_rate: self.peak_rate.max(next.peak_rate),
+        }
+    }
+}
+
+/// Follows the packets of the current burst of a flow, to tell the shape of the burst as it is
+/// sent.
+///
+/// The peak rate is measured at each packet, over the packets of the window ending with it, so
+/// only the packets of the last 10 ms are kept. The tracker is kept by the flow between bursts, so
+/// the window is only allocated once.
+pub(super) struct DetailTracker {
+    last_time: f64,
+    last_iat: Option<f64>,
+    iats: u32,
+    iat_sum: f64,
+    min_iat: f64,
+    max_iat: f64,
+    jitter_sum: f64,
+    /// Time and size of the packets of the window, and their bytes. Empty between bursts.
+    window: VecDeque<(f64, u32)>,
+    window_bytes: u64,
+    peak_bytes: u64,
+}
+
+impl DetailTracker {
+    /// Start following a burst with its first packet
+    pub(super) fn new(time: f64, size: u32) -> Self {
+        DetailTracker::start(VecDeque::new(), time, size)
+    }
+
+    /// Start following a burst in the window of an earlier one
+    fn start(mut window: VecDeque<(f64, u32)>, time: f64, size: u32) -> Self {
+        window.push_back((time, size));
+        DetailTracker {
+            last_time: time,
+            last_iat: None,
+            iats: 0,
+            iat_sum: 0.0,
+            min_iat: f64::INFINITY,
+            max_iat: 0.0,
+            jitter_sum: 0.0,
+            window,
+            window_bytes: size as u64,
+            peak_bytes: size as u64,
+        }
+    }
+
+    /// Add a later packet of the burst, or start the next burst with its first packet
+    pub(super) fn add(&mut self, time: f64, size: u32) {
+        if self.window.is_empty() {
+            *self = DetailTracker::start(mem::take(&mut self.window), time, size);
+            return;
+        }
+        // Packets captured out of order count as arriving together
+        let iat = (time - self.last_time).max(0.0);
+        let time = self.last_time.max(time);
+        if let Some(last_iat) = self.last_iat {
+            self.jitter_sum += (iat - last_iat).abs();
+        }
+        self.last_time = time;
+        self.last_iat = Some(iat);
+        self.iats += 1;
+        self.iat_sum += iat;
+        self.min_iat = self.min_iat.min(iat);
+        self.max_iat = self.max_iat.max(iat);
+
+        self.window.push_back((time, size));
+        self.window_bytes += size as u64;
+        // Packets a full window earlier, to the nanosecond precision of capture times, are out of it
+        while let Some(&(first, size)) = self.window.front() {
+            if time - first < PEAK_WINDOW - 1e-9 {
+                break;
+            }
+            self.window.pop_front();
+            self.window_bytes -= size as u64;
+        }
+        self.peak_bytes = self.peak_bytes.max(self.window_bytes);
+    }
+
+    /// Move the times of the packets by the offset, to another clock
+    pub(super) fn rebase(&mut self, offset: f64) {
+        self.last_time += offset;
+        for (time, _) in &mut self.window {
+            *time += offset;
+        }
+    }
+
+    /// The shape of the burst as it is sent, unless none is followed, making way for the next
+    pub(super) fn finish(&mut self) -> Option<BurstDetail> {
+        let detail = (!self.window.is_empty()).then(|| self.detail());
+        self.window.clear();
+        detail
+    }
+
+    /// The shape of the burst
+    fn detail(&self) -> BurstDetail {
+        // Rounded to nanoseconds, the precision of capture times
+        let round = |seconds: f64| (seconds * 1e9).round() / 1e9;
+        let iats = (self.iats > 0).then_some(self.iats as f64);
+        BurstDetail {
+            min_iat: iats.map(|_| round(self.min_iat)),
+            mean_iat: iats.map(|iats| round(self.iat_sum / iats)),
+            max_iat: iats.map(|_| round(self.max_iat)),
+            jitter: (self.iats > 1).then(|| round(self.jitter_sum / (self.iats - 1) as f64)),
+            peak_rate: self.peak_bytes as f64 / PEAK_WINDOW,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BurstDetail, DetailTracker};
+
+    fn close(a: Option<f64>, b: f64) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 1e-9)
+    }
+
+    #[test]
+    fn single_packets_only_have_a_peak_rate() {
+        let detail = DetailTracker::new(1.0, 100).finish().unwrap();
+        assert_eq!(
+            (detail.min_iat, detail.mean_iat, detail.jitter),
+            (None, None, None)
+        );
+        assert_eq!(detail.peak_rate, 10_000.0);
+    }
+
+    #[test]
+    fn bursts_are_detailed_by_their_packets() {
+        let mut tracker = DetailTracker::new(1.0, 100);
+        for time in [1.002, 1.006, 1.020] {
+            tracker.add(time, 100);
+        }
+        let detail = tracker.finish().unwrap();
+        assert_eq!(detail.min_iat, Some(0.002));
+        assert_eq!(detail.mean_iat, Some(0.006666667));
+        assert_eq!(detail.max_iat, Some(0.014));
+        assert!(close(detail.jitter, 0.006));
+        // The first three packets are within 10 ms, and the last is alone in its window
+        assert_eq!(detail.peak_rate, 30_000.0);
+        assert!(tracker.finish().is_none());
+    }
+
+    #[test]
+    fn the_next_burst_starts_after_finishing() {
+        let mut tracker = DetailTracker::new(1.0, 100);
+        tracker.add(1.001, 100);
+        tracker.finish();
+        tracker.add(2.0, 50);
+        // Captured out of order, so arriving together with the packet before
+        tracker.add(1.999, 50);
+        let detail = tracker.finish().unwrap();
+        assert_eq!((detail.min_iat, detail.max_iat), (Some(0.0), Some(0.0)));
+        assert_eq!(detail.peak_rate, 10_000.0);
+    }
+
+    #[test]
+    fn merged_bursts_count_the_gap_as_an_inter_arrival_time() {
+        let first = BurstDetail {
+            min_iat: Some(0.002),
+            mean_iat: Some(0.003),
+            max_iat: Some(0.004),
+            jitter: Some(0.002),
+            peak_rate: 100.0,
+        };
+        let next = BurstDetail {
+            min_iat: Some(0.005),
+            mean_iat: Some(0.005),
+            max_iat: Some(0.005),
+            jitter: None,
+            peak_rate: 200.0,
+        };
+        let merged = first.merge(3, &next, 2, 0.1);
+        assert_eq!((merged.min_iat, merged.max_iat), (Some(0.002), Some(0.1)));
+        assert!(close(merged.mean_iat, 0.02775));
+        assert_eq!(merged.jitter, Some(0.002));
+        assert_eq!(merged.peak_rate, 200.0);
+
+        let single = DetailTracker::new(1.0, 100).finish().unwrap();
+        let merged = single.merge(1, &single, 1, 0.5);
+        assert_eq!(merged.min_iat, Some(0.5));
+        assert_eq!((merged.mean_iat, merged.max_iat), (Some(0.5), Some(0.5)));
+        assert_eq!(merged.jitter, None);
+    }
+}
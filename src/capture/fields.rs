@@ -0,0 +1,108 @@
+use memchr::memchr;
+
+use crate::BurstsharkError;
+
+/// Separator between fields in the output of `tshark -T fields`
+const SEPARATOR: u8 = b'\t';
+
+/// A tokenizer over the fields of a tshark output line
+///
+/// Unlike `split_whitespace`, empty fields are kept so that the position of each field matches the
+/// position of its `-e` argument. Field boundaries are located with the SIMD accelerated `memchr`.
+pub struct Fields<'a> {
+    line: &'a str,
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self {
+            line: line.trim_end_matches(['\n', '\r']),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Get the next field, failing if the line has no more fields
+    pub fn field(&mut self) -> Result<&'a str, BurstsharkError> {
+        self.next()
+            .ok_or_else(|| BurstsharkError::MissingField(self.line.to_string()))
+    }
+
+    /// Get the first non-empty field among the next `n` fields
+    pub fn first_present(&mut self, n: usize) -> Result<&'a str, BurstsharkError> {
+        let mut present = None;
+        for _ in 0..n {
+            let field = self.field()?;
+            if present.is_none() && !field.is_empty() {
+                present = Some(field);
+            }
+        }
+        present.ok_or_else(|| BurstsharkError::MissingField(self.line.to_string()))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let rest = &self.line[self.pos..];
+        match memchr(SEPARATOR, rest.as_bytes()) {
+            Some(end) => {
+                self.pos += end + 1;
+                Some(&rest[..end])
+            }
+            None => {
+                self.done = true;
+                Some(rest)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fields;
+    use crate::BurstsharkError;
+
+    #[test]
+    fn empty_fields_keep_their_position() {
+        let fields: Vec<&str> = Fields::new("1.0\t\t443\t\n").collect();
+        assert_eq!(fields, ["1.0", "", "443", ""]);
+    }
+
+    #[test]
+    fn line_endings_are_not_part_of_the_last_field() {
+        let mut fields = Fields::new("1.0\t1400\r\n");
+        assert_eq!(fields.field().unwrap(), "1.0");
+        assert_eq!(fields.field().unwrap(), "1400");
+        assert_eq!(fields.next(), None);
+    }
+
+    #[test]
+    fn the_first_present_field_is_taken() {
+        let mut fields = Fields::new("\t50000\t\t443\t\t\t1400");
+        assert_eq!(fields.first_present(2).unwrap(), "50000");
+        assert_eq!(fields.first_present(2).unwrap(), "443");
+        assert_eq!(fields.first_present(3).unwrap(), "1400");
+    }
+
+    #[test]
+    fn missing_fields_are_errors() {
+        let mut fields = Fields::new("1.0\t\t");
+        assert!(fields.field().is_ok());
+        assert!(matches!(
+            fields.first_present(2),
+            Err(BurstsharkError::MissingField(_))
+        ));
+        assert!(matches!(
+            fields.field(),
+            Err(BurstsharkError::MissingField(_))
+        ));
+    }
+}
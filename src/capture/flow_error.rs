@@ -0,0 +1,91 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// ICMP types of errors that quote the packet that caused them
+const ICMP_UNREACHABLE: u8 = 3;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+const ICMPV6_UNREACHABLE: u8 = 1;
+const ICMPV6_TIME_EXCEEDED: u8 = 3;
+
+/// A packet referencing a flow that signals the flow failed
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowError {
+    /// A TCP segment with the reset flag, sent by either endpoint
+    TcpReset,
+    /// An ICMP destination unreachable message, quoting a packet of the flow
+    IcmpUnreachable,
+    /// An ICMP time exceeded message, quoting a packet of the flow whose TTL ran out
+    IcmpTimeExceeded,
+}
+
+impl FlowError {
+    pub(super) fn from_icmp(icmp_type: u8) -> Option<Self> {
+        match icmp_type {
+            ICMP_UNREACHABLE => Some(FlowError::IcmpUnreachable),
+            ICMP_TIME_EXCEEDED => Some(FlowError::IcmpTimeExceeded),
+            _ => None,
+        }
+    }
+
+    pub(super) fn from_icmpv6(icmp_type: u8) -> Option<Self> {
+        match icmp_type {
+            ICMPV6_UNREACHABLE => Some(FlowError::IcmpUnreachable),
+            ICMPV6_TIME_EXCEEDED => Some(FlowError::IcmpTimeExceeded),
+            _ => None,
+        }
+    }
+
+    /// The error of a packet from the printed reset flag and ICMP type, either of which may be empty
+    pub(super) fn from_tshark(reset: &str, icmp_type: &str) -> Option<Self> {
+        match (reset, icmp_type) {
+            ("1" | "True", _) => Some(FlowError::TcpReset),
+            (_, "") => None,
+            (_, icmp_type) => icmp_type.parse().ok().and_then(FlowError::from_icmp),
+        }
+    }
+}
+
+impl fmt::Display for FlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowError::TcpReset => write!(f, "tcp_reset"),
+            FlowError::IcmpUnreachable => write!(f, "icmp_unreachable"),
+            FlowError::IcmpTimeExceeded => write!(f, "icmp_time_exceeded"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlowError;
+
+    #[test]
+    fn resets_and_icmp_errors_are_flow_errors() {
+        assert_eq!(FlowError::from_tshark("1", ""), Some(FlowError::TcpReset));
+        assert_eq!(
+            FlowError::from_tshark("True", ""),
+            Some(FlowError::TcpReset)
+        );
+        assert_eq!(
+            FlowError::from_tshark("", "3"),
+            Some(FlowError::IcmpUnreachable)
+        );
+        assert_eq!(
+            FlowError::from_tshark("0", "11"),
+            Some(FlowError::IcmpTimeExceeded)
+        );
+        assert_eq!(FlowError::from_icmpv6(1), Some(FlowError::IcmpUnreachable));
+        assert_eq!(FlowError::from_icmpv6(3), Some(FlowError::IcmpTimeExceeded));
+    }
+
+    #[test]
+    fn other_packets_are_not_errors() {
+        assert_eq!(FlowError::from_tshark("0", ""), None);
+        assert_eq!(FlowError::from_tshark("False", "8"), None);
+        assert_eq!(FlowError::from_tshark("", "not a type"), None);
+        // Echo requests
+        assert_eq!(FlowError::from_icmpv6(128), None);
+    }
+}
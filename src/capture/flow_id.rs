@@ -0,0 +1,115 @@
+use std::{fmt::Write, net::IpAddr, sync::Arc};
+
+use sha2::{Digest, Sha256};
+
+use super::tunnel::TunnelKey;
+
+/// How the canonical id of a flow is presented
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowIdFormat {
+    /// The sorted endpoints, such as 10.0.0.1:40000-10.0.0.2:443
+    Key,
+    /// The first 16 hex digits of the SHA-256 hash of the key
+    Hash,
+}
+
+/// An id of a flow that is the same for both of its directions, with the endpoints sorted.
+pub fn flow_id(
+    (src, dst, src_port, dst_port, tunnel): (
+        IpAddr,
+        IpAddr,
+        Option<u16>,
+        Option<u16>,
+        Option<TunnelKey>,
+    ),
+    format: FlowIdFormat,
+) -> Arc<str> {
+    let (a, b) = match (src, src_port) <= (dst, dst_port) {
+        true => ((src, src_port), (dst, dst_port)),
+        false => ((dst, dst_port), (src, src_port)),
+    };
+
+    let mut key = String::new();
+    for (i, (addr, port)) in [a, b].into_iter().enumerate() {
+        if i > 0 {
+            key.push('-');
+        }
+        match (addr, port) {
+            (IpAddr::V6(addr), Some(port)) => write!(key, "[{addr}]:{port}"),
+            (addr, Some(port)) => write!(key, "{addr}:{port}"),
+            (addr, None) => write!(key, "{addr}"),
+        }
+        .unwrap();
+    }
+    if let Some(tunnel) = tunnel {
+        write!(key, "/{}", tunnel.to_string().replace(' ', ":")).unwrap();
+    }
+
+    match format {
+        FlowIdFormat::Key => Arc::from(key),
+        FlowIdFormat::Hash => {
+            let digest = Sha256::digest(key.as_bytes());
+            let mut hash = String::with_capacity(16);
+            for byte in &digest[..8] {
+                write!(hash, "{byte:02x}").unwrap();
+            }
+            Arc::from(hash)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{flow_id, FlowIdFormat};
+    use crate::capture::tunnel::{TunnelKey, TunnelKind};
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn both_directions_have_the_same_id() {
+        let (client, server) = (ip("10.0.0.1"), ip("10.0.0.2"));
+        for format in [FlowIdFormat::Key, FlowIdFormat::Hash] {
+            assert_eq!(
+                flow_id((client, server, Some(40000), Some(443), None), format),
+                flow_id((server, client, Some(443), Some(40000), None), format)
+            );
+        }
+    }
+
+    #[test]
+    fn keys_list_the_sorted_endpoints() {
+        let key = |flow| flow_id(flow, FlowIdFormat::Key).to_string();
+        assert_eq!(
+            key((ip("10.0.0.2"), ip("10.0.0.1"), Some(443), Some(40000), None)),
+            "10.0.0.1:40000-10.0.0.2:443"
+        );
+        assert_eq!(
+            key((
+                ip("2001:db8::1"),
+                ip("2001:db8::2"),
+                Some(5353),
+                Some(53),
+                None
+            )),
+            "[2001:db8::1]:5353-[2001:db8::2]:53"
+        );
+        let tunnel = TunnelKey {
+            kind: TunnelKind::Esp,
+            id: Some(0xabcd),
+        };
+        assert_eq!(
+            key((ip("10.0.0.1"), ip("10.0.0.2"), None, None, Some(tunnel))),
+            "10.0.0.1-10.0.0.2/esp:0x0000abcd"
+        );
+    }
+
+    #[test]
+    fn hashes_are_the_start_of_the_sha256_of_the_key() {
+        let flow = (ip("10.0.0.1"), ip("10.0.0.2"), Some(40000), Some(443), None);
+        assert_eq!(&*flow_id(flow, FlowIdFormat::Hash), "07b318681e33fb88");
+    }
+}
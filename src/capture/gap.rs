@@ -0,0 +1,75 @@
+/// Weight of each new inter-arrival time in the moving average, as for the smoothed RTT of TCP
+const WEIGHT: f64 = 1.0 / 8.0;
+
+/// Gaps of flows that adapt to the times between their packets, so flows with packets far apart
+/// are not split into many bursts, while flows with packets close together are not merged.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveGap {
+    /// The gap is this many times the average inter-arrival time of the flow
+    pub factor: f64,
+    pub min: f64,
+    /// Also the gap of flows whose inter-arrival time is not yet known
+    pub max: f64,
+}
+
+/// Moving average of the times between the packets within the bursts of a flow. The times between
+/// bursts are left out, as they would make the gap grow beyond them.
+#[derive(Default)]
+pub(super) struct InterArrival {
+    mean: Option<f64>,
+}
+
+impl InterArrival {
+    /// Continue the average of a flow restored from earlier
+    pub(super) fn resume(mean: Option<f64>) -> Self {
+        InterArrival { mean }
+    }
+
+    pub(super) fn mean(&self) -> Option<f64> {
+        self.mean
+    }
+
+    pub(super) fn add(&mut self, time: f64) {
+        self.mean = Some(match self.mean {
+            Some(mean) => mean + (time - mean) * WEIGHT,
+            None => time,
+        });
+    }
+
+    /// The time without packets after which the burst of the flow is complete
+    pub(super) fn gap(&self, adaptive: &AdaptiveGap) -> f64 {
+        self.mean.map_or(adaptive.max, |mean| {
+            (mean * adaptive.factor).clamp(adaptive.min, adaptive.max)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdaptiveGap, InterArrival};
+
+    const ADAPTIVE: AdaptiveGap = AdaptiveGap {
+        factor: 4.0,
+        min: 0.01,
+        max: 1.0,
+    };
+
+    #[test]
+    fn the_gap_is_a_multiple_of_the_average_inter_arrival_time() {
+        let mut iat = InterArrival::default();
+        assert_eq!(iat.gap(&ADAPTIVE), 1.0);
+        iat.add(0.05);
+        assert_eq!(iat.gap(&ADAPTIVE), 0.2);
+        // Each new time moves the average an eighth of the way towards it
+        iat.add(0.13);
+        assert!((iat.mean().unwrap() - 0.06).abs() < 1e-12);
+    }
+
+    #[test]
+    fn the_gap_stays_within_its_bounds() {
+        let fast = InterArrival::resume(Some(0.0001));
+        let slow = InterArrival::resume(Some(10.0));
+        assert_eq!(fast.gap(&ADAPTIVE), 0.01);
+        assert_eq!(slow.gap(&ADAPTIVE), 1.0);
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CastKind {
+    Multicast,
+    Broadcast,
+}
+
+/// The multicast group or broadcast domain a flow is sent to
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CastGroup {
+    pub kind: CastKind,
+
+    /// Well-known name of the group or broadcast protocol, such as ssdp or dhcp
+    pub name: Option<&'static str>,
+}
+
+impl CastGroup {
+    /// The group of a destination, if it is a multicast or broadcast address
+    pub fn of(dst: IpAddr, dst_port: Option<u16>) -> Option<Self> {
+        if dst.is_multicast() {
+            Some(CastGroup {
+                kind: CastKind::Multicast,
+                name: multicast_name(dst),
+            })
+        } else if dst == IpAddr::V4(Ipv4Addr::BROADCAST) {
+            Some(CastGroup {
+                kind: CastKind::Broadcast,
+                name: dst_port.and_then(broadcast_name),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for CastGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            CastKind::Multicast => "multicast",
+            CastKind::Broadcast => "broadcast",
+        };
+        match self.name {
+            Some(name) => write!(f, "{kind}:{name}"),
+            None => write!(f, "{kind}"),
+        }
+    }
+}
+
+fn multicast_name(group: IpAddr) -> Option<&'static str> {
+    match group {
+        IpAddr::V4(group) => match group.octets() {
+            [224, 0, 0, 1] => Some("all-hosts"),
+            [224, 0, 0, 2] => Some("all-routers"),
+            [224, 0, 0, 5] | [224, 0, 0, 6] => Some("ospf"),
+            [224, 0, 0, 9] => Some("rip"),
+            [224, 0, 0, 13] => Some("pim"),
+            [224, 0, 0, 18] => Some("vrrp"),
+            [224, 0, 0, 22] => Some("igmp"),
+            [224, 0, 0, 251] => Some("mdns"),
+            [224, 0, 0, 252] => Some("llmnr"),
+            [224, 0, 1, 1] => Some("ntp"),
+            [239, 255, 255, 250] => Some("ssdp"),
+            [232, ..] => Some("ssm"),
+            _ => None,
+        },
+        IpAddr::V6(group) => {
+            const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+            const ALL_ROUTERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+            const SSDP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xc);
+            const MDNS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+            const DHCP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 1, 2);
+            const LLMNR: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 1, 3);
+            match group {
+                ALL_NODES => Some("all-nodes"),
+                ALL_ROUTERS => Some("all-routers"),
+                SSDP => Some("ssdp"),
+                MDNS => Some("mdns"),
+                DHCP => Some("dhcpv6"),
+                LLMNR => Some("llmnr"),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn broadcast_name(dst_port: u16) -> Option<&'static str> {
+    match dst_port {
+        67 | 68 => Some("dhcp"),
+        137 | 138 => Some("netbios"),
+        1900 => Some("ssdp"),
+        5353 => Some("mdns"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{CastGroup, CastKind};
+
+    fn group(dst: &str, dst_port: u16) -> Option<String> {
+        CastGroup::of(dst.parse::<IpAddr>().unwrap(), Some(dst_port)).map(|g| g.to_string())
+    }
+
+    #[test]
+    fn well_known_groups_are_named() {
+        assert_eq!(
+            group("224.0.0.251", 5353).as_deref(),
+            Some("multicast:mdns")
+        );
+        assert_eq!(
+            group("239.255.255.250", 1900).as_deref(),
+            Some("multicast:ssdp")
+        );
+        assert_eq!(group("232.1.2.3", 5000).as_deref(), Some("multicast:ssm"));
+        assert_eq!(group("ff02::1:2", 547).as_deref(), Some("multicast:dhcpv6"));
+        assert_eq!(group("239.1.2.3", 5000).as_deref(), Some("multicast"));
+    }
+
+    #[test]
+    fn broadcasts_are_named_by_port() {
+        assert_eq!(
+            group("255.255.255.255", 67).as_deref(),
+            Some("broadcast:dhcp")
+        );
+        assert_eq!(group("255.255.255.255", 9).as_deref(), Some("broadcast"));
+        let group = CastGroup::of("255.255.255.255".parse().unwrap(), None).unwrap();
+        assert_eq!((group.kind, group.name), (CastKind::Broadcast, None));
+    }
+
+    #[test]
+    fn unicast_destinations_have_no_group() {
+        assert_eq!(group("192.168.1.255", 67), None);
+        assert_eq!(group("2001:db8::1", 547), None);
+    }
+}
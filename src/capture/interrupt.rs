@@ -0,0 +1,53 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, OnceLock,
+};
+
+use nix::{sys::signal, unistd::Pid};
+
+use crate::BurstsharkError;
+
+/// Cleared when the process is interrupted (ctrl-c), which stops all captures
+static RUNNING: AtomicBool = AtomicBool::new(true);
+
+/// Process ids of the running tshark instances, which are interrupted along with the process
+static TSHARK_PIDS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+/// Result of setting up the handler, which can only be done once per process
+static HANDLER: OnceLock<Result<(), String>> = OnceLock::new();
+
+/// Set up the interrupt handler the first time a capture starts, and return the flag that is
+/// cleared when interrupted.
+pub fn handle() -> Result<&'static AtomicBool, BurstsharkError> {
+    HANDLER
+        .get_or_init(|| {
+            ctrlc::set_handler(|| {
+                RUNNING.store(false, Ordering::SeqCst);
+                for pid in TSHARK_PIDS.lock().unwrap().iter() {
+                    signal::kill(Pid::from_raw(*pid), signal::Signal::SIGINT)
+                        .expect("Failed to send SIGINT to tshark");
+                }
+            })
+            .map_err(|err| err.to_string())
+        })
+        .clone()
+        .map_err(BurstsharkError::InterruptHandler)?;
+    Ok(&RUNNING)
+}
+
+/// Whether the process was interrupted, so captures ended early
+pub fn interrupted() -> bool {
+    !RUNNING.load(Ordering::SeqCst)
+}
+
+/// Pass interrupts on to a tshark instance while it runs.
+pub fn add_tshark(pid: u32) {
+    TSHARK_PIDS.lock().unwrap().push(pid as i32);
+}
+
+pub fn remove_tshark(pid: u32) {
+    TSHARK_PIDS
+        .lock()
+        .unwrap()
+        .retain(|other| *other != pid as i32);
+}
@@ -1,145 +1,916 @@
+mod airtime;
+mod bad_lines;
 mod burst;
-mod fifo;
-
-use std::{
-    error::Error,
-    io::BufRead,
-    net::IpAddr,
-    process::{Command, Stdio},
-    str::FromStr,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
-        Arc,
-    },
-};
+mod concurrency;
+mod decode;
+mod detail;
+mod fields;
+mod flow_error;
+mod flow_id;
+mod gap;
+mod group;
+mod interrupt;
+mod native;
+mod pool;
+mod proto;
+mod qos;
+mod queue;
+mod quic;
+mod random_mac;
+mod rate;
+mod reader;
+mod remote;
+mod retransmit;
+mod rtp;
+mod sequence;
+mod shard;
+mod source;
+mod ssh;
+mod state;
+mod surge;
+mod synthetic;
+mod tunnel;
 
-pub use burst::Burst;
+use std::{net::IpAddr, str::FromStr, sync::Arc};
+
+use bad_lines::BadLines;
+pub use burst::{
+    Burst, BurstDirection, BurstFilter, BurstPacket, FlowEvent, FlowEventKind, FlowEvictions,
+};
+pub use concurrency::HostConcurrency;
+use decode::Frame;
+pub use detail::BurstDetail;
+pub use fields::Fields;
+pub use flow_error::FlowError;
+pub use flow_id::FlowIdFormat;
+pub use gap::AdaptiveGap;
 use macaddr::MacAddr;
-use nix::sys::signal;
+pub use native::NativeSource;
+pub use proto::IpProto;
+use qos::IpQos;
+pub use queue::BurstQueue;
+use quic::QuicIds;
+pub use rate::RateSample;
+pub use remote::RemoteHost;
+use rtp::RtpHeader;
+pub use sequence::{OutlierPolicy, SequenceWindow};
+use serde::Serialize;
+use shard::{ShardedPacket, Workers};
+use source::LineSource;
+use state::{StateFile, StateKind};
+pub use surge::{FlowSurge, SurgeOptions};
+use synthetic::SyntheticPacket;
+pub use synthetic::SyntheticTraffic;
+use tunnel::Tunnel;
+pub use tunnel::TunnelMode;
+
+use crate::{
+    channel::{ChannelOptions, Sender},
+    compare::BurstDelta,
+    control::Controls,
+    histogram::FlowHistogram,
+    privacy::BurstHistogram,
+    profile::{self, PipelineProfile, Stage},
+    sched::ThreadSched,
+    BurstsharkError,
+};
+
+/// A record sent from the capture to the output
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+// Most records are bursts, so boxing them would only add an allocation to each
+#[allow(clippy::large_enum_variant)]
+pub enum Record {
+    Burst(Burst),
+    FlowSurge(FlowSurge),
+    FlowEvent(FlowEvent),
+    Concurrency(HostConcurrency),
+    BurstDelta(BurstDelta),
+    BurstHistogram(BurstHistogram),
+    FlowHistogram(FlowHistogram),
+    RateSample(RateSample),
+}
+
+/// How packets are captured and dissected
+#[derive(Clone)]
+pub enum CaptureBackend {
+    /// Run tshark with these arguments and parse the fields it prints
+    Tshark { args: Vec<String> },
+    /// Run tshark with these arguments on a remote host over SSH, where files are read from and
+    /// written to
+    Remote { host: RemoteHost, args: Vec<String> },
+    /// Capture and decode the packet headers in process, without tshark
+    Native {
+        source: NativeSource,
+        epoch_time: bool,
+    },
+    /// Parse fields printed by tshark earlier, such as the fixtures of the self-test
+    Lines { lines: String },
+    /// Run this shell command and parse the fields it prints, in the layout tshark would print them
+    Exec { command: String },
+    /// Parse the fields read from stdin, in the layout tshark would print them
+    Stdin,
+    /// Generate packets in process, such as to benchmark the creation of bursts
+    Synthetic(SyntheticTraffic),
+}
+
+impl CaptureBackend {
+    /// The live capture by tshark restarted with another capture filter, unless it already
+    /// captures with it
+    fn with_capture_filter(&self, filter: &str) -> Option<CaptureBackend> {
+        let mut backend = self.clone();
+        let (CaptureBackend::Tshark { args } | CaptureBackend::Remote { args, .. }) = &mut backend
+        else {
+            return None;
+        };
+        let position = args.iter().position(|arg| arg == "-f")? + 1;
+        if args.get(position)? == filter {
+            return None;
+        }
+        args[position] = filter.to_string();
+        Some(backend)
+    }
+}
 
+#[derive(Clone)]
 pub struct CommonOptions {
-    pub tshark_args: Vec<String>,
+    pub backend: CaptureBackend,
     pub inactive_time: f64,
-    pub tx: Sender<Burst>,
+    /// Packets are read from a file, so bursts are completed by packet times alone and flushed at the end
+    pub offline: bool,
+    /// Also send events when flows are opened and closed
+    pub flow_events: bool,
+    /// Bursts outside these limits are never sent
+    pub filter: BurstFilter,
+    /// Settings changed while the capture runs, which take the place of the filter, and of the
+    /// capture filter of live captures by tshark
+    pub controls: Option<Arc<Controls>>,
+    /// Complete bursts once they have this many packets, even if the flow does not pause
+    pub max_burst_packets: Option<u16>,
+    /// Give each flow a gap of its own instead of the inactive time
+    pub adaptive_gap: Option<AdaptiveGap>,
+    /// Evict flows without packets for this many seconds, and the least recently active beyond
+    /// this many flows per worker, counting the evicted flows
+    pub flow_timeout: Option<f64>,
+    pub max_flows: Option<usize>,
+    pub evictions: Arc<FlowEvictions>,
+    /// Annotate bursts with the time and size of up to this many of their packets
+    pub packet_timing: Option<usize>,
+    /// Annotate bursts with the inter-arrival times, jitter and peak rate of their packets
+    pub detailed_bursts: bool,
+    /// Seconds between samples of the bytes and bursts per second of each active flow
+    pub rate_interval: Option<f64>,
+    /// Threads to distribute the flows over when creating bursts
+    pub workers: usize,
+    /// Capacity of the channels of packets to the workers, and what to do when they are full
+    pub channels: ChannelOptions,
+    /// Also write the lines of tshark output that could not be parsed to this file
+    pub bad_lines_file: Option<String>,
+    /// Report the lines of tshark output that could not be parsed, rather than only counting them
+    pub log_malformed: bool,
+    /// Save the bursts in progress to this file when a live capture stops, and continue the bursts
+    /// saved to it when one starts
+    pub state_file: Option<String>,
+    /// Names of the interfaces captured on, when capturing on several, by the ids tshark prints
+    /// as the last field of each packet
+    pub interfaces: Vec<Arc<str>>,
+    pub tx: Sender<Record>,
+    pub reader_sched: ThreadSched,
+    pub worker_sched: ThreadSched,
+    pub profile: Option<Arc<PipelineProfile>>,
+}
+
+impl CommonOptions {
+    /// Options with the defaults of the command line, sending records to `tx`.
+    ///
+    /// Files are read offline, while live captures complete bursts in real time.
+    pub fn new(backend: CaptureBackend, tx: Sender<Record>) -> Self {
+        let offline = match &backend {
+            CaptureBackend::Tshark { args } | CaptureBackend::Remote { args, .. } => {
+                args.iter().any(|arg| arg == "-r")
+            }
+            CaptureBackend::Native { source, .. } => matches!(source, NativeSource::File(_)),
+            CaptureBackend::Lines { .. }
+            | CaptureBackend::Exec { .. }
+            | CaptureBackend::Stdin
+            | CaptureBackend::Synthetic(_) => true,
+        };
+
+        CommonOptions {
+            backend,
+            inactive_time: 1.0,
+            offline,
+            flow_events: false,
+            filter: BurstFilter::default(),
+            controls: None,
+            max_burst_packets: None,
+            adaptive_gap: None,
+            flow_timeout: None,
+            max_flows: None,
+            evictions: Arc::default(),
+            packet_timing: None,
+            detailed_bursts: false,
+            rate_interval: None,
+            workers: 1,
+            channels: ChannelOptions::default(),
+            bad_lines_file: None,
+            log_malformed: false,
+            state_file: None,
+            interfaces: Vec::new(),
+            tx,
+            reader_sched: ThreadSched::default(),
+            worker_sched: ThreadSched::default(),
+            profile: None,
+        }
+    }
+}
+
+/// Options of IP captures, on how flows are keyed and what bursts are annotated with
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IpOptions {
+    pub ignore_ports: bool,
+    /// Key multicast and broadcast flows on their group rather than ports
+    pub group_keys: bool,
+    /// Detect ESP and WireGuard packets, and key their flows on the tunnel
+    pub tunnels: Option<TunnelMode>,
+    /// Key flows of packets in VXLAN, GRE and GTP-U tunnels on their inner addresses and ports, and
+    /// the tunnel
+    pub decapsulate: bool,
+    /// Annotate bursts of SSH flows with the inferred session phase
+    pub ssh_phases: bool,
+    /// Aggregate both directions of flows, and annotate bursts with their direction
+    pub bidirectional: bool,
+    pub surge: Option<SurgeOptions>,
+    /// Seconds between reports of the concurrent bursts per host
+    pub concurrency_interval: Option<f64>,
+    /// Annotate bursts and flow events with an id of the flow that is the same for both directions
+    pub flow_ids: Option<FlowIdFormat>,
+    /// Flag bursts during which TCP resets or ICMP errors referencing the flow were seen
+    pub flow_errors: bool,
+    /// Key QUIC flows on their connection ids rather than ports, which only tshark dissects
+    pub quic: bool,
+    /// Key RTP streams on their SSRC along with the ports, and count the packets of a stream
+    /// missed by the capture from its sequence numbers, which only tshark dissects
+    pub rtp: bool,
+    /// Disable guessing the sizes of missed RTP packets
+    pub no_guess: bool,
+    /// Annotate bursts with the DSCP and IPv6 flow label of their first packet
+    pub qos: bool,
+    /// Leave the bytes of retransmitted TCP segments out of the sizes of bursts
+    pub exclude_retransmissions: bool,
+    /// Annotate bursts with the IP protocol of their first packet
+    pub protocols: bool,
+    /// Burstify packets of protocols without ports, such as ICMP and ESP, keying their flows on the
+    /// addresses and IP protocol
+    pub portless: bool,
 }
 
+/// Options of WLAN captures, on how frames are counted from their sequence numbers
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WlanOptions {
+    /// Disable guessing the sizes of frames missed by the monitor mode device
+    pub no_guess: bool,
+    pub sequence: SequenceWindow,
+    /// Estimate the airtime of bursts from the PHY rates of their frames
+    pub airtime: bool,
+    /// Track sequence numbers per channel, to merge the frames of stations that move between
+    /// channels, and annotate bursts with their channels
+    pub channels: bool,
+    /// Merge the frames of all TIDs between two stations into one flow, rather than a flow per TID
+    pub no_tid: bool,
+    /// Count the MSDUs aggregated in A-MSDU frames as packets of their own, rather than the frames
+    pub msdus: bool,
+    /// Annotate bursts with a synthetic id of their device, correlating the randomized addresses
+    /// of devices from their sequence numbers
+    pub correlate_macs: bool,
+}
+
+#[allow(clippy::enum_variant_names)]
 pub enum CaptureType {
     IPCapture {
         opts: CommonOptions,
-        ignore_ports: bool,
+        ip: IpOptions,
     },
     WLANCapture {
         opts: CommonOptions,
-        no_guess: bool,
-        max_deviation: u16,
+        wlan: WlanOptions,
+    },
+    EthCapture {
+        opts: CommonOptions,
     },
 }
 
 impl CaptureType {
-    pub fn run(&self) -> Result<(), Box<dyn Error>> {
-        let opts = match self {
-            CaptureType::IPCapture { opts, .. } | CaptureType::WLANCapture { opts, .. } => opts,
-        };
-
-        let mut tshark = Command::new("tshark")
-            .args(&opts.tshark_args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn()
-            .map_err(|err| format!("Failed to start tshark: {err}"))?;
-
-        // Set up interrupt handler (ctrl-c)
-        let running = Arc::new(AtomicBool::new(true));
-        let r = running.clone();
-        let tshark_pid = tshark.id() as i32;
-        ctrlc::set_handler(move || {
-            r.store(false, Ordering::SeqCst);
-            let pid = nix::unistd::Pid::from_raw(tshark_pid);
-            signal::kill(pid, signal::Signal::SIGINT).expect("Failed to send SIGINT to tshark");
-        })?;
-
-        let stdout = tshark.stdout.take().unwrap();
-        let reader = std::io::BufReader::new(stdout);
-
+    pub fn run(&self) -> Result<(), BurstsharkError> {
         match self {
-            CaptureType::IPCapture { ignore_ports, .. } => {
-                // Spawn a thread that will handle all the burstification of the packets. Just leave parsing here
-                // TODO: If too high load, we can distribute flows over threads
-                let burst_tx = burst::start_ip(opts.inactive_time, *ignore_ports, opts.tx.clone())?;
-
-                for line in reader.lines() {
-                    if let Ok(packet) = IpPacket::from_tshark(&line.unwrap()) {
-                        burst_tx.send(packet)?;
-                    }
+            CaptureType::IPCapture { opts, ip } => {
+                if opts.workers > 1 && (ip.surge.is_some() || ip.concurrency_interval.is_some()) {
+                    return Err(BurstsharkError::InvalidOptions(
+                        "Surges and concurrency span the flows of a host, so they need a single worker"
+                            .to_string(),
+                    ));
                 }
+
+                // Spawn threads that will handle all the burstification of the packets. Just leave parsing here
+                let state = open_state(opts, StateKind::Ip)?;
+                let mut workers = Workers::start(opts, |opts, progress| {
+                    burst::start_ip(opts, *ip, progress, state.clone())
+                })?;
+                read_packets(
+                    opts,
+                    &mut workers,
+                    |line| IpPacket::from_tshark(line, ip),
+                    IpPacket::synthetic,
+                    |frame| decode::ip(frame, ip),
+                )
             }
-            CaptureType::WLANCapture {
-                no_guess,
-                max_deviation,
-                ..
-            } => {
-                // Spawn a thread that will handle all the burstification of the packets. Just leave parsing here
-                let burst_tx = burst::start_wlan(
-                    opts.inactive_time,
-                    *no_guess,
-                    *max_deviation,
-                    opts.tx.clone(),
-                )?;
-
-                for line in reader.lines() {
-                    if let Ok(packet) = WlanPacket::from_tshark(&line.unwrap()) {
-                        burst_tx.send(packet)?;
-                    }
+            CaptureType::WLANCapture { opts, wlan } => {
+                if opts.workers > 1 && wlan.correlate_macs {
+                    return Err(BurstsharkError::InvalidOptions(
+                        "Devices span the addresses of their flows, so correlating them needs a single worker"
+                            .to_string(),
+                    ));
                 }
+
+                // Spawn threads that will handle all the burstification of the packets. Just leave parsing here
+                let state = open_state(opts, StateKind::Wlan)?;
+                let mut workers = Workers::start(opts, |opts, progress| {
+                    burst::start_wlan(opts, *wlan, progress, state.clone())
+                })?;
+                let airtime = wlan.airtime;
+                let channels = wlan.channels;
+                let msdus = wlan.msdus;
+                read_packets(
+                    opts,
+                    &mut workers,
+                    |line| WlanPacket::from_tshark(line, airtime, channels, msdus),
+                    WlanPacket::synthetic,
+                    |frame| decode::wlan(frame, airtime, channels, msdus),
+                )
+            }
+            CaptureType::EthCapture { opts } => {
+                // Spawn threads that will handle all the burstification of the packets. Just leave parsing here
+                let state = open_state(opts, StateKind::Eth)?;
+                let mut workers = Workers::start(opts, |opts, progress| {
+                    burst::start_eth(opts, progress, state.clone())
+                })?;
+                read_packets(
+                    opts,
+                    &mut workers,
+                    EthPacket::from_tshark,
+                    EthPacket::synthetic,
+                    decode::eth,
+                )
+            }
+        }
+    }
+}
+
+/// The state file of the options, read before the workers start. Only live captures keep their
+/// bursts across restarts, as files are read from the start again.
+fn open_state(
+    opts: &CommonOptions,
+    kind: StateKind,
+) -> Result<Option<Arc<StateFile>>, BurstsharkError> {
+    match &opts.state_file {
+        Some(_) if opts.offline => Err(BurstsharkError::InvalidOptions(
+            "The state file is only kept by live captures".to_string(),
+        )),
+        Some(path) => Ok(Some(Arc::new(StateFile::open(path, kind, opts.workers)?))),
+        None => Ok(None),
+    }
+}
+
+/// Read packets with the configured backend and send them to the burst workers.
+///
+/// Lines printed by tshark are parsed with `parse`, generated packets are created by `synthesize`,
+/// while frames of the native backend are decoded with `decode`, which skips frames that are not
+/// of interest.
+fn read_packets<P: ShardedPacket + 'static>(
+    opts: &CommonOptions,
+    workers: &mut Workers<P>,
+    parse: impl Fn(&str) -> Result<P, BurstsharkError>,
+    synthesize: impl Fn(&SyntheticPacket) -> P,
+    decode: impl Fn(&Frame) -> Option<P>,
+) -> Result<(), BurstsharkError> {
+    // Lines end with the id of the interface of the packet when capturing on several
+    let interfaces = opts.interfaces.len() > 1;
+    let parse_line = |line: &str| -> Result<P, BurstsharkError> {
+        if !interfaces {
+            return parse(line);
+        }
+        let (line, interface) = line
+            .rsplit_once('\t')
+            .ok_or_else(|| BurstsharkError::MissingField(line.to_string()))?;
+        let mut packet = parse(line)?;
+        packet.set_interface(interface.parse::<u16>()?);
+        Ok(packet)
+    };
+
+    match &opts.backend {
+        CaptureBackend::Synthetic(traffic) => {
+            opts.reader_sched.apply_or_warn("reader");
+
+            for packet in traffic.generate() {
+                let packet = profile::time(&opts.profile, Stage::Parse, || synthesize(&packet));
+                profile::time(&opts.profile, Stage::Enqueue, || workers.send(packet))?;
             }
         }
+        CaptureBackend::Native { source, epoch_time } => {
+            opts.reader_sched.apply_or_warn("reader");
+
+            native::capture(source, *epoch_time, |frame| {
+                let packet = profile::time(&opts.profile, Stage::Parse, || decode(frame));
+                if let Some(packet) = packet {
+                    profile::time(&opts.profile, Stage::Enqueue, || workers.send(packet))?;
+                }
+                Ok(())
+            })?;
+        }
+        // All other backends print lines of fields
+        backend => {
+            // The lines are read on a runtime of the reader thread, which also ticks the workers of
+            // live captures on time
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .enable_time()
+                .build()?;
+            let _guard = runtime.enter();
 
-        tshark.wait()?;
-        Ok(())
+            // Set up interrupt handler (ctrl-c), which is passed on to the printing process
+            interrupt::handle()?;
+            opts.reader_sched.apply_or_warn("reader");
+
+            let tick = (!opts.offline).then(|| workers.tick_period());
+            let mut bad_lines = None;
+            let mut seen = 0;
+            // Tshark is restarted with the capture filter when it is changed
+            let mut restarted: Option<CaptureBackend> = None;
+            loop {
+                let current = restarted.take();
+                let backend = current.as_ref().unwrap_or(backend);
+                let (source, output) = LineSource::start(backend)?;
+                if bad_lines.is_none() {
+                    bad_lines = Some(BadLines::new(
+                        source.layout(),
+                        opts.bad_lines_file.as_deref(),
+                        opts.log_malformed,
+                    )?);
+                }
+                let lines = bad_lines.as_mut().unwrap();
+
+                let result = runtime.block_on(reader::read_lines(
+                    output,
+                    workers,
+                    tick,
+                    |workers, line| {
+                        match profile::time(&opts.profile, Stage::Parse, || parse_line(line)) {
+                            Ok(packet) => profile::time(&opts.profile, Stage::Enqueue, || {
+                                workers.send(packet)
+                            })?,
+                            Err(e) => lines.add(line, &e),
+                        }
+                        Ok(())
+                    },
+                    || {
+                        let settings = opts.controls.as_ref()?.changed(&mut seen)?;
+                        backend.with_capture_filter(&settings.tshark_filter?)
+                    },
+                ));
+
+                let closed = runtime.block_on(source.close(!matches!(result, Ok(None))));
+                match (result, closed) {
+                    (Ok(Some(backend)), Ok(())) => restarted = Some(backend),
+                    (result, closed) => {
+                        if let Some(bad_lines) = bad_lines.take() {
+                            bad_lines.finish();
+                        }
+                        closed?;
+                        result?;
+                        break;
+                    }
+                }
+            }
+        }
     }
+
+    Ok(())
+}
+
+/// Parse a line of tshark output of an IP capture, discarding the packet, to measure how fast
+/// lines are parsed
+pub fn parse_ip_line(line: &str, ip: &IpOptions) -> Result<(), BurstsharkError> {
+    IpPacket::from_tshark(line, ip).map(drop)
 }
 
 struct IpPacket {
     time: f64,
     src: IpAddr,
     dst: IpAddr,
-    src_port: u16,
-    dst_port: u16,
+    /// Ports of UDP and TCP packets, which other protocols have none of
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
     data_len: u32,
+    tunnel: Option<Tunnel>,
+    /// Set for packets signalling that the flow failed, which are not part of its bursts
+    error: Option<FlowError>,
+    quic: Option<QuicIds>,
+    rtp: Option<RtpHeader>,
+    qos: Option<IpQos>,
+    /// Sequence number of TCP segments, when excluding retransmissions
+    tcp_seq: Option<u32>,
+    /// IP protocol of the payload, when annotating bursts with it or burstifying protocols without
+    /// ports
+    proto: Option<IpProto>,
+    /// Id of the interface the packet was captured on, when capturing on several
+    interface: Option<u16>,
 }
 
 struct WlanPacket {
     time: f64,
     src: MacAddr,
     dst: MacAddr,
+    /// Size of the frame body, or of its MSDUs when counting those of A-MSDU frames
     data_len: u32,
+    /// MSDUs aggregated in the frame, which is one unless counting those of A-MSDU frames
+    msdus: u16,
     seq_number: u16,
+    /// PHY rate in Mb/s, when estimating airtime
+    data_rate: Option<f64>,
+    /// Traffic identifier of QoS data frames, whose sequence numbers are counted per TID
+    tid: Option<u8>,
+    /// Channel the frame was received on, when merging channels
+    channel: Option<u16>,
+    /// Id of the interface the frame was captured on, when capturing on several
+    interface: Option<u16>,
+}
+
+struct EthPacket {
+    time: f64,
+    src: MacAddr,
+    dst: MacAddr,
+    ethertype: u16,
+    data_len: u32,
+    /// Id of the interface the frame was captured on, when capturing on several
+    interface: Option<u16>,
+}
+
+/// Address of the `n`th host of generated traffic, in the 10.0.0.0/8 range
+fn synthetic_ip(n: u32) -> IpAddr {
+    IpAddr::from([10, (n >> 16) as u8, (n >> 8) as u8, n as u8])
+}
+
+/// Locally administered MAC address of the `n`th host of generated traffic
+fn synthetic_mac(n: u32) -> MacAddr {
+    let [_, a, b, c] = n.to_be_bytes();
+    MacAddr::from([0x02, 0, 0, a, b, c])
+}
+
+/// Size of the IP payload of a packet from its source, from the total and header lengths of IPv4
+/// or the payload length of IPv6, of the innermost header of tunneled packets
+fn ip_payload_len(
+    src: IpAddr,
+    ip_len: &str,
+    ip_hdr_len: &str,
+    ipv6_plen: &str,
+) -> Result<u32, BurstsharkError> {
+    Ok(match src {
+        IpAddr::V4(_) => tunnel::innermost(ip_len)
+            .parse::<u32>()?
+            .saturating_sub(tunnel::innermost(ip_hdr_len).parse::<u32>()?),
+        IpAddr::V6(_) => tunnel::innermost(ipv6_plen).parse::<u32>()?,
+    })
 }
 
 impl IpPacket {
-    fn from_tshark(line: &str) -> Result<Self, Box<dyn Error>> {
-        let mut fields = line.split_whitespace();
+    /// A UDP packet of a generated flow, from a host per flow to a common destination
+    fn synthetic(packet: &SyntheticPacket) -> Self {
+        IpPacket {
+            time: packet.time,
+            src: synthetic_ip(packet.flow + 1),
+            dst: synthetic_ip(0),
+            src_port: Some(443),
+            dst_port: Some(50000),
+            data_len: packet.len,
+            tunnel: None,
+            error: None,
+            quic: None,
+            rtp: None,
+            qos: None,
+            tcp_seq: None,
+            proto: None,
+            interface: None,
+        }
+    }
+
+    /// Parse the printed fields, which are followed by the tunnel fields when detecting tunnels,
+    /// the encapsulation fields when decapsulating, the error fields when detecting flow errors,
+    /// the QUIC fields when keying on connection ids, the RTP fields when keying on SSRCs, the QoS
+    /// fields when capturing QoS markings, the TCP sequence number when excluding retransmissions,
+    /// the IP protocol when annotating bursts with it, and then the IP lengths when burstifying
+    /// protocols without ports.
+    fn from_tshark<'a>(line: &'a str, ip: &IpOptions) -> Result<Self, BurstsharkError> {
+        let mut fields = Fields::new(line);
+        let time = fields.field()?.parse::<f64>()?;
+        // ICMP errors also print the addresses of the packet they quote, after their own, as do
+        // tunneled packets of their inner packet
+        let src = fields.field()?;
+        let dst = fields.field()?;
+        let (src_port, dst_port, data_len) = match ip.decapsulate {
+            // The ports and lengths of the inner packet, which are the last printed. Tunnels over
+            // UDP also print the ports of the outer header, so TCP ports are those of the inner.
+            true => {
+                let (src_udp, src_tcp) = (fields.field()?, fields.field()?);
+                let (dst_udp, dst_tcp) = (fields.field()?, fields.field()?);
+                let (data, udp_len, tcp_len) = (fields.field()?, fields.field()?, fields.field()?);
+                let innermost = |preferred: &[&'a str]| {
+                    preferred
+                        .iter()
+                        .find(|field| !field.is_empty())
+                        .map(|field| tunnel::innermost(field))
+                        .ok_or_else(|| BurstsharkError::MissingField(line.to_string()))
+                };
+                (
+                    innermost(&[src_tcp, src_udp]),
+                    innermost(&[dst_tcp, dst_udp]),
+                    innermost(&[data, tcp_len, udp_len]),
+                )
+            }
+            false => (
+                // Only one of udp and tcp ports are present
+                fields.first_present(2),
+                fields.first_present(2),
+                // Prefer data.len, then udp.length or tcp.len
+                fields.first_present(3),
+            ),
+        };
+
+        let tunnel_fields = match ip.tunnels.is_some() {
+            true => Some([
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+            ]),
+            false => None,
+        };
+        let encapsulation = match ip.decapsulate {
+            // Packets with a single IP header were not tunneled
+            true if src.contains(',') => Tunnel::from_tshark_encapsulation(
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+                fields.field()?,
+            )?,
+            true => {
+                for _ in 0..6 {
+                    fields.field()?;
+                }
+                None
+            }
+            false => None,
+        };
+        let error = match ip.flow_errors {
+            true => FlowError::from_tshark(fields.field()?, fields.field()?),
+            false => None,
+        };
+        let quic = match ip.quic {
+            true => QuicIds::from_tshark(fields.field()?, fields.field()?)?,
+            false => None,
+        };
+        let rtp = match ip.rtp {
+            true => RtpHeader::from_tshark(fields.field()?, fields.field()?, fields.field()?)?,
+            false => None,
+        };
+        let qos = match ip.qos {
+            true => IpQos::from_tshark(fields.first_present(2), fields.field()?)?,
+            false => None,
+        };
+        // Empty for UDP
+        let tcp_seq = match ip.exclude_retransmissions {
+            true => Some(fields.field()?)
+                .filter(|seq| !seq.is_empty())
+                .map(str::parse::<u32>)
+                .transpose()?,
+            false => None,
+        };
+        let proto = match ip.protocols || ip.portless {
+            true => Some(IpProto::from_tshark(fields.first_present(2)?)?),
+            false => None,
+        };
+        let ip_lens = match ip.portless {
+            true => Some((fields.field()?, fields.field()?, fields.field()?)),
+            false => None,
+        };
+
+        if let Some(error) = error {
+            // Errors reference the flow of the quoted packet, and carry none of its data
+            let quoted = |addrs: &str| IpAddr::from_str(tunnel::innermost(addrs));
+            return Ok(IpPacket {
+                time,
+                src: quoted(src)?,
+                dst: quoted(dst)?,
+                src_port: Some(src_port?.parse::<u16>()?),
+                dst_port: Some(dst_port?.parse::<u16>()?),
+                data_len: 0,
+                tunnel: None,
+                error: Some(error),
+                quic: None,
+                rtp: None,
+                qos: None,
+                tcp_seq: None,
+                proto: None,
+                interface: None,
+            });
+        }
+
+        let src = IpAddr::from_str(tunnel::innermost(src))?;
+        let dst = IpAddr::from_str(tunnel::innermost(dst))?;
+        let Some([esp_spi, wg_type, wg_receiver_index, ip_len, ip_hdr_len]) = tunnel_fields else {
+            let (src_port, dst_port, data_len) = match (src_port, dst_port, ip_lens) {
+                // Protocols without ports, such as ICMP, have the size of their IP payload
+                (Err(_), Err(_), Some((ip_len, ip_hdr_len, ipv6_plen))) => (
+                    None,
+                    None,
+                    ip_payload_len(src, ip_len, ip_hdr_len, ipv6_plen)?,
+                ),
+                (src_port, dst_port, _) => (
+                    Some(src_port?.parse::<u16>()?),
+                    Some(dst_port?.parse::<u16>()?),
+                    data_len?.parse::<u32>()?,
+                ),
+            };
+            return Ok(IpPacket {
+                time,
+                src,
+                dst,
+                src_port,
+                dst_port,
+                data_len,
+                tunnel: encapsulation,
+                error: None,
+                quic,
+                rtp,
+                qos,
+                tcp_seq,
+                proto,
+                interface: None,
+            });
+        };
+
+        let (src_port, dst_port, data_len) = match (src_port, dst_port, data_len, ip_lens) {
+            // ESP directly over IP has no ports, and its size is that of the IP payload
+            (Err(_), Err(_), Err(_), _) if !esp_spi.is_empty() => (
+                None,
+                None,
+                ip_len
+                    .parse::<u32>()?
+                    .saturating_sub(ip_hdr_len.parse::<u32>()?),
+            ),
+            (Err(_), Err(_), _, Some((ip_len, ip_hdr_len, ipv6_plen))) => (
+                None,
+                None,
+                ip_payload_len(src, ip_len, ip_hdr_len, ipv6_plen)?,
+            ),
+            (src_port, dst_port, data_len, _) => (
+                Some(src_port?.parse::<u16>()?),
+                Some(dst_port?.parse::<u16>()?),
+                data_len?.parse::<u32>()?,
+            ),
+        };
+
         Ok(IpPacket {
-            time: fields.next().unwrap().parse::<f64>()?,
-            src: IpAddr::from_str(fields.next().unwrap())?,
-            dst: IpAddr::from_str(fields.next().unwrap())?,
-            src_port: fields.next().unwrap().parse::<u16>()?,
-            dst_port: fields.next().unwrap().parse::<u16>()?,
-            data_len: fields.next().unwrap().parse::<u32>()?,
+            time,
+            src,
+            dst,
+            src_port,
+            dst_port,
+            data_len,
+            tunnel: Tunnel::from_tshark(esp_spi, wg_type, wg_receiver_index, data_len)?,
+            error: None,
+            quic,
+            rtp,
+            qos,
+            tcp_seq,
+            proto,
+            interface: None,
         })
     }
 }
 
 impl WlanPacket {
-    fn from_tshark(line: &str) -> Result<Self, Box<dyn Error>> {
-        let mut fields = line.split_whitespace();
-        Ok(WlanPacket {
-            time: fields.next().unwrap().parse::<f64>()?,
-            src: MacAddr::from_str(fields.next().unwrap())?,
-            dst: MacAddr::from_str(fields.next().unwrap())?,
-            data_len: fields.next().unwrap().parse::<u32>()?,
-            seq_number: fields.next().unwrap().parse::<u16>()?,
+    /// A data frame of a generated flow, without gaps in its sequence numbers
+    fn synthetic(packet: &SyntheticPacket) -> Self {
+        WlanPacket {
+            time: packet.time,
+            src: synthetic_mac(packet.flow + 1),
+            dst: synthetic_mac(0),
+            data_len: packet.len,
+            msdus: 1,
+            seq_number: (packet.seq % 4096) as u16,
+            data_rate: None,
+            tid: None,
+            channel: None,
+            interface: None,
+        }
+    }
+
+    /// Parse the printed fields, which are followed by the PHY rate if `airtime` is set, the
+    /// channel if `channels` is set, and the lengths of the A-MSDU subframes if `msdus` is set
+    fn from_tshark(
+        line: &str,
+        airtime: bool,
+        channels: bool,
+        msdus: bool,
+    ) -> Result<Self, BurstsharkError> {
+        let mut fields = Fields::new(line);
+        let time = fields.field()?.parse::<f64>()?;
+        let src = MacAddr::from_str(fields.field()?)?;
+        let dst = MacAddr::from_str(fields.field()?)?;
+        let data_len = fields.field()?.parse::<u32>()?;
+        let seq_number = fields.field()?.parse::<u16>()?;
+        // Printed in hex by newer versions of tshark, such as 0x0028, and in decimal by older
+        let type_subtype = fields.field()?;
+        let type_subtype = match type_subtype.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16)?,
+            None => type_subtype.parse::<u16>()?,
+        };
+        let tid = fields.field()?;
+        let mut packet = WlanPacket {
+            time,
+            src,
+            dst,
+            data_len,
+            msdus: 1,
+            seq_number,
+            tid: match is_qos_data(type_subtype) {
+                true => Some(tid.parse::<u8>()?),
+                false => None,
+            },
+            // Missing without a radio header, such as when captured without monitor mode
+            data_rate: match airtime {
+                true => fields
+                    .field()?
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|rate| *rate > 0.0),
+                false => None,
+            },
+            channel: match channels {
+                true => fields.field()?.parse::<u16>().ok(),
+                false => None,
+            },
+            interface: None,
+        };
+        if msdus {
+            // A length per subframe, such as 1500,1500,620, and empty for frames that are not
+            // aggregated
+            let lengths = fields.field()?;
+            if !lengths.is_empty() {
+                let lengths = lengths
+                    .split(',')
+                    .map(|length| length.parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                packet.msdus = lengths.len() as u16;
+                packet.data_len = lengths.iter().sum();
+            }
+        }
+        Ok(packet)
+    }
+}
+
+/// Whether the type and subtype of a WLAN frame, as in `wlan.fc.type_subtype`, are those of QoS
+/// data frames, which have a TID
+fn is_qos_data(type_subtype: u16) -> bool {
+    type_subtype >> 4 == 2 && type_subtype & 0x08 != 0
+}
+
+impl EthPacket {
+    /// An IPv4 frame of a generated flow
+    fn synthetic(packet: &SyntheticPacket) -> Self {
+        EthPacket {
+            time: packet.time,
+            src: synthetic_mac(packet.flow + 1),
+            dst: synthetic_mac(0),
+            ethertype: 0x0800,
+            data_len: packet.len,
+            interface: None,
+        }
+    }
+
+    fn from_tshark(line: &str) -> Result<Self, BurstsharkError> {
+        let mut fields = Fields::new(line);
+        Ok(EthPacket {
+            time: fields.field()?.parse::<f64>()?,
+            src: MacAddr::from_str(fields.field()?)?,
+            dst: MacAddr::from_str(fields.field()?)?,
+            // Printed in hex, such as 0x0806
+            ethertype: u16::from_str_radix(fields.field()?.trim_start_matches("0x"), 16)?,
+            data_len: fields.field()?.parse::<u32>()?,
+            interface: None,
         })
     }
 }
@@ -0,0 +1,566 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use pnet_datalink::{Channel, Config};
+
+use super::{
+    decode::{Frame, LinkType},
+    interrupt,
+};
+use crate::BurstsharkError;
+
+/// How often a live capture checks whether it has been interrupted.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Largest frame accepted from a pcap file, larger records are considered corrupt.
+const MAX_SNAPLEN: u32 = 262144;
+
+/// Where the native backend reads frames from
+#[derive(Clone, Debug)]
+pub enum NativeSource {
+    /// Live capture on an interface, the first non-loopback interface if None
+    Interface(Option<String>),
+    /// A classic pcap file, pcapng is not supported
+    File(String),
+}
+
+/// Converts capture timestamps to the configured time format
+struct Clock {
+    epoch_time: bool,
+    first_time: Option<Duration>,
+}
+
+impl Clock {
+    /// Convert a time since the UNIX epoch, subtracting before converting to keep the precision.
+    fn time(&mut self, time: Duration) -> f64 {
+        match self.epoch_time {
+            true => time.as_secs_f64(),
+            false => time
+                .saturating_sub(*self.first_time.get_or_insert(time))
+                .as_secs_f64(),
+        }
+    }
+}
+
+/// Read frames from the source until it is exhausted or the capture is interrupted.
+pub fn capture(
+    source: &NativeSource,
+    epoch_time: bool,
+    handle: impl FnMut(&Frame) -> Result<(), BurstsharkError>,
+) -> Result<(), BurstsharkError> {
+    // Set up interrupt handler (ctrl-c)
+    let running = interrupt::handle()?;
+
+    let clock = Clock {
+        epoch_time,
+        first_time: None,
+    };
+
+    match source {
+        NativeSource::Interface(name) => live(name.as_deref(), clock, running, handle),
+        NativeSource::File(path) => read_pcap(path, clock, running, handle),
+    }
+}
+
+fn live(
+    name: Option<&str>,
+    mut clock: Clock,
+    running: &AtomicBool,
+    mut handle: impl FnMut(&Frame) -> Result<(), BurstsharkError>,
+) -> Result<(), BurstsharkError> {
+    let interface = pnet_datalink::interfaces()
+        .into_iter()
+        .find(|interface| match name {
+            Some(name) => interface.name == name,
+            None => interface.is_up() && !interface.is_loopback(),
+        })
+        .ok_or_else(|| {
+            BurstsharkError::InvalidOptions(match name {
+                Some(name) => format!("No interface named {name}"),
+                None => String::from("No interface to capture on"),
+            })
+        })?;
+
+    let hardware_type = fs::read_to_string(format!("/sys/class/net/{}/type", interface.name))?
+        .trim()
+        .parse::<u32>()?;
+    let unsupported = |reason: String| BurstsharkError::Capture {
+        interface: interface.name.clone(),
+        reason,
+    };
+    let link = LinkType::from_arphrd(hardware_type)
+        .ok_or_else(|| unsupported(format!("unsupported hardware type {hardware_type}")))?;
+
+    let config = Config {
+        read_buffer_size: 1 << 16,
+        read_timeout: Some(READ_TIMEOUT),
+        ..Default::default()
+    };
+    let mut rx = match pnet_datalink::channel(&interface, config)
+        .map_err(|err| unsupported(err.to_string()))?
+    {
+        Channel::Ethernet(_, rx) => rx,
+        _ => return Err(unsupported("unsupported capture channel".to_string())),
+    };
+
+    while running.load(Ordering::SeqCst) {
+        match rx.next() {
+            Ok(data) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                handle(&Frame {
+                    time: clock.time(now),
+                    link,
+                    data,
+                    len: data.len() as u32,
+                })?;
+            }
+            // Timeouts and signals give a chance to check if the capture was interrupted
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::TimedOut
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_pcap(
+    path: &str,
+    clock: Clock,
+    running: &AtomicBool,
+    handle: impl FnMut(&Frame) -> Result<(), BurstsharkError>,
+) -> Result<(), BurstsharkError> {
+    let mut file = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    match magic {
+        PCAPNG_SECTION => read_pcapng(path, file, clock, running, handle),
+        _ => read_classic_pcap(path, magic, file, clock, running, handle),
+    }
+}
+
+fn read_classic_pcap(
+    path: &str,
+    magic: [u8; 4],
+    mut file: impl Read,
+    mut clock: Clock,
+    running: &AtomicBool,
+    mut handle: impl FnMut(&Frame) -> Result<(), BurstsharkError>,
+) -> Result<(), BurstsharkError> {
+    let (big_endian, nanos) = match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+        _ => return Err(corrupt(path, "not a pcap or pcapng file".to_string())),
+    };
+
+    let mut header = [0; 20];
+    file.read_exact(&mut header)?;
+
+    // The upper bits may hold the FCS length
+    let link_type = read_u32(&header, 16, big_endian) & 0x0fff_ffff;
+    let link = LinkType::from_pcap(link_type)
+        .ok_or_else(|| corrupt(path, format!("unsupported link type {link_type}")))?;
+
+    let mut record = [0; 16];
+    let mut data = Vec::new();
+    while running.load(Ordering::SeqCst) {
+        // A capture that was interrupted while writing can end with a partial record
+        if !read_or_eof(&mut file, &mut record)? {
+            break;
+        }
+
+        let captured_len = read_u32(&record, 8, big_endian);
+        if captured_len > MAX_SNAPLEN {
+            return Err(corrupt(
+                path,
+                format!("corrupt record of {captured_len} bytes"),
+            ));
+        }
+        data.resize(captured_len as usize, 0);
+        if !read_or_eof(&mut file, &mut data)? {
+            break;
+        }
+
+        let fraction = read_u32(&record, 4, big_endian);
+        let time = Duration::new(
+            read_u32(&record, 0, big_endian) as u64,
+            if nanos { fraction } else { fraction * 1000 },
+        );
+        handle(&Frame {
+            time: clock.time(time),
+            link,
+            data: &data,
+            len: read_u32(&record, 12, big_endian),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Block type of pcapng section headers, which is the same in both byte orders.
+const PCAPNG_SECTION: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+/// Interface description option with the resolution of the timestamps.
+const PCAPNG_TSRESOL: u16 = 9;
+
+/// Interface of a pcapng section, with the timestamp resolution
+struct PcapngInterface {
+    /// None for link types that can not be decoded, whose packets are skipped
+    link: Option<LinkType>,
+    units_per_second: u64,
+}
+
+fn read_pcapng(
+    path: &str,
+    mut file: impl Read,
+    mut clock: Clock,
+    running: &AtomicBool,
+    mut handle: impl FnMut(&Frame) -> Result<(), BurstsharkError>,
+) -> Result<(), BurstsharkError> {
+    let mut big_endian = false;
+    let mut interfaces = Vec::new();
+
+    // The block type of the first section header is already read
+    let mut header = [0; 8];
+    header[..4].copy_from_slice(&PCAPNG_SECTION);
+    file.read_exact(&mut header[4..])?;
+    let mut header_read = true;
+
+    let mut body = Vec::new();
+    while running.load(Ordering::SeqCst) {
+        if !header_read && !read_or_eof(&mut file, &mut header)? {
+            break;
+        }
+        header_read = false;
+
+        // Section headers set the byte order of the blocks that follow, and reset the interfaces
+        if header[..4] == PCAPNG_SECTION {
+            let mut magic = [0; 4];
+            file.read_exact(&mut magic)?;
+            big_endian = match magic {
+                [0x1a, 0x2b, 0x3c, 0x4d] => true,
+                [0x4d, 0x3c, 0x2b, 0x1a] => false,
+                _ => return Err(corrupt(path, "corrupt section header".to_string())),
+            };
+            interfaces.clear();
+        }
+
+        let block_type = read_u32(&header, 0, big_endian);
+        let block_len = read_u32(&header, 4, big_endian);
+        if !(12..=MAX_SNAPLEN + 64).contains(&block_len) {
+            return Err(corrupt(path, format!("corrupt block of {block_len} bytes")));
+        }
+
+        // The rest of the block, after the magic of section headers
+        let read = if header[..4] == PCAPNG_SECTION { 12 } else { 8 };
+        body.resize(block_len as usize - read, 0);
+        if !read_or_eof(&mut file, &mut body)? {
+            break;
+        }
+
+        match block_type {
+            PCAPNG_INTERFACE => {
+                interfaces.push(PcapngInterface {
+                    link: LinkType::from_pcap(read_u16(&body, 0, big_endian) as u32),
+                    units_per_second: tsresol(&body, big_endian),
+                });
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let interface = interfaces
+                    .get(read_u32(&body, 0, big_endian) as usize)
+                    .ok_or_else(|| {
+                        corrupt(path, "packet of an undescribed interface".to_string())
+                    })?;
+                let Some(link) = interface.link else {
+                    continue;
+                };
+
+                let timestamp = (read_u32(&body, 4, big_endian) as u64) << 32
+                    | read_u32(&body, 8, big_endian) as u64;
+                let units = interface.units_per_second;
+                let time = Duration::new(
+                    timestamp / units,
+                    ((timestamp % units) as u128 * 1_000_000_000 / units as u128) as u32,
+                );
+
+                let captured_len = read_u32(&body, 12, big_endian) as usize;
+                let data = body
+                    .get(20..20 + captured_len)
+                    .ok_or_else(|| corrupt(path, "corrupt packet block".to_string()))?;
+                handle(&Frame {
+                    time: clock.time(time),
+                    link,
+                    data,
+                    len: read_u32(&body, 16, big_endian),
+                })?;
+            }
+            // Statistics, name resolution and other blocks are of no interest
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Timestamp units per second of an interface description block, microseconds by default.
+fn tsresol(body: &[u8], big_endian: bool) -> u64 {
+    // Options follow the link type, reserved bytes and snapshot length
+    let mut offset = 8;
+    while offset + 4 <= body.len() {
+        let code = read_u16(body, offset, big_endian);
+        let len = read_u16(body, offset + 2, big_endian) as usize;
+        if code == 0 {
+            break;
+        }
+        if code == PCAPNG_TSRESOL && len >= 1 && offset + 4 < body.len() {
+            let resolution = body[offset + 4];
+            // The most significant bit tells if the exponent is of 2 or 10
+            return match resolution & 0x80 {
+                0 => 10u64.saturating_pow(resolution as u32),
+                _ => 1u64 << (resolution & 0x7f).min(63),
+            };
+        }
+        offset += 4 + len.next_multiple_of(4);
+    }
+    1_000_000
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = bytes[offset..offset + 2].try_into().unwrap();
+    match big_endian {
+        true => u16::from_be_bytes(bytes),
+        false => u16::from_le_bytes(bytes),
+    }
+}
+
+/// A capture file that can not be read as a pcap or pcapng file
+fn corrupt(path: &str, reason: String) -> BurstsharkError {
+    BurstsharkError::InvalidFile {
+        path: path.to_string(),
+        reason,
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = bytes[offset..offset + 4].try_into().unwrap();
+    match big_endian {
+        true => u32::from_be_bytes(bytes),
+        false => u32::from_le_bytes(bytes),
+    }
+}
+
+/// Fill the buffer, returning false if the end of the file is reached first.
+fn read_or_eof(file: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match file.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::{read_classic_pcap, read_pcapng, tsresol, Clock, LinkType};
+    use crate::BurstsharkError;
+
+    /// A little-endian pcap file of raw IP packets with microsecond times, without its magic
+    fn pcap(records: &[(u32, u32, &[u8], u32)]) -> Vec<u8> {
+        let mut file = vec![2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0];
+        file.extend(101u32.to_le_bytes());
+        for (secs, micros, data, len) in records {
+            for field in [*secs, *micros, data.len() as u32, *len] {
+                file.extend(field.to_le_bytes());
+            }
+            file.extend(*data);
+        }
+        file
+    }
+
+    /// The time, link type, data and length of a frame
+    type Frames = Vec<(f64, LinkType, Vec<u8>, u32)>;
+
+    fn read(magic: [u8; 4], file: &[u8], epoch_time: bool) -> Result<Frames, BurstsharkError> {
+        let clock = Clock {
+            epoch_time,
+            first_time: None,
+        };
+        let mut frames = Vec::new();
+        read_classic_pcap(
+            "test.pcap",
+            magic,
+            file,
+            clock,
+            &AtomicBool::new(true),
+            |f| {
+                frames.push((f.time, f.link, f.data.to_vec(), f.len));
+                Ok(())
+            },
+        )?;
+        Ok(frames)
+    }
+
+    const MAGIC: [u8; 4] = [0xd4, 0xc3, 0xb2, 0xa1];
+
+    #[test]
+    fn frames_are_read_with_times_since_the_first() {
+        let file = pcap(&[
+            (100, 250_000, &[0x45, 1], 60),
+            (101, 0, &[0x45, 2, 3], 1500),
+        ]);
+        let frames = read(MAGIC, &file, false).unwrap();
+        assert_eq!(
+            frames,
+            [
+                (0.0, LinkType::RawIp, vec![0x45, 1], 60),
+                (0.75, LinkType::RawIp, vec![0x45, 2, 3], 1500)
+            ]
+        );
+        assert_eq!(read(MAGIC, &file, true).unwrap()[1].0, 101.0);
+    }
+
+    #[test]
+    fn partial_records_at_the_end_are_skipped() {
+        let mut file = pcap(&[(100, 0, &[0x45, 1], 60), (101, 0, &[0x45, 2, 3], 1500)]);
+        file.pop();
+        assert_eq!(read(MAGIC, &file, false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn big_endian_files_with_nanosecond_times_are_read() {
+        let mut file = vec![0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0];
+        file.extend(1u32.to_be_bytes());
+        for field in [100u32, 500, 2, 64] {
+            file.extend(field.to_be_bytes());
+        }
+        file.extend([0xaa, 0xbb]);
+        let frames = read([0xa1, 0xb2, 0x3c, 0x4d], &file, true).unwrap();
+        assert_eq!(
+            frames,
+            [(100.0000005, LinkType::Ethernet, vec![0xaa, 0xbb], 64)]
+        );
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        let file = pcap(&[]);
+        assert!(read(*b"GIF8", &file, false).is_err());
+
+        let mut file = pcap(&[]);
+        file[16..20].copy_from_slice(&999u32.to_le_bytes());
+        assert!(read(MAGIC, &file, false).is_err());
+
+        let mut file = pcap(&[]);
+        for field in [0, 0, 1 << 20, 1 << 20] {
+            file.extend(u32::to_le_bytes(field));
+        }
+        assert!(read(MAGIC, &file, false).is_err());
+    }
+
+    /// A little-endian pcapng block with the body, padded to 32 bits
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().next_multiple_of(4);
+        let len = (12 + padded) as u32;
+        let mut block = block_type.to_le_bytes().to_vec();
+        block.extend(len.to_le_bytes());
+        block.extend(body);
+        block.resize(8 + padded, 0);
+        block.extend(len.to_le_bytes());
+        block
+    }
+
+    /// An interface description of the link type, with the resolution option if any
+    fn interface(link_type: u16, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = link_type.to_le_bytes().to_vec();
+        body.extend([0, 0, 0, 0, 4, 0]);
+        if let Some(resolution) = tsresol {
+            body.extend([9, 0, 1, 0, resolution, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        block(1, &body)
+    }
+
+    fn packet(interface: u32, timestamp: u64, data: &[u8], len: u32) -> Vec<u8> {
+        let mut body = interface.to_le_bytes().to_vec();
+        body.extend(((timestamp >> 32) as u32).to_le_bytes());
+        body.extend((timestamp as u32).to_le_bytes());
+        body.extend((data.len() as u32).to_le_bytes());
+        body.extend(len.to_le_bytes());
+        body.extend(data);
+        block(6, &body)
+    }
+
+    /// The frames of a pcapng file, whose block type of the first section is already read
+    fn read_ng(blocks: &[Vec<u8>]) -> Result<Frames, BurstsharkError> {
+        let mut file = block(0x0a0d_0d0a, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0]);
+        file.extend(blocks.concat());
+        let clock = Clock {
+            epoch_time: true,
+            first_time: None,
+        };
+        let mut frames = Vec::new();
+        read_pcapng(
+            "test.pcapng",
+            &file[4..],
+            clock,
+            &AtomicBool::new(true),
+            |f| {
+                frames.push((f.time, f.link, f.data.to_vec(), f.len));
+                Ok(())
+            },
+        )?;
+        Ok(frames)
+    }
+
+    #[test]
+    fn pcapng_packets_are_read_with_the_resolution_of_their_interface() {
+        let frames = read_ng(&[
+            interface(101, None),
+            interface(1, Some(9)),
+            packet(0, 1_500_000, &[0x45, 1, 2], 60),
+            // Statistics blocks are skipped
+            block(5, &[0; 8]),
+            packet(1, 2_250_000_000, &[0xaa], 64),
+        ])
+        .unwrap();
+        assert_eq!(
+            frames,
+            [
+                (1.5, LinkType::RawIp, vec![0x45, 1, 2], 60),
+                (2.25, LinkType::Ethernet, vec![0xaa], 64)
+            ]
+        );
+    }
+
+    #[test]
+    fn pcapng_packets_of_unsupported_links_are_skipped() {
+        let frames = read_ng(&[interface(999, None), packet(0, 1_000_000, &[1], 1)]).unwrap();
+        assert!(frames.is_empty());
+        assert!(read_ng(&[packet(0, 1_000_000, &[1], 1)]).is_err());
+    }
+
+    #[test]
+    fn timestamp_resolutions_are_powers_of_ten_or_two() {
+        let body = |resolution: u8| [0, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, 0, resolution, 0, 0, 0];
+        assert_eq!(tsresol(&body(9), false), 1_000_000_000);
+        assert_eq!(tsresol(&body(0x8a), false), 1024);
+        assert_eq!(tsresol(&[0; 8], false), 1_000_000);
+    }
+}
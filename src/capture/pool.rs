@@ -0,0 +1,90 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, hash::Hash, net::IpAddr, sync::Arc};
+
+use macaddr::MacAddr;
+
+/// Addresses kept by a pool before those no longer used by any flow or burst are dropped
+const POOL_ADDRS: usize = 1 << 16;
+
+/// Formatted addresses shared by the flows of a shard.
+///
+/// Captures of many short-lived flows mostly see the same hosts over and over, such as a client
+/// talking to a few servers from ever new ports, so the address strings of a new flow are taken
+/// from those of earlier flows rather than allocated for each.
+struct AddrPool<A> {
+    strings: HashMap<A, Arc<str>>,
+    /// Addresses to keep before dropping unused ones, raised while most are in use
+    limit: usize,
+}
+
+impl<A: Hash + Eq + Display> AddrPool<A> {
+    fn new() -> Self {
+        AddrPool {
+            strings: HashMap::new(),
+            limit: POOL_ADDRS,
+        }
+    }
+
+    fn get(&mut self, addr: A) -> Arc<str> {
+        if let Some(string) = self.strings.get(&addr) {
+            return string.clone();
+        }
+        if self.strings.len() >= self.limit {
+            self.strings
+                .retain(|_, string| Arc::strong_count(string) > 1);
+            self.limit = (self.strings.len() * 2).max(POOL_ADDRS);
+        }
+        let string: Arc<str> = Arc::from(addr.to_string());
+        self.strings.insert(addr, string.clone());
+        string
+    }
+}
+
+thread_local! {
+    // Each shard runs on a thread of its own, so its pools are never contended
+    static IP_ADDRS: RefCell<AddrPool<IpAddr>> = RefCell::new(AddrPool::new());
+    static MAC_ADDRS: RefCell<AddrPool<MacAddr>> = RefCell::new(AddrPool::new());
+}
+
+/// Addresses whose strings are shared from the pool of the shard
+pub(super) trait PooledAddr {
+    fn shared(self) -> Arc<str>;
+}
+
+impl PooledAddr for IpAddr {
+    fn shared(self) -> Arc<str> {
+        IP_ADDRS.with(|pool| pool.borrow_mut().get(self))
+    }
+}
+
+impl PooledAddr for MacAddr {
+    fn shared(self) -> Arc<str> {
+        MAC_ADDRS.with(|pool| pool.borrow_mut().get(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::Arc};
+
+    use super::{AddrPool, PooledAddr, POOL_ADDRS};
+
+    #[test]
+    fn strings_of_an_address_are_shared() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let (a, b) = (ip.shared(), ip.shared());
+        assert_eq!(&*a, "10.0.0.1");
+        assert!(Arc::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn unused_addresses_are_dropped_once_the_pool_is_full() {
+        let mut pool = AddrPool::new();
+        let kept = pool.get(0u32);
+        for addr in 1..POOL_ADDRS as u32 {
+            pool.get(addr);
+        }
+        pool.get(POOL_ADDRS as u32);
+        assert_eq!(pool.strings.len(), 2);
+        assert!(Arc::ptr_eq(&kept, &pool.get(0)));
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fmt, num::ParseIntError};
+
+use serde::{Serialize, Serializer};
+
+/// IP protocol number of the payload of packets, such as 6 for TCP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpProto(pub u8);
+
+impl IpProto {
+    /// The last protocol of a printed field, which lists those of every IP header of tunneled
+    /// packets from the outside in
+    pub(super) fn from_tshark(field: &str) -> Result<Self, ParseIntError> {
+        Ok(IpProto(field.rsplit(',').next().unwrap_or(field).parse()?))
+    }
+}
+
+/// Names the protocol as Suricata does, or by its number if it has no name here
+impl fmt::Display for IpProto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            1 => f.pad("ICMP"),
+            6 => f.pad("TCP"),
+            17 => f.pad("UDP"),
+            47 => f.pad("GRE"),
+            50 => f.pad("ESP"),
+            58 => f.pad("IPv6-ICMP"),
+            132 => f.pad("SCTP"),
+            number => f.pad(&number.to_string()),
+        }
+    }
+}
+
+impl Serialize for IpProto {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IpProto;
+
+    #[test]
+    fn the_innermost_protocol_of_tunneled_packets_is_used() {
+        assert_eq!(IpProto::from_tshark("17"), Ok(IpProto(17)));
+        assert_eq!(IpProto::from_tshark("47,6"), Ok(IpProto(6)));
+        assert!(IpProto::from_tshark("").is_err());
+    }
+
+    #[test]
+    fn protocols_are_named_as_suricata_does() {
+        assert_eq!(IpProto(58).to_string(), "IPv6-ICMP");
+        assert_eq!(format!("{:>4}", IpProto(6)), " TCP");
+        assert_eq!(IpProto(89).to_string(), "89");
+        assert_eq!(serde_json::to_string(&IpProto(17)).unwrap(), "\"UDP\"");
+    }
+}
@@ -0,0 +1,58 @@
+use super::tunnel::parse_hex;
+use crate::BurstsharkError;
+
+/// Quality of service markings of an IP packet
+#[derive(Clone, Copy, Debug)]
+pub struct IpQos {
+    pub dscp: u8,
+    /// Only IPv6 packets have a flow label
+    pub flow_label: Option<u32>,
+}
+
+impl IpQos {
+    /// Parse the DSCP of either IP version, if tshark dissected one, and the IPv6 flow label.
+    ///
+    /// Packets quoted by ICMP errors have their fields printed after those of the packet itself.
+    pub fn from_tshark(
+        dscp: Result<&str, BurstsharkError>,
+        flow_label: &str,
+    ) -> Result<Option<Self>, BurstsharkError> {
+        let Ok(dscp) = dscp else {
+            return Ok(None);
+        };
+        let first = |field: &str| field.split(',').next().unwrap_or_default().to_string();
+        let flow_label = first(flow_label);
+
+        Ok(Some(IpQos {
+            dscp: first(dscp).parse()?,
+            flow_label: match flow_label.is_empty() {
+                true => None,
+                false => Some(parse_hex(&flow_label)?),
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IpQos;
+    use crate::BurstsharkError;
+
+    #[test]
+    fn the_markings_of_the_packet_itself_are_used() {
+        let qos = IpQos::from_tshark(Ok("46,0"), "0x000abcde,0x00000000")
+            .unwrap()
+            .unwrap();
+        assert_eq!((qos.dscp, qos.flow_label), (46, Some(0xabcde)));
+
+        let ipv4 = IpQos::from_tshark(Ok("10"), "").unwrap().unwrap();
+        assert_eq!((ipv4.dscp, ipv4.flow_label), (10, None));
+    }
+
+    #[test]
+    fn packets_without_a_dscp_have_no_markings() {
+        let missing = Err(BurstsharkError::MissingField(String::new()));
+        assert!(IpQos::from_tshark(missing, "0x00001").unwrap().is_none());
+        assert!(IpQos::from_tshark(Ok("high"), "").is_err());
+    }
+}
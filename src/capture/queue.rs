@@ -0,0 +1,262 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// The flows with bursts in progress, in the order their bursts complete if no more packets of the
+/// flow arrive.
+///
+/// Each flow has a single entry with the end of its burst, which is moved as packets of the flow
+/// arrive, in a binary heap of entries found by the flow key. Memory is bounded by the flows rather
+/// than by their packets, and each packet costs a lookup of its flow and O(log n) moves in the
+/// number of flows.
+pub struct BurstQueue<K> {
+    /// Whether flows have gaps of their own, so bursts are ordered by when they complete, rather
+    /// than all flows having the same gap and bursts completing in the order of their ends
+    adaptive: bool,
+    gap: f64,
+    /// Entry of each queued flow
+    slots: HashMap<K, usize>,
+    entries: Vec<Entry<K>>,
+    /// Entries no longer in use, to be reused before growing the entries
+    free: Vec<usize>,
+    /// Entries in the order their bursts complete
+    heap: Vec<usize>,
+    /// Number of queued packets, which orders the entries of bursts with the same deadline
+    count: u64,
+}
+
+/// The end of the burst of a flow, and where the flow is in the heap
+struct Entry<K> {
+    key: K,
+    time: f64,
+    deadline: f64,
+    count: u64,
+    position: usize,
+}
+
+impl<K: Clone + Eq + Hash> BurstQueue<K> {
+    /// A queue where all flows have the gap, or their own gaps if `adaptive` is set
+    pub fn new(gap: f64, adaptive: bool) -> Self {
+        BurstQueue {
+            adaptive,
+            gap,
+            slots: HashMap::new(),
+            entries: Vec::new(),
+            free: Vec::new(),
+            heap: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Queue the burst of a flow ending at the time, with the gap of the flow when adaptive,
+    /// replacing the earlier end of the flow
+    pub fn enqueue(&mut self, key: K, time: f64, gap: f64) {
+        let count = self.count;
+        self.count += 1;
+
+        if let Some(&slot) = self.slots.get(&key) {
+            let entry = &mut self.entries[slot];
+            entry.time = time;
+            entry.deadline = time + gap;
+            entry.count = count;
+            // Deadlines of adaptive gaps can move either way, while the end otherwise only grows
+            let position = entry.position;
+            let position = match self.adaptive {
+                true => self.sift_up(position),
+                false => position,
+            };
+            self.sift_down(position);
+            return;
+        }
+
+        let entry = Entry {
+            key: key.clone(),
+            time,
+            deadline: time + gap,
+            count,
+            position: self.heap.len(),
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.entries[slot] = entry;
+                slot
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        self.slots.insert(key, slot);
+        self.heap.push(slot);
+        self.sift_up(self.heap.len() - 1);
+    }
+
+    /// Dequeue the next flow whose burst is complete at the current time, with its end
+    pub fn dequeue_complete(&mut self, current_time: f64) -> Option<(K, f64)> {
+        let next = &self.entries[*self.heap.first()?];
+        let complete = match self.adaptive {
+            true => current_time >= next.deadline,
+            false => current_time - next.time >= self.gap,
+        };
+        complete.then(|| self.pop())?
+    }
+
+    /// Dequeue the next flow in the order the bursts complete, regardless of the time
+    pub fn dequeue(&mut self) -> Option<(K, f64)> {
+        self.pop()
+    }
+
+    fn pop(&mut self) -> Option<(K, f64)> {
+        let last = self.heap.len().checked_sub(1)?;
+        self.swap(0, last);
+        let slot = self.heap.pop()?;
+        if !self.heap.is_empty() {
+            self.sift_down(0);
+        }
+
+        let entry = &self.entries[slot];
+        self.slots.remove(&entry.key);
+        self.free.push(slot);
+        Some((entry.key.clone(), entry.time))
+    }
+
+    /// Whether the entry at the position in the heap completes before the entry at the other
+    fn before(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.entries[self.heap[a]], &self.entries[self.heap[b]]);
+        match self.adaptive {
+            true => a
+                .deadline
+                .total_cmp(&b.deadline)
+                .then(a.count.cmp(&b.count)),
+            false => a.time.total_cmp(&b.time).then(a.count.cmp(&b.count)),
+        }
+        .is_lt()
+    }
+
+    fn sift_up(&mut self, mut position: usize) -> usize {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if !self.before(position, parent) {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+        position
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let mut first = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.heap.len() && self.before(child, first) {
+                    first = child;
+                }
+            }
+            if first == position {
+                break;
+            }
+            self.swap(position, first);
+            position = first;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.entries[self.heap[a]].position = a;
+        self.entries[self.heap[b]].position = b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BurstQueue;
+
+    /// Dequeue all flows, regardless of the time
+    fn drain(queue: &mut BurstQueue<&'static str>) -> Vec<(&'static str, f64)> {
+        std::iter::from_fn(|| queue.dequeue()).collect()
+    }
+
+    #[test]
+    fn flows_are_dequeued_in_the_order_their_bursts_end() {
+        let mut queue = BurstQueue::new(0.5, false);
+        queue.enqueue("a", 0.2, 0.5);
+        queue.enqueue("b", 0.1, 0.5);
+        queue.enqueue("c", 0.3, 0.5);
+        assert_eq!(drain(&mut queue), [("b", 0.1), ("a", 0.2), ("c", 0.3)]);
+    }
+
+    #[test]
+    fn adaptive_flows_are_dequeued_in_the_order_of_their_deadlines() {
+        let mut queue = BurstQueue::new(0.5, true);
+        queue.enqueue("a", 0.0, 1.0);
+        queue.enqueue("b", 0.1, 0.2);
+        queue.enqueue("c", 0.2, 0.5);
+        assert_eq!(drain(&mut queue), [("b", 0.1), ("c", 0.2), ("a", 0.0)]);
+    }
+
+    #[test]
+    fn only_complete_bursts_are_dequeued() {
+        let mut queue = BurstQueue::new(0.5, false);
+        queue.enqueue("a", 0.0, 0.5);
+        queue.enqueue("b", 0.3, 0.5);
+        assert_eq!(queue.dequeue_complete(0.4), None);
+        assert_eq!(queue.dequeue_complete(0.5), Some(("a", 0.0)));
+        assert_eq!(queue.dequeue_complete(0.5), None);
+        assert_eq!(queue.dequeue_complete(0.8), Some(("b", 0.3)));
+    }
+
+    #[test]
+    fn enqueueing_a_queued_flow_moves_its_entry() {
+        let mut queue = BurstQueue::new(0.5, false);
+        queue.enqueue("a", 0.0, 0.5);
+        queue.enqueue("b", 0.1, 0.5);
+        queue.enqueue("a", 0.2, 0.5);
+        assert_eq!(queue.heap.len(), 2);
+        assert_eq!(queue.dequeue_complete(0.6), Some(("b", 0.1)));
+        assert_eq!(queue.dequeue_complete(0.6), None);
+        assert_eq!(drain(&mut queue), [("a", 0.2)]);
+    }
+
+    #[test]
+    fn adaptive_deadlines_move_either_way() {
+        let mut queue = BurstQueue::new(0.5, true);
+        queue.enqueue("a", 0.0, 1.0);
+        queue.enqueue("b", 0.0, 0.5);
+        // The gap of a shrinks, so its burst now completes first
+        queue.enqueue("a", 0.1, 0.1);
+        assert_eq!(queue.dequeue_complete(0.2), Some(("a", 0.1)));
+        // And that of b grows, so it completes later
+        queue.enqueue("b", 0.1, 2.0);
+        assert_eq!(queue.dequeue_complete(1.0), None);
+        assert_eq!(queue.dequeue_complete(2.1), Some(("b", 0.1)));
+    }
+
+    #[test]
+    fn entries_of_dequeued_flows_are_reused() {
+        let mut queue = BurstQueue::new(0.5, false);
+        queue.enqueue("a", 0.0, 0.5);
+        queue.enqueue("b", 0.1, 0.5);
+        assert_eq!(queue.dequeue(), Some(("a", 0.0)));
+        queue.enqueue("c", 0.2, 0.5);
+        assert_eq!(queue.entries.len(), 2);
+        assert!(queue.free.is_empty());
+        assert_eq!(drain(&mut queue), [("b", 0.1), ("c", 0.2)]);
+        assert_eq!(queue.free.len(), 2);
+        assert!(queue.slots.is_empty());
+    }
+
+    #[test]
+    fn bursts_ending_at_the_same_time_are_dequeued_in_the_order_they_were_queued() {
+        for adaptive in [false, true] {
+            let mut queue = BurstQueue::new(0.5, adaptive);
+            for key in ["c", "a", "d", "b"] {
+                queue.enqueue(key, 1.0, 0.5);
+            }
+            // Requeueing moves a flow after those queued before
+            queue.enqueue("c", 1.0, 0.5);
+            assert_eq!(
+                drain(&mut queue),
+                [("a", 1.0), ("d", 1.0), ("b", 1.0), ("c", 1.0)]
+            );
+        }
+    }
+}
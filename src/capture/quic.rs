@@ -0,0 +1,169 @@
+use std::{collections::HashMap, fmt};
+
+use serde::{Serialize, Serializer};
+
+use crate::BurstsharkError;
+
+/// Longest connection id of QUIC version 1
+const MAX_CID_LEN: usize = 20;
+
+/// A QUIC connection id, chosen by the endpoint that receives the packets carrying it
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId {
+    len: u8,
+    bytes: [u8; MAX_CID_LEN],
+}
+
+impl ConnectionId {
+    /// Parse a connection id printed in hex, with or without colons between the bytes
+    fn from_hex(field: &str) -> Result<Self, BurstsharkError> {
+        let hex: Vec<u8> = field.bytes().filter(|&b| b != b':').collect();
+        if !hex.len().is_multiple_of(2) || hex.len() / 2 > MAX_CID_LEN {
+            return Err(BurstsharkError::InvalidField {
+                name: "QUIC connection id",
+                value: field.to_string(),
+            });
+        }
+
+        let mut id = ConnectionId {
+            len: (hex.len() / 2) as u8,
+            bytes: [0; MAX_CID_LEN],
+        };
+        for (byte, pair) in id.bytes.iter_mut().zip(hex.chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair)?, 16)?;
+        }
+        Ok(id)
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.bytes[..self.len as usize] {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Serialize for ConnectionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Connection ids of a QUIC packet. Only the long header packets of the handshake carry the id
+/// chosen by the sender.
+#[derive(Clone, Copy, Debug)]
+pub struct QuicIds {
+    pub dcid: ConnectionId,
+    pub scid: Option<ConnectionId>,
+}
+
+impl QuicIds {
+    /// The ids from the quic.dcid and quic.scid fields, of the first of any coalesced packets
+    pub fn from_tshark(dcid: &str, scid: &str) -> Result<Option<Self>, BurstsharkError> {
+        let first = |field: &str| field.split(',').next().unwrap_or_default().to_string();
+        let (dcid, scid) = (first(dcid), first(scid));
+        if dcid.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(QuicIds {
+            dcid: ConnectionId::from_hex(&dcid)?,
+            scid: match scid.is_empty() {
+                true => None,
+                false => Some(ConnectionId::from_hex(&scid)?),
+            },
+        }))
+    }
+}
+
+/// Keys the flows of QUIC packets on the connection ids they are sent to, so a flow continues when
+/// the client migrates to another port or address.
+///
+/// The client first sends to a random id, and then to the one the server chooses in its reply. The
+/// ids of the handshake packets link both to the same flow.
+pub struct QuicFlows<K> {
+    /// The flow of the packets sent to each id
+    flows: HashMap<ConnectionId, K>,
+    /// The flow of the packets sent by the endpoint that chose each id
+    senders: HashMap<ConnectionId, K>,
+}
+
+impl<K: Copy> QuicFlows<K> {
+    pub fn new() -> Self {
+        QuicFlows {
+            flows: HashMap::new(),
+            senders: HashMap::new(),
+        }
+    }
+
+    /// The key of the flow of a packet with these ids, which is `key` if it starts a new flow
+    pub fn key(&mut self, key: K, ids: QuicIds) -> K {
+        let key = *self.flows.entry(ids.dcid).or_insert(key);
+
+        if let Some(scid) = ids.scid {
+            self.senders.insert(scid, key);
+            // The receiver of the packet will send its flow to the id chosen by the sender
+            if let Some(&reply) = self.senders.get(&ids.dcid) {
+                self.flows.entry(scid).or_insert(reply);
+            }
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectionId, QuicFlows, QuicIds};
+
+    fn cid(hex: &str) -> ConnectionId {
+        ConnectionId::from_hex(hex).unwrap()
+    }
+
+    fn ids(dcid: &str, scid: Option<&str>) -> QuicIds {
+        QuicIds {
+            dcid: cid(dcid),
+            scid: scid.map(cid),
+        }
+    }
+
+    #[test]
+    fn connection_ids_are_parsed_with_or_without_colons() {
+        assert_eq!(cid("01:02:ab"), cid("0102ab"));
+        assert_eq!(cid("01:02:ab").to_string(), "0102ab");
+        assert_eq!(cid("").to_string(), "");
+        assert!(ConnectionId::from_hex("012").is_err());
+        assert!(ConnectionId::from_hex("zz").is_err());
+        assert!(ConnectionId::from_hex(&"ab".repeat(21)).is_err());
+    }
+
+    #[test]
+    fn ids_are_those_of_the_first_coalesced_packet() {
+        let ids = QuicIds::from_tshark("aa01,aa02", "bb01,bb02")
+            .unwrap()
+            .unwrap();
+        assert_eq!((ids.dcid, ids.scid), (cid("aa01"), Some(cid("bb01"))));
+        let short = QuicIds::from_tshark("aa01", "").unwrap().unwrap();
+        assert_eq!(short.scid, None);
+        assert!(QuicIds::from_tshark("", "").unwrap().is_none());
+    }
+
+    #[test]
+    fn migrated_clients_keep_their_flow() {
+        let mut flows = QuicFlows::new();
+        // The client sends its initial packet to a random id, and the server replies from its own
+        assert_eq!(flows.key("client", ids("a0a0", Some("c1c1"))), "client");
+        assert_eq!(flows.key("server", ids("c1c1", Some("5e5e"))), "server");
+        // After migrating, the client sends to the id the server chose from another port
+        assert_eq!(flows.key("migrated", ids("5e5e", None)), "client");
+        assert_eq!(flows.key("reply", ids("c1c1", None)), "server");
+        assert_eq!(flows.key("other", ids("0f0f", None)), "other");
+    }
+}
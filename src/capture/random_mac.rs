@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use macaddr::MacAddr;
+
+use super::WlanPacket;
+
+/// Frames of a new address continue the sequence numbers of an earlier address if they are at most
+/// this far ahead of them, leaving room for frames missed by the capture
+const MAX_SEQUENCE_STEP: u16 = 64;
+
+/// Seconds an address may have been silent for another address to continue its sequence numbers
+const MAX_HANDOVER: f64 = 10.0;
+
+/// Sequence numbers wrap around after 4096 frames
+const SEQUENCE_MASK: u16 = 0x0fff;
+
+/// The last frame of an address and TID
+struct Station {
+    device: u32,
+    dst: MacAddr,
+    last_time: f64,
+    last_seq: u16,
+}
+
+/// Groups the randomized addresses of stations into devices, with a synthetic id per device.
+///
+/// Devices that rotate their randomized MAC address often keep counting sequence numbers where the
+/// previous address stopped. The first frame of a new randomized address continues the address
+/// that sent to the same destination with the same TID, went silent shortly before, and whose last
+/// sequence number is closest behind that of the frame. Each address is continued at most once,
+/// and addresses that are not randomized are devices of their own.
+pub(super) struct MacCorrelator {
+    /// Device of each address seen
+    devices: HashMap<MacAddr, u32>,
+    /// Addresses and TIDs that sent frames within the handover time
+    stations: HashMap<(MacAddr, Option<u8>), Station>,
+    next_device: u32,
+}
+
+impl MacCorrelator {
+    pub(super) fn new() -> Self {
+        MacCorrelator {
+            devices: HashMap::new(),
+            stations: HashMap::new(),
+            next_device: 1,
+        }
+    }
+
+    /// The device sending the frame
+    pub(super) fn device(&mut self, packet: &WlanPacket) -> u32 {
+        let key = (packet.src, packet.tid);
+        if let Some(station) = self.stations.get_mut(&key) {
+            station.dst = packet.dst;
+            station.last_time = packet.time;
+            station.last_seq = packet.seq_number;
+            return station.device;
+        }
+
+        let device = match self.devices.get(&packet.src) {
+            Some(&device) => device,
+            None => {
+                let device = match is_randomized(packet.src) {
+                    true => self.continued(packet),
+                    false => None,
+                }
+                .unwrap_or_else(|| {
+                    self.next_device += 1;
+                    self.next_device - 1
+                });
+                self.devices.insert(packet.src, device);
+                device
+            }
+        };
+        self.stations.insert(
+            key,
+            Station {
+                device,
+                dst: packet.dst,
+                last_time: packet.time,
+                last_seq: packet.seq_number,
+            },
+        );
+        device
+    }
+
+    /// The device of the randomized address whose sequence numbers the frame of a new address
+    /// continues, if any, which is then no longer continued by other addresses
+    fn continued(&mut self, packet: &WlanPacket) -> Option<u32> {
+        self.stations
+            .retain(|_, station| packet.time - station.last_time <= MAX_HANDOVER);
+
+        let (&key, _) = self
+            .stations
+            .iter()
+            .filter(|((src, tid), station)| {
+                is_randomized(*src)
+                    && *tid == packet.tid
+                    && station.dst == packet.dst
+                    && station.last_time <= packet.time
+            })
+            .filter_map(|(key, station)| {
+                let step = packet.seq_number.wrapping_sub(station.last_seq) & SEQUENCE_MASK;
+                (1..=MAX_SEQUENCE_STEP)
+                    .contains(&step)
+                    .then_some((key, (step, -station.last_time)))
+            })
+            .min_by(|(_, a), (_, b)| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))?;
+        self.stations.remove(&key).map(|station| station.device)
+    }
+}
+
+/// Whether the address is locally administered, as randomized addresses are, and not a group
+fn is_randomized(addr: MacAddr) -> bool {
+    match addr {
+        MacAddr::V6(addr) => addr.is_local() && addr.is_unicast(),
+        MacAddr::V8(addr) => addr.is_local() && addr.is_unicast(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MacCorrelator;
+    use crate::capture::WlanPacket;
+
+    /// A QoS data frame of TID 0 from the address to an access point
+    fn frame(time: f64, src: &str, seq_number: u16) -> WlanPacket {
+        WlanPacket {
+            time,
+            src: src.parse().unwrap(),
+            dst: "00:11:22:33:44:55".parse().unwrap(),
+            data_len: 100,
+            msdus: 1,
+            seq_number,
+            data_rate: None,
+            tid: Some(0),
+            channel: None,
+            interface: None,
+        }
+    }
+
+    #[test]
+    fn new_addresses_continue_the_sequence_numbers_of_their_device() {
+        let mut macs = MacCorrelator::new();
+        assert_eq!(macs.device(&frame(1.0, "02:00:00:00:00:01", 100)), 1);
+        assert_eq!(macs.device(&frame(1.1, "02:00:00:00:00:01", 101)), 1);
+        assert_eq!(macs.device(&frame(2.0, "06:00:00:00:00:02", 110)), 1);
+        // Each address is only continued once
+        assert_eq!(macs.device(&frame(2.5, "0a:00:00:00:00:03", 105)), 2);
+        assert_eq!(macs.device(&frame(3.0, "02:00:00:00:00:01", 102)), 1);
+    }
+
+    #[test]
+    fn the_closest_sequence_number_behind_is_continued() {
+        let mut macs = MacCorrelator::new();
+        assert_eq!(macs.device(&frame(1.0, "02:00:00:00:00:01", 100)), 1);
+        assert_eq!(macs.device(&frame(1.0, "02:00:00:00:00:02", 50)), 2);
+        assert_eq!(macs.device(&frame(2.0, "02:00:00:00:00:03", 110)), 1);
+        // Sequence numbers wrap around
+        assert_eq!(macs.device(&frame(3.0, "02:00:00:00:00:04", 4090)), 3);
+        assert_eq!(macs.device(&frame(4.0, "02:00:00:00:00:05", 5)), 3);
+    }
+
+    #[test]
+    fn addresses_are_not_continued_too_far_ahead_or_too_late() {
+        let mut macs = MacCorrelator::new();
+        assert_eq!(macs.device(&frame(1.0, "02:00:00:00:00:01", 100)), 1);
+        assert_eq!(macs.device(&frame(2.0, "02:00:00:00:00:02", 200)), 2);
+        assert_eq!(macs.device(&frame(20.0, "02:00:00:00:00:03", 201)), 3);
+    }
+
+    #[test]
+    fn global_addresses_are_devices_of_their_own() {
+        let mut macs = MacCorrelator::new();
+        assert_eq!(macs.device(&frame(1.0, "02:00:00:00:00:01", 100)), 1);
+        assert_eq!(macs.device(&frame(2.0, "00:1b:63:00:00:01", 101)), 2);
+        assert_eq!(macs.device(&frame(3.0, "00:1b:63:00:00:02", 200)), 3);
+        assert_eq!(macs.device(&frame(4.0, "02:00:00:00:00:03", 201)), 4);
+    }
+}
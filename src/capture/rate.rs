@@ -0,0 +1,133 @@
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+use serde::Serialize;
+
+/// Bytes and bursts per second of a flow during an interval
+#[derive(Debug, Clone, Serialize)]
+pub struct RateSample {
+    /// End of the interval
+    pub time: f64,
+    pub interval: f64,
+    pub src: Arc<str>,
+    pub dst: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flow_id: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tid: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssrc: Option<u32>,
+    pub bytes_per_sec: f64,
+    /// Bursts started during the interval, per second
+    pub bursts_per_sec: f64,
+}
+
+/// Samples the throughput of each flow once per interval, from the growth of its totals.
+///
+/// Intervals are aligned to multiples of their length, so the samples of flows handled by
+/// different workers cover the same intervals. Intervals without packets are skipped.
+pub struct RateSampler<K> {
+    interval: f64,
+    interval_end: Option<f64>,
+    /// Bytes and bursts of each flow at the end of the last interval
+    last: HashMap<K, (u64, u32)>,
+}
+
+impl<K: Clone + Eq + Hash> RateSampler<K> {
+    pub fn new(interval: f64) -> Self {
+        RateSampler {
+            interval,
+            interval_end: None,
+            last: HashMap::new(),
+        }
+    }
+
+    /// The end of the interval and its length, if it has ended by the current time
+    pub fn due(&mut self, time: f64) -> Option<(f64, f64)> {
+        let interval_end = *self
+            .interval_end
+            .get_or_insert(((time / self.interval).floor() + 1.0) * self.interval);
+        if time < interval_end {
+            return None;
+        }
+
+        // Skip intervals without any packets
+        let skipped = ((time - interval_end) / self.interval).floor();
+        self.interval_end = Some(interval_end + (skipped + 1.0) * self.interval);
+        Some((interval_end, self.interval))
+    }
+
+    /// The end and length of the part of the interval in progress until the capture ended
+    pub fn flush(&mut self, time: f64) -> Option<(f64, f64)> {
+        let interval_end = self.interval_end.take()?;
+        let end = time.min(interval_end);
+        // Rounded to nanoseconds, the precision of capture times
+        let elapsed = ((end - (interval_end - self.interval)) * 1e9).round() / 1e9;
+        (elapsed > 0.0).then_some((end, elapsed))
+    }
+
+    /// Bytes and bursts per second of a flow over an interval of `elapsed` seconds, from its
+    /// totals at the end of it, or None if the flow was idle
+    pub fn rates(&mut self, key: &K, bytes: u64, bursts: u32, elapsed: f64) -> Option<(f64, f64)> {
+        // Flows evicted and seen again since start over from zero
+        let (last_bytes, last_bursts) = match self.last.insert(key.clone(), (bytes, bursts)) {
+            Some((last_bytes, last_bursts)) if bytes >= last_bytes && bursts >= last_bursts => {
+                (last_bytes, last_bursts)
+            }
+            _ => (0, 0),
+        };
+        (bytes > last_bytes || bursts > last_bursts).then(|| {
+            (
+                (bytes - last_bytes) as f64 / elapsed,
+                (bursts - last_bursts) as f64 / elapsed,
+            )
+        })
+    }
+
+    /// Forget the totals of flows that are gone
+    pub fn retain(&mut self, mut keep: impl FnMut(&K) -> bool) {
+        self.last.retain(|key, _| keep(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateSampler;
+
+    #[test]
+    fn intervals_are_aligned_to_their_length() {
+        let mut sampler = RateSampler::<u32>::new(10.0);
+        assert_eq!(sampler.due(13.0), None);
+        assert_eq!(sampler.due(19.9), None);
+        assert_eq!(sampler.due(20.0), Some((20.0, 10.0)));
+        // Intervals without packets are skipped
+        assert_eq!(sampler.due(55.0), Some((30.0, 10.0)));
+        assert_eq!(sampler.due(59.0), None);
+        assert_eq!(sampler.due(60.0), Some((60.0, 10.0)));
+    }
+
+    #[test]
+    fn the_interval_in_progress_is_flushed_to_the_end_of_the_capture() {
+        let mut sampler = RateSampler::<u32>::new(10.0);
+        assert_eq!(sampler.flush(5.0), None);
+        sampler.due(13.0);
+        assert_eq!(sampler.flush(14.5), Some((14.5, 4.5)));
+        assert_eq!(sampler.flush(15.0), None);
+    }
+
+    #[test]
+    fn rates_are_the_growth_of_the_totals() {
+        let mut sampler = RateSampler::new(10.0);
+        assert_eq!(sampler.rates(&1, 5000, 2, 10.0), Some((500.0, 0.2)));
+        assert_eq!(sampler.rates(&1, 8000, 2, 10.0), Some((300.0, 0.0)));
+        assert_eq!(sampler.rates(&1, 8000, 2, 10.0), None);
+        // Evicted and seen again, with smaller totals
+        assert_eq!(sampler.rates(&1, 1000, 1, 5.0), Some((200.0, 0.2)));
+
+        sampler.retain(|key| *key != 1);
+        assert_eq!(sampler.rates(&1, 1000, 1, 10.0), Some((100.0, 0.1)));
+    }
+}
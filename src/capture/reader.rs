@@ -0,0 +1,159 @@
+use std::{future, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    time::{self, Interval, MissedTickBehavior},
+};
+
+use super::shard::{ShardedPacket, Workers};
+use crate::BurstsharkError;
+
+/// Read the lines printed by tshark as they arrive, handing each to `handle`, and tick the workers
+/// every `tick` while waiting for them, returning None once the lines end.
+///
+/// Ticks let the workers of a live capture complete bursts as soon as their flows have been
+/// inactive for long enough, rather than only when packets arrive or their own timeouts expire.
+/// At each tick, reading stops if `restart` returns what to restart the capture with.
+pub(super) async fn read_lines<P: ShardedPacket, R>(
+    output: impl AsyncRead + Unpin,
+    workers: &mut Workers<P>,
+    tick: Option<Duration>,
+    mut handle: impl FnMut(&mut Workers<P>, &str) -> Result<(), BurstsharkError>,
+    mut restart: impl FnMut() -> Option<R>,
+) -> Result<Option<R>, BurstsharkError> {
+    let mut lines = BufReader::new(output).lines();
+    let mut ticks = tick.map(|period| {
+        let mut ticks = time::interval(period);
+        // Ticks are only needed while no lines arrive, so those missed reading lines are dropped
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks
+    });
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) => handle(workers, &line)?,
+                None => return Ok(None),
+            },
+            _ = next_tick(&mut ticks) => {
+                workers.tick_now()?;
+                if let Some(restart) = restart() {
+                    return Ok(Some(restart));
+                }
+            }
+        }
+    }
+}
+
+/// Wait for the next tick, or forever without ticks
+async fn next_tick(ticks: &mut Option<Interval>) {
+    match ticks {
+        Some(ticks) => {
+            ticks.tick().await;
+        }
+        None => future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{io::AsyncWriteExt, runtime::Runtime};
+
+    use super::read_lines;
+    use crate::{
+        capture::{
+            shard::{Input, ShardedPacket, Workers},
+            CaptureBackend, CommonOptions,
+        },
+        channel::{self, Receiver},
+    };
+
+    /// A packet at a time, all of the same hosts
+    struct Packet(f64);
+
+    impl ShardedPacket for Packet {
+        fn time(&self) -> f64 {
+            self.0
+        }
+
+        fn shard_hash(&self) -> u64 {
+            0
+        }
+
+        fn set_interface(&mut self, _interface: u16) {}
+    }
+
+    fn workers() -> (Workers<Packet>, Receiver<Input<Packet>>) {
+        let opts = CommonOptions::new(CaptureBackend::Stdin, channel::unbounded().0);
+        let (tx, rx) = channel::unbounded();
+        let workers = Workers::start(&opts, |_, _| Ok(tx.clone())).unwrap();
+        (workers, rx)
+    }
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn lines_are_handled_until_they_end() {
+        let (mut workers, rx) = workers();
+        let mut lines = Vec::new();
+        let restart = runtime()
+            .block_on(read_lines(
+                &b"1.5\n2.5\n"[..],
+                &mut workers,
+                Some(Duration::from_secs(60)),
+                |workers, line| {
+                    lines.push(line.to_string());
+                    workers.send(Packet(line.parse().unwrap()))?;
+                    Ok(())
+                },
+                || Some(()),
+            ))
+            .unwrap();
+        assert!(restart.is_none());
+        assert_eq!(lines, ["1.5", "2.5"]);
+        assert!(matches!(rx.try_recv(), Ok(Input::Packet(Packet(1.5)))));
+        assert!(matches!(rx.try_recv(), Ok(Input::Packet(Packet(2.5)))));
+    }
+
+    #[test]
+    fn workers_are_ticked_while_waiting_for_lines() {
+        let (mut workers, rx) = workers();
+        let runtime = runtime();
+        let (mut writer, output) = tokio::io::duplex(64);
+        runtime.block_on(writer.write_all(b"1.5\n")).unwrap();
+
+        let mut ticks = 0;
+        let restart = runtime
+            .block_on(read_lines(
+                output,
+                &mut workers,
+                Some(Duration::from_millis(1)),
+                |workers, line| {
+                    workers.send(Packet(line.parse().unwrap()))?;
+                    Ok(())
+                },
+                || {
+                    ticks += 1;
+                    (ticks == 3).then_some("restart")
+                },
+            ))
+            .unwrap();
+        // Reading stops at the tick asked to restart, with the lines still open
+        assert_eq!(restart, Some("restart"));
+        let inputs: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        // Nothing is sent at ticks before the first packet, and the time of the capture after it
+        assert!(matches!(inputs[0], Input::Packet(Packet(1.5))));
+        assert!((2..=4).contains(&inputs.len()));
+        assert!(inputs[1..]
+            .iter()
+            .all(|input| matches!(input, Input::Tick(time) if *time >= 1.5)));
+    }
+}
@@ -0,0 +1,130 @@
+use std::process::Command;
+
+/// A host to run tshark on over SSH, as with `ssh host tshark ...`
+#[derive(Clone, Debug)]
+pub struct RemoteHost {
+    /// Destination as given to ssh, such as `user@router`
+    pub destination: String,
+    pub port: Option<u16>,
+    /// Private key to authenticate with, instead of those of the agent or the SSH config
+    pub identity: Option<String>,
+    /// Options passed to ssh with `-o`, such as `StrictHostKeyChecking=accept-new`
+    pub options: Vec<String>,
+    /// Path of tshark on the remote host
+    pub tshark: String,
+}
+
+impl RemoteHost {
+    pub fn new(destination: String) -> Self {
+        RemoteHost {
+            destination,
+            port: None,
+            identity: None,
+            options: Vec::new(),
+            tshark: "tshark".to_string(),
+        }
+    }
+
+    /// The ssh command running tshark with the arguments on the host.
+    ///
+    /// Remote tshark is stopped when the standard input of ssh is closed, such as when ssh exits
+    /// on an interrupt or the connection drops, so it never outlives the capture. It is run in the
+    /// background for this, where shells ignore SIGINT, and is stopped with SIGTERM instead.
+    pub(super) fn command(&self, args: &[String]) -> Command {
+        let tshark = std::iter::once(&self.tshark)
+            .chain(args)
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        // Commands in the background get /dev/null as input, so the input is kept as descriptor 3
+        let script = format!(
+            "exec 3<&0; {tshark} 3<&- & pid=$!; (cat <&3 >/dev/null; kill $pid) >/dev/null 2>&1 & \
+             exec 3<&-; wait $pid"
+        );
+
+        let mut command = Command::new("ssh");
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity) = &self.identity {
+            command.arg("-i").arg(identity);
+        }
+        for option in &self.options {
+            command.arg("-o").arg(option);
+        }
+        // Without a terminal, so the output is passed on unchanged
+        command
+            .arg("-T")
+            .arg("--")
+            .arg(&self.destination)
+            .arg(script);
+        command
+    }
+}
+
+/// Quote an argument for a POSIX shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::{shell_quote, RemoteHost};
+
+    fn args(host: &RemoteHost, tshark: &[&str]) -> Vec<String> {
+        let tshark: Vec<String> = tshark.iter().map(|arg| arg.to_string()).collect();
+        let command = host.command(&tshark);
+        assert_eq!(command.get_program(), OsStr::new("ssh"));
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn arguments_are_quoted_for_the_shell() {
+        assert_eq!(shell_quote("eth0"), "'eth0'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$(reboot)"), "'$(reboot)'");
+    }
+
+    #[test]
+    fn tshark_is_run_on_the_destination() {
+        let args = args(
+            &RemoteHost::new("user@router".to_string()),
+            &["-i", "eth 0"],
+        );
+        assert_eq!(args[..3], ["-T", "--", "user@router"]);
+        assert_eq!(args.len(), 4);
+        assert!(args[3].contains("'tshark' '-i' 'eth 0' 3<&- &"));
+    }
+
+    #[test]
+    fn ssh_options_come_before_the_destination() {
+        let host = RemoteHost {
+            port: Some(2222),
+            identity: Some("/keys/router".to_string()),
+            options: vec!["StrictHostKeyChecking=accept-new".to_string()],
+            tshark: "/usr/local/bin/tshark".to_string(),
+            ..RemoteHost::new("router".to_string())
+        };
+        let args = args(&host, &[]);
+        assert_eq!(
+            args[..9],
+            [
+                "-p",
+                "2222",
+                "-i",
+                "/keys/router",
+                "-o",
+                "StrictHostKeyChecking=accept-new",
+                "-T",
+                "--",
+                "router"
+            ]
+        );
+        assert!(args[9].contains("'/usr/local/bin/tshark' 3<&- &"));
+    }
+}
@@ -0,0 +1,61 @@
+/// Tracks the sequence numbers of the TCP segments of one direction of a flow, to tell the bytes
+/// that are new from those sent before.
+///
+/// Bytes up to the end of the furthest segment so far count as sent, so retransmissions and
+/// duplicates only add what lies beyond it. Sequence numbers wrap around, and are compared within
+/// half of their range as TCP does.
+#[derive(Default, Clone, Copy)]
+pub(super) struct SeqTracker {
+    /// Sequence number after the furthest segment so far
+    next: Option<u32>,
+}
+
+impl SeqTracker {
+    /// Add a segment, returning how many of its bytes are new
+    pub(super) fn add(&mut self, seq: u32, len: u32) -> u32 {
+        let end = seq.wrapping_add(len);
+        let Some(next) = self.next else {
+            self.next = Some(end);
+            return len;
+        };
+
+        let beyond = end.wrapping_sub(next) as i32;
+        if beyond <= 0 {
+            return 0;
+        }
+        self.next = Some(end);
+        len.min(beyond as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeqTracker;
+
+    #[test]
+    fn only_bytes_beyond_the_furthest_segment_are_new() {
+        let mut seqs = SeqTracker::default();
+        assert_eq!(seqs.add(1000, 100), 100);
+        assert_eq!(seqs.add(1100, 100), 100);
+        // A retransmission, and one overlapping the end of what was sent
+        assert_eq!(seqs.add(1000, 100), 0);
+        assert_eq!(seqs.add(1150, 100), 50);
+    }
+
+    #[test]
+    fn segments_after_a_gap_are_new() {
+        let mut seqs = SeqTracker::default();
+        seqs.add(1000, 100);
+        assert_eq!(seqs.add(1500, 100), 100);
+        // The lost segment arriving late is taken as sent before
+        assert_eq!(seqs.add(1100, 100), 0);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let mut seqs = SeqTracker::default();
+        assert_eq!(seqs.add(u32::MAX - 49, 100), 100);
+        assert_eq!(seqs.add(50, 100), 100);
+        assert_eq!(seqs.add(u32::MAX - 49, 100), 0);
+    }
+}
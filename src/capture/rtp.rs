@@ -0,0 +1,58 @@
+use crate::BurstsharkError;
+
+/// The RTP header of a packet, which starts a stream per synchronization source (SSRC)
+#[derive(Clone, Copy, Debug)]
+pub struct RtpHeader {
+    pub ssrc: u32,
+    pub payload_type: u8,
+    pub seq: u16,
+}
+
+impl RtpHeader {
+    /// The header from the rtp.ssrc, rtp.p_type and rtp.seq fields, which are empty for packets
+    /// that are not RTP, such as RTCP packets multiplexed on the same ports
+    pub fn from_tshark(
+        ssrc: &str,
+        payload_type: &str,
+        seq: &str,
+    ) -> Result<Option<Self>, BurstsharkError> {
+        if ssrc.is_empty() {
+            return Ok(None);
+        }
+
+        // Printed in hex, such as 0x1234abcd
+        let ssrc = u32::from_str_radix(ssrc.trim_start_matches("0x"), 16)?;
+        Ok(Some(RtpHeader {
+            ssrc,
+            payload_type: payload_type.parse::<u8>()?,
+            seq: seq.parse::<u16>()?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RtpHeader;
+
+    #[test]
+    fn headers_are_parsed_with_the_ssrc_in_hex() {
+        let header = RtpHeader::from_tshark("0x1234abcd", "96", "65535")
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.ssrc, 0x1234abcd);
+        assert_eq!(header.payload_type, 96);
+        assert_eq!(header.seq, 65535);
+    }
+
+    #[test]
+    fn packets_without_rtp_have_no_header() {
+        assert!(RtpHeader::from_tshark("", "", "").unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_fields_are_refused() {
+        assert!(RtpHeader::from_tshark("0xnothex", "96", "1").is_err());
+        assert!(RtpHeader::from_tshark("0x1", "256", "1").is_err());
+        assert!(RtpHeader::from_tshark("0x1", "96", "65536").is_err());
+    }
+}
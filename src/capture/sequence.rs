@@ -0,0 +1,189 @@
+/// Sequence numbers of WLAN frames are 12 bits, and wrap around after this
+const SEQ_MODULO: i32 = 4096;
+/// Sequence numbers of RTP packets are 16 bits
+const RTP_SEQ_MODULO: i32 = 65536;
+
+/// What to do with frames whose sequence numbers are too far from the expected one to be
+/// retransmissions or to follow missed frames
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutlierPolicy {
+    /// Skip the frame, and expect the sequence number after the expected one, as if the frame took
+    /// its place
+    #[default]
+    Skip,
+    /// Skip the frame, and keep expecting the same sequence number
+    Ignore,
+    /// Count the frame, and continue from its sequence number
+    Resync,
+}
+
+/// How far sequence numbers of WLAN frames may be from the expected one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SequenceWindow {
+    /// Most sequence numbers a frame can be behind the expected one to be taken as a retransmission
+    pub reorder_tolerance: u16,
+    /// Most frames that can have been missed before a frame, to count them
+    pub max_missed: u16,
+    pub outliers: OutlierPolicy,
+}
+
+impl SequenceWindow {
+    /// A window of `max_deviation` sequence numbers on either side, excluded
+    pub fn symmetric(max_deviation: u16) -> Self {
+        SequenceWindow {
+            reorder_tolerance: max_deviation.saturating_sub(1),
+            max_missed: max_deviation.saturating_sub(1),
+            outliers: OutlierPolicy::default(),
+        }
+    }
+}
+
+impl Default for SequenceWindow {
+    fn default() -> Self {
+        SequenceWindow::symmetric(50)
+    }
+}
+
+/// How a frame relates to the frames of the flow before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Sequence {
+    /// A frame that was not seen before, after `missed` frames that were not captured
+    New { missed: u16 },
+    /// A frame that was already seen, probably being retransmitted
+    Repeated,
+    /// A frame too far from the expected sequence number, which is not counted
+    Outlier,
+}
+
+/// Tracks the sequence numbers of the frames of a WLAN flow, to tell new frames from retransmitted
+/// ones and count the frames the monitor mode device missed. Also tracks the packets of RTP
+/// streams, whose sequence numbers are longer.
+///
+/// The first frame is not enough to tell retransmissions, as its original may have been missed,
+/// so frames are compared with the sequence number expected next, in both directions around it.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct SequenceTracker {
+    expected: u16,
+    window: SequenceWindow,
+    modulo: i32,
+}
+
+impl SequenceTracker {
+    /// Start tracking after a frame that is already counted
+    pub(super) fn new(seq_number: u16, window: SequenceWindow) -> Self {
+        SequenceTracker {
+            expected: next(seq_number, SEQ_MODULO),
+            window,
+            modulo: SEQ_MODULO,
+        }
+    }
+
+    /// Start tracking after an RTP packet that is already counted
+    pub(super) fn rtp(seq_number: u16, window: SequenceWindow) -> Self {
+        SequenceTracker {
+            expected: next(seq_number, RTP_SEQ_MODULO),
+            window,
+            modulo: RTP_SEQ_MODULO,
+        }
+    }
+
+    /// Sequence number of the last frame counted, or of the one taken to be, which the tracker
+    /// can be started again after
+    pub(super) fn last(&self) -> u16 {
+        self.expected.wrapping_sub(1) & (self.modulo - 1) as u16
+    }
+
+    pub(super) fn add(&mut self, seq_number: u16) -> Sequence {
+        let diff = (seq_number as i32 - self.expected as i32).rem_euclid(self.modulo);
+        let signed_diff = match diff <= self.modulo / 2 {
+            true => diff,
+            false => diff - self.modulo,
+        };
+
+        if signed_diff < 0 && -signed_diff <= self.window.reorder_tolerance as i32 {
+            return Sequence::Repeated;
+        }
+        if 0 <= signed_diff && signed_diff <= self.window.max_missed as i32 {
+            self.expected = next(seq_number, self.modulo);
+            return Sequence::New {
+                missed: diff as u16,
+            };
+        }
+
+        match self.window.outliers {
+            OutlierPolicy::Skip => {
+                self.expected = next(self.expected, self.modulo);
+                Sequence::Outlier
+            }
+            OutlierPolicy::Ignore => Sequence::Outlier,
+            OutlierPolicy::Resync => {
+                self.expected = next(seq_number, self.modulo);
+                Sequence::New { missed: 0 }
+            }
+        }
+    }
+}
+
+fn next(seq_number: u16, modulo: i32) -> u16 {
+    seq_number.wrapping_add(1) & (modulo - 1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutlierPolicy, Sequence, SequenceTracker, SequenceWindow};
+
+    #[test]
+    fn missed_and_repeated_frames_are_told_apart() {
+        let mut tracker = SequenceTracker::new(100, SequenceWindow::default());
+        assert_eq!(tracker.add(101), Sequence::New { missed: 0 });
+        assert_eq!(tracker.add(101), Sequence::Repeated);
+        assert_eq!(tracker.add(105), Sequence::New { missed: 3 });
+        assert_eq!(tracker.add(103), Sequence::Repeated);
+        assert_eq!(tracker.last(), 105);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let mut wlan = SequenceTracker::new(4095, SequenceWindow::default());
+        assert_eq!(wlan.add(1), Sequence::New { missed: 1 });
+        assert_eq!(wlan.add(4095), Sequence::Repeated);
+
+        let mut rtp = SequenceTracker::rtp(4095, SequenceWindow::default());
+        assert_eq!(rtp.add(4096), Sequence::New { missed: 0 });
+        let mut rtp = SequenceTracker::rtp(65535, SequenceWindow::default());
+        assert_eq!(rtp.add(0), Sequence::New { missed: 0 });
+    }
+
+    #[test]
+    fn outliers_are_handled_by_the_policy() {
+        let window = |outliers| SequenceWindow {
+            outliers,
+            ..SequenceWindow::symmetric(10)
+        };
+
+        let mut skip = SequenceTracker::new(100, window(OutlierPolicy::Skip));
+        assert_eq!(skip.add(2000), Sequence::Outlier);
+        assert_eq!(skip.add(102), Sequence::New { missed: 0 });
+
+        let mut ignore = SequenceTracker::new(100, window(OutlierPolicy::Ignore));
+        assert_eq!(ignore.add(2000), Sequence::Outlier);
+        assert_eq!(ignore.add(101), Sequence::New { missed: 0 });
+
+        let mut resync = SequenceTracker::new(100, window(OutlierPolicy::Resync));
+        assert_eq!(resync.add(2000), Sequence::New { missed: 0 });
+        assert_eq!(resync.add(2001), Sequence::New { missed: 0 });
+    }
+
+    #[test]
+    fn windows_can_be_asymmetric() {
+        let window = SequenceWindow {
+            reorder_tolerance: 2,
+            max_missed: 20,
+            outliers: OutlierPolicy::Ignore,
+        };
+        let mut tracker = SequenceTracker::new(100, window);
+        assert_eq!(tracker.add(97), Sequence::Outlier);
+        assert_eq!(tracker.add(99), Sequence::Repeated);
+        assert_eq!(tracker.add(121), Sequence::New { missed: 20 });
+    }
+}
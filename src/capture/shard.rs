@@ -0,0 +1,333 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{CommonOptions, EthPacket, IpPacket, Record, WlanPacket};
+use crate::{
+    channel::{self, Receiver, SendError, Sender},
+    BurstsharkError,
+};
+
+/// Ticks sent to the workers per inactive time of the capture, or the shortest adaptive gap, which
+/// bounds how much later bursts can be completed than with a single worker
+const TICKS_PER_INACTIVE_TIME: f64 = 100.0;
+
+/// Input of a burst worker
+pub(super) enum Input<P> {
+    Packet(P),
+    /// All packets up to this time have been read, so bursts inactive since can be completed
+    Tick(f64),
+}
+
+/// A packet that can be routed to one of several workers
+pub(super) trait ShardedPacket {
+    fn time(&self) -> f64;
+
+    /// Hash of the hosts of the packet, the same for both directions. Flows are keyed on at least
+    /// the hosts, so all packets of a flow go to the same worker.
+    fn shard_hash(&self) -> u64;
+
+    /// Set the id of the interface the packet was captured on, when capturing on several
+    fn set_interface(&mut self, interface: u16);
+}
+
+impl ShardedPacket for IpPacket {
+    fn time(&self) -> f64 {
+        self.time
+    }
+
+    fn shard_hash(&self) -> u64 {
+        hash_hosts(self.src, self.dst)
+    }
+
+    fn set_interface(&mut self, interface: u16) {
+        self.interface = Some(interface);
+    }
+}
+
+impl ShardedPacket for WlanPacket {
+    fn time(&self) -> f64 {
+        self.time
+    }
+
+    fn shard_hash(&self) -> u64 {
+        hash_hosts(self.src, self.dst)
+    }
+
+    fn set_interface(&mut self, interface: u16) {
+        self.interface = Some(interface);
+    }
+}
+
+impl ShardedPacket for EthPacket {
+    fn time(&self) -> f64 {
+        self.time
+    }
+
+    fn shard_hash(&self) -> u64 {
+        hash_hosts(self.src, self.dst)
+    }
+
+    fn set_interface(&mut self, interface: u16) {
+        self.interface = Some(interface);
+    }
+}
+
+pub(super) fn hash_hosts<T: Hash + Ord>(a: T, b: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match a <= b {
+        true => (a, b).hash(&mut hasher),
+        false => (b, a).hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+/// Reports how far a worker has come to the merger, so its records can be ordered with those of the
+/// other workers. Does nothing for a single worker, whose records are sent directly.
+#[derive(Default)]
+pub(super) struct Progress(Option<(usize, mpsc::Sender<(usize, f64)>)>);
+
+impl Progress {
+    /// Index of the worker, which is the only one unless reporting to the merger
+    pub(super) fn worker(&self) -> usize {
+        self.0.as_ref().map_or(0, |(index, _)| *index)
+    }
+
+    /// The worker will send no records from before this time
+    pub(super) fn report(&self, time: f64) {
+        if let Some((index, tx)) = &self.0 {
+            let _ = tx.send((*index, time));
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.report(f64::INFINITY);
+    }
+}
+
+/// Distributes packets over the burst workers by their hosts.
+///
+/// With several workers, all of them are also sent ticks with the time of the capture, so they
+/// complete bursts as packets of other flows arrive.
+pub(super) struct Workers<P> {
+    inputs: Vec<Sender<Input<P>>>,
+    tick_interval: f64,
+    next_tick: f64,
+    last_time: f64,
+    /// When the packet of the last time was read, to tell the time of the capture by
+    last_arrival: Instant,
+}
+
+impl<P: ShardedPacket> Workers<P> {
+    /// Start the configured number of workers with `start_worker`, and merge their records onto the
+    /// output of the options if there are several.
+    pub(super) fn start(
+        opts: &CommonOptions,
+        start_worker: impl Fn(&CommonOptions, Progress) -> Result<Sender<Input<P>>, BurstsharkError>,
+    ) -> Result<Self, BurstsharkError> {
+        let mut workers = Workers {
+            inputs: Vec::new(),
+            tick_interval: opts.adaptive_gap.map_or(opts.inactive_time, |gap| gap.min)
+                / TICKS_PER_INACTIVE_TIME,
+            next_tick: f64::NEG_INFINITY,
+            last_time: f64::NEG_INFINITY,
+            last_arrival: Instant::now(),
+        };
+
+        if opts.workers <= 1 {
+            workers
+                .inputs
+                .push(start_worker(opts, Progress::default())?);
+            return Ok(workers);
+        }
+
+        let (progress_tx, progress_rx) = mpsc::channel();
+        let mut records = Vec::new();
+        for index in 0..opts.workers {
+            // Unbounded, as the merger only takes records as the workers report their progress
+            let (tx, rx) = channel::unbounded();
+            let mut worker_opts = opts.clone();
+            worker_opts.tx = tx;
+            worker_opts.worker_sched.cpu = opts.worker_sched.cpu.map(|cpu| cpu + index);
+            // Flows are distributed over the workers, and so are the most flows
+            worker_opts.max_flows = opts.max_flows.map(|max| max.div_ceil(opts.workers));
+
+            let progress = Progress(Some((index, progress_tx.clone())));
+            workers.inputs.push(start_worker(&worker_opts, progress)?);
+            records.push(rx);
+        }
+
+        let output_tx = opts.tx.clone();
+        thread::spawn(move || merge(records, progress_rx, output_tx));
+
+        Ok(workers)
+    }
+
+    /// Send a packet to the worker of its flow
+    pub(super) fn send(&mut self, packet: P) -> Result<(), SendError<Input<P>>> {
+        let time = packet.time();
+        self.last_time = time;
+        self.last_arrival = Instant::now();
+        if self.inputs.len() == 1 {
+            return self.inputs[0].send(Input::Packet(packet));
+        }
+
+        if time >= self.next_tick {
+            self.tick(time)?;
+            self.next_tick = time + self.tick_interval;
+        }
+
+        let index = (packet.shard_hash() % self.inputs.len() as u64) as usize;
+        self.inputs[index].send(Input::Packet(packet))
+    }
+}
+
+impl<P> Workers<P> {
+    /// Time between the ticks of the workers, of a live capture while no packets arrive
+    pub(super) fn tick_period(&self) -> Duration {
+        Duration::from_secs_f64(self.tick_interval)
+    }
+
+    /// Tick all workers with the time of a live capture, as told by the time of the last packet and
+    /// how long ago it was read. Nothing is sent before the first packet.
+    pub(super) fn tick_now(&self) -> Result<(), SendError<Input<P>>> {
+        match self.last_time.is_finite() {
+            true => self.tick(self.last_time + self.last_arrival.elapsed().as_secs_f64()),
+            false => Ok(()),
+        }
+    }
+
+    fn tick(&self, time: f64) -> Result<(), SendError<Input<P>>> {
+        for input in &self.inputs {
+            input.send(Input::Tick(time))?;
+        }
+        Ok(())
+    }
+}
+
+impl<P> Drop for Workers<P> {
+    fn drop(&mut self) {
+        // Let all workers end at the time of the last packet, as a single worker would
+        if self.inputs.len() > 1 && self.last_time.is_finite() {
+            let _ = self.tick(self.last_time);
+        }
+    }
+}
+
+fn record_time(record: &Record) -> f64 {
+    match record {
+        Record::Burst(burst) => burst.completion_time,
+        Record::FlowSurge(surge) => surge.time,
+        Record::FlowEvent(event) => event.time,
+        Record::Concurrency(concurrency) => concurrency.time,
+        Record::BurstDelta(delta) => delta.time,
+        Record::BurstHistogram(histogram) => histogram.time,
+        Record::FlowHistogram(histogram) => histogram.time,
+        Record::RateSample(sample) => sample.time,
+    }
+}
+
+/// Forward the records of the workers in the order of their times, and of the workers on ties.
+///
+/// The records of a worker are only forwarded once every other worker has reported that it will
+/// send none earlier. Records a worker sends out of order keep their place after its earlier ones.
+fn merge(
+    records: Vec<Receiver<Record>>,
+    progress: mpsc::Receiver<(usize, f64)>,
+    output_tx: Sender<Record>,
+) {
+    let workers = records.len();
+    let mut queues: Vec<VecDeque<(f64, Record)>> = (0..workers).map(|_| VecDeque::new()).collect();
+    let mut latest = vec![f64::NEG_INFINITY; workers];
+    let mut reached = vec![f64::NEG_INFINITY; workers];
+
+    for (index, time) in progress {
+        // Everything the worker sent before reporting its progress has arrived
+        for record in records[index].try_iter() {
+            latest[index] = latest[index].max(record_time(&record));
+            queues[index].push_back((latest[index], record));
+        }
+        reached[index] = reached[index].max(time);
+
+        loop {
+            // The earliest record, or the earliest time a worker without records may send one at
+            let next = (0..workers)
+                .map(|i| match queues[i].front() {
+                    Some((time, _)) => (*time, i, true),
+                    None => (reached[i], i, false),
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+            match next {
+                Some((_, i, true)) => {
+                    let (_, record) = queues[i].pop_front().unwrap();
+                    if output_tx.send(record).is_err() {
+                        return;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::mpsc};
+
+    use super::{hash_hosts, merge};
+    use crate::{
+        capture::{Burst, Record},
+        channel,
+    };
+
+    #[test]
+    fn both_directions_go_to_the_same_worker() {
+        let (a, b) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        assert_eq!(hash_hosts(a, b), hash_hosts(b, a));
+        assert_ne!(hash_hosts(a, b), hash_hosts(a, a));
+    }
+
+    /// A burst completed at a time, sized to tell which worker sent it
+    fn record(time: f64, worker: u32) -> Record {
+        Record::Burst(Burst::test("10.0.0.1:1", "10.0.0.2:2", time, time, worker))
+    }
+
+    #[test]
+    fn records_of_workers_are_merged_in_time_order() {
+        let (progress_tx, progress_rx) = mpsc::channel();
+        let (output_tx, output_rx) = channel::unbounded();
+        let workers: Vec<_> = (0..2).map(|_| channel::unbounded()).collect();
+
+        // Records arrive from the first worker before the second has reported its progress
+        workers[0].0.send(record(1.0, 0)).unwrap();
+        workers[0].0.send(record(3.0, 0)).unwrap();
+        progress_tx.send((0, 3.0)).unwrap();
+        workers[1].0.send(record(2.0, 1)).unwrap();
+        // Sent out of order, so kept after the earlier record of the worker
+        workers[1].0.send(record(0.5, 1)).unwrap();
+        progress_tx.send((1, 2.0)).unwrap();
+        progress_tx.send((0, f64::INFINITY)).unwrap();
+        progress_tx.send((1, f64::INFINITY)).unwrap();
+        drop(progress_tx);
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = workers.into_iter().unzip();
+        merge(receivers, progress_rx, output_tx);
+        drop(senders);
+
+        let merged: Vec<(f64, u32)> = output_rx
+            .try_iter()
+            .map(|record| match record {
+                Record::Burst(burst) => (burst.completion_time, burst.size),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(merged, [(1.0, 0), (2.0, 1), (0.5, 1), (3.0, 0)]);
+    }
+}
@@ -0,0 +1,210 @@
+use std::process::Stdio;
+
+use tokio::{
+    io::{self, AsyncRead},
+    process::{Child, Command},
+};
+
+use super::{bad_lines::BadLines, interrupt, remote::RemoteHost, CaptureBackend};
+use crate::BurstsharkError;
+
+/// The printed lines of a source
+pub(super) type Output<'a> = Box<dyn AsyncRead + Unpin + 'a>;
+
+/// Lines of packet fields, printed by tshark or another process, or read from stdin or memory.
+///
+/// The lines are parsed by the capture the same way regardless of where they come from, so any
+/// program printing the fields tshark would can stand in for it. Processes are interrupted along
+/// with burstshark, and stopped if their lines can no longer be handled.
+#[derive(Default)]
+pub(super) struct LineSource<'a> {
+    /// The process printing the lines, with its pid
+    child: Option<(Child, u32)>,
+    /// The host tshark runs on over ssh
+    remote: Option<&'a RemoteHost>,
+    /// The shell command printing the lines, in place of tshark
+    command: Option<&'a str>,
+    /// The fields printed on each line, when known
+    layout: Vec<String>,
+}
+
+impl<'a> LineSource<'a> {
+    /// Start the process printing the lines of the backend, which must be run within a tokio
+    /// runtime, returning the source along with its output
+    pub(super) fn start(
+        backend: &'a CaptureBackend,
+    ) -> Result<(Self, Output<'a>), BurstsharkError> {
+        let mut child = match backend {
+            // Remote tshark runs until the piped input is closed along with the child
+            CaptureBackend::Remote { host, args } => Command::from(host.command(args))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .spawn()
+                .map_err(BurstsharkError::Ssh)?,
+            CaptureBackend::Tshark { args } => Command::new("tshark")
+                .args(args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .spawn()
+                .map_err(BurstsharkError::Tshark)?,
+            CaptureBackend::Exec { command } => Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .spawn()
+                .map_err(BurstsharkError::Exec)?,
+            CaptureBackend::Stdin => {
+                return Ok((LineSource::default(), Box::new(io::stdin())));
+            }
+            CaptureBackend::Lines { lines } => {
+                return Ok((LineSource::default(), Box::new(lines.as_bytes())));
+            }
+            CaptureBackend::Native { .. } | CaptureBackend::Synthetic(_) => {
+                return Err(BurstsharkError::InvalidOptions(
+                    "The backend prints no lines of packet fields".to_string(),
+                ))
+            }
+        };
+
+        let pid = child
+            .id()
+            .ok_or_else(|| BurstsharkError::Exec(io::Error::other("it exited as it started")))?;
+        let output = child
+            .stdout
+            .take()
+            .ok_or_else(|| BurstsharkError::Exec(io::Error::other("its output is not piped")))?;
+        interrupt::add_tshark(pid);
+
+        let source = LineSource {
+            child: Some((child, pid)),
+            remote: match backend {
+                CaptureBackend::Remote { host, .. } => Some(host),
+                _ => None,
+            },
+            command: match backend {
+                CaptureBackend::Exec { command } => Some(command),
+                _ => None,
+            },
+            layout: match backend {
+                CaptureBackend::Tshark { args } | CaptureBackend::Remote { args, .. } => {
+                    BadLines::tshark_layout(args)
+                }
+                _ => Vec::new(),
+            },
+        };
+        Ok((source, Box::new(output)))
+    }
+
+    /// The fields printed on each line, to report the lines that could not be parsed with
+    pub(super) fn layout(&self) -> Vec<String> {
+        self.layout.clone()
+    }
+
+    /// Wait for the process to exit, stopping it first if the lines could not all be handled, such
+    /// as when a worker stopped
+    pub(super) async fn close(self, failed: bool) -> Result<(), BurstsharkError> {
+        let Some((mut child, pid)) = self.child else {
+            return Ok(());
+        };
+        interrupt::remove_tshark(pid);
+        if failed {
+            let _ = child.start_kill();
+        }
+        let status = child.wait().await?;
+
+        // Which ssh exits with when it fails to connect or authenticate, as told on stderr, but
+        // also when interrupted
+        if let (Some(host), Some(255), false) =
+            (self.remote, status.code(), interrupt::interrupted())
+        {
+            return Err(BurstsharkError::Remote(host.destination.clone()));
+        }
+        // Tshark tells why it failed on stderr, while other commands may fail silently
+        if let (Some(command), false, false) = (
+            self.command,
+            status.success(),
+            failed || interrupt::interrupted(),
+        ) {
+            return Err(BurstsharkError::CommandFailed {
+                command: command.to_string(),
+                status,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncReadExt, runtime::Runtime};
+
+    use super::LineSource;
+    use crate::{
+        capture::{CaptureBackend, SyntheticTraffic},
+        BurstsharkError,
+    };
+
+    fn runtime() -> Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap()
+    }
+
+    /// All lines printed by the backend, and what closing the source returned
+    fn read(backend: &CaptureBackend) -> (String, Result<(), BurstsharkError>) {
+        runtime().block_on(async {
+            let (source, mut output) = LineSource::start(backend).unwrap();
+            let mut lines = String::new();
+            output.read_to_string(&mut lines).await.unwrap();
+            drop(output);
+            (lines, source.close(false).await)
+        })
+    }
+
+    #[test]
+    fn lines_are_read_from_memory() {
+        let backend = CaptureBackend::Lines {
+            lines: "1.0\t10.0.0.1\n".to_string(),
+        };
+        let (lines, closed) = read(&backend);
+        assert_eq!(lines, "1.0\t10.0.0.1\n");
+        assert!(closed.is_ok());
+    }
+
+    #[test]
+    fn lines_are_read_from_commands() {
+        let backend = CaptureBackend::Exec {
+            command: "printf '1.0\\n2.0\\n'".to_string(),
+        };
+        let (lines, closed) = read(&backend);
+        assert_eq!(lines, "1.0\n2.0\n");
+        assert!(closed.is_ok());
+    }
+
+    #[test]
+    fn commands_failing_are_errors() {
+        let backend = CaptureBackend::Exec {
+            command: "echo 1.0; exit 3".to_string(),
+        };
+        let (lines, closed) = read(&backend);
+        assert_eq!(lines, "1.0\n");
+        assert!(matches!(closed, Err(BurstsharkError::CommandFailed { .. })));
+    }
+
+    #[test]
+    fn backends_without_lines_are_refused() {
+        let backend = CaptureBackend::Synthetic(SyntheticTraffic {
+            flows: 1,
+            packet_rate: 100.0,
+            burst_packets: 3,
+            burst_gap: 1.0,
+            packet_size: 100,
+            duration: 1.0,
+        });
+        let _runtime = runtime().enter();
+        assert!(LineSource::start(&backend).is_err());
+    }
+}
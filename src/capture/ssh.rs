@@ -0,0 +1,72 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use super::Burst;
+
+pub const SSH_PORT: u16 = 22;
+
+/// Bursts with at least this many packets can be bulk transfers.
+const BULK_PACKETS: u16 = 10;
+
+/// Bursts of bulk transfers have at least this mean packet size, while keystrokes and their echoes
+/// are sent in packets of less than a hundred bytes.
+const BULK_MEAN_SIZE: u32 = 512;
+
+/// Phase of an SSH session inferred from the packets of a burst
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SshPhase {
+    /// Keystrokes, their echoes and command output
+    Interactive,
+    /// File transfers, or traffic tunneled through the session
+    Bulk,
+}
+
+impl SshPhase {
+    pub fn of(burst: &Burst) -> Self {
+        if burst.num_packets >= BULK_PACKETS
+            && burst.size / burst.num_packets as u32 >= BULK_MEAN_SIZE
+        {
+            SshPhase::Bulk
+        } else {
+            SshPhase::Interactive
+        }
+    }
+}
+
+impl fmt::Display for SshPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SshPhase::Interactive => write!(f, "ssh:interactive"),
+            SshPhase::Bulk => write!(f, "ssh:bulk"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Burst, SshPhase};
+
+    fn phase(num_packets: u16, size: u32) -> SshPhase {
+        let burst = Burst {
+            num_packets,
+            ..Burst::test("10.0.0.1:22", "10.0.0.2:50000", 0.0, 0.1, size)
+        };
+        SshPhase::of(&burst)
+    }
+
+    #[test]
+    fn large_bursts_of_large_packets_are_bulk_transfers() {
+        assert_eq!(phase(10, 10 * 512), SshPhase::Bulk);
+        assert_eq!(phase(200, 200 * 1400), SshPhase::Bulk);
+    }
+
+    #[test]
+    fn keystrokes_and_short_bursts_are_interactive() {
+        assert_eq!(phase(1, 36), SshPhase::Interactive);
+        assert_eq!(phase(40, 40 * 100), SshPhase::Interactive);
+        // Few packets, even if large
+        assert_eq!(phase(9, 9 * 1400), SshPhase::Interactive);
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Burst, IpProto};
+use crate::BurstsharkError;
+
+/// Version of the state file, increased whenever the saved state changes
+const STATE_VERSION: u32 = 1;
+
+/// Layer of the flows of a capture, which are only restored by captures of the same layer
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum StateKind {
+    Ip,
+    Wlan,
+    Eth,
+}
+
+/// A flow with a burst in progress as a live capture stopped, with the times of the burst on the
+/// wall clock, in seconds since the epoch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct FlowState {
+    pub(super) src: String,
+    pub(super) dst: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) src_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) dst_port: Option<u16>,
+    /// SSRC of RTP streams, TID of WLAN flows and ethertype of layer 2 flows, which are keyed on
+    /// them along with the addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) ssrc: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) tid: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) ethertype: Option<u16>,
+    /// Id of the interface of the flow, when capturing on several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) interface: Option<u16>,
+    pub(super) start: f64,
+    pub(super) end: f64,
+    pub(super) packets: u16,
+    pub(super) size: u32,
+    /// Annotations of the burst, as set by the options of the capture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) proto: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) dscp: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) flow_label: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) retransmitted_bytes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) payload_type: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) lost_packets: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) airtime: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) channels: Vec<u16>,
+    /// Bytes of the burst sent from the second endpoint to the first, when aggregating both
+    /// directions of flows
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub(super) reversed_size: u32,
+    /// Sequence numbers of the last packets counted, per channel and TID of WLAN flows, or of
+    /// RTP streams
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) sequences: Vec<SequenceState>,
+    /// Size of the last packet, to guess the sizes of missed packets from
+    #[serde(default)]
+    pub(super) last_packet_len: u32,
+    /// Average time between the packets of the flow, for adaptive gaps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) inter_arrival: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(super) struct SequenceState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) channel: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) tid: Option<u8>,
+    pub(super) last: u16,
+}
+
+impl FlowState {
+    /// The state of a flow with the burst in progress, whose times are moved to the wall clock by
+    /// the offset
+    pub(super) fn new(burst: &Burst, interface: Option<u16>, offset: f64) -> Self {
+        FlowState {
+            src: burst.src.to_string(),
+            dst: burst.dst.to_string(),
+            src_port: burst.src_port,
+            dst_port: burst.dst_port,
+            ssrc: burst.ssrc,
+            tid: burst.tid,
+            ethertype: burst.ethertype,
+            interface,
+            start: burst.start + offset,
+            end: burst.end + offset,
+            packets: burst.num_packets,
+            size: burst.size,
+            proto: burst.proto.map(|proto| proto.0),
+            dscp: burst.dscp,
+            flow_label: burst.flow_label,
+            retransmitted_bytes: burst.retransmitted_bytes,
+            payload_type: burst.payload_type,
+            lost_packets: burst.lost_packets,
+            airtime: burst.airtime,
+            channels: burst.channels.clone(),
+            reversed_size: 0,
+            sequences: Vec::new(),
+            last_packet_len: 0,
+            inter_arrival: None,
+        }
+    }
+
+    /// Continue the saved burst with a burst started at its first packet
+    pub(super) fn restore(&self, burst: &mut Burst) {
+        burst.start = self.start;
+        burst.end = self.end;
+        burst.completion_time = self.end;
+        burst.num_packets = self.packets;
+        burst.size = self.size;
+        burst.proto = self.proto.map(IpProto);
+        burst.dscp = self.dscp;
+        burst.flow_label = self.flow_label;
+        burst.retransmitted_bytes = self.retransmitted_bytes;
+        burst.payload_type = self.payload_type;
+        burst.lost_packets = self.lost_packets;
+        burst.airtime = self.airtime;
+        burst.channels = self.channels.clone();
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+#[derive(Deserialize)]
+struct StateVersion {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    version: u32,
+    kind: StateKind,
+    flows: Vec<FlowState>,
+}
+
+/// Flows with bursts in progress, kept in a JSON file across restarts of live captures.
+///
+/// The flows saved when a capture stops are restored by the next one, so bursts continue across
+/// the restart if it is shorter than their gaps. Times are saved on the wall clock, and rebased on
+/// the clock of the next capture once its first packet arrives. The file is removed once read, so
+/// the flows are only restored once.
+pub(super) struct StateFile {
+    path: PathBuf,
+    kind: StateKind,
+    /// Flows read from the file, which each worker restores those of its hosts from
+    restored: Vec<FlowState>,
+    /// Flows saved by the workers that have stopped, and how many are still running
+    saved: Mutex<(Vec<FlowState>, usize)>,
+}
+
+impl StateFile {
+    /// Read the flows saved by an earlier capture of the same layer, if any, to be restored and
+    /// saved again by the workers
+    pub(super) fn open(
+        path: &str,
+        kind: StateKind,
+        workers: usize,
+    ) -> Result<Self, BurstsharkError> {
+        let restored = match fs::read_to_string(path) {
+            Ok(content) => {
+                let invalid = |err| BurstsharkError::InvalidFile {
+                    path: path.to_string(),
+                    reason: format!("invalid flow state: {err}"),
+                };
+                fs::remove_file(path)?;
+                // Other versions may save flows differently, so only their version is read
+                let version = serde_json::from_str::<StateVersion>(&content).map_err(invalid)?;
+                if version.version != STATE_VERSION {
+                    eprintln!(
+                        "Ignoring the flow state in {path} of version {}, as version {STATE_VERSION} is read",
+                        version.version
+                    );
+                    Vec::new()
+                } else {
+                    let state = serde_json::from_str::<SavedState>(&content).map_err(invalid)?;
+                    if state.kind == kind {
+                        state.flows
+                    } else {
+                        eprintln!("Ignoring the flow state in {path} of another capture type");
+                        Vec::new()
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(StateFile {
+            path: PathBuf::from(path),
+            kind,
+            restored,
+            saved: Mutex::new((Vec::new(), workers.max(1))),
+        })
+    }
+
+    pub(super) fn restored(&self) -> &[FlowState] {
+        &self.restored
+    }
+
+    /// Add the flows of a stopped worker, and write the file once all workers have stopped
+    pub(super) fn save(&self, flows: Vec<FlowState>) -> Result<(), BurstsharkError> {
+        let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        saved.0.extend(flows);
+        saved.1 -= 1;
+        if saved.1 > 0 {
+            return Ok(());
+        }
+
+        let state = SavedState {
+            version: STATE_VERSION,
+            kind: self.kind,
+            flows: std::mem::take(&mut saved.0),
+        };
+        // Written next to the file and moved over it, so it is never left half written
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&state)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Seconds to add to times of a capture clock to get times on the wall clock, from a time of the
+/// capture clock that is now
+pub(super) fn wall_clock_offset(now: f64) -> Result<f64, BurstsharkError> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64() - now)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::{FlowState, StateFile, StateKind};
+    use crate::capture::Burst;
+
+    fn state_path(test: &str) -> String {
+        let path = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn bursts_are_restored_on_the_new_clock() {
+        let burst = Burst {
+            num_packets: 3,
+            dscp: Some(46),
+            ..Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 3000)
+        };
+        let state = FlowState::new(&burst, Some(2), 100.0);
+        assert_eq!((state.start, state.end), (101.0, 101.5));
+        assert_eq!(state.interface, Some(2));
+
+        let mut restored = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 101.6, 101.6, 100);
+        state.restore(&mut restored);
+        assert_eq!((restored.start, restored.end), (101.0, 101.5));
+        assert_eq!(restored.completion_time, 101.5);
+        assert_eq!((restored.num_packets, restored.size), (3, 3000));
+        assert_eq!(restored.dscp, Some(46));
+    }
+
+    #[test]
+    fn flows_are_saved_once_all_workers_stop_and_restored_once() {
+        let path = state_path("state");
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 3000);
+        let state = StateFile::open(&path, StateKind::Ip, 2).unwrap();
+        assert!(state.restored().is_empty());
+        state.save(vec![FlowState::new(&burst, None, 0.0)]).unwrap();
+        assert!(fs::metadata(&path).is_err());
+        state.save(vec![FlowState::new(&burst, None, 0.0)]).unwrap();
+
+        let state = StateFile::open(&path, StateKind::Ip, 1).unwrap();
+        assert_eq!(state.restored().len(), 2);
+        assert_eq!(state.restored()[0].src, "10.0.0.1");
+        // The file is removed once read
+        assert!(fs::metadata(&path).is_err());
+    }
+
+    #[test]
+    fn flows_of_other_layers_and_versions_are_ignored() {
+        let path = state_path("state-kind");
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.0, 1.5, 3000);
+        let state = StateFile::open(&path, StateKind::Ip, 1).unwrap();
+        state.save(vec![FlowState::new(&burst, None, 0.0)]).unwrap();
+        assert!(StateFile::open(&path, StateKind::Wlan, 1)
+            .unwrap()
+            .restored()
+            .is_empty());
+
+        fs::write(&path, r#"{"version":99,"flows":"changed"}"#).unwrap();
+        assert!(StateFile::open(&path, StateKind::Ip, 1)
+            .unwrap()
+            .restored()
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_files_are_refused() {
+        let path = state_path("state-invalid");
+        fs::write(&path, "{").unwrap();
+        assert!(StateFile::open(&path, StateKind::Ip, 1).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    net::IpAddr,
+};
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SurgeOptions {
+    /// Number of new flows from one host within a window that is considered a surge
+    pub threshold: u32,
+
+    /// Length of the windows in seconds
+    pub window: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SurgeKind {
+    /// Most new flows go to different ports of the same hosts
+    PortScan,
+
+    /// Most new flows go to different hosts
+    HostScan,
+
+    /// Many new flows, but to few destinations, such as a SYN flood
+    FlowSurge,
+}
+
+impl fmt::Display for SurgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            SurgeKind::PortScan => "port-scan",
+            SurgeKind::HostScan => "host-scan",
+            SurgeKind::FlowSurge => "flow-surge",
+        })
+    }
+}
+
+/// Alert that a host created new flows at a rate above the threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowSurge {
+    pub time: f64,
+    pub src: IpAddr,
+    pub kind: SurgeKind,
+    pub window: f64,
+    pub new_flows: u32,
+    pub distinct_dsts: usize,
+    pub distinct_ports: usize,
+}
+
+/// New flows of a host in the current window
+struct HostWindow {
+    start: f64,
+    new_flows: u32,
+    dsts: HashSet<IpAddr>,
+    ports: HashSet<u16>,
+    alerted: bool,
+}
+
+/// Tracks the rate of new flows per source host
+pub struct SurgeDetector {
+    opts: SurgeOptions,
+    hosts: HashMap<IpAddr, HostWindow>,
+    last_prune: f64,
+}
+
+impl SurgeDetector {
+    pub fn new(opts: SurgeOptions) -> Self {
+        SurgeDetector {
+            opts,
+            hosts: HashMap::new(),
+            last_prune: f64::MIN,
+        }
+    }
+
+    /// Register a new flow, returning an alert the first time its host crosses the threshold in a window
+    pub fn new_flow(
+        &mut self,
+        time: f64,
+        src: IpAddr,
+        dst: IpAddr,
+        dst_port: u16,
+    ) -> Option<FlowSurge> {
+        let window = self.opts.window;
+        if time - self.last_prune >= window {
+            // Forget hosts which have been quiet for a full window
+            self.hosts.retain(|_, host| time - host.start < window);
+            self.last_prune = time;
+        }
+
+        let host = self.hosts.entry(src).or_insert_with(|| HostWindow {
+            start: time,
+            new_flows: 0,
+            dsts: HashSet::new(),
+            ports: HashSet::new(),
+            alerted: false,
+        });
+
+        if time - host.start >= window {
+            host.start = time;
+            host.new_flows = 0;
+            host.dsts.clear();
+            host.ports.clear();
+            host.alerted = false;
+        }
+
+        host.new_flows += 1;
+        host.dsts.insert(dst);
+        host.ports.insert(dst_port);
+
+        if host.alerted || host.new_flows < self.opts.threshold {
+            return None;
+        }
+        host.alerted = true;
+
+        let half = (self.opts.threshold / 2) as usize;
+        let kind = if host.dsts.len() > half {
+            SurgeKind::HostScan
+        } else if host.ports.len() > half {
+            SurgeKind::PortScan
+        } else {
+            SurgeKind::FlowSurge
+        };
+
+        Some(FlowSurge {
+            time,
+            src,
+            kind,
+            window,
+            new_flows: host.new_flows,
+            distinct_dsts: host.dsts.len(),
+            distinct_ports: host.ports.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{SurgeDetector, SurgeKind, SurgeOptions};
+
+    const OPTS: SurgeOptions = SurgeOptions {
+        threshold: 10,
+        window: 1.0,
+    };
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    /// The alerts of flows from one host, to the destination and port of each index
+    fn surges(flows: impl Iterator<Item = (f64, IpAddr, u16)>) -> Vec<(f64, SurgeKind, u32)> {
+        let mut detector = SurgeDetector::new(OPTS);
+        flows
+            .filter_map(|(time, dst, port)| detector.new_flow(time, ip(1), dst, port))
+            .map(|surge| (surge.time, surge.kind, surge.new_flows))
+            .collect()
+    }
+
+    #[test]
+    fn scans_are_told_apart_from_floods() {
+        let times = (0..10).map(|i| i as f64 * 0.01);
+        let host_scan = surges(times.clone().map(|t| (t, ip(100 + (t * 100.0) as u8), 22)));
+        let port_scan = surges(times.clone().map(|t| (t, ip(2), 1000 + (t * 100.0) as u16)));
+        let flood = surges(times.map(|t| (t, ip(2), 80)));
+        assert_eq!(host_scan, [(0.09, SurgeKind::HostScan, 10)]);
+        assert_eq!(port_scan, [(0.09, SurgeKind::PortScan, 10)]);
+        assert_eq!(flood, [(0.09, SurgeKind::FlowSurge, 10)]);
+    }
+
+    #[test]
+    fn hosts_are_alerted_on_once_per_window() {
+        let time = |i| i as f64 * 0.05;
+        let flows = (0..40).map(|i| (time(i), ip(2), 80));
+        // A window starts at the first flow, and a new one at the first flow after it ends
+        assert_eq!(
+            surges(flows),
+            [
+                (time(9), SurgeKind::FlowSurge, 10),
+                (time(29), SurgeKind::FlowSurge, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn flows_spread_over_windows_are_no_surge() {
+        let flows = (0..30).map(|i| (i as f64 * 0.2, ip(2), 80));
+        assert!(surges(flows).is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// Flows of generated packets, each sending bursts of packets at a fixed rate separated by pauses.
+///
+/// The bursts of the flows are spread evenly over the pause, so the packets of different flows are
+/// interleaved as in a real capture.
+#[derive(Clone, Debug)]
+pub struct SyntheticTraffic {
+    pub flows: u32,
+    /// Packets per second of a flow during its bursts
+    pub packet_rate: f64,
+    pub burst_packets: u16,
+    /// Seconds from the last packet of a burst to the first of the next
+    pub burst_gap: f64,
+    /// Bytes of data of each packet
+    pub packet_size: u32,
+    /// Seconds during which bursts are started, which end after the last is complete
+    pub duration: f64,
+}
+
+/// A generated packet, of the flow with the given index
+#[derive(Clone, Copy, Debug)]
+pub(super) struct SyntheticPacket {
+    pub(super) time: f64,
+    pub(super) flow: u32,
+    /// Packets of the flow before this one
+    pub(super) seq: u64,
+    pub(super) len: u32,
+}
+
+impl SyntheticTraffic {
+    /// Seconds from the start of a burst of a flow to the start of its next
+    fn period(&self) -> f64 {
+        (self.burst_packets - 1) as f64 / self.packet_rate + self.burst_gap
+    }
+
+    /// Seconds into the capture of the first burst of a flow
+    fn phase(&self, flow: u32) -> f64 {
+        self.period() * flow as f64 / self.flows as f64
+    }
+
+    /// Bursts a flow starts during the duration
+    fn flow_bursts(&self, flow: u32) -> u64 {
+        let phase = self.phase(flow);
+        match phase < self.duration {
+            true => ((self.duration - phase) / self.period()).ceil() as u64,
+            false => 0,
+        }
+    }
+
+    /// Bursts of all flows
+    pub fn bursts(&self) -> u64 {
+        (0..self.flows).map(|flow| self.flow_bursts(flow)).sum()
+    }
+
+    /// Packets of all flows
+    pub fn packets(&self) -> u64 {
+        self.bursts() * self.burst_packets as u64
+    }
+
+    /// The packets of all flows in the order they are sent
+    pub(super) fn generate(&self) -> impl Iterator<Item = SyntheticPacket> + '_ {
+        // Ordered by time in nanoseconds, and then by flow, with the packets a flow has sent
+        let mut next: BinaryHeap<Reverse<(u64, u32, u64)>> = (0..self.flows)
+            .filter(|flow| self.flow_bursts(*flow) > 0)
+            .map(|flow| Reverse((nanos(self.phase(flow)), flow, 0)))
+            .collect();
+
+        std::iter::from_fn(move || {
+            let Reverse((time, flow, seq)) = next.pop()?;
+            let next_seq = seq + 1;
+            if next_seq < self.flow_bursts(flow) * self.burst_packets as u64 {
+                let (burst, packet) = (
+                    next_seq / self.burst_packets as u64,
+                    next_seq % self.burst_packets as u64,
+                );
+                let next_time = self.phase(flow)
+                    + burst as f64 * self.period()
+                    + packet as f64 / self.packet_rate;
+                next.push(Reverse((nanos(next_time), flow, next_seq)));
+            }
+
+            Some(SyntheticPacket {
+                time: time as f64 / 1e9,
+                flow,
+                seq,
+                len: self.packet_size,
+            })
+        })
+    }
+}
+
+/// Seconds rounded to nanoseconds, the precision of capture times
+fn nanos(seconds: f64) -> u64 {
+    (seconds * 1e9).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyntheticTraffic;
+
+    fn traffic() -> SyntheticTraffic {
+        SyntheticTraffic {
+            flows: 2,
+            packet_rate: 100.0,
+            burst_packets: 3,
+            burst_gap: 1.0,
+            packet_size: 100,
+            duration: 2.0,
+        }
+    }
+
+    #[test]
+    fn bursts_are_started_during_the_duration() {
+        // Bursts start every 1.02 seconds, those of the second flow 0.51 seconds in
+        let traffic = traffic();
+        assert_eq!((traffic.bursts(), traffic.packets()), (4, 12));
+        let short = SyntheticTraffic {
+            duration: 0.5,
+            ..traffic
+        };
+        assert_eq!(short.bursts(), 1);
+    }
+
+    #[test]
+    fn packets_of_the_flows_are_interleaved_in_time() {
+        let packets: Vec<_> = traffic()
+            .generate()
+            .map(|packet| (packet.time, packet.flow, packet.seq))
+            .collect();
+        assert_eq!(
+            packets[..6],
+            [
+                (0.0, 0, 0),
+                (0.01, 0, 1),
+                (0.02, 0, 2),
+                (0.51, 1, 0),
+                (0.52, 1, 1),
+                (0.53, 1, 2)
+            ]
+        );
+        assert_eq!(packets[6], (1.02, 0, 3));
+        assert_eq!(packets.len(), 12);
+        assert!(packets.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::BurstsharkError;
+
+/// ESP header (SPI and sequence number), AES-GCM IV, trailer (pad length and next header) and ICV.
+/// The padding depends on the payload and is not included.
+const ESP_OVERHEAD: u32 = 8 + 8 + 2 + 16;
+
+/// Header (type, receiver index and counter) and authentication tag of WireGuard transport data.
+const WIREGUARD_OVERHEAD: u32 = 16 + 16;
+
+/// WireGuard message type of transport data, the other types are handshake messages.
+const WIREGUARD_DATA: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelKind {
+    Esp,
+    WireGuard,
+    Vxlan,
+    Gre,
+    Gtp,
+}
+
+/// How flows of tunnel packets are keyed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TunnelMode {
+    /// One flow per security association (SPI) or WireGuard session (receiver index)
+    Session,
+    /// One flow per tunnel between two hosts, across rekeying and port changes
+    Single,
+}
+
+/// The tunnel a packet belongs to, and the bytes of it spent on tunneling
+#[derive(Clone, Copy, Debug)]
+pub struct Tunnel {
+    pub kind: TunnelKind,
+    /// SPI, receiver index, VNI, GRE key or TEID
+    pub id: u32,
+    pub overhead: u32,
+}
+
+impl Tunnel {
+    pub fn esp(spi: u32, data_len: u32) -> Self {
+        Tunnel {
+            kind: TunnelKind::Esp,
+            id: spi,
+            overhead: ESP_OVERHEAD.min(data_len),
+        }
+    }
+
+    /// Handshake messages are tunnel overhead in their entirety.
+    pub fn wireguard(message_type: u8, receiver_index: u32, data_len: u32) -> Self {
+        Tunnel {
+            kind: TunnelKind::WireGuard,
+            id: receiver_index,
+            overhead: match message_type {
+                WIREGUARD_DATA => WIREGUARD_OVERHEAD.min(data_len),
+                _ => data_len,
+            },
+        }
+    }
+
+    /// The tunnel of a packet from the tshark esp.spi, wg.type and wg.receiver_index fields.
+    ///
+    /// WireGuard handshake initiations have no receiver index, and are not considered tunnel packets.
+    pub fn from_tshark(
+        esp_spi: &str,
+        wg_type: &str,
+        wg_receiver_index: &str,
+        data_len: u32,
+    ) -> Result<Option<Self>, BurstsharkError> {
+        if !esp_spi.is_empty() {
+            Ok(Some(Tunnel::esp(parse_hex(esp_spi)?, data_len)))
+        } else if !wg_receiver_index.is_empty() {
+            Ok(Some(Tunnel::wireguard(
+                wg_type.parse()?,
+                parse_hex(wg_receiver_index)?,
+                data_len,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// A tunnel that a packet was decapsulated from, with `overhead` bytes of headers between the
+    /// outer and inner IP headers
+    pub fn encapsulation(kind: TunnelKind, id: u32, overhead: u32) -> Self {
+        Tunnel { kind, id, overhead }
+    }
+
+    /// The tunnel a decapsulated packet was carried in, from the tshark vxlan.vni, gre.proto,
+    /// gre.key and gtp.teid fields, and the ip.len and ip.hdr_len fields of each IP header.
+    ///
+    /// GRE packets without a key have a key of 0. The overhead is only known when both IP headers
+    /// are IPv4.
+    pub fn from_tshark_encapsulation(
+        vni: &str,
+        gre_proto: &str,
+        gre_key: &str,
+        teid: &str,
+        ip_len: &str,
+        ip_hdr_len: &str,
+    ) -> Result<Option<Self>, BurstsharkError> {
+        let (kind, id) = if !vni.is_empty() {
+            (TunnelKind::Vxlan, parse_int(vni)?)
+        } else if !teid.is_empty() {
+            (TunnelKind::Gtp, parse_int(teid)?)
+        } else if !gre_proto.is_empty() {
+            match gre_key {
+                "" => (TunnelKind::Gre, 0),
+                key => (TunnelKind::Gre, parse_int(key)?),
+            }
+        } else {
+            return Ok(None);
+        };
+
+        // The payload of the outer IPv4 header, less the inner packet
+        let overhead = match (ip_len.split_once(','), ip_hdr_len.split_once(',')) {
+            (Some((outer_len, inner_len)), Some((outer_hdr_len, _))) => outer_len
+                .parse::<u32>()?
+                .saturating_sub(outer_hdr_len.parse::<u32>()?)
+                .saturating_sub(innermost(inner_len).parse::<u32>()?),
+            _ => 0,
+        };
+        Ok(Some(Tunnel::encapsulation(kind, id, overhead)))
+    }
+
+    /// Whether the packet was decapsulated from the tunnel, rather than being tunnel traffic
+    /// itself
+    pub fn decapsulated(&self) -> bool {
+        matches!(
+            self.kind,
+            TunnelKind::Vxlan | TunnelKind::Gre | TunnelKind::Gtp
+        )
+    }
+
+    pub fn key(&self, mode: TunnelMode) -> TunnelKey {
+        TunnelKey {
+            kind: self.kind,
+            id: (mode == TunnelMode::Session).then_some(self.id),
+        }
+    }
+}
+
+/// Identifies the tunnel of a flow, without an id when each tunnel is a single flow
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct TunnelKey {
+    pub kind: TunnelKind,
+    pub id: Option<u32>,
+}
+
+impl fmt::Display for TunnelKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            TunnelKind::Esp => "esp",
+            TunnelKind::WireGuard => "wireguard",
+            TunnelKind::Vxlan => "vxlan",
+            TunnelKind::Gre => "gre",
+            TunnelKind::Gtp => "gtp",
+        };
+        match (self.kind, self.id) {
+            // VNIs are 24 bits, and written in decimal
+            (TunnelKind::Vxlan, Some(id)) => write!(f, "{kind} {id}"),
+            (_, Some(id)) => write!(f, "{kind} 0x{id:08x}"),
+            (_, None) => write!(f, "{kind}"),
+        }
+    }
+}
+
+/// Parse a hexadecimal field, such as 0x0000abcd
+pub(super) fn parse_hex(field: &str) -> Result<u32, BurstsharkError> {
+    Ok(u32::from_str_radix(field.trim_start_matches("0x"), 16)?)
+}
+
+/// Parse a field printed in decimal, or in hexadecimal such as 0x0000abcd
+fn parse_int(field: &str) -> Result<u32, BurstsharkError> {
+    let field = innermost(field);
+    match field.strip_prefix("0x") {
+        Some(hex) => Ok(u32::from_str_radix(hex, 16)?),
+        None => Ok(field.parse::<u32>()?),
+    }
+}
+
+/// The last of the comma-separated values tshark prints for a field of each header of a packet,
+/// which is that of the innermost header
+pub(super) fn innermost(field: &str) -> &str {
+    field.rsplit(',').next().unwrap_or(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{innermost, Tunnel, TunnelKind, TunnelMode};
+
+    #[test]
+    fn esp_and_wireguard_packets_are_tunnel_packets() {
+        let esp = Tunnel::from_tshark("0x0000abcd", "", "", 1400)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (esp.kind, esp.id, esp.overhead),
+            (TunnelKind::Esp, 0xabcd, 34)
+        );
+
+        let data = Tunnel::from_tshark("", "4", "0x12345678", 1400)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (data.kind, data.id, data.overhead),
+            (TunnelKind::WireGuard, 0x12345678, 32)
+        );
+        // Handshake messages are all overhead
+        let response = Tunnel::from_tshark("", "2", "0x12345678", 92)
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.overhead, 92);
+
+        assert!(Tunnel::from_tshark("", "1", "", 148).unwrap().is_none());
+        assert!(Tunnel::from_tshark("0xnope", "", "", 100).is_err());
+    }
+
+    #[test]
+    fn overhead_is_at_most_the_packet() {
+        assert_eq!(Tunnel::esp(1, 20).overhead, 20);
+        assert_eq!(Tunnel::wireguard(4, 1, 16).overhead, 16);
+    }
+
+    #[test]
+    fn single_tunnels_are_keyed_without_their_id() {
+        let tunnel = Tunnel::esp(0xabcd, 100);
+        assert_eq!(
+            tunnel.key(TunnelMode::Session).to_string(),
+            "esp 0x0000abcd"
+        );
+        assert_eq!(tunnel.key(TunnelMode::Single).to_string(), "esp");
+        let vxlan = Tunnel::encapsulation(TunnelKind::Vxlan, 42, 50);
+        assert_eq!(vxlan.key(TunnelMode::Session).to_string(), "vxlan 42");
+        assert!(vxlan.decapsulated() && !tunnel.decapsulated());
+    }
+
+    #[test]
+    fn the_innermost_header_is_printed_last() {
+        assert_eq!(innermost("60,40"), "40");
+        assert_eq!(innermost("40"), "40");
+    }
+
+    #[test]
+    fn decapsulated_packets_are_marked_with_their_tunnel() {
+        // An inner IPv4 packet of 100 bytes in VXLAN, after the UDP, VXLAN and ethernet headers
+        let vxlan = Tunnel::from_tshark_encapsulation("42", "", "", "", "150,100", "20,20")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (vxlan.kind, vxlan.id, vxlan.overhead),
+            (TunnelKind::Vxlan, 42, 30)
+        );
+        assert!(vxlan.decapsulated());
+
+        let gre = Tunnel::from_tshark_encapsulation("", "0x0800", "", "", "", "")
+            .unwrap()
+            .unwrap();
+        assert_eq!((gre.kind, gre.id, gre.overhead), (TunnelKind::Gre, 0, 0));
+        let gtp = Tunnel::from_tshark_encapsulation("", "", "", "0x00001234", "", "")
+            .unwrap()
+            .unwrap();
+        assert_eq!((gtp.kind, gtp.id), (TunnelKind::Gtp, 0x1234));
+
+        assert!(
+            Tunnel::from_tshark_encapsulation("", "", "", "", "100", "20")
+                .unwrap()
+                .is_none()
+        );
+        assert!(!Tunnel::esp(1, 100).decapsulated());
+    }
+
+    #[test]
+    fn vnis_are_shown_in_decimal() {
+        let vxlan = Tunnel::encapsulation(TunnelKind::Vxlan, 42, 30);
+        assert_eq!(vxlan.key(TunnelMode::Session).to_string(), "vxlan 42");
+        let gre = Tunnel::encapsulation(TunnelKind::Gre, 7, 8);
+        assert_eq!(gre.key(TunnelMode::Session).to_string(), "gre 0x00000007");
+    }
+}
@@ -0,0 +1,343 @@
+//! Channels between the threads of a capture, from the reader to the burst workers and from them
+//! to the output.
+//!
+//! Channels are unbounded unless given a capacity. When a bounded channel is full, senders either
+//! wait for room, so that a slow output slows the capture down rather than buffering without limit,
+//! or drop the oldest item to make room, which keeps the capture going and counts the dropped items.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+
+/// What senders do when a bounded channel is full
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Overflow {
+    /// Wait for the receiver to take an item
+    #[default]
+    Block,
+    /// Drop the oldest item in the channel
+    DropOldest,
+}
+
+/// Capacity of channels, and the count of the items dropped from all channels created with them
+#[derive(Clone, Debug, Default)]
+pub struct ChannelOptions {
+    /// Items a channel holds before it is full, or None for unbounded channels
+    pub capacity: Option<usize>,
+    pub overflow: Overflow,
+    pub dropped: Arc<AtomicU64>,
+}
+
+impl ChannelOptions {
+    /// Items dropped as channels were full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receiver: bool,
+    /// Whether the receiver waits for an item, and how many senders wait for room, so that
+    /// neither is woken up needlessly
+    receiver_waiting: bool,
+    senders_waiting: usize,
+}
+
+/// A bounded channel, shared by its senders and receiver
+pub struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signalled when an item is queued or the last sender is dropped
+    items: Condvar,
+    /// Signalled when an item is taken or the receiver is dropped
+    room: Condvar,
+    opts: ChannelOptions,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A channel with the capacity and overflow of the options
+pub fn channel<T>(opts: &ChannelOptions) -> (Sender<T>, Receiver<T>) {
+    if opts.capacity.is_none() {
+        return unbounded();
+    }
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            senders: 1,
+            receiver: true,
+            receiver_waiting: false,
+            senders_waiting: 0,
+        }),
+        items: Condvar::new(),
+        room: Condvar::new(),
+        opts: opts.clone(),
+    });
+    (Sender::Bounded(shared.clone()), Receiver::Bounded(shared))
+}
+
+/// A channel without a capacity, which is a channel of the standard library
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = mpsc::channel();
+    (Sender::Unbounded(tx), Receiver::Unbounded(rx))
+}
+
+/// The sending half of a channel, which can be cloned to send from several threads
+pub enum Sender<T> {
+    Unbounded(mpsc::Sender<T>),
+    Bounded(Arc<Shared<T>>),
+}
+
+impl<T> Sender<T> {
+    /// Queue an item, which fails with the item if the receiver is gone
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let shared = match self {
+            Sender::Unbounded(tx) => return tx.send(item),
+            Sender::Bounded(shared) => shared,
+        };
+        let mut state = shared.lock();
+        if let Some(capacity) = shared.opts.capacity {
+            while state.receiver && state.queue.len() >= capacity {
+                match shared.opts.overflow {
+                    Overflow::Block => {
+                        state.senders_waiting += 1;
+                        state = shared
+                            .room
+                            .wait(state)
+                            .unwrap_or_else(PoisonError::into_inner);
+                        state.senders_waiting -= 1;
+                    }
+                    Overflow::DropOldest => {
+                        state.queue.pop_front();
+                        shared.opts.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        if !state.receiver {
+            return Err(SendError(item));
+        }
+
+        state.queue.push_back(item);
+        if state.receiver_waiting {
+            shared.items.notify_one();
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Sender::Unbounded(tx) => Sender::Unbounded(tx.clone()),
+            Sender::Bounded(shared) => {
+                shared.lock().senders += 1;
+                Sender::Bounded(shared.clone())
+            }
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if let Sender::Bounded(shared) = self {
+            let mut state = shared.lock();
+            state.senders -= 1;
+            if state.senders == 0 {
+                shared.items.notify_all();
+            }
+        }
+    }
+}
+
+/// The receiving half of a channel
+pub enum Receiver<T> {
+    Unbounded(mpsc::Receiver<T>),
+    Bounded(Arc<Shared<T>>),
+}
+
+impl<T> Receiver<T> {
+    /// Take the next item, waiting until one is sent or all senders are gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        match self {
+            Receiver::Unbounded(rx) => rx.recv(),
+            Receiver::Bounded(shared) => shared.recv_until(None).map_err(|_| RecvError),
+        }
+    }
+
+    /// Take the next item, waiting at most the timeout
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        match self {
+            Receiver::Unbounded(rx) => rx.recv_timeout(timeout),
+            Receiver::Bounded(shared) => shared.recv_until(Some(Instant::now() + timeout)),
+        }
+    }
+
+    /// Take the next item if there is one, without waiting
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let shared = match self {
+            Receiver::Unbounded(rx) => return rx.try_recv(),
+            Receiver::Bounded(shared) => shared,
+        };
+        let mut state = shared.lock();
+        match state.queue.pop_front() {
+            Some(item) => {
+                if state.senders_waiting > 0 {
+                    shared.room.notify_one();
+                }
+                Ok(item)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// The items sent until all senders are gone
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// The items already in the channel
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+}
+
+impl<T> Shared<T> {
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = self.lock();
+        loop {
+            if let Some(item) = state.queue.pop_front() {
+                if state.senders_waiting > 0 {
+                    self.room.notify_one();
+                }
+                return Ok(item);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            state.receiver_waiting = true;
+            state = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if timeout.is_zero() {
+                        state.receiver_waiting = false;
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    self.items
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .items
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+            state.receiver_waiting = false;
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if let Receiver::Bounded(shared) = self {
+            let mut state = shared.lock();
+            state.receiver = false;
+            state.queue.clear();
+            shared.room.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{channel, ChannelOptions, Overflow, RecvTimeoutError, TryRecvError};
+
+    fn bounded(capacity: usize, overflow: Overflow) -> ChannelOptions {
+        ChannelOptions {
+            capacity: Some(capacity),
+            overflow,
+            ..ChannelOptions::default()
+        }
+    }
+
+    #[test]
+    fn full_channels_drop_the_oldest_items_when_asked() {
+        let opts = bounded(2, Overflow::DropOldest);
+        let (tx, rx) = channel(&opts);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(opts.dropped(), 3);
+    }
+
+    #[test]
+    fn full_channels_block_senders_until_there_is_room() {
+        let opts = bounded(1, Overflow::Block);
+        let (tx, rx) = channel(&opts);
+        let sender = thread::spawn(move || {
+            for i in 0..100 {
+                tx.send(i).unwrap();
+            }
+        });
+        assert_eq!(rx.iter().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+        sender.join().unwrap();
+        assert_eq!(opts.dropped(), 0);
+    }
+
+    #[test]
+    fn receivers_see_when_the_senders_are_gone() {
+        let (tx, rx) = channel(&bounded(4, Overflow::Block));
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        drop(tx2);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn senders_see_when_the_receiver_is_gone() {
+        let (tx, rx) = channel(&bounded(1, Overflow::Block));
+        tx.send(1).unwrap();
+        let sender = thread::spawn(move || tx.send(2));
+        // The blocked sender is woken up with its item
+        thread::sleep(Duration::from_millis(10));
+        drop(rx);
+        assert_eq!(sender.join().unwrap().unwrap_err().0, 2);
+    }
+
+    #[test]
+    fn channels_without_a_capacity_are_unbounded() {
+        let (tx, rx) = channel(&ChannelOptions::default());
+        for i in 0..1000 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(rx.try_iter().count(), 1000);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::capture::Burst;
+
+/// Recent bursts of a flow its class is inferred from
+const WINDOW: usize = 16;
+
+/// Bursts of a flow needed for full confidence in its class
+const CONFIDENT_BURSTS: usize = 8;
+
+/// Lowest share of the conditions of a class a flow must meet to be labeled with it
+const MIN_SCORE: f64 = 0.5;
+
+/// Seconds between pruning flows without recent bursts
+const PRUNE_INTERVAL: f64 = 60.0;
+
+/// Seconds without bursts after which the class of a flow is forgotten
+const FLOW_TIMEOUT: f64 = 300.0;
+
+/// Kind of traffic a flow is inferred to carry from the pattern of its bursts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlowClass {
+    /// Large bursts at a regular period of seconds, as players fetch segments
+    VideoStreaming,
+    /// Long bursts of small packets at a steady rate
+    Voip,
+    /// Long bursts of full packets with many bytes
+    BulkDownload,
+    /// Medium bursts at irregular times, as pages and their resources are loaded
+    WebBrowsing,
+    /// Few small packets now and then, such as keepalives and notifications
+    IdleChatter,
+}
+
+impl fmt::Display for FlowClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            FlowClass::VideoStreaming => "video-streaming",
+            FlowClass::Voip => "voip",
+            FlowClass::BulkDownload => "bulk-download",
+            FlowClass::WebBrowsing => "web-browsing",
+            FlowClass::IdleChatter => "idle-chatter",
+        })
+    }
+}
+
+/// Source and destination address and port of a burst
+type Direction = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+/// Characteristics of the recent bursts of a flow
+struct Features {
+    /// Mean bytes of a burst
+    size: f64,
+    /// Mean packets of a burst
+    packets: f64,
+    /// Mean bytes of a packet
+    packet_size: f64,
+    /// Mean seconds a burst lasts
+    duration: f64,
+    /// Packets per second within the bursts
+    packet_rate: f64,
+    /// Mean seconds between the starts of bursts, and its coefficient of variation, once a flow
+    /// has a few bursts
+    period: Option<(f64, f64)>,
+}
+
+impl Features {
+    fn of(bursts: &VecDeque<(f64, f64, u32, u16)>) -> Self {
+        let n = bursts.len() as f64;
+        let bytes: f64 = bursts.iter().map(|(_, _, size, _)| *size as f64).sum();
+        let packets: f64 = bursts
+            .iter()
+            .map(|(_, _, _, packets)| *packets as f64)
+            .sum();
+        let duration: f64 = bursts.iter().map(|(start, end, _, _)| end - start).sum();
+
+        let intervals: Vec<f64> = bursts
+            .iter()
+            .zip(bursts.iter().skip(1))
+            .map(|((a, ..), (b, ..))| b - a)
+            .collect();
+        let period = (intervals.len() >= 2).then(|| {
+            let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+            let variance =
+                intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+            let cv = match mean > f64::EPSILON {
+                true => variance.sqrt() / mean,
+                false => f64::INFINITY,
+            };
+            (mean, cv)
+        });
+
+        Features {
+            size: bytes / n,
+            packets: packets / n,
+            packet_size: bytes / packets.max(1.0),
+            duration: duration / n,
+            packet_rate: match duration > f64::EPSILON {
+                true => packets / duration,
+                false => 0.0,
+            },
+            period,
+        }
+    }
+
+    /// Share of the conditions of the class the flow meets
+    fn score(&self, class: FlowClass) -> f64 {
+        let regular = |max_cv| self.period.is_some_and(|(_, cv)| cv < max_cv);
+        let conditions = match class {
+            FlowClass::VideoStreaming => vec![
+                self.size >= 50_000.0,
+                self.packet_size >= 1_000.0,
+                self.period
+                    .is_some_and(|(period, _)| (0.5..=15.0).contains(&period)),
+                regular(0.5),
+            ],
+            FlowClass::Voip => vec![
+                self.packet_size < 300.0,
+                (20.0..=120.0).contains(&self.packet_rate),
+                self.duration >= 0.5,
+            ],
+            FlowClass::BulkDownload => vec![
+                self.packet_size >= 1_000.0,
+                self.size >= 1_000_000.0,
+                self.duration >= 1.0,
+            ],
+            FlowClass::WebBrowsing => vec![
+                (1_000.0..2_000_000.0).contains(&self.size),
+                self.packet_size >= 300.0,
+                self.period.is_some_and(|(_, cv)| cv >= 0.5),
+            ],
+            FlowClass::IdleChatter => vec![
+                self.size < 1_000.0,
+                self.packets <= 5.0,
+                self.period.is_some_and(|(period, _)| period >= 1.0),
+            ],
+        };
+        conditions.iter().filter(|met| **met).count() as f64 / conditions.len() as f64
+    }
+}
+
+struct FlowHistory {
+    /// Start, end, size and packets of the recent bursts
+    bursts: VecDeque<(f64, f64, u32, u16)>,
+    last_time: f64,
+}
+
+/// Labels the bursts of each flow with the class of traffic that best matches its recent bursts,
+/// and the confidence in it.
+///
+/// Classes are scored by rules on the sizes, rates and periods of the bursts. The confidence is the
+/// share of the rules of the class that are met, lowered for flows with few bursts yet.
+#[derive(Default)]
+pub struct FlowClassifier {
+    flows: HashMap<Direction, FlowHistory>,
+    last_prune: f64,
+}
+
+impl FlowClassifier {
+    pub fn observe(&mut self, burst: &mut Burst) {
+        let direction = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+        );
+        let flow = self.flows.entry(direction).or_insert_with(|| FlowHistory {
+            bursts: VecDeque::with_capacity(WINDOW),
+            last_time: burst.completion_time,
+        });
+        if flow.bursts.len() == WINDOW {
+            flow.bursts.pop_front();
+        }
+        flow.bursts
+            .push_back((burst.start, burst.end, burst.size, burst.num_packets));
+        flow.last_time = burst.completion_time;
+
+        let features = Features::of(&flow.bursts);
+        // Earlier classes win ties, as their rules are more specific
+        let (class, score) = [
+            FlowClass::Voip,
+            FlowClass::VideoStreaming,
+            FlowClass::BulkDownload,
+            FlowClass::IdleChatter,
+            FlowClass::WebBrowsing,
+        ]
+        .into_iter()
+        .map(|class| (class, features.score(class)))
+        .fold((FlowClass::WebBrowsing, 0.0), |best, candidate| {
+            match candidate.1 > best.1 {
+                true => candidate,
+                false => best,
+            }
+        });
+        if score >= MIN_SCORE {
+            let evidence = (flow.bursts.len() as f64 / CONFIDENT_BURSTS as f64).min(1.0);
+            burst.class = Some(class);
+            burst.class_confidence = Some(score * evidence);
+        }
+
+        if burst.completion_time - self.last_prune >= PRUNE_INTERVAL {
+            let oldest = burst.completion_time - FLOW_TIMEOUT;
+            self.flows.retain(|_, flow| flow.last_time >= oldest);
+            self.last_prune = burst.completion_time;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlowClass, FlowClassifier};
+    use crate::capture::Burst;
+
+    /// Classify bursts of the flow starting every `period` seconds, returning the last
+    fn classify(n: usize, period: f64, duration: f64, size: u32, packets: u16) -> Burst {
+        let mut classifier = FlowClassifier::default();
+        let mut last = None;
+        for i in 0..n {
+            let start = i as f64 * period;
+            let mut burst = Burst {
+                num_packets: packets,
+                ..Burst::test(
+                    "10.0.0.1:443",
+                    "10.0.0.2:50000",
+                    start,
+                    start + duration,
+                    size,
+                )
+            };
+            classifier.observe(&mut burst);
+            last = Some(burst);
+        }
+        last.unwrap()
+    }
+
+    #[test]
+    fn regular_large_bursts_are_video() {
+        let burst = classify(8, 2.0, 0.3, 100_000, 80);
+        assert_eq!(burst.class, Some(FlowClass::VideoStreaming));
+        assert_eq!(burst.class_confidence, Some(1.0));
+    }
+
+    #[test]
+    fn long_bursts_of_small_packets_are_voip() {
+        let burst = classify(8, 1.5, 1.0, 10_000, 50);
+        assert_eq!(burst.class, Some(FlowClass::Voip));
+    }
+
+    #[test]
+    fn rare_small_bursts_are_idle_chatter() {
+        let burst = classify(8, 30.0, 0.0, 100, 1);
+        assert_eq!(burst.class, Some(FlowClass::IdleChatter));
+    }
+
+    #[test]
+    fn confidence_grows_with_the_bursts() {
+        let burst = classify(4, 2.0, 0.3, 100_000, 80);
+        assert_eq!(burst.class, Some(FlowClass::VideoStreaming));
+        assert_eq!(burst.class_confidence, Some(0.5));
+    }
+
+    #[test]
+    fn flows_are_classified_per_direction() {
+        let mut classifier = FlowClassifier::default();
+        for i in 0..8 {
+            let start = i as f64 * 2.0;
+            let mut video = Burst {
+                num_packets: 80,
+                ..Burst::test(
+                    "10.0.0.1:443",
+                    "10.0.0.2:50000",
+                    start,
+                    start + 0.3,
+                    100_000,
+                )
+            };
+            classifier.observe(&mut video);
+            let mut request = Burst::test("10.0.0.2:50000", "10.0.0.1:443", start, start, 100);
+            classifier.observe(&mut request);
+            assert_ne!(request.class, Some(FlowClass::VideoStreaming));
+        }
+    }
+}
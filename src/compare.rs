@@ -0,0 +1,265 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use serde::Serialize;
+
+use crate::{
+    capture::{Burst, Record},
+    channel,
+};
+
+/// Side of the middlebox a burst was captured on
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Before,
+    After,
+}
+
+/// Addresses and ports of a flow, the same on both sides of the middlebox
+type FlowKey = (Arc<str>, Arc<str>, Option<u16>, Option<u16>);
+
+/// How a burst changed passing through a middlebox, from matching it between captures on both sides
+#[derive(Debug, Clone, Serialize)]
+pub struct BurstDelta {
+    /// Completion time of the burst after the middlebox
+    pub time: f64,
+    pub src: Arc<str>,
+    pub dst: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    /// Start of the burst before the middlebox
+    pub start: f64,
+    /// Seconds the start and end of the burst were delayed by
+    pub latency: f64,
+    pub end_latency: f64,
+    /// Bytes and packets added to the burst, negative if removed
+    pub size_change: i64,
+    pub packet_change: i32,
+}
+
+impl BurstDelta {
+    fn new(before: &Burst, after: &Burst) -> Self {
+        BurstDelta {
+            time: after.completion_time,
+            src: before.src.clone(),
+            dst: before.dst.clone(),
+            src_port: before.src_port,
+            dst_port: before.dst_port,
+            start: before.start,
+            latency: after.start - before.start,
+            end_latency: after.end - before.end,
+            size_change: after.size as i64 - before.size as i64,
+            packet_change: after.num_packets as i32 - before.num_packets as i32,
+        }
+    }
+}
+
+/// Matches the bursts of two captures of the same traffic, before and after a middlebox.
+///
+/// The bursts of a flow are paired in order, as long as they start within the window of each
+/// other. Bursts without a match in time, such as those dropped by the middlebox, are discarded.
+/// Only the deltas of matched bursts are sent on, while other records are dropped.
+pub struct BurstComparison {
+    handle: JoinHandle<()>,
+}
+
+impl BurstComparison {
+    /// Start matching bursts, returning the senders for the captures before and after the middlebox.
+    /// The captures need epoch times, so the times of both are comparable.
+    pub fn start(
+        window: f64,
+        output_tx: channel::Sender<Record>,
+    ) -> (channel::Sender<Record>, channel::Sender<Record>, Self) {
+        let (tx, rx) = mpsc::channel();
+        let before = forward(Side::Before, tx.clone());
+        let after = forward(Side::After, tx);
+
+        let handle = thread::spawn(move || {
+            let mut matcher = Matcher::new(window);
+            for (side, burst) in rx {
+                if let Some(delta) = matcher.add(side, burst) {
+                    if output_tx.send(Record::BurstDelta(delta)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        (before, after, BurstComparison { handle })
+    }
+
+    /// Wait until the bursts of both captures are matched
+    pub fn stop(self) {
+        let _ = self.handle.join();
+    }
+}
+
+/// A sender for a capture, whose bursts are tagged with its side of the middlebox
+fn forward(side: Side, tx: Sender<(Side, Burst)>) -> channel::Sender<Record> {
+    let (record_tx, record_rx) = channel::unbounded();
+    thread::spawn(move || {
+        for record in record_rx.iter() {
+            if let Record::Burst(burst) = record {
+                if tx.send((side, burst)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    record_tx
+}
+
+/// Unmatched bursts of a flow, of the side that is ahead of the other
+struct Pending {
+    side: Side,
+    bursts: VecDeque<Burst>,
+}
+
+struct Matcher {
+    window: f64,
+    flows: HashMap<FlowKey, Pending>,
+    /// Completion time of the latest burst of each side, and when all flows were last pruned
+    latest: [f64; 2],
+    last_prune: f64,
+}
+
+impl Matcher {
+    fn new(window: f64) -> Self {
+        Matcher {
+            window,
+            flows: HashMap::new(),
+            latest: [f64::NEG_INFINITY; 2],
+            last_prune: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a burst, and return its delta if it matches a pending burst of the other side
+    fn add(&mut self, side: Side, burst: Burst) -> Option<BurstDelta> {
+        let latest = &mut self.latest[side as usize];
+        *latest = latest.max(burst.completion_time);
+        if self.progress() - self.last_prune > self.window {
+            self.prune();
+        }
+
+        let key = (
+            burst.src.clone(),
+            burst.dst.clone(),
+            burst.src_port,
+            burst.dst_port,
+        );
+        let pending = self.flows.entry(key).or_insert_with(|| Pending {
+            side,
+            bursts: VecDeque::new(),
+        });
+
+        if pending.side == side || pending.bursts.is_empty() {
+            pending.side = side;
+            pending.bursts.push_back(burst);
+            return None;
+        }
+
+        // Bursts of the other side that started too long before this one were never matched
+        while let Some(other) = pending.bursts.front() {
+            if burst.start - other.start <= self.window {
+                break;
+            }
+            pending.bursts.pop_front();
+        }
+
+        match pending.bursts.front() {
+            Some(other) if (other.start - burst.start).abs() <= self.window => {
+                let other = pending.bursts.pop_front().unwrap();
+                Some(match side {
+                    Side::After => BurstDelta::new(&other, &burst),
+                    Side::Before => BurstDelta::new(&burst, &other),
+                })
+            }
+            // The pending bursts started too long after this one, which has no match
+            Some(_) => None,
+            None => {
+                pending.side = side;
+                pending.bursts.push_back(burst);
+                None
+            }
+        }
+    }
+
+    /// Completion time both sides have reached, as one side may lag behind the other
+    fn progress(&self) -> f64 {
+        self.latest[0].min(self.latest[1])
+    }
+
+    /// Forget the bursts too old to be matched by any burst still to come
+    fn prune(&mut self) {
+        let (latest, window) = (self.progress(), self.window);
+        self.flows.retain(|_, pending| {
+            pending
+                .bursts
+                .retain(|burst| latest - burst.completion_time <= window);
+            !pending.bursts.is_empty()
+        });
+        self.last_prune = latest;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Matcher, Side};
+    use crate::capture::Burst;
+
+    fn burst(start: f64, size: u32) -> Burst {
+        Burst::test("10.0.0.1:50000", "10.0.0.2:443", start, start + 0.1, size)
+    }
+
+    #[test]
+    fn bursts_are_matched_across_the_middlebox() {
+        let mut matcher = Matcher::new(0.5);
+        assert!(matcher.add(Side::Before, burst(1.0, 1000)).is_none());
+        let delta = matcher.add(Side::After, burst(1.02, 1040)).unwrap();
+        assert!((delta.latency - 0.02).abs() < 1e-9);
+        assert!((delta.end_latency - 0.02).abs() < 1e-9);
+        assert_eq!(
+            (delta.start, delta.size_change, delta.packet_change),
+            (1.0, 40, 0)
+        );
+    }
+
+    #[test]
+    fn either_capture_can_be_ahead() {
+        let mut matcher = Matcher::new(0.5);
+        assert!(matcher.add(Side::After, burst(1.02, 900)).is_none());
+        assert!(matcher.add(Side::After, burst(2.02, 900)).is_none());
+        let first = matcher.add(Side::Before, burst(1.0, 1000)).unwrap();
+        let second = matcher.add(Side::Before, burst(2.0, 1000)).unwrap();
+        assert_eq!((first.start, first.size_change), (1.0, -100));
+        assert_eq!(second.start, 2.0);
+    }
+
+    #[test]
+    fn dropped_bursts_are_skipped() {
+        let mut matcher = Matcher::new(0.5);
+        // The first burst never made it through the middlebox
+        matcher.add(Side::Before, burst(1.0, 1000));
+        matcher.add(Side::Before, burst(3.0, 2000));
+        let delta = matcher.add(Side::After, burst(3.01, 2000)).unwrap();
+        assert_eq!((delta.start, delta.size_change), (3.0, 0));
+
+        // A burst after the middlebox with nothing near it before
+        matcher.add(Side::Before, burst(6.0, 1000));
+        assert!(matcher.add(Side::After, burst(5.0, 1000)).is_none());
+    }
+
+    #[test]
+    fn other_flows_are_not_matched() {
+        let mut matcher = Matcher::new(0.5);
+        matcher.add(Side::Before, burst(1.0, 1000));
+        let other = Burst::test("10.0.0.1:50001", "10.0.0.2:443", 1.0, 1.1, 1000);
+        assert!(matcher.add(Side::After, other).is_none());
+    }
+}
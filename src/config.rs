@@ -0,0 +1,228 @@
+//! Named profiles of command line options, read from a TOML file so that the options of a
+//! measurement campaign can be given by name rather than spelled out each time:
+//!
+//! ```toml
+//! [profiles.streaming]
+//! interface = ["wlan0"]
+//! inactive_time = 0.5
+//! output_format = "jsonl"
+//! display_filter = "udp.port == 443"
+//! ignore_ports = true
+//! ```
+//!
+//! Keys are the long names of the options, with underscores or dashes. Flags are set by `true`,
+//! options given several times by arrays, and all others by a single value. Options set on the
+//! command line, or by their environment variable, take the place of those of the profile.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{parser::ValueSource, ArgMatches, Command};
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use burstshark::BurstsharkError;
+
+/// Options that select the profile, and can not be set by one
+const SELECTING: [&str; 2] = ["config", "profile"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, Table>,
+}
+
+/// The configuration file in the user's configuration directory, `$XDG_CONFIG_HOME` or `~/.config`
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("burstshark").join("config.toml"))
+}
+
+/// The command line arguments of a profile in the configuration file, checked against the options
+/// of the command, without the options already set in `given`
+pub fn profile_args(
+    path: &Path,
+    profile: &str,
+    command: &Command,
+    given: &ArgMatches,
+) -> Result<Vec<OsString>, BurstsharkError> {
+    let content = fs::read_to_string(path);
+    let path = path.display().to_string();
+    let content = content.map_err(|source| BurstsharkError::File {
+        action: "read config file",
+        path: path.clone(),
+        source,
+    })?;
+    let invalid = |reason| BurstsharkError::InvalidFile {
+        path: path.clone(),
+        reason,
+    };
+    let mut file: ConfigFile =
+        toml::from_str(&content).map_err(|e| invalid(format!("invalid config file: {e}")))?;
+    let options = file
+        .profiles
+        .remove(profile)
+        .ok_or_else(|| invalid(format!("no profile {profile}")))?;
+
+    let mut args = Vec::new();
+    for (key, value) in options {
+        let name = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()))
+            .filter(|_| !SELECTING.contains(&name.as_str()))
+            .ok_or_else(|| invalid(format!("unknown option {key} in profile {profile}")))?;
+        let option = format!("--{name}");
+        if let Some(ValueSource::CommandLine | ValueSource::EnvVariable) =
+            given.value_source(arg.get_id().as_str())
+        {
+            continue;
+        }
+
+        match value {
+            Value::Boolean(set) if !arg.get_action().takes_values() => {
+                if set {
+                    args.push(option.into());
+                }
+            }
+            _ if !arg.get_action().takes_values() => {
+                return Err(invalid(format!(
+                    "option {key} in profile {profile} is a flag, set by true or false"
+                )));
+            }
+            Value::Array(values) => {
+                for value in values {
+                    args.push(option.clone().into());
+                    args.push(scalar(&value, &key, profile).map_err(invalid)?.into());
+                }
+            }
+            value => {
+                args.push(option.into());
+                args.push(scalar(&value, &key, profile).map_err(invalid)?.into());
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// A value of an option as it would be written on the command line
+fn scalar(value: &Value, key: &str, profile: &str) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err(format!(
+            "option {key} in profile {profile} has a value that is not a string, number or boolean"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use clap::{Arg, ArgAction, Command};
+
+    use super::profile_args;
+
+    fn command() -> Command {
+        Command::new("burstshark")
+            .arg(
+                Arg::new("interface")
+                    .long("interface")
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("inactive_time").long("inactive-time"))
+            .arg(
+                Arg::new("ignore_ports")
+                    .long("ignore-ports")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("config").long("config"))
+            .arg(Arg::new("profile").long("profile"))
+    }
+
+    /// The arguments of the profile in a config file of its own for the test, with the arguments
+    /// given on the command line
+    fn args(
+        test: &str,
+        config: &str,
+        profile: &str,
+        given: &[&str],
+    ) -> Result<Vec<String>, String> {
+        let path = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        fs::write(&path, config).unwrap();
+        let given = command().get_matches_from([&["burstshark"], given].concat());
+        let args = profile_args(&path, profile, &command(), &given);
+        let _ = fs::remove_file(path);
+        args.map(|args| {
+            args.into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect()
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    const CONFIG: &str = r#"
+[profiles.streaming]
+interface = ["wlan0", "wlan1"]
+inactive-time = 0.5
+ignore_ports = true
+
+[profiles.quiet]
+ignore_ports = false
+"#;
+
+    #[test]
+    fn profiles_are_options_of_the_command_line() {
+        assert_eq!(
+            args("config-profile", CONFIG, "streaming", &[]).unwrap(),
+            [
+                "--ignore-ports",
+                "--inactive-time",
+                "0.5",
+                "--interface",
+                "wlan0",
+                "--interface",
+                "wlan1"
+            ]
+        );
+        assert_eq!(
+            args("config-unset", CONFIG, "quiet", &[]).unwrap(),
+            [] as [&str; 0]
+        );
+    }
+
+    #[test]
+    fn options_given_take_the_place_of_the_profile() {
+        let given = ["--inactive-time", "2", "--interface", "eth0"];
+        assert_eq!(
+            args("config-given", CONFIG, "streaming", &given).unwrap(),
+            ["--ignore-ports"]
+        );
+    }
+
+    #[test]
+    fn invalid_profiles_are_refused() {
+        let missing = args("config-missing", CONFIG, "video", &[]).unwrap_err();
+        assert!(missing.contains("no profile video"), "{missing}");
+
+        let unknown = "[profiles.a]\nworkers = 4\n";
+        assert!(args("config-unknown", unknown, "a", &[]).is_err());
+        let selecting = "[profiles.a]\nprofile = \"b\"\n";
+        assert!(args("config-selecting", selecting, "a", &[]).is_err());
+        let flag = "[profiles.a]\nignore_ports = \"yes\"\n";
+        assert!(args("config-flag", flag, "a", &[]).is_err());
+        let table = "[profiles.a]\ninactive_time = { seconds = 1 }\n";
+        assert!(args("config-table", table, "a", &[]).is_err());
+    }
+}
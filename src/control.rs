@@ -0,0 +1,390 @@
+//! Changing the filters and output of a running capture, without restarting it and losing the
+//! state of its flows.
+//!
+//! Settings are changed by commands sent as lines to a Unix socket, each answered by any lines it
+//! prints followed by `ok`, or by `error: ` and what went wrong:
+//!
+//! ```text
+//! show                     print the settings
+//! set <setting> <value>    change a setting
+//! unset <setting>          clear a filter, or the suppression of the standard output
+//! reload                   go back to the settings of the command line and profile, re-reading
+//!                          the profile from the config file
+//! ```
+//!
+//! The settings are `min-bytes`, `max-bytes`, `min-packets` and `max-packets` of the bursts to
+//! output, `suppress` of the standard output, and `capture-filter`. The capture filter is changed
+//! by restarting tshark with it, so it can only be changed for live captures by tshark, and the
+//! packets captured while tshark restarts are missed.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{capture::BurstFilter, socket, BurstsharkError};
+
+/// Settings of a capture that can be changed while it runs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuntimeSettings {
+    /// Bursts to output
+    pub filter: BurstFilter,
+    /// Leave the bursts out of the standard output
+    pub suppress: bool,
+    /// Capture filter as given, and the filter tshark captures with when it was changed, merged
+    /// with the default filter for data packets
+    pub capture_filter: Option<String>,
+    pub tshark_filter: Option<String>,
+}
+
+/// The settings shared by the stages of a capture.
+///
+/// Each change of the settings increases their version, which the stages check as they handle
+/// packets and records, taking up all settings of a change at once.
+pub struct Controls {
+    version: AtomicU64,
+    settings: Mutex<RuntimeSettings>,
+}
+
+impl Controls {
+    pub fn new(settings: RuntimeSettings) -> Self {
+        Controls {
+            version: AtomicU64::new(0),
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> RuntimeSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Change the settings, unless `change` fails
+    pub fn update<E>(
+        &self,
+        change: impl FnOnce(&mut RuntimeSettings) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut settings = self.settings.lock().unwrap();
+        let mut changed = settings.clone();
+        change(&mut changed)?;
+        if changed != *settings {
+            *settings = changed;
+            self.version.fetch_add(1, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// The settings if they changed since the version last seen, which is moved to theirs
+    pub fn changed(&self, seen: &mut u64) -> Option<RuntimeSettings> {
+        if self.version.load(Ordering::Acquire) == *seen {
+            return None;
+        }
+        let settings = self.settings.lock().unwrap();
+        *seen = self.version.load(Ordering::Acquire);
+        Some(settings.clone())
+    }
+}
+
+/// The configuration of the capture the commands depend on
+pub trait Configuration: Send + Sync + 'static {
+    /// The settings of the command line and profile, re-reading the config file
+    fn reload(&self) -> Result<RuntimeSettings, BurstsharkError>;
+
+    /// The filter to restart tshark with for the capture filter, along with the default filter for
+    /// data packets, or why tshark can not be restarted
+    fn tshark_filter(&self, capture_filter: Option<&str>) -> Result<String, BurstsharkError>;
+}
+
+/// A Unix socket taking commands to change the settings, which is removed when dropped
+pub struct ControlSocket {
+    path: String,
+}
+
+impl ControlSocket {
+    pub fn start(
+        path: &str,
+        controls: Arc<Controls>,
+        config: impl Configuration,
+    ) -> Result<Self, BurstsharkError> {
+        // Only the user running the capture may change it
+        let listener = socket::bind_private(path).map_err(|source| BurstsharkError::Listen {
+            what: "commands",
+            addr: path.to_string(),
+            source,
+        })?;
+
+        let config = Arc::new(config);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (controls, config) = (controls.clone(), config.clone());
+                thread::spawn(move || serve(stream, &controls, &*config));
+            }
+        });
+        Ok(ControlSocket {
+            path: path.to_string(),
+        })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answer the commands of a connection until it is closed
+fn serve(stream: UnixStream, controls: &Controls, config: &dyn Configuration) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match execute(line.trim(), controls, config, &mut writer) {
+            Ok(()) => writeln!(writer, "ok")?,
+            Err(e) => writeln!(writer, "error: {e}")?,
+        }
+    }
+    Ok(())
+}
+
+fn execute(
+    line: &str,
+    controls: &Controls,
+    config: &dyn Configuration,
+    out: &mut impl Write,
+) -> Result<(), BurstsharkError> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+    match command {
+        "show" => {
+            let settings = controls.settings();
+            let filter = settings.filter;
+            let optional = |value: Option<String>| value.unwrap_or("-".to_string());
+            writeln!(
+                out,
+                "min-bytes {}",
+                optional(filter.min_bytes.map(|v| v.to_string()))
+            )?;
+            writeln!(
+                out,
+                "max-bytes {}",
+                optional(filter.max_bytes.map(|v| v.to_string()))
+            )?;
+            writeln!(
+                out,
+                "min-packets {}",
+                optional(filter.min_packets.map(|v| v.to_string()))
+            )?;
+            writeln!(
+                out,
+                "max-packets {}",
+                optional(filter.max_packets.map(|v| v.to_string()))
+            )?;
+            writeln!(out, "suppress {}", settings.suppress)?;
+            writeln!(out, "capture-filter {}", optional(settings.capture_filter))?;
+            Ok(())
+        }
+        "set" => {
+            let (name, value) = rest.split_once(' ').ok_or_else(|| {
+                BurstsharkError::InvalidCommand("set needs a setting and its value".to_string())
+            })?;
+            controls.update(|settings| change(settings, name, Some(value.trim()), config))
+        }
+        "unset" => controls.update(|settings| change(settings, rest, None, config)),
+        "reload" => {
+            let mut reloaded = config.reload()?;
+            controls.update(|settings| {
+                reloaded.tshark_filter = match reloaded.capture_filter == settings.capture_filter {
+                    true => settings.tshark_filter.take(),
+                    false => Some(config.tshark_filter(reloaded.capture_filter.as_deref())?),
+                };
+                *settings = reloaded;
+                Ok(())
+            })
+        }
+        _ => Err(BurstsharkError::InvalidCommand(format!(
+            "Unknown command '{command}', expected show, set, unset or reload"
+        ))),
+    }
+}
+
+/// Set a setting to the value, or clear it without one
+fn change(
+    settings: &mut RuntimeSettings,
+    name: &str,
+    value: Option<&str>,
+    config: &dyn Configuration,
+) -> Result<(), BurstsharkError> {
+    fn parse<T: FromStr>(name: &str, value: Option<&str>) -> Result<Option<T>, BurstsharkError>
+    where
+        T::Err: Display,
+    {
+        value
+            .map(|value| value.parse::<T>())
+            .transpose()
+            .map_err(|e| {
+                BurstsharkError::InvalidCommand(format!(
+                    "Invalid {name} '{}': {e}",
+                    value.unwrap_or_default()
+                ))
+            })
+    }
+
+    match name {
+        "min-bytes" => settings.filter.min_bytes = parse(name, value)?,
+        "max-bytes" => settings.filter.max_bytes = parse(name, value)?,
+        "min-packets" => settings.filter.min_packets = parse(name, value)?,
+        "max-packets" => settings.filter.max_packets = parse(name, value)?,
+        "suppress" => settings.suppress = parse(name, value)?.unwrap_or(false),
+        "capture-filter" => {
+            settings.tshark_filter = Some(config.tshark_filter(value)?);
+            settings.capture_filter = value.map(str::to_string);
+        }
+        _ => {
+            return Err(BurstsharkError::InvalidCommand(format!(
+                "Unknown setting '{name}'"
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        process,
+        sync::Arc,
+    };
+
+    use super::{execute, Configuration, ControlSocket, Controls, RuntimeSettings};
+    use crate::{capture::BurstFilter, BurstsharkError};
+
+    /// A configuration of a live capture by tshark, with a minimum size on the command line
+    struct Live;
+
+    impl Configuration for Live {
+        fn reload(&self) -> Result<RuntimeSettings, BurstsharkError> {
+            Ok(settings())
+        }
+
+        fn tshark_filter(&self, capture_filter: Option<&str>) -> Result<String, BurstsharkError> {
+            Ok(match capture_filter {
+                Some(filter) => format!("({filter}) and ip"),
+                None => "ip".to_string(),
+            })
+        }
+    }
+
+    fn settings() -> RuntimeSettings {
+        RuntimeSettings {
+            filter: BurstFilter {
+                min_bytes: Some(100),
+                ..BurstFilter::default()
+            },
+            ..RuntimeSettings::default()
+        }
+    }
+
+    /// What the command printed, or the error it failed with
+    fn run(controls: &Controls, line: &str) -> Result<String, String> {
+        let mut out = Vec::new();
+        execute(line, controls, &Live, &mut out).map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn settings_are_set_and_unset() {
+        let controls = Controls::new(settings());
+        let mut seen = 0;
+        assert!(controls.changed(&mut seen).is_none());
+
+        run(&controls, "set max-packets 50").unwrap();
+        run(&controls, "set suppress true").unwrap();
+        run(&controls, "unset min-bytes").unwrap();
+        let changed = controls.changed(&mut seen).unwrap();
+        assert_eq!(changed.filter.max_packets, Some(50));
+        assert_eq!(changed.filter.min_bytes, None);
+        assert!(changed.suppress);
+        assert_eq!(seen, 3);
+
+        // Setting a setting to its value is no change
+        run(&controls, "set max-packets 50").unwrap();
+        assert!(controls.changed(&mut seen).is_none());
+    }
+
+    #[test]
+    fn settings_are_shown() {
+        let controls = Controls::new(settings());
+        run(&controls, "set capture-filter port 443").unwrap();
+        assert_eq!(
+            run(&controls, "show").unwrap(),
+            "min-bytes 100\nmax-bytes -\nmin-packets -\nmax-packets -\nsuppress false\n\
+             capture-filter port 443\n"
+        );
+        assert_eq!(
+            controls.settings().tshark_filter.as_deref(),
+            Some("(port 443) and ip")
+        );
+    }
+
+    #[test]
+    fn reloading_restores_the_configured_settings() {
+        let controls = Controls::new(settings());
+        run(&controls, "set min-bytes 5000").unwrap();
+        run(&controls, "set capture-filter udp").unwrap();
+        run(&controls, "reload").unwrap();
+        let reloaded = controls.settings();
+        assert_eq!(reloaded.filter.min_bytes, Some(100));
+        // The capture filter of the configuration is restarted with
+        assert_eq!(reloaded.capture_filter, None);
+        assert_eq!(reloaded.tshark_filter.as_deref(), Some("ip"));
+    }
+
+    #[test]
+    fn invalid_commands_change_nothing() {
+        let controls = Controls::new(settings());
+        for line in [
+            "start",
+            "set min-bytes",
+            "set min-bytes many",
+            "set max-packets 70000",
+            "unset sample-rate",
+        ] {
+            assert!(run(&controls, line).is_err(), "{line}");
+        }
+        assert_eq!(controls.settings(), settings());
+    }
+
+    #[test]
+    fn commands_are_answered_on_the_socket() {
+        let dir = std::env::temp_dir().join(format!("burstshark-control-{}", process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("control").to_string_lossy().into_owned();
+        let controls = Arc::new(Controls::new(settings()));
+        let socket = ControlSocket::start(&path, controls.clone(), Live).unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(b"set max-bytes 9000\n\nset max-bytes lots\n")
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "ok");
+        assert!(lines.next().unwrap().unwrap().starts_with("error: "));
+        assert_eq!(controls.settings().filter.max_bytes, Some(9000));
+
+        // The socket is removed when dropped
+        drop(socket);
+        assert!(std::fs::metadata(&path).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd},
+    process,
+};
+
+use nix::unistd::{dup2, fork, pipe, setsid, ForkResult};
+
+use burstshark::BurstsharkError;
+
+/// A PID file which is removed when dropped
+pub struct PidFile {
+    path: String,
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The daemon, with the errors it prints passed on to the original process until it has started
+pub struct Daemon {
+    _pid_file: Option<PidFile>,
+    log: File,
+    /// Pipe to the original process, which is also the standard error until started
+    ready: Option<File>,
+}
+
+impl Daemon {
+    /// Report that the daemon has started, letting the original process exit successfully, and
+    /// move the standard error to the log file
+    pub fn started(&mut self) -> io::Result<()> {
+        let Some(mut ready) = self.ready.take() else {
+            return Ok(());
+        };
+        ready.write_all(STARTED)?;
+        dup2(self.log.as_raw_fd(), 2)?;
+        Ok(())
+    }
+}
+
+/// Written by the daemon after anything it printed while starting, once it has started
+const STARTED: &[u8] = b"\0";
+
+/// Detach from the controlling terminal and continue running in the background
+///
+/// Must be called before any threads are spawned. The original process only exits once the daemon
+/// has written its PID file and reported that it started, so init systems expecting classic forking
+/// daemons can rely on it. Until then, the standard error of the daemon is printed by the original
+/// process, which exits with a non-zero status if the daemon exits before it started.
+/// Standard input is redirected from /dev/null, and standard output and error to the log file.
+/// The working directory is kept so relative paths of the other options still work.
+pub fn daemonize(
+    pid_file: Option<&str>,
+    log_file: Option<&str>,
+) -> Result<Daemon, BurstsharkError> {
+    let log = match log_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|source| BurstsharkError::File {
+                action: "open log file",
+                path: path.to_string(),
+                source,
+            })?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let null = File::open("/dev/null")?;
+
+    let (ready_rx, ready_tx) = pipe()?;
+    let (mut ready_rx, ready_tx) =
+        unsafe { (File::from_raw_fd(ready_rx), File::from_raw_fd(ready_tx)) };
+
+    if let ForkResult::Parent { .. } = unsafe { fork()? } {
+        // Wait for the daemon to report that it has started, printing what it printed until then
+        drop(ready_tx);
+        let mut output = Vec::new();
+        let _ = ready_rx.read_to_end(&mut output);
+        let mut stderr = io::stderr();
+        if let Some(output) = output.strip_suffix(STARTED) {
+            let _ = stderr.write_all(output);
+            process::exit(0);
+        }
+        let _ = stderr.write_all(&output);
+        if output.is_empty() {
+            eprintln!("Error starting daemon: it exited before it started");
+        }
+        process::exit(1);
+    }
+    drop(ready_rx);
+
+    setsid()?;
+
+    // Fork again so the daemon can never reacquire a controlling terminal
+    if let ForkResult::Parent { .. } = unsafe { fork()? } {
+        process::exit(0);
+    }
+
+    dup2(null.as_raw_fd(), 0)?;
+    dup2(log.as_raw_fd(), 1)?;
+    dup2(ready_tx.as_raw_fd(), 2)?;
+
+    let pid_file = pid_file
+        .map(|path| {
+            fs::write(path, format!("{}\n", process::id()))
+                .map(|_| PidFile {
+                    path: path.to_string(),
+                })
+                .map_err(|source| BurstsharkError::File {
+                    action: "write PID file",
+                    path: path.to_string(),
+                    source,
+                })
+        })
+        .transpose()?;
+
+    Ok(Daemon {
+        _pid_file: pid_file,
+        log,
+        ready: Some(ready_tx),
+    })
+}
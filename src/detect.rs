@@ -0,0 +1,275 @@
+//! Detection of flows whose bursts follow patterns given by the user.
+//!
+//! Signatures are read from a TOML file, each describing a run of bursts by ranges of their sizes,
+//! packets and durations, and of the time between them, such as for the segments of DASH video:
+//!
+//! ```toml
+//! [[signature]]
+//! label = "video-stream"
+//! min_bursts = 3
+//! size = [1_000_000, 3_000_000]
+//! period = [2.0, 6.0]
+//! ```
+
+use std::{collections::HashMap, fs, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{capture::Burst, BurstsharkError};
+
+/// Seconds between pruning flows without recent bursts
+const PRUNE_INTERVAL: f64 = 60.0;
+
+/// Seconds without bursts after which the runs of a flow are forgotten
+const FLOW_TIMEOUT: f64 = 300.0;
+
+/// Lowest and highest value allowed, both included
+type Range = [f64; 2];
+
+fn within(range: &Option<Range>, value: f64) -> bool {
+    range.is_none_or(|[min, max]| min <= value && value <= max)
+}
+
+/// A pattern of bursts, matched by runs of consecutive bursts of a flow in one direction
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Signature {
+    /// Label of the bursts of flows matching the signature
+    label: Arc<str>,
+    /// Consecutive matching bursts before the flow is labeled
+    #[serde(default = "default_min_bursts")]
+    min_bursts: u32,
+    /// Bytes of each burst
+    size: Option<Range>,
+    packets: Option<Range>,
+    /// Seconds from the start to the end of each burst
+    duration: Option<Range>,
+    /// Seconds from the start of the previous burst
+    period: Option<Range>,
+    /// Port of either end of the flow
+    port: Option<u16>,
+}
+
+fn default_min_bursts() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignatureFile {
+    #[serde(default)]
+    signature: Vec<Signature>,
+}
+
+impl Signature {
+    /// Whether the burst itself matches, apart from the time since the previous one
+    fn matches(&self, burst: &Burst) -> bool {
+        within(&self.size, burst.size as f64)
+            && within(&self.packets, burst.num_packets as f64)
+            && within(&self.duration, burst.end - burst.start)
+            && self
+                .port
+                .is_none_or(|port| burst.src_port == Some(port) || burst.dst_port == Some(port))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.label.is_empty() {
+            return Err("Signatures need a label".to_string());
+        }
+        if self.min_bursts == 0 {
+            return Err(format!(
+                "Signature {} needs min_bursts of at least 1",
+                self.label
+            ));
+        }
+        let ranges = [&self.size, &self.packets, &self.duration, &self.period];
+        if ranges
+            .iter()
+            .any(|range| range.is_some_and(|[min, max]| !(0.0 <= min && min <= max)))
+        {
+            return Err(format!(
+                "Ranges of signature {} must be non-negative, and start at most where they end",
+                self.label
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Consecutive bursts of a flow matching a signature
+#[derive(Clone, Copy, Default)]
+struct Run {
+    bursts: u32,
+    last_start: f64,
+}
+
+/// Source and destination address and port of a burst
+type Direction = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+struct FlowRuns {
+    /// Run of each signature, in the order of the signatures
+    runs: Vec<Run>,
+    last_time: f64,
+}
+
+/// Labels the bursts of flows matching the signatures. A flow is labeled once it had the minimum
+/// number of consecutive matching bursts, until a burst no longer matches. The first signature
+/// matched in the order of the file labels the burst.
+pub struct SignatureDetector {
+    signatures: Vec<Signature>,
+    flows: HashMap<Direction, FlowRuns>,
+    last_prune: f64,
+}
+
+impl SignatureDetector {
+    pub fn load(path: &str) -> Result<Self, BurstsharkError> {
+        let content = fs::read_to_string(path).map_err(|source| BurstsharkError::File {
+            action: "read signatures file",
+            path: path.to_string(),
+            source,
+        })?;
+        let invalid = |reason: String| BurstsharkError::InvalidFile {
+            path: path.to_string(),
+            reason,
+        };
+        let file: SignatureFile =
+            toml::from_str(&content).map_err(|e| invalid(format!("invalid signatures: {e}")))?;
+        if file.signature.is_empty() {
+            return Err(invalid("no [[signature]] tables".to_string()));
+        }
+        for signature in &file.signature {
+            signature.check().map_err(invalid)?;
+        }
+
+        Ok(SignatureDetector {
+            signatures: file.signature,
+            flows: HashMap::new(),
+            last_prune: 0.0,
+        })
+    }
+
+    pub fn observe(&mut self, burst: &mut Burst) {
+        let direction = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+        );
+        let flow = self.flows.entry(direction).or_insert_with(|| FlowRuns {
+            runs: vec![Run::default(); self.signatures.len()],
+            last_time: burst.completion_time,
+        });
+        flow.last_time = burst.completion_time;
+
+        for (signature, run) in self.signatures.iter().zip(&mut flow.runs) {
+            if !signature.matches(burst) {
+                *run = Run::default();
+                continue;
+            }
+            // A burst out of period starts a new run
+            let in_period =
+                run.bursts > 0 && within(&signature.period, burst.start - run.last_start);
+            run.bursts = match in_period {
+                true => run.bursts + 1,
+                false => 1,
+            };
+            run.last_start = burst.start;
+
+            if burst.label.is_none() && run.bursts >= signature.min_bursts {
+                burst.label = Some(signature.label.clone());
+            }
+        }
+
+        if burst.completion_time - self.last_prune >= PRUNE_INTERVAL {
+            let oldest = burst.completion_time - FLOW_TIMEOUT;
+            self.flows.retain(|_, flow| flow.last_time >= oldest);
+            self.last_prune = burst.completion_time;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::SignatureDetector;
+    use crate::capture::Burst;
+
+    const VIDEO: &str = r#"
+[[signature]]
+label = "video-stream"
+min_bursts = 3
+size = [1_000_000, 3_000_000]
+period = [2.0, 6.0]
+
+[[signature]]
+label = "large"
+size = [1_000_000, 10_000_000]
+"#;
+
+    /// Load signatures from a file of its own for the test
+    fn load(test: &str, signatures: &str) -> Result<SignatureDetector, String> {
+        let path = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        fs::write(&path, signatures).unwrap();
+        let detector = SignatureDetector::load(path.to_str().unwrap());
+        let _ = fs::remove_file(path);
+        detector.map_err(|e| e.to_string())
+    }
+
+    /// Labels of bursts of the flow starting at the times, each of the size
+    fn labels(detector: &mut SignatureDetector, starts: &[f64], size: u32) -> Vec<Option<String>> {
+        starts
+            .iter()
+            .map(|start| {
+                let mut burst =
+                    Burst::test("10.0.0.1:443", "10.0.0.2:50000", *start, start + 0.5, size);
+                detector.observe(&mut burst);
+                burst.label.map(|label| label.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flows_are_labeled_after_enough_bursts_in_period() {
+        let mut detector = load("signatures-period", VIDEO).unwrap();
+        let video = Some("video-stream".to_string());
+        let large = Some("large".to_string());
+        assert_eq!(
+            labels(&mut detector, &[0.0, 4.0, 8.0, 12.0], 2_000_000),
+            [large.clone(), large.clone(), video.clone(), video]
+        );
+        // A burst out of period starts a new run
+        assert_eq!(labels(&mut detector, &[30.0], 2_000_000), [large]);
+    }
+
+    #[test]
+    fn bursts_not_matching_end_the_run() {
+        let mut detector = load("signatures-run", VIDEO).unwrap();
+        labels(&mut detector, &[0.0, 4.0], 2_000_000);
+        assert_eq!(labels(&mut detector, &[8.0], 1_000), [None]);
+        assert_eq!(
+            labels(&mut detector, &[12.0], 2_000_000),
+            [Some("large".to_string())]
+        );
+    }
+
+    #[test]
+    fn invalid_signatures_are_refused() {
+        assert!(load("signatures-empty", "").is_err());
+        assert!(load(
+            "signatures-unknown",
+            "[[signature]]\nlabel = \"a\"\nbytes = [1, 2]\n"
+        )
+        .is_err());
+        assert!(load(
+            "signatures-zero",
+            "[[signature]]\nlabel = \"a\"\nmin_bursts = 0\n"
+        )
+        .is_err());
+        let reversed = load(
+            "signatures-range",
+            "[[signature]]\nlabel = \"a\"\nsize = [2, 1]\n",
+        );
+        assert!(reversed.is_err_and(|e| e.contains("Ranges of signature a")));
+    }
+}
@@ -0,0 +1,199 @@
+//! Comparing the bursts of each flow between two burst logs, such as captures before and after a
+//! network change.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::burstlog::LoggedBurst;
+
+/// Source and destination address and port of a flow
+type FlowKey = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+/// Bursts of a flow in one of the logs
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FlowTotals {
+    pub bursts: usize,
+    pub bytes: u64,
+}
+
+/// How the bursts of a flow differ between the logs
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowDiff {
+    pub src: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst: Arc<str>,
+    pub dst_port: Option<u16>,
+    pub a: FlowTotals,
+    pub b: FlowTotals,
+    /// Two-sample Kolmogorov-Smirnov statistic of the burst sizes, from 0 for the same
+    /// distribution to 1 for distributions that do not overlap, when the flow is in both logs
+    pub ks: Option<f64>,
+}
+
+/// Align the flows of the logs by their addresses and ports and compare their bursts, with the
+/// most different size distributions first, followed by the flows in only one of the logs
+pub fn diff(a: &[LoggedBurst], b: &[LoggedBurst]) -> Vec<FlowDiff> {
+    let mut flows: BTreeMap<FlowKey, [Vec<u64>; 2]> = BTreeMap::new();
+    for (side, bursts) in [a, b].into_iter().enumerate() {
+        for burst in bursts {
+            let key = (
+                burst.src.clone(),
+                burst.src_port,
+                burst.dst.clone(),
+                burst.dst_port,
+            );
+            flows.entry(key).or_default()[side].push(burst.size);
+        }
+    }
+
+    let mut diffs: Vec<FlowDiff> = flows
+        .into_iter()
+        .map(|((src, src_port, dst, dst_port), [mut a, mut b])| {
+            let totals = |sizes: &[u64]| FlowTotals {
+                bursts: sizes.len(),
+                bytes: sizes.iter().sum(),
+            };
+            FlowDiff {
+                src,
+                src_port,
+                dst,
+                dst_port,
+                a: totals(&a),
+                b: totals(&b),
+                ks: ks_statistic(&mut a, &mut b),
+            }
+        })
+        .collect();
+    // Stable, so ties stay in address order
+    diffs.sort_by(|x, y| y.ks.unwrap_or(-1.0).total_cmp(&x.ks.unwrap_or(-1.0)));
+    diffs
+}
+
+/// Largest distance between the empirical distribution functions of two samples, or None if
+/// either is empty
+fn ks_statistic(a: &mut [u64], b: &mut [u64]) -> Option<f64> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    a.sort_unstable();
+    b.sort_unstable();
+
+    let (mut i, mut j) = (0, 0);
+    let mut max: f64 = 0.0;
+    while i < a.len() && j < b.len() {
+        // Step past all samples of the smallest value in either, so ties move together
+        let value = a[i].min(b[j]);
+        while i < a.len() && a[i] == value {
+            i += 1;
+        }
+        while j < b.len() && b[j] == value {
+            j += 1;
+        }
+        max = max.max((i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs());
+    }
+    Some(max)
+}
+
+/// Print a table of the differences, with the change of the totals from the first log to the
+/// second
+pub fn write_table(out: &mut impl Write, diffs: &[FlowDiff]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:15} {:6} {:15} {:5} {:>8} {:>8} {:>8} {:>12} {:>12} {:>8} {:>5}",
+        "src", "port", "dst", "port", "bursts a", "b", "change", "bytes a", "b", "change", "ks",
+    )?;
+
+    for flow in diffs {
+        let change = |a: f64, b: f64| match a > 0.0 {
+            true => format!("{:+.1}%", (b - a) / a * 100.0),
+            false => "new".to_string(),
+        };
+        writeln!(
+            out,
+            "{:15} {:6} {:15} {:5} {:8} {:8} {:>8} {:12} {:12} {:>8} {:>5}",
+            flow.src,
+            flow.src_port.map_or("".to_string(), |p| p.to_string()),
+            flow.dst,
+            flow.dst_port.map_or("".to_string(), |p| p.to_string()),
+            flow.a.bursts,
+            flow.b.bursts,
+            change(flow.a.bursts as f64, flow.b.bursts as f64),
+            flow.a.bytes,
+            flow.b.bytes,
+            change(flow.a.bytes as f64, flow.b.bytes as f64),
+            flow.ks.map_or("-".to_string(), |ks| format!("{ks:.3}")),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, ks_statistic, write_table};
+    use crate::burstlog::LoggedBurst;
+
+    fn burst(src_port: u16, size: u64) -> LoggedBurst {
+        LoggedBurst {
+            src: "10.0.0.1".into(),
+            dst: "10.0.0.2".into(),
+            src_port: Some(src_port),
+            dst_port: Some(443),
+            start: 1.0,
+            end: 1.25,
+            num_packets: 3,
+            size,
+        }
+    }
+
+    #[test]
+    fn ks_statistic_is_the_largest_distance() {
+        assert_eq!(ks_statistic(&mut [3, 1, 2], &mut [2, 3, 1]), Some(0.0));
+        assert_eq!(ks_statistic(&mut [1, 2], &mut [3, 4]), Some(1.0));
+        // Tied values move together
+        assert_eq!(ks_statistic(&mut [1, 2, 2, 3], &mut [2, 2]), Some(0.25));
+        assert_eq!(ks_statistic(&mut [], &mut [1]), None);
+    }
+
+    #[test]
+    fn the_most_different_flows_come_first() {
+        let a = [burst(1, 100), burst(1, 200), burst(2, 100), burst(3, 100)];
+        let b = [burst(1, 100), burst(1, 200), burst(2, 900), burst(4, 100)];
+        let diffs = diff(&a, &b);
+        let order: Vec<_> = diffs
+            .iter()
+            .map(|flow| (flow.src_port.unwrap(), flow.ks))
+            .collect();
+        assert_eq!(
+            order,
+            [(2, Some(1.0)), (1, Some(0.0)), (3, None), (4, None)]
+        );
+        assert_eq!((diffs[2].a.bursts, diffs[2].b.bursts), (1, 0));
+        assert_eq!((diffs[0].a.bytes, diffs[0].b.bytes), (100, 900));
+    }
+
+    #[test]
+    fn tables_show_the_change_of_the_totals() {
+        let diffs = diff(
+            &[burst(1, 100)],
+            &[burst(1, 100), burst(1, 300), burst(2, 50)],
+        );
+        let mut table = Vec::new();
+        write_table(&mut table, &diffs).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows[0][4..],
+            ["1", "2", "+100.0%", "100", "400", "+300.0%", "0.500"]
+        );
+        assert_eq!(rows[1][4..], ["0", "1", "new", "0", "50", "new", "-"]);
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    fmt, io,
+    net::AddrParseError,
+    num::{ParseFloatError, ParseIntError},
+    process::ExitStatus,
+    str::Utf8Error,
+    sync::mpsc::SendError,
+    time::SystemTimeError,
+};
+
+use thiserror::Error;
+
+/// Errors of captures and their outputs
+#[derive(Debug, Error)]
+pub enum BurstsharkError {
+    /// A line printed by tshark has fewer fields than expected
+    #[error("Missing field in line '{0}'")]
+    MissingField(String),
+    /// A field printed by tshark that can not be parsed
+    #[error("Invalid {name} '{value}'")]
+    InvalidField { name: &'static str, value: String },
+    #[error(transparent)]
+    ParseInt(#[from] ParseIntError),
+    #[error(transparent)]
+    ParseFloat(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParseAddr(#[from] AddrParseError),
+    #[error(transparent)]
+    ParseMac(#[from] macaddr::ParseError),
+    #[error(transparent)]
+    Utf8(#[from] Utf8Error),
+    #[error("Failed to start tshark: {0}")]
+    Tshark(#[source] io::Error),
+    #[error("Failed to start ssh: {0}")]
+    Ssh(#[source] io::Error),
+    #[error("Failed to run the command printing packets: {0}")]
+    Exec(#[source] io::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Fmt(#[from] fmt::Error),
+    #[error(transparent)]
+    Clock(#[from] SystemTimeError),
+    /// The thread records or packets were sent to has stopped, such as after failing to write the
+    /// output, so the capture can not continue
+    #[error("A thread of the capture stopped before it ended")]
+    ChannelClosed,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+    #[error(transparent)]
+    MessagePack(#[from] rmp_serde::encode::Error),
+    #[error(transparent)]
+    Http(#[from] ureq::Error),
+    #[error(transparent)]
+    Sys(#[from] nix::Error),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    /// Options that are invalid, or can not be used together
+    #[error("{0}")]
+    InvalidOptions(String),
+    /// A command of the control socket that can not be carried out
+    #[error("{0}")]
+    InvalidCommand(String),
+    /// A file that could not be opened, read, created or removed
+    #[error("Failed to {action} {path}: {source}")]
+    File {
+        action: &'static str,
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// A file whose contents can not be used
+    #[error("{path}: {reason}")]
+    InvalidFile { path: String, reason: String },
+    /// A socket or address that could not be listened on
+    #[error("Failed to listen for {what} on {addr}: {source}")]
+    Listen {
+        what: &'static str,
+        addr: String,
+        #[source]
+        source: io::Error,
+    },
+    /// A finished output file that could not be uploaded to object storage
+    #[error("Failed to upload {path}: {reason}")]
+    Upload { path: String, reason: String },
+    /// Packets can not be captured on an interface by the native backend
+    #[error("Failed to capture on {interface}: {reason}")]
+    Capture { interface: String, reason: String },
+    /// A command run by the capture or its outputs that failed
+    #[error("The command '{command}' failed with {status}")]
+    CommandFailed { command: String, status: ExitStatus },
+    /// Ssh failed to connect to the host to capture on, or to authenticate
+    #[error("Failed to run tshark on {0} over ssh")]
+    Remote(String),
+    /// Scheduling settings that could not be applied to a thread
+    #[error("Failed to {setting}: {source}")]
+    Sched {
+        setting: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The handler stopping captures on Ctrl-C could not be set up
+    #[error("Failed to handle interrupts: {0}")]
+    InterruptHandler(String),
+    #[error("Failed to generate an anonymization key: {0}")]
+    Random(#[from] getrandom::Error),
+    #[error("Failed to open GeoIP database {path}: {source}")]
+    GeoIp {
+        path: String,
+        #[source]
+        source: maxminddb::MaxMindDBError,
+    },
+    #[cfg(feature = "sqlite")]
+    #[error("Failed to open SQLite database {path}: {source}")]
+    Database {
+        path: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// An option needing an optional feature that was not built
+    #[error("BurstShark was built without {name} support, build it with --features {feature}")]
+    MissingFeature {
+        name: &'static str,
+        feature: &'static str,
+    },
+    /// A thread of the capture or output that panicked
+    #[error("The {0} thread panicked")]
+    Panicked(&'static str),
+    #[error("Internal error: Tried to transmit an empty burst")]
+    EmptyBurst,
+}
+
+impl<T> From<SendError<T>> for BurstsharkError {
+    fn from(_: SendError<T>) -> Self {
+        BurstsharkError::ChannelClosed
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{capture::Burst, timestamp, BurstsharkError};
+
+/// How long to wait before checking the EVE file for new lines again
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Seconds an alert is kept after the bursts have moved past it
+const ALERT_RETENTION: f64 = 300.0;
+
+/// Seconds of slack when matching alert times against the time range of a burst
+const TIME_SLACK: f64 = 1.0;
+
+/// Metadata of a Suricata alert attached to a burst
+#[derive(Debug, Clone, Serialize)]
+pub struct EveAlert {
+    pub signature: String,
+    pub severity: u8,
+}
+
+#[derive(Deserialize)]
+struct EveRecord {
+    timestamp: String,
+    event_type: String,
+    src_ip: Option<IpAddr>,
+    src_port: Option<u16>,
+    dest_ip: Option<IpAddr>,
+    dest_port: Option<u16>,
+    alert: Option<EveRecordAlert>,
+}
+
+#[derive(Deserialize)]
+struct EveRecordAlert {
+    signature: String,
+    severity: u8,
+}
+
+/// Endpoints of a flow, ordered so both directions share the same key
+type Endpoints = ((IpAddr, Option<u16>), (IpAddr, Option<u16>));
+
+fn endpoints(a: (IpAddr, Option<u16>), b: (IpAddr, Option<u16>)) -> Endpoints {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+struct IndexedAlert {
+    endpoints: Endpoints,
+    time: f64,
+    alert: EveAlert,
+}
+
+/// Alerts read from the EVE file, indexed on their (ordered) address pair
+#[derive(Default)]
+struct AlertIndex {
+    alerts: HashMap<(IpAddr, IpAddr), Vec<IndexedAlert>>,
+}
+
+/// Correlates bursts with the alerts of a Suricata EVE JSON file which is followed in the background
+pub struct EveCorrelator {
+    index: Arc<Mutex<AlertIndex>>,
+    last_prune: f64,
+}
+
+impl EveCorrelator {
+    /// Start following the EVE file. When `from_start` is false, only alerts appended from now on are used.
+    pub fn start(path: &str, from_start: bool) -> Result<Self, BurstsharkError> {
+        let failed = |action| {
+            move |source| BurstsharkError::File {
+                action,
+                path: path.to_string(),
+                source,
+            }
+        };
+        let mut file = File::open(path).map_err(failed("open EVE file"))?;
+        if !from_start {
+            file.seek(SeekFrom::End(0))
+                .map_err(failed("seek EVE file"))?;
+        }
+
+        // Load what is already in the file before any bursts are annotated
+        let index = Arc::new(Mutex::new(AlertIndex::default()));
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        read_available(&mut reader, &mut line, &index).map_err(failed("read EVE file"))?;
+
+        let thread_index = index.clone();
+        thread::spawn(move || follow(reader, line, thread_index));
+
+        Ok(EveCorrelator {
+            index,
+            last_prune: f64::MIN,
+        })
+    }
+
+    /// Attach all alerts of the same flow within the time range of the burst
+    pub fn annotate(&mut self, burst: &mut Burst) {
+        let (Ok(src), Ok(dst)) = (burst.src.parse::<IpAddr>(), burst.dst.parse::<IpAddr>()) else {
+            return;
+        };
+
+        let mut index = self.index.lock().unwrap();
+
+        let start = burst.start - TIME_SLACK;
+        let end = burst.end + TIME_SLACK;
+        let flow = endpoints((src, burst.src_port), (dst, burst.dst_port));
+        if let Some(alerts) = index.alerts.get(&(flow.0 .0, flow.1 .0)) {
+            // Without ports the burst covers all port pairs between the addresses
+            let ports_ignored = burst.src_port.is_none() && burst.dst_port.is_none();
+            for alert in alerts
+                .iter()
+                .filter(|a| ports_ignored || a.endpoints == flow)
+                .filter(|a| start <= a.time && a.time <= end)
+            {
+                // A signature often fires for many packets of the same burst, only keep it once
+                if !burst
+                    .alerts
+                    .iter()
+                    .any(|a| a.signature == alert.alert.signature)
+                {
+                    burst.alerts.push(alert.alert.clone());
+                }
+            }
+        }
+
+        if burst.completion_time - self.last_prune > ALERT_RETENTION {
+            let oldest = burst.completion_time - ALERT_RETENTION;
+            index.alerts.retain(|_, alerts| {
+                alerts.retain(|a| a.time >= oldest);
+                !alerts.is_empty()
+            });
+            self.last_prune = burst.completion_time;
+        }
+    }
+}
+
+/// Read all currently available records from the EVE file into the index
+///
+/// A partially written last line is kept in `line` until the rest of it is available.
+fn read_available(
+    reader: &mut BufReader<File>,
+    line: &mut String,
+    index: &Mutex<AlertIndex>,
+) -> std::io::Result<()> {
+    loop {
+        if reader.read_line(line)? == 0 {
+            return Ok(());
+        }
+
+        if !line.ends_with('\n') {
+            continue;
+        }
+
+        if let Some(alert) = parse_alert(line) {
+            let addrs = (alert.endpoints.0 .0, alert.endpoints.1 .0);
+            index
+                .lock()
+                .unwrap()
+                .alerts
+                .entry(addrs)
+                .or_default()
+                .push(alert);
+        }
+        line.clear();
+    }
+}
+
+/// Continuously read new records from the EVE file into the index
+fn follow(mut reader: BufReader<File>, mut line: String, index: Arc<Mutex<AlertIndex>>) {
+    loop {
+        if let Err(e) = read_available(&mut reader, &mut line, &index) {
+            eprintln!("Error reading EVE file: {}", e);
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn parse_alert(line: &str) -> Option<IndexedAlert> {
+    let record: EveRecord = serde_json::from_str(line).ok()?;
+    if record.event_type != "alert" {
+        return None;
+    }
+
+    let alert = record.alert?;
+    Some(IndexedAlert {
+        endpoints: endpoints(
+            (record.src_ip?, record.src_port),
+            (record.dest_ip?, record.dest_port),
+        ),
+        time: timestamp::parse_iso8601(&record.timestamp)?,
+        alert: EveAlert {
+            signature: alert.signature,
+            severity: alert.severity,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::BufReader,
+        process,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{parse_alert, read_available, AlertIndex, EveCorrelator};
+    use crate::capture::Burst;
+
+    /// An alert of a flow from 10.0.0.1:50000 to 10.0.0.2:443, at 10:00:10 UTC
+    const ALERT: &str = r#"{"timestamp":"2023-04-01T12:00:10.000000+0200","event_type":"alert","src_ip":"10.0.0.1","src_port":50000,"dest_ip":"10.0.0.2","dest_port":443,"alert":{"signature":"ET POLICY Test","severity":2}}"#;
+    const ALERT_TIME: f64 = 1680343210.0;
+
+    /// A correlator with the alerts of the lines of an EVE file
+    fn correlator(test: &str, lines: &str) -> EveCorrelator {
+        let path = std::env::temp_dir().join(format!("burstshark-eve-{test}-{}", process::id()));
+        fs::write(&path, lines).unwrap();
+        let index = Arc::new(Mutex::new(AlertIndex::default()));
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        read_available(&mut reader, &mut String::new(), &index).unwrap();
+        fs::remove_file(&path).unwrap();
+        EveCorrelator {
+            index,
+            last_prune: f64::MIN,
+        }
+    }
+
+    #[test]
+    fn only_alert_records_are_parsed() {
+        let alert = parse_alert(ALERT).unwrap();
+        assert_eq!(alert.time, ALERT_TIME);
+        assert_eq!(alert.alert.signature, "ET POLICY Test");
+        assert_eq!(alert.alert.severity, 2);
+
+        let flow = ALERT.replace(r#""event_type":"alert""#, r#""event_type":"flow""#);
+        assert!(parse_alert(&flow).is_none());
+        assert!(parse_alert("not json").is_none());
+    }
+
+    #[test]
+    fn alerts_are_attached_to_bursts_of_either_direction() {
+        let mut eve = correlator("direction", &format!("{ALERT}\n{ALERT}\n"));
+        let mut burst = Burst::test(
+            "10.0.0.2:443",
+            "10.0.0.1:50000",
+            ALERT_TIME - 0.5,
+            ALERT_TIME,
+            1000,
+        );
+        eve.annotate(&mut burst);
+        // The signature fired twice, but is only attached once
+        assert_eq!(burst.alerts.len(), 1);
+        assert_eq!(burst.alerts[0].signature, "ET POLICY Test");
+    }
+
+    #[test]
+    fn alerts_of_other_ports_or_times_are_not_attached() {
+        let mut eve = correlator("mismatch", &format!("{ALERT}\n"));
+        let mut other_port = Burst::test(
+            "10.0.0.1:50001",
+            "10.0.0.2:443",
+            ALERT_TIME,
+            ALERT_TIME,
+            1000,
+        );
+        let mut later = Burst::test(
+            "10.0.0.1:50000",
+            "10.0.0.2:443",
+            ALERT_TIME + 5.0,
+            ALERT_TIME + 6.0,
+            1000,
+        );
+        eve.annotate(&mut other_port);
+        eve.annotate(&mut later);
+        assert!(other_port.alerts.is_empty());
+        assert!(later.alerts.is_empty());
+    }
+
+    #[test]
+    fn partially_written_lines_are_not_parsed() {
+        let mut eve = correlator("partial", ALERT);
+        let mut burst = Burst::test(
+            "10.0.0.1:50000",
+            "10.0.0.2:443",
+            ALERT_TIME,
+            ALERT_TIME,
+            1000,
+        );
+        eve.annotate(&mut burst);
+        assert!(burst.alerts.is_empty());
+    }
+}
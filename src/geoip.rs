@@ -0,0 +1,127 @@
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+
+use crate::{capture::Burst, BurstsharkError};
+
+/// Most addresses whose locations are kept, after which the cache starts over
+const CACHE_SIZE: usize = 65536;
+
+/// Country and autonomous system of an address, either of which may be unknown
+#[derive(Clone, Default)]
+struct Location {
+    country: Option<Arc<str>>,
+    asn: Option<u32>,
+}
+
+/// Annotates bursts with the countries and autonomous systems of their addresses, looked up in
+/// MaxMind databases such as GeoLite2-Country and GeoLite2-ASN.
+///
+/// Each address is looked up in every database, keeping the first country and autonomous system
+/// found, and the result is cached so addresses are only looked up once.
+pub struct GeoIp {
+    readers: Vec<Reader<Vec<u8>>>,
+    cache: HashMap<Arc<str>, Location>,
+}
+
+impl GeoIp {
+    pub fn open(paths: &[String]) -> Result<Self, BurstsharkError> {
+        let readers = paths
+            .iter()
+            .map(|path| {
+                Reader::open_readfile(path).map_err(|source| BurstsharkError::GeoIp {
+                    path: path.to_string(),
+                    source,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(GeoIp {
+            readers,
+            cache: HashMap::new(),
+        })
+    }
+
+    pub fn annotate(&mut self, burst: &mut Burst) {
+        let src = self.location(&burst.src);
+        let dst = self.location(&burst.dst);
+        burst.src_country = src.country;
+        burst.src_asn = src.asn;
+        burst.dst_country = dst.country;
+        burst.dst_asn = dst.asn;
+    }
+
+    fn location(&mut self, addr: &Arc<str>) -> Location {
+        if let Some(location) = self.cache.get(addr) {
+            return location.clone();
+        }
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.clear();
+        }
+        // Layer 2 flows have MAC addresses, which have no location
+        let location = match addr.parse::<IpAddr>() {
+            Ok(ip) => self.lookup(ip),
+            Err(_) => Location::default(),
+        };
+        self.cache.insert(addr.clone(), location.clone());
+        location
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Location {
+        let mut location = Location::default();
+        for reader in &self.readers {
+            if location.country.is_none() {
+                location.country = reader
+                    .lookup::<geoip2::Country>(ip)
+                    .map_err(warn_lookup)
+                    .ok()
+                    .and_then(|country| country.country?.iso_code.map(Arc::from));
+            }
+            if location.asn.is_none() {
+                location.asn = reader
+                    .lookup::<geoip2::Asn>(ip)
+                    .map_err(warn_lookup)
+                    .ok()
+                    .and_then(|asn| asn.autonomous_system_number);
+            }
+        }
+        location
+    }
+}
+
+/// Report a failed lookup, unless the address is just not in the database
+fn warn_lookup(e: MaxMindDBError) {
+    if !matches!(e, MaxMindDBError::AddressNotFoundError(_)) {
+        eprintln!("Error looking up address in GeoIP database: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::GeoIp;
+    use crate::capture::Burst;
+
+    #[test]
+    fn missing_databases_are_refused() {
+        let path = "/nonexistent/GeoLite2-Country.mmdb".to_string();
+        let err = GeoIp::open(&[path]).err().unwrap();
+        assert!(err.to_string().contains("GeoLite2-Country.mmdb"));
+    }
+
+    #[test]
+    fn addresses_are_looked_up_once() {
+        let mut geoip = GeoIp {
+            readers: Vec::new(),
+            cache: HashMap::new(),
+        };
+        let mut burst = Burst {
+            dst: "02:00:00:00:00:02".into(),
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", 1.0, 1.5, 1000)
+        };
+        geoip.annotate(&mut burst);
+        geoip.annotate(&mut burst);
+        assert_eq!(geoip.cache.len(), 2);
+        assert_eq!((burst.src_country, burst.dst_asn), (None, None));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::{capture::Burst, probe::ProbeIdentity};
+
+/// Bits of the values kept within each power of two, giving 8 buckets per power of two, which are
+/// at most an eighth of their value wide
+const SUB_BUCKET_BITS: u32 = 3;
+
+/// Addresses and ports of a flow
+type FlowKey = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+/// Sizes of the bursts of a flow and the gaps between them over an interval
+#[derive(Debug, Clone, Serialize)]
+pub struct FlowHistogram {
+    /// End of the interval, which started `interval` seconds earlier
+    pub time: f64,
+    pub interval: f64,
+    pub src: Arc<str>,
+    pub src_port: Option<u16>,
+    pub dst: Arc<str>,
+    pub dst_port: Option<u16>,
+    pub bursts: u64,
+    pub bytes: u64,
+    /// Number of bursts by the smallest size of their bucket
+    pub sizes: BTreeMap<u64, u64>,
+    /// Number of gaps between the bursts of the flow, from the end of one to the start of the
+    /// next, by the smallest microseconds of their bucket
+    pub gaps_us: BTreeMap<u64, u64>,
+    /// Id and tags of the probe of the bursts
+    #[serde(flatten)]
+    pub probe: ProbeIdentity,
+}
+
+/// Summarizes the bursts of each flow with histograms of their sizes and the gaps between them per
+/// interval of time.
+///
+/// Buckets are log-scaled as in HDR histograms, with 8 linear buckets per power of two, so their
+/// counts keep the shape of the distributions at a fixed cost per flow. Intervals are aligned to
+/// multiples of their length, so the histograms of several probes cover the same intervals. Gaps
+/// are counted for flows that had bursts in the same or the previous interval.
+pub struct FlowHistograms {
+    interval: f64,
+    /// End of the current interval, set by the first burst
+    end: Option<f64>,
+    flows: HashMap<FlowKey, FlowHistogram>,
+    /// End of the last burst of each flow with bursts in the current or previous interval
+    ends: HashMap<FlowKey, f64>,
+}
+
+impl FlowHistograms {
+    pub fn new(interval: f64) -> Self {
+        FlowHistograms {
+            interval,
+            end: None,
+            flows: HashMap::new(),
+            ends: HashMap::new(),
+        }
+    }
+
+    /// Add a burst, returning the histograms of the interval it completed after
+    pub fn add(&mut self, burst: &Burst) -> Vec<FlowHistogram> {
+        let mut finished = Vec::new();
+        let end = *self
+            .end
+            .get_or_insert(((burst.completion_time / self.interval).floor() + 1.0) * self.interval);
+        if burst.completion_time >= end {
+            finished = self.take(end);
+            // Skip the intervals without bursts
+            let skipped = ((burst.completion_time - end) / self.interval).floor();
+            self.end = Some(end + (skipped + 1.0) * self.interval);
+        }
+
+        let key = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+        );
+        let gap = self
+            .ends
+            .insert(key.clone(), burst.end)
+            .map(|end| (burst.start - end).max(0.0));
+        let end = self.end.unwrap();
+        let histogram = self.flows.entry(key).or_insert_with(|| FlowHistogram {
+            time: end,
+            interval: self.interval,
+            src: burst.src.clone(),
+            src_port: burst.src_port,
+            dst: burst.dst.clone(),
+            dst_port: burst.dst_port,
+            bursts: 0,
+            bytes: 0,
+            sizes: BTreeMap::new(),
+            gaps_us: BTreeMap::new(),
+            probe: burst.probe.clone(),
+        });
+        histogram.bursts += 1;
+        histogram.bytes += burst.size as u64;
+        *histogram
+            .sizes
+            .entry(bucket(burst.size as u64))
+            .or_default() += 1;
+        if let Some(gap) = gap {
+            let micros = (gap * 1e6).round() as u64;
+            *histogram.gaps_us.entry(bucket(micros)).or_default() += 1;
+        }
+
+        finished
+    }
+
+    /// The histograms of the last interval, as the capture ended
+    pub fn finish(&mut self) -> Vec<FlowHistogram> {
+        match self.end {
+            Some(end) => self.take(end),
+            None => Vec::new(),
+        }
+    }
+
+    /// Take the histograms of the interval ending at the time, ordered by flow
+    fn take(&mut self, end: f64) -> Vec<FlowHistogram> {
+        // Flows without bursts in this interval have no gaps to count in the next one
+        self.ends.retain(|key, _| self.flows.contains_key(key));
+        let mut histograms: Vec<_> = self
+            .flows
+            .drain()
+            .map(|(_, mut histogram)| {
+                histogram.time = end;
+                histogram
+            })
+            .collect();
+        histograms.sort_by(|a, b| {
+            (&a.src, a.src_port, &a.dst, a.dst_port).cmp(&(&b.src, b.src_port, &b.dst, b.dst_port))
+        });
+        histograms
+    }
+}
+
+/// Smallest value of the bucket of a value, which keeps the highest bits of the value
+fn bucket(value: u64) -> u64 {
+    match value {
+        0 => 0,
+        value => {
+            let shift = value.ilog2().saturating_sub(SUB_BUCKET_BITS);
+            (value >> shift) << shift
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket, FlowHistograms};
+    use crate::capture::Burst;
+
+    #[test]
+    fn buckets_keep_the_highest_bits() {
+        assert_eq!(bucket(0), 0);
+        assert_eq!(bucket(7), 7);
+        assert_eq!(bucket(15), 15);
+        assert_eq!(bucket(17), 16);
+        assert_eq!(bucket(1000), 960);
+    }
+
+    #[test]
+    fn histograms_are_taken_per_aligned_interval_by_flow() {
+        let mut histograms = FlowHistograms::new(1.0);
+        assert!(histograms
+            .add(&Burst::test(
+                "10.0.0.3:443",
+                "10.0.0.2:50000",
+                0.1,
+                0.2,
+                100
+            ))
+            .is_empty());
+        histograms.add(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            0.3,
+            0.4,
+            1000,
+        ));
+        histograms.add(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            0.5,
+            0.6,
+            1000,
+        ));
+
+        let finished = histograms.add(&Burst::test("10.0.0.1:443", "10.0.0.2:50000", 1.5, 1.6, 17));
+        assert_eq!(finished.len(), 2);
+        let first = &finished[0];
+        assert_eq!(&*first.src, "10.0.0.1");
+        assert_eq!((first.time, first.interval), (1.0, 1.0));
+        assert_eq!((first.bursts, first.bytes), (2, 2000));
+        assert_eq!(first.sizes.iter().collect::<Vec<_>>(), [(&960, &2)]);
+        // 100 ms between the bursts
+        assert_eq!(first.gaps_us.iter().collect::<Vec<_>>(), [(&98304, &1)]);
+        assert_eq!(&*finished[1].src, "10.0.0.3");
+        assert!(finished[1].gaps_us.is_empty());
+
+        let last = histograms.finish();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].time, 2.0);
+        assert_eq!(last[0].sizes.iter().collect::<Vec<_>>(), [(&16, &1)]);
+        assert_eq!(last[0].gaps_us.len(), 1);
+    }
+
+    #[test]
+    fn intervals_without_bursts_are_skipped() {
+        let mut histograms = FlowHistograms::new(1.0);
+        histograms.add(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            0.1,
+            0.2,
+            100,
+        ));
+        histograms.add(&Burst::test(
+            "10.0.0.3:443",
+            "10.0.0.2:50000",
+            1.1,
+            1.2,
+            100,
+        ));
+        let finished = histograms.add(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            5.1,
+            5.2,
+            100,
+        ));
+        assert_eq!(finished[0].time, 2.0);
+        let last = histograms.finish();
+        assert_eq!(last[0].time, 6.0);
+        // The flow had no bursts in the previous interval, so no gap is counted
+        assert!(last[0].gaps_us.is_empty());
+    }
+
+    #[test]
+    fn nothing_is_finished_without_bursts() {
+        assert!(FlowHistograms::new(1.0).finish().is_empty());
+    }
+}
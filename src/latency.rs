@@ -0,0 +1,103 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::capture::Burst;
+
+/// Seconds between pruning requests that are too old to be answered
+const PRUNE_INTERVAL: f64 = 10.0;
+
+/// Source and destination address and port of a burst
+type Direction = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+/// Pairs bursts with the preceding burst in the opposite direction of the flow, and sets the time
+/// from the end of that request to the start of the response as the response latency.
+pub struct ResponseTracker {
+    /// End of the last burst in each direction, which has not been answered yet
+    requests: HashMap<Direction, f64>,
+    max_latency: f64,
+    last_prune: f64,
+}
+
+impl ResponseTracker {
+    pub fn new(max_latency: f64) -> Self {
+        ResponseTracker {
+            requests: HashMap::new(),
+            max_latency,
+            last_prune: 0.0,
+        }
+    }
+
+    pub fn observe(&mut self, burst: &mut Burst) {
+        let reverse = (
+            burst.dst.clone(),
+            burst.dst_port,
+            burst.src.clone(),
+            burst.src_port,
+        );
+
+        // Each request is answered at most once
+        if let Some(request_end) = self.requests.remove(&reverse) {
+            let latency = burst.start - request_end;
+            if (0.0..=self.max_latency).contains(&latency) {
+                burst.response_latency = Some(latency);
+            }
+        }
+
+        let direction = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+        );
+        self.requests.insert(direction, burst.end);
+
+        if burst.start - self.last_prune >= PRUNE_INTERVAL {
+            let oldest = burst.start - self.max_latency;
+            self.requests.retain(|_, end| *end >= oldest);
+            self.last_prune = burst.start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResponseTracker;
+    use crate::capture::Burst;
+
+    const CLIENT: &str = "10.0.0.1:50000";
+    const SERVER: &str = "10.0.0.2:443";
+
+    #[test]
+    fn responses_are_timed_from_the_end_of_the_request() {
+        let mut tracker = ResponseTracker::new(1.0);
+        let mut request = Burst::test(CLIENT, SERVER, 1.0, 1.1, 500);
+        let mut response = Burst::test(SERVER, CLIENT, 1.35, 1.5, 20000);
+        tracker.observe(&mut request);
+        tracker.observe(&mut response);
+        assert_eq!(request.response_latency, None);
+        assert!((response.response_latency.unwrap() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn requests_are_answered_once() {
+        let mut tracker = ResponseTracker::new(1.0);
+        tracker.observe(&mut Burst::test(CLIENT, SERVER, 1.0, 1.1, 500));
+        let mut first = Burst::test(SERVER, CLIENT, 1.2, 1.3, 1000);
+        let mut second = Burst::test(SERVER, CLIENT, 1.4, 1.5, 1000);
+        tracker.observe(&mut first);
+        tracker.observe(&mut second);
+        assert!(first.response_latency.is_some());
+        assert_eq!(second.response_latency, None);
+    }
+
+    #[test]
+    fn late_responses_and_other_flows_are_not_paired() {
+        let mut tracker = ResponseTracker::new(1.0);
+        tracker.observe(&mut Burst::test(CLIENT, SERVER, 1.0, 1.1, 500));
+        let mut other_flow = Burst::test(SERVER, "10.0.0.1:50001", 1.2, 1.3, 1000);
+        let mut late = Burst::test(SERVER, CLIENT, 2.5, 2.6, 1000);
+        tracker.observe(&mut other_flow);
+        tracker.observe(&mut late);
+        assert_eq!(other_flow.response_latency, None);
+        assert_eq!(late.response_latency, None);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{capture::Burst, BurstsharkError};
+
+/// Bursts a profile must have learned before bursts are scored against it
+const MIN_SAMPLES: u64 = 10;
+
+/// Running mean and variance of a burst characteristic, using Welford's algorithm
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct RunningStats {
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    fn add(&mut self, value: f64, count: u64) {
+        let delta = value - self.mean;
+        self.mean += delta / count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Number of standard deviations the value is from the mean
+    fn z_score(&self, value: f64, count: u64) -> f64 {
+        let std_dev = (self.m2 / (count - 1) as f64).sqrt();
+        match std_dev > f64::EPSILON {
+            true => (value - self.mean) / std_dev,
+            false => 0.0,
+        }
+    }
+}
+
+/// Typical bursts of a service port in one direction
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PortProfile {
+    bursts: u64,
+    /// Sizes and packet counts vary over orders of magnitude, so their logarithms are used
+    log_size: RunningStats,
+    log_packets: RunningStats,
+    duration: RunningStats,
+}
+
+/// Characteristics of a burst compared against the profiles
+fn characteristics(burst: &Burst) -> [f64; 3] {
+    [
+        (burst.size as f64).ln_1p(),
+        (burst.num_packets as f64).ln(),
+        burst.end - burst.start,
+    ]
+}
+
+impl PortProfile {
+    fn add(&mut self, burst: &Burst) {
+        self.bursts += 1;
+        let [size, packets, duration] = characteristics(burst);
+        self.log_size.add(size, self.bursts);
+        self.log_packets.add(packets, self.bursts);
+        self.duration.add(duration, self.bursts);
+    }
+
+    /// Root mean square of the z-scores of the burst characteristics
+    fn deviation(&self, burst: &Burst) -> f64 {
+        let [size, packets, duration] = characteristics(burst);
+        let squares = [
+            self.log_size.z_score(size, self.bursts),
+            self.log_packets.z_score(packets, self.bursts),
+            self.duration.z_score(duration, self.bursts),
+        ]
+        .map(|z| z * z);
+        (squares.iter().sum::<f64>() / squares.len() as f64).sqrt()
+    }
+}
+
+/// Learns the typical bursts per service port during a baseline period, and then scores bursts by
+/// how much they deviate from them. The profiles are stored in a JSON file between runs.
+pub struct PortProfiles {
+    path: PathBuf,
+    profiles: BTreeMap<String, PortProfile>,
+    learn_time: Option<f64>,
+    /// End of the baseline period, set by the first burst
+    learn_until: Option<f64>,
+}
+
+impl PortProfiles {
+    /// Load the profiles of earlier runs, if any, and learn for `learn_time` seconds if set.
+    pub fn load(path: &str, learn_time: Option<f64>) -> Result<Self, BurstsharkError> {
+        let profiles = match fs::read_to_string(path) {
+            Ok(content) => {
+                serde_json::from_str(&content).map_err(|err| BurstsharkError::InvalidFile {
+                    path: path.to_string(),
+                    reason: format!("invalid port profiles: {err}"),
+                })?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(PortProfiles {
+            path: PathBuf::from(path),
+            profiles,
+            learn_time,
+            learn_until: None,
+        })
+    }
+
+    /// Learn from the burst during the baseline period, and set its deviation score after it.
+    pub fn observe(&mut self, burst: &mut Burst) {
+        let Some(key) = profile_key(burst) else {
+            return;
+        };
+
+        let learning = match self.learn_time {
+            Some(learn_time) => {
+                burst.start < *self.learn_until.get_or_insert(burst.start + learn_time)
+            }
+            None => false,
+        };
+
+        if learning {
+            self.profiles.entry(key).or_default().add(burst);
+        } else if let Some(profile) = self.profiles.get(&key) {
+            if profile.bursts >= MIN_SAMPLES {
+                burst.deviation = Some(profile.deviation(burst));
+            }
+        }
+    }
+
+    /// Store the profiles, if any were learned during this run.
+    pub fn save(&self) -> Result<(), BurstsharkError> {
+        if self.learn_time.is_some() {
+            fs::write(&self.path, serde_json::to_string_pretty(&self.profiles)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// The service port of a burst and whether it is sent to or from it, such as 443/up or 443/down.
+///
+/// The lower port of the flow is assumed to be the service port.
+fn profile_key(burst: &Burst) -> Option<String> {
+    match (burst.src_port?, burst.dst_port?) {
+        (src_port, dst_port) if dst_port <= src_port => Some(format!("{dst_port}/up")),
+        (src_port, _) => Some(format!("{src_port}/down")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::{profile_key, PortProfiles, RunningStats};
+    use crate::capture::Burst;
+
+    /// A burst of a client uploading to port 443 at a time, varying a little in size
+    fn upload(time: f64, size: u32) -> Burst {
+        Burst {
+            num_packets: (size / 1000).max(1) as u16,
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", time, time + 0.01, size)
+        }
+    }
+
+    fn profiles_path(test: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("burstshark-profiles-{test}-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn running_stats_match_the_sample_variance() {
+        let mut stats = RunningStats::default();
+        for (count, value) in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+            .into_iter()
+            .enumerate()
+        {
+            stats.add(value, count as u64 + 1);
+        }
+        assert_eq!(stats.mean, 5.0);
+        assert!((stats.m2 / 7.0 - 32.0 / 7.0).abs() < 1e-12);
+        assert!((stats.z_score(5.0 + (32.0f64 / 7.0).sqrt(), 8) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bursts_are_profiled_by_their_service_port_and_direction() {
+        let download = Burst::test("10.0.0.2:443", "10.0.0.1:50000", 0.0, 0.0, 1);
+        assert_eq!(profile_key(&upload(0.0, 1)).as_deref(), Some("443/up"));
+        assert_eq!(profile_key(&download).as_deref(), Some("443/down"));
+        let portless = Burst {
+            src_port: None,
+            dst_port: None,
+            ..download
+        };
+        assert_eq!(profile_key(&portless), None);
+    }
+
+    #[test]
+    fn bursts_after_the_baseline_are_scored_by_their_deviation() {
+        let path = profiles_path("score");
+        let mut profiles = PortProfiles::load(&path, Some(10.0)).unwrap();
+        for i in 0..20 {
+            let mut burst = upload(i as f64 * 0.5, 5000 + i * 50);
+            profiles.observe(&mut burst);
+            assert_eq!(burst.deviation, None);
+        }
+
+        let (mut typical, mut unusual) = (upload(11.0, 5500), upload(12.0, 500_000));
+        profiles.observe(&mut typical);
+        profiles.observe(&mut unusual);
+        assert!(typical.deviation.unwrap() < 1.0);
+        assert!(unusual.deviation.unwrap() > 10.0);
+    }
+
+    #[test]
+    fn profiles_with_few_bursts_are_not_scored() {
+        let path = profiles_path("few");
+        let mut profiles = PortProfiles::load(&path, Some(1.0)).unwrap();
+        for i in 0..5 {
+            profiles.observe(&mut upload(i as f64 * 0.1, 5000));
+        }
+        let mut burst = upload(2.0, 500_000);
+        profiles.observe(&mut burst);
+        assert_eq!(burst.deviation, None);
+    }
+
+    #[test]
+    fn learned_profiles_are_used_by_later_runs() {
+        let path = profiles_path("saved");
+        let mut learning = PortProfiles::load(&path, Some(100.0)).unwrap();
+        for i in 0..20 {
+            learning.observe(&mut upload(i as f64, 5000 + i * 50));
+        }
+        learning.save().unwrap();
+
+        let mut scoring = PortProfiles::load(&path, None).unwrap();
+        let mut burst = upload(0.0, 500_000);
+        scoring.observe(&mut burst);
+        fs::remove_file(&path).unwrap();
+        assert!(burst.deviation.unwrap() > 10.0);
+    }
+}
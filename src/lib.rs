@@ -0,0 +1,67 @@
+//! Identify and analyze bursty application data traffic, in real-time or from capture files.
+//!
+//! Packets are captured by tshark, or by the native backend, and grouped into bursts per flow that
+//! are completed after a period of inactivity. [`BurstStream`] runs a capture in the background and
+//! iterates over its bursts:
+//!
+//! ```no_run
+//! use burstshark::{BurstStream, CaptureBackend, CaptureType, CommonOptions, NativeSource};
+//!
+//! let mut stream = BurstStream::start(|tx| CaptureType::EthCapture {
+//!     opts: CommonOptions::new(
+//!         CaptureBackend::Native {
+//!             source: NativeSource::File("capture.pcap".to_string()),
+//!             epoch_time: false,
+//!         },
+//!         tx,
+//!     ),
+//! });
+//!
+//! for burst in stream.by_ref() {
+//!     println!("{} -> {}: {} bytes", burst.src, burst.dst, burst.size);
+//! }
+//! stream.finish().expect("Capture failed");
+//! ```
+//!
+//! The first capture sets up a handler for Ctrl-C, which stops all captures of the process gracefully.
+
+pub mod alert;
+pub mod anonymize;
+pub mod bench;
+#[cfg(feature = "sqlite")]
+pub mod burstdb;
+pub mod burstlog;
+pub mod capture;
+pub mod channel;
+pub mod classify;
+pub mod compare;
+pub mod control;
+pub mod detect;
+pub mod diff;
+mod error;
+pub mod eve;
+pub mod geoip;
+pub mod histogram;
+mod latency;
+mod learn;
+pub mod output;
+pub mod pipeline;
+pub mod privacy;
+pub mod probe;
+pub mod profile;
+pub mod resolve;
+pub mod sched;
+pub mod selftest;
+mod socket;
+mod stats;
+mod stream;
+mod timestamp;
+pub mod tshark;
+pub mod upload;
+
+pub use capture::{
+    Burst, CaptureBackend, CaptureType, CommonOptions, IpOptions, NativeSource, Record,
+    SyntheticTraffic, TunnelMode,
+};
+pub use error::BurstsharkError;
+pub use stream::BurstStream;
@@ -1,19 +1,71 @@
-use clap::{Parser, ValueEnum};
+use std::{
+    ffi::OsString,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
-mod capture;
-mod output;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 
-use capture::{CaptureType, CommonOptions};
-use output::OutputWriter;
+mod config;
+mod daemon;
+
+use burstshark::{
+    alert::AlertCondition,
+    anonymize::AnonymizeMode,
+    bench, burstlog,
+    capture::{
+        AdaptiveGap, BurstFilter, CaptureBackend, CaptureType, CommonOptions, FlowEvictions,
+        FlowIdFormat, IpOptions, NativeSource, OutlierPolicy, Record, RemoteHost, SequenceWindow,
+        SurgeOptions, SyntheticTraffic, TunnelMode, WlanOptions,
+    },
+    channel::{ChannelOptions, Overflow, Sender},
+    compare::BurstComparison,
+    control::{Configuration, ControlSocket, Controls, RuntimeSettings},
+    diff,
+    output::{
+        AlertOptions, Compression, FifoPolicy, InfluxOptions, OutputFormat, OutputOptions,
+        OutputSink, OutputWriter, RotateOptions, SplitOptions,
+    },
+    privacy::PrivacyMode,
+    probe::ProbeIdentity,
+    profile::{PipelineProfile, ProfileReporter},
+    sched::ThreadSched,
+    tshark::{self, FieldMapping, FieldPart},
+    upload::ObjectStore,
+    BurstsharkError,
+};
+
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 #[derive(Parser, Clone, Debug)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Network interface to use for live capture. First non-loopback interface if no interface or file supplied.
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Take options from a profile of the config file, which options on the command line override.
+    #[clap(long = "profile", value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Config file with the profiles, as TOML with a [profiles.NAME] table of options per profile. Defaults to ~/.config/burstshark/config.toml.
+    #[clap(long = "config", value_name = "PATH", requires = "profile")]
+    config: Option<PathBuf>,
+
+    /// Network interface to use for live capture. First non-loopback interface if no interface or file supplied. Repeat to capture on several interfaces, annotating bursts with their interface, or on several channels in monitor mode.
     #[clap(short = 'i', long = "interface")]
-    interface: Option<String>,
+    interface: Vec<String>,
 
-    /// Read packet data from infile.
+    /// Read packet data from infile, as fast as possible with the times of the packets in the file.
     #[clap(short = 'r', long = "read-file", conflicts_with = "interface")]
     infile: Option<String>,
 
@@ -25,32 +77,250 @@ struct Args {
     #[clap(short = 'Y', long = "display-filter", requires = "infile")]
     display_filter: Option<String>,
 
+    /// How packets are captured. The native backend needs no tshark, but supports no filters.
+    #[clap(value_enum, long = "backend", default_value_t = Backend::Tshark)]
+    backend: Backend,
+
+    /// Run tshark on this host over SSH, such as user@router, and create bursts of the packets it prints here. Files of -r and -w are on the remote host.
+    #[clap(long = "remote", value_name = "DESTINATION")]
+    remote: Option<String>,
+
+    /// Port of the SSH server of the remote host.
+    #[clap(long = "ssh-port", requires = "remote")]
+    ssh_port: Option<u16>,
+
+    /// Private key to authenticate to the remote host with, instead of those of the agent or SSH config.
+    #[clap(long = "ssh-identity", value_name = "PATH", requires = "remote")]
+    ssh_identity: Option<String>,
+
+    /// Option passed to ssh, such as BatchMode=yes. Repeat for several.
+    #[clap(long = "ssh-option", value_name = "OPTION", requires = "remote")]
+    ssh_option: Vec<String>,
+
+    /// Path of tshark on the remote host.
+    #[clap(
+        long = "remote-tshark",
+        value_name = "PATH",
+        default_value = "tshark",
+        requires = "remote"
+    )]
+    remote_tshark: String,
+
+    /// Read lines of packet fields from stdin instead of running tshark, tab-separated as tshark prints the fields burstshark would run it with.
+    #[clap(
+        long = "stdin",
+        conflicts_with_all = ["interface", "infile", "capture_filter", "display_filter", "backend", "remote", "capture_outfile", "compare_interface"]
+    )]
+    stdin: bool,
+
+    /// Run this shell command instead of tshark, and read lines of packet fields from its output as with --stdin.
+    #[clap(
+        long = "command",
+        value_name = "COMMAND",
+        conflicts_with_all = ["stdin", "interface", "infile", "capture_filter", "display_filter", "backend", "remote", "capture_outfile", "compare_interface"]
+    )]
+    source_command: Option<String>,
+
+    /// Fields of the lines read with --stdin or --command, as those of IP captures, monitor mode or layer 2 mode. -I and --l2-mode select the same fields.
+    #[clap(value_enum, long = "format", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    format: Option<Protocol>,
+
+    /// Complete the bursts of lines read with --stdin or --command in real time, as in live captures, rather than by the times of their packets alone.
+    #[clap(long = "live")]
+    live: bool,
+
+    /// Fields tshark prints parts of packets with in place of the defaults, as comma-separated name=field such as src=ipv6.src. Alternatives are separated by |.
+    #[clap(long = "tshark-fields", value_delimiter = ',')]
+    tshark_fields: Option<Vec<String>>,
+
+    /// File with a name=field mapping of tshark fields per line, overridden by --tshark-fields.
+    #[clap(long = "tshark-fields-file")]
+    tshark_fields_file: Option<String>,
+
+    /// Write the lines of tshark output that could not be parsed to a file, such as for bug reports.
+    #[clap(long = "dump-bad-lines", conflicts_with = "compare_interface")]
+    dump_bad_lines: Option<String>,
+
+    /// Report the lines of tshark output that could not be parsed on stderr, rather than only counting them.
+    #[clap(long = "log-malformed", conflicts_with = "compare_interface")]
+    log_malformed: bool,
+
     /// Seconds with no activity to consider a new burst.
     #[clap(short = 't', long = "inactive-time", default_value_t = 1.0)]
     inactive_time: f64,
 
+    /// Give each flow its own inactive time, this many times the moving average time between the packets of its bursts.
+    #[clap(long = "adaptive-gap")]
+    adaptive_gap: Option<f64>,
+
+    /// Shortest inactive time of flows with an adaptive gap.
+    #[clap(long = "min-gap", default_value_t = 0.01, requires = "adaptive_gap")]
+    min_gap: f64,
+
+    /// Longest inactive time of flows with an adaptive gap, also used until a flow has bursts of several packets. Defaults to the inactive time.
+    #[clap(long = "max-gap", requires = "adaptive_gap")]
+    max_gap: Option<f64>,
+
+    /// Merge consecutive bursts of a flow with less than this many seconds between them, such as bursts split by a packet straggling just past the inactive time.
+    #[clap(long = "merge-gap", value_name = "SECS")]
+    merge_gap: Option<f64>,
+
     /// Ignore ports when and create bursts based on IP addresses only.
     #[clap(short = 'p', long = "ignore-ports", conflicts_with = "monitor_mode")]
     ignore_ports: bool,
 
+    /// Key multicast flows on source and group, and broadcast flows on source and destination port, and annotate their bursts with the group.
+    #[clap(long = "multicast-groups", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    multicast_groups: bool,
+
+    /// Detect ESP and WireGuard tunnels and report their overhead, with flows per SPI or receiver index (session) or per tunnel between two hosts (single).
+    #[clap(value_enum, long = "tunnels", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    tunnels: Option<Tunnels>,
+
+    /// Also burstify packets of protocols without ports, such as ICMP, ESP and OSPF, with a flow per source, destination and IP protocol. Annotates bursts with the protocol.
+    #[clap(long = "all-protocols", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    all_protocols: bool,
+
+    /// Key flows of packets in VXLAN, GRE and GTP-U tunnels on their inner addresses and ports and the tunnel id, and report the overhead of the tunnel.
+    #[clap(long = "decapsulate", conflicts_with_all = ["monitor_mode", "l2_mode", "tunnels"])]
+    decapsulate: bool,
+
+    /// Annotate bursts of SSH flows on port 22 with the inferred phase, interactive or bulk, such as file transfers or tunneled traffic.
+    #[clap(long = "ssh-phases", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    ssh_phases: bool,
+
+    /// Aggregate both directions of flows, and annotate each burst with the direction most of its bytes were sent in, a_to_b or b_to_a between the sorted endpoints.
+    #[clap(
+        long = "bidirectional",
+        conflicts_with_all = ["monitor_mode", "l2_mode", "response_latency"]
+    )]
+    bidirectional: bool,
+
+    /// Annotate bursts and flow events with an id of the flow that is the same for both directions, for joining with other tools.
+    #[clap(value_enum, long = "flow-id", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    flow_id: Option<FlowId>,
+
+    /// Alert when a host opens this many new flows within the surge window, such as during scans or SYN floods.
+    #[clap(long = "surge-threshold", conflicts_with = "monitor_mode")]
+    surge_threshold: Option<u32>,
+
+    /// Seconds of the windows in which new flows per host are counted.
+    #[clap(
+        long = "surge-window",
+        default_value_t = 1.0,
+        requires = "surge_threshold"
+    )]
+    surge_window: f64,
+
+    /// Report hosts with bursts in several flows at the same time, such as parallel downloads, every this many seconds.
+    #[clap(long = "concurrency-interval", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    concurrency_interval: Option<f64>,
+
+    /// Output the bytes and bursts per second of each active flow every this many seconds.
+    #[clap(long = "rate-interval", value_name = "SECS")]
+    rate_interval: Option<f64>,
+
+    /// Flag bursts during which TCP resets, or ICMP unreachable or time exceeded errors, referencing the flow were seen.
+    #[clap(long = "flow-errors", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    flow_errors: bool,
+
+    /// Key QUIC flows on their connection ids rather than ports, so they continue when the client migrates. Needs the tshark backend.
+    #[clap(long = "quic", conflicts_with_all = ["monitor_mode", "l2_mode", "bidirectional"])]
+    quic: bool,
+
+    /// Key RTP streams on their SSRC, as several can share ports, and count their packets missed by the capture from their sequence numbers. Annotates bursts with the SSRC, payload type and lost packets. Needs the tshark backend.
+    #[clap(long = "rtp", conflicts_with_all = ["monitor_mode", "l2_mode", "bidirectional"])]
+    rtp: bool,
+
+    /// Annotate bursts with the DSCP, and the IPv6 flow label, of their first packet.
+    #[clap(long = "capture-qos", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    capture_qos: bool,
+
+    /// Leave the bytes of retransmitted and duplicate TCP segments out of the sizes of bursts, and annotate bursts with them.
+    #[clap(long = "exclude-retransmissions", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    exclude_retransmissions: bool,
+
+    /// Also capture on this interface, after a middlebox, and output how each burst changed between the interfaces instead of the bursts. Requires epoch time format.
+    #[clap(
+        long = "compare-interface",
+        conflicts_with_all = ["infile", "capture_outfile"]
+    )]
+    compare_interface: Option<String>,
+
+    /// Seconds bursts may be apart on the two interfaces to be matched.
+    #[clap(
+        long = "compare-window",
+        default_value_t = 1.0,
+        requires = "compare_interface"
+    )]
+    compare_window: f64,
+
+    /// Also output events when flows are first seen and when they are closed, with their lifetime totals.
+    #[clap(long = "flow-events")]
+    flow_events: bool,
+
     /// Write captured packets by tshark to a capture file.
     #[clap(short = 'w', long = "write-capture")]
     capture_outfile: Option<String>,
 
     /// Write output from BurstShark to a file.
-    #[clap(short = 'W', long = "write-bursts")]
+    #[clap(short = 'W', long = "write-bursts", visible_alias = "output-file")]
     bursts_outfile: Option<String>,
 
+    /// Bytes after which the bursts file is moved to a numbered file, such as bursts.csv.1, and started over.
+    #[clap(long = "rotate-size", requires = "bursts_outfile")]
+    rotate_size: Option<u64>,
+
+    /// Seconds after which the bursts file is moved to a numbered file, such as bursts.csv.1, and started over.
+    #[clap(long = "rotate-interval", requires = "bursts_outfile")]
+    rotate_interval: Option<f64>,
+
+    /// Compress the rotated bursts files.
+    #[clap(value_enum, long = "compress")]
+    compress: Option<Compression>,
+
+    /// What to do with output written to a named pipe while no reader is attached.
+    #[clap(value_enum, long = "fifo-policy", default_value_t = Fifo::Block)]
+    fifo_policy: Fifo,
+
+    /// Lines kept for a named pipe while no reader is attached, with the buffer policy.
+    #[clap(long = "fifo-buffer", default_value_t = 10000)]
+    fifo_buffer: usize,
+
+    /// Format of the bursts, and other records, on the standard output and in the bursts file.
+    #[clap(value_enum, long = "output-format", default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Output bursts as Suricata EVE JSON entries of the burst event type, for SIEM pipelines that ingest its alerts. Requires epoch time format.
+    #[clap(long = "eve-compat", conflicts_with_all = ["output_format", "tui"])]
+    eve_compat: bool,
+
     /// Don't display bursts on the standard output.
     #[clap(short = 'q', long = "suppress")]
     suppress: bool,
 
-    /// Only display bursts with a minimum amount of bytes.
-    #[clap(short = 'b', long = "min-bytes")]
+    /// Show a live table of the active flows in the terminal, with the sizes of their recent bursts, instead of the bursts.
+    #[clap(long = "tui", conflicts_with_all = ["suppress", "daemon"])]
+    tui: bool,
+
+    /// Serve a web page charting the bursts of each flow live on this address, such as 0.0.0.0:8080.
+    #[clap(long = "serve-http")]
+    serve_http: Option<String>,
+
+    /// Stream bursts as NDJSON to every process that connects to this Unix socket, such as /run/burstshark.sock, or TCP address, such as 127.0.0.1:9000.
+    #[clap(long = "serve", value_name = "SOCKET|ADDR")]
+    serve: Option<String>,
+
+    /// Take commands to change the burst filters, the suppression of the standard output and the capture filter while capturing on this Unix socket, such as /run/burstshark.ctl.
+    #[clap(long = "control-socket", value_name = "PATH")]
+    control_socket: Option<String>,
+
+    /// Only display bursts with a minimum amount of bytes, to suppress tiny keep-alive bursts.
+    #[clap(short = 'b', long = "min-bytes", visible_alias = "min-burst-size")]
     min_bytes: Option<u32>,
 
     /// Only display bursts with a maximum amount of bytes.
-    #[clap(short = 'B', long = "max-bytes")]
+    #[clap(short = 'B', long = "max-bytes", visible_alias = "max-burst-size")]
     max_bytes: Option<u32>,
 
     /// Only display bursts with a minimum amount of packets/frames.
@@ -61,6 +331,38 @@ struct Args {
     #[clap(short = 'N', long = "max-packets")]
     max_packets: Option<u16>,
 
+    /// Complete bursts once they reach this many packets/frames, even if the flow never pauses.
+    #[clap(long = "max-burst-packets", value_parser = clap::value_parser!(u16).range(1..))]
+    max_burst_packets: Option<u16>,
+
+    /// Evict flows without packets for this many seconds, to bound the memory of long captures. Evicted flows start over if they have packets again.
+    #[clap(long = "flow-timeout")]
+    flow_timeout: Option<f64>,
+
+    /// Most flows to keep, evicting the least recently active beyond it, to bound the memory of long captures. Divided among the workers.
+    #[clap(long = "max-flows", value_parser = clap::value_parser!(u64).range(1..))]
+    max_flows: Option<u64>,
+
+    /// Save the bursts in progress to this file when a live capture stops, and continue those saved to it when one starts, so bursts span restarts.
+    #[clap(long = "state-file", value_name = "FILE", conflicts_with = "infile")]
+    state_file: Option<String>,
+
+    /// Annotate bursts with the time, relative to the start of the burst, and size of each of their packets.
+    #[clap(long = "packet-timing")]
+    packet_timing: bool,
+
+    /// Most packets of each burst to annotate with their timing.
+    #[clap(
+        long = "max-timing-packets",
+        default_value_t = 64,
+        requires = "packet_timing"
+    )]
+    max_timing_packets: usize,
+
+    /// Annotate bursts with the shortest, mean and longest times between their packets, the jitter of those times, and their peak rate in bytes per second over 10 ms.
+    #[clap(long = "detailed-bursts")]
+    detailed_bursts: bool,
+
     /// Which time format to use for output.
     #[clap(value_enum, short = 'T', long = "time-format", default_value_t = TimeFormat::Relative)]
     time_format: TimeFormat,
@@ -69,8 +371,16 @@ struct Args {
     #[clap(short = 'I', long = "monitor-mode")]
     monitor_mode: bool,
 
-    /// Disable guessing sizes of WLAN data frames missed by the monitor mode device.
-    #[clap(short = 'G', long = "no-guess", requires = "monitor_mode")]
+    /// Capture non-IP layer 2 traffic, such as ARP or LLDP, with bursts per MAC address pair and EtherType.
+    #[clap(
+        short = 'L',
+        long = "l2-mode",
+        conflicts_with_all = ["monitor_mode", "ignore_ports", "surge_threshold"]
+    )]
+    l2_mode: bool,
+
+    /// Disable guessing sizes of WLAN data frames missed by the monitor mode device, or of RTP packets missed by the capture.
+    #[clap(short = 'G', long = "no-guess")]
     no_guess: bool,
 
     /// Maximum allowed deviation from the expected sequence number for WLAN frames.
@@ -82,10 +392,443 @@ struct Args {
     )]
     max_deviation: u16,
 
+    /// Most sequence numbers a WLAN frame can be behind the expected one to be taken as a retransmission. Defaults to one less than the maximum deviation.
+    #[clap(long = "reorder-tolerance", requires = "monitor_mode")]
+    reorder_tolerance: Option<u16>,
+
+    /// Most WLAN frames that can be missed before a frame to count them. Defaults to one less than the maximum deviation.
+    #[clap(long = "max-missed", requires = "monitor_mode")]
+    max_missed: Option<u16>,
+
+    /// What to do with WLAN frames too far from the expected sequence number.
+    #[clap(
+        long = "outliers",
+        value_enum,
+        default_value_t = Outliers::Skip,
+        requires = "monitor_mode"
+    )]
+    outliers: Outliers,
+
+    /// Track WLAN sequence numbers per channel, to merge the frames of stations moving between channels, and annotate bursts with their channels. Implied by several interfaces.
+    #[clap(long = "merge-channels", requires = "monitor_mode")]
+    merge_channels: bool,
+
+    /// Merge the WLAN frames of all TIDs between two stations into one flow, instead of a flow per TID.
+    #[clap(long = "no-tid", requires = "monitor_mode")]
+    no_tid: bool,
+
+    /// Count the MSDUs aggregated in A-MSDU frames as packets, sized without their subframe headers, rather than counting the frames.
+    #[clap(long = "count-msdus", requires = "monitor_mode")]
+    count_msdus: bool,
+
+    /// Estimate the airtime of WLAN bursts from the PHY rates and sizes of their frames.
+    #[clap(long = "airtime", requires = "monitor_mode")]
+    airtime: bool,
+
+    /// Annotate WLAN bursts with a synthetic id of their device, which stays the same as devices rotate randomized MAC addresses, by matching the sequence numbers of new addresses to those of addresses that just went silent.
+    #[clap(long = "correlate-random-macs", requires = "monitor_mode")]
+    correlate_random_macs: bool,
+
+    /// Pin the thread reading packets from tshark to a CPU core.
+    #[clap(long = "reader-cpu")]
+    reader_cpu: Option<usize>,
+
+    /// Distribute the flows over this many threads creating bursts, for high packet rates. Bursts are then completed up to a hundredth of the inactive time later.
+    #[clap(
+        long = "workers",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with_all = ["surge_threshold", "concurrency_interval"]
+    )]
+    workers: u16,
+
+    /// Most packets queued for each worker, and records queued for the output, to bound memory when the output falls behind. Unbounded by default.
+    #[clap(long = "channel-capacity", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    channel_capacity: Option<u32>,
+
+    /// What to do when a queue is full: wait for room, slowing down the capture, or drop the oldest packet or record, and report how many when finished.
+    #[clap(value_enum, long = "overflow", default_value_t = OverflowPolicy::Block, requires = "channel_capacity")]
+    overflow: OverflowPolicy,
+
+    /// Pin the thread creating bursts to a CPU core, and further workers to the following cores.
+    #[clap(long = "worker-cpu")]
+    worker_cpu: Option<usize>,
+
+    /// Pin the thread writing the output to a CPU core.
+    #[clap(long = "output-cpu")]
+    output_cpu: Option<usize>,
+
+    /// Scheduling niceness of the BurstShark threads. Negative values raise the priority.
+    #[clap(long = "nice", allow_hyphen_values = true)]
+    nice: Option<i32>,
+
+    /// Measure and periodically report the latency and throughput of each pipeline stage.
+    #[clap(long = "profile-pipeline")]
+    profile_pipeline: bool,
+
+    /// Seconds between the periodic pipeline profile summaries.
+    #[clap(
+        long = "profile-interval",
+        default_value_t = 5.0,
+        value_parser = parse_seconds,
+        requires = "profile_pipeline"
+    )]
+    profile_interval: f64,
+
+    /// Also write the bursts of each flow to a file of its own in this directory, named after the flow.
+    #[clap(long = "split-output")]
+    split_output: Option<String>,
+
+    /// Most files of flows kept open at once, closing the least recently used.
+    #[clap(
+        long = "split-max-open",
+        default_value_t = 256,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "split_output"
+    )]
+    split_max_open: u64,
+
+    /// Bytes after which the file of a flow is continued in a new, numbered file.
+    #[clap(long = "split-rotate-size", requires = "split_output")]
+    split_rotate_size: Option<u64>,
+
+    /// Also export bursts to a MessagePack (.msgpack) or Parquet (.parquet) file, by its extension.
+    #[clap(long = "export")]
+    export: Option<String>,
+
+    /// Also insert bursts into a table of this SQLite database, created unless it exists, to find them with the query subcommand.
+    #[clap(long = "sqlite")]
+    sqlite: Option<String>,
+
+    /// Also write bursts to the InfluxDB server at this URL, such as http://localhost:8086. Requires epoch time format.
+    #[clap(long = "influx-url", requires = "influx_bucket")]
+    influx_url: Option<String>,
+
+    /// InfluxDB bucket to write bursts to.
+    #[clap(long = "influx-bucket", requires = "influx_url")]
+    influx_bucket: Option<String>,
+
+    /// InfluxDB organization of the bucket.
+    #[clap(long = "influx-org", requires = "influx_url")]
+    influx_org: Option<String>,
+
+    /// InfluxDB API token to write with.
+    #[clap(long = "influx-token", env = "INFLUX_TOKEN", hide_env_values = true)]
+    influx_token: Option<String>,
+
+    /// Follow a Suricata EVE JSON file and attach matching alerts to bursts. Requires epoch time format.
+    #[clap(long = "eve-file")]
+    eve_file: Option<String>,
+
+    /// Score bursts by how much they deviate from the typical bursts of their service port, learned into this file.
+    #[clap(long = "port-profiles", conflicts_with_all = ["monitor_mode", "l2_mode"])]
+    port_profiles: Option<String>,
+
+    /// Seconds at the start of the capture to learn port profiles from, which are then stored.
+    #[clap(long = "learn-time", requires = "port_profiles")]
+    learn_time: Option<f64>,
+
+    /// Pair bursts with the preceding burst in the opposite direction, and annotate them with the latency of the response.
+    #[clap(long = "response-latency")]
+    response_latency: bool,
+
+    /// Longest time in seconds between a burst and the response to it.
+    #[clap(
+        long = "max-response-time",
+        default_value_t = 5.0,
+        requires = "response_latency"
+    )]
+    max_response_time: f64,
+
+    /// Label bursts with the class of traffic their flow is inferred to carry, such as video-streaming or voip, and the confidence in it.
+    #[clap(long = "classify-flows")]
+    classify_flows: bool,
+
+    /// Label the bursts of flows matching the burst patterns of a TOML file of signatures.
+    #[clap(long = "signatures")]
+    signatures: Option<String>,
+
+    /// Raise an alert for bursts matching this condition, such as 'size>10MB && duration<1s'. Can be given several times.
+    #[clap(long = "alert-on", value_name = "CONDITION")]
+    alert_on: Vec<String>,
+
+    /// POST the JSON of each alerted burst to this URL.
+    #[clap(long = "alert-webhook", value_name = "URL", requires = "alert_on")]
+    alert_webhook: Option<String>,
+
+    /// Run this shell command for each alerted burst, with the burst as JSON on its standard input.
+    #[clap(long = "alert-exec", value_name = "COMMAND", requires = "alert_on")]
+    alert_exec: Option<String>,
+
+    /// Print a summary of the bursts of each flow when the capture ends, such as on ctrl-c.
+    #[clap(long = "flow-stats")]
+    flow_stats: bool,
+
+    /// Write the summary of the bursts of each flow to this file as JSON when the capture ends.
+    #[clap(long = "stats-file")]
+    stats_file: Option<String>,
+
+    /// Only output histograms of the burst sizes of each flow, with the addresses made anonymous, instead of the bursts.
+    #[clap(
+        value_enum,
+        long = "privacy",
+        conflicts_with_all = [
+            "capture_outfile",
+            "tui",
+            "serve_http",
+            "serve",
+            "influx_url",
+            "alert_on",
+            "flow_stats",
+            "stats_file",
+            "flow_events",
+            "surge_threshold",
+            "concurrency_interval",
+            "rate_interval",
+            "compare_interface",
+        ]
+    )]
+    privacy: Option<PrivacyMode>,
+
+    /// Anonymize the addresses written to these outputs, as set by --anonymize.
+    #[clap(
+        value_enum,
+        long = "anonymize-output",
+        value_delimiter = ',',
+        conflicts_with = "privacy"
+    )]
+    anonymize_output: Vec<OutputSink>,
+
+    /// How to anonymize addresses, in every output unless --anonymize-output is given.
+    #[clap(value_enum, long = "anonymize", conflicts_with = "privacy")]
+    anonymize: Option<AnonymizeMode>,
+
+    /// Anonymize with the key in this file, so addresses are anonymized the same way by every run using it. A random key is written to the file if it does not exist.
+    #[clap(long = "anonymize-key")]
+    anonymize_key: Option<String>,
+
+    /// Seconds of bursts summarized by each histogram in privacy mode.
+    #[clap(
+        long = "privacy-interval",
+        default_value_t = 60.0,
+        requires = "privacy"
+    )]
+    privacy_interval: f64,
+
+    /// Also output histograms of the burst sizes and the gaps between bursts of each flow, every this many seconds of bursts, with log-scaled buckets.
+    #[clap(long = "histogram", value_name = "SECONDS", conflicts_with = "privacy")]
+    histogram: Option<f64>,
+
+    /// Only output the histograms on the standard output and output file, rather than the bursts as well. Other outputs still get the bursts.
+    #[clap(long = "histogram-only", requires = "histogram")]
+    histogram_only: bool,
+
+    /// Id of this probe to attach to every burst and statistic, to tell the records of many probes apart.
+    #[clap(long = "probe-id")]
+    probe_id: Option<String>,
+
+    /// Tag to attach to every burst and statistic as key=value, can be given several times.
+    #[clap(long = "tag", value_name = "KEY=VALUE")]
+    tag: Vec<String>,
+
+    /// Annotate bursts with the host names of their addresses, resolved by reverse DNS in the background, and the service names of their ports.
+    #[clap(long = "resolve")]
+    resolve: bool,
+
+    /// Annotate bursts with the countries and autonomous systems of their addresses from this MaxMind database, such as GeoLite2-Country or GeoLite2-ASN, can be given several times.
+    #[clap(long = "geoip-db", value_name = "MMDB")]
+    geoip_db: Vec<String>,
+
+    /// Upload the bursts file to this S3-compatible object storage endpoint when finished, along with each rotated file, and delete the local copies.
+    #[clap(long = "upload-endpoint", requires_all = ["bursts_outfile", "upload_bucket"])]
+    upload_endpoint: Option<String>,
+
+    /// Object storage bucket to upload to.
+    #[clap(long = "upload-bucket", requires = "upload_endpoint")]
+    upload_bucket: Option<String>,
+
+    /// Prefix of the object keys of uploaded files.
+    #[clap(
+        long = "upload-prefix",
+        default_value = "",
+        requires = "upload_endpoint"
+    )]
+    upload_prefix: String,
+
+    /// Region used when signing uploads.
+    #[clap(long = "upload-region", default_value = "us-east-1")]
+    upload_region: String,
+
+    /// Access key used when signing uploads.
+    #[clap(
+        long = "upload-access-key",
+        env = "AWS_ACCESS_KEY_ID",
+        hide_env_values = true
+    )]
+    upload_access_key: Option<String>,
+
+    /// Secret key used when signing uploads.
+    #[clap(
+        long = "upload-secret-key",
+        env = "AWS_SECRET_ACCESS_KEY",
+        hide_env_values = true
+    )]
+    upload_secret_key: Option<String>,
+
+    /// Run the capture pipeline over bundled captures of each capture type, and verify the bursts.
+    #[clap(long = "self-test", conflicts_with = "daemon")]
+    self_test: bool,
+
+    /// Detach and run in the background as a daemon.
+    #[clap(long = "daemon")]
+    daemon: bool,
+
+    /// Write the process id of the daemon to this file.
+    #[clap(long = "pid-file", requires = "daemon")]
+    pid_file: Option<String>,
+
+    /// Redirect the standard output and error of the daemon to this file.
+    #[clap(long = "log-file", requires = "daemon")]
+    log_file: Option<String>,
+
     #[clap(value_delimiter=' ', hide(true), conflicts_with_all(["capture_filter", "display_filter"]))]
     positional_filter: Option<Vec<String>>,
 }
 
+#[derive(Subcommand, Clone, Debug)]
+enum Command {
+    /// Compare the bursts of each flow between two burst logs, such as captures before and after a network change.
+    Diff(DiffArgs),
+
+    /// Measure how fast bursts are created from generated traffic, and verify that they are the bursts generated.
+    Bench(BenchArgs),
+
+    /// Find the bursts stored with --sqlite by their addresses, ports, sizes and times, or the flows they belong to.
+    Query(QueryArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct DiffArgs {
+    /// Burst log written with --output-format jsonl or json.
+    a: String,
+
+    /// Burst log to compare with the first.
+    b: String,
+
+    /// Print a JSON object per flow instead of a table.
+    #[clap(long = "jsonl")]
+    jsonl: bool,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct QueryArgs {
+    /// SQLite database written with --sqlite.
+    database: String,
+
+    /// Only bursts from this address.
+    #[clap(long = "src")]
+    src: Option<String>,
+
+    /// Only bursts to this address.
+    #[clap(long = "dst")]
+    dst: Option<String>,
+
+    /// Only bursts from or to this address.
+    #[clap(long = "host")]
+    host: Option<String>,
+
+    /// Only bursts from or to this port.
+    #[clap(long = "port")]
+    port: Option<u16>,
+
+    /// Only bursts of at least this size, in bytes or with a unit such as 1MB or 64KiB.
+    #[clap(long = "min-size", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Only bursts of at most this size, in bytes or with a unit such as 1MB or 64KiB.
+    #[clap(long = "max-size", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Only bursts that started at or after this time, in the time format of the capture.
+    #[clap(long = "from")]
+    from: Option<f64>,
+
+    /// Only bursts that ended at or before this time, in the time format of the capture.
+    #[clap(long = "to")]
+    to: Option<f64>,
+
+    /// Print the bursts, bytes and time range of each flow with matching bursts instead of the bursts, with the most bytes first.
+    #[clap(long = "flows")]
+    flows: bool,
+
+    /// Print at most this many bursts or flows.
+    #[clap(long = "limit")]
+    limit: Option<u64>,
+
+    /// Print a JSON object per burst or flow instead of a table. Bursts are printed as by --output-format jsonl, so they can be compared with diff.
+    #[clap(long = "jsonl")]
+    jsonl: bool,
+}
+
+/// A size in bytes, optionally with a unit
+fn parse_size(value: &str) -> Result<u64, String> {
+    match burstshark::alert::parse_bytes(value)? {
+        bytes if bytes >= 0.0 => Ok(bytes.round() as u64),
+        _ => Err(format!("negative size '{value}'")),
+    }
+}
+
+/// Parse a positive number of seconds, which can be made a duration
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>().map_err(|e| e.to_string())? {
+        seconds if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!("'{value}' is not a positive number of seconds")),
+    }
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct BenchArgs {
+    /// Type of packets to generate and create bursts of.
+    #[clap(long = "capture-type", value_enum, default_value_t = Protocol::Ip)]
+    capture_type: Protocol,
+
+    /// Flows sending bursts at the same time, each between a host of its own and a common destination.
+    #[clap(long = "flows", default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..=0xffffff))]
+    flows: u32,
+
+    /// Packets per second of each flow during its bursts.
+    #[clap(long = "packet-rate", default_value_t = 1000.0)]
+    packet_rate: f64,
+
+    /// Packets of each burst.
+    #[clap(long = "burst-packets", default_value_t = 50, value_parser = clap::value_parser!(u16).range(1..))]
+    burst_packets: u16,
+
+    /// Seconds each flow pauses between its bursts.
+    #[clap(long = "burst-gap", default_value_t = 2.0)]
+    burst_gap: f64,
+
+    /// Bytes of data of each packet.
+    #[clap(long = "packet-size", default_value_t = 1200)]
+    packet_size: u32,
+
+    /// Seconds of traffic to generate.
+    #[clap(long = "duration", default_value_t = 60.0)]
+    duration: f64,
+
+    /// Seconds of inactivity after which a burst is complete.
+    #[clap(long = "inactive-time", default_value_t = 1.0)]
+    inactive_time: f64,
+
+    /// Threads to distribute the flows over when creating bursts.
+    #[clap(long = "workers", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    workers: u16,
+
+    /// Print the results as a JSON object.
+    #[clap(long = "json")]
+    json: bool,
+}
+
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
 enum TimeFormat {
     /// Time relative to the first packet/frame.
@@ -95,36 +838,162 @@ enum TimeFormat {
     Epoch,
 }
 
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+enum Backend {
+    /// Dissect packets with tshark.
+    Tshark,
+
+    /// Capture and decode packet headers without tshark.
+    Native,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Tunnels {
+    /// A flow per security association or WireGuard session.
+    Session,
+
+    /// A flow per tunnel between two hosts.
+    Single,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Fifo {
+    /// Wait for a reader, stalling the capture.
+    Block,
+
+    /// Keep the latest lines until a reader attaches.
+    Buffer,
+
+    /// Drop lines, and report how many when finished.
+    Drop,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OverflowPolicy {
+    /// Wait for room in the queue.
+    Block,
+
+    /// Drop the oldest packet or record in the queue.
+    DropOldest,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Outliers {
+    /// Skip the frame, and expect the sequence number after the expected one.
+    Skip,
+
+    /// Skip the frame, and keep expecting the same sequence number.
+    Ignore,
+
+    /// Count the frame, and continue from its sequence number.
+    Resync,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum FlowId {
+    /// The sorted endpoints, such as 10.0.0.1:40000-10.0.0.2:443.
+    Key,
+
+    /// A 64-bit hash of the sorted endpoints, in hex.
+    Hash,
+}
+
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
 enum Protocol {
+    /// UDP and TCP packets.
     Ip,
+
+    /// 802.11 data frames.
     Wlan,
+
+    /// Non-IP ethernet frames.
+    Eth,
 }
 
-fn tshark_args(protocol: &Protocol, args: Args) -> Vec<String> {
-    let default_filter = match (&args.infile, &args.monitor_mode) {
-        (None, false) => String::from(
+/// The filter of tshark, which is a capture filter for live captures and a display filter for
+/// files, of the data packets along with the filter given
+fn tshark_filter(protocol: &Protocol, args: &Args) -> String {
+    let default_filter = match (&args.infile, protocol) {
+        (None, Protocol::Ip) => String::from(
             "udp or (tcp and (((ip[2:2] - ((ip[0]&0xf)<<2)) - ((tcp[12]&0xf0)>>2)) != 0))",
         ),
-        (None, true) => String::from("wlan type data subtype qos-data"),
-        (Some(_), false) => String::from("udp or (tcp and tcp.len > 0)"),
-        (Some(_), true) => String::from("wlan and wlan.fc.type_subtype == 40"),
+        (None, Protocol::Wlan) => String::from("wlan type data subtype qos-data"),
+        (None, Protocol::Eth) => String::from("not ip and not ip6"),
+        (Some(_), Protocol::Ip) => String::from("udp or (tcp and tcp.len > 0)"),
+        (Some(_), Protocol::Wlan) => String::from("wlan and wlan.fc.type_subtype == 40"),
+        (Some(_), Protocol::Eth) => String::from("eth and not ip and not ipv6"),
     };
 
-    let optional_filter = args.capture_filter.or(args.display_filter);
-    let supplied_filter = optional_filter.or(args.positional_filter.map(|f| f.join(" ")));
+    // Connection attempts carry no data, but are needed to detect scans and SYN floods
+    let default_filter = match (&args.infile, args.surge_threshold) {
+        (_, None) => default_filter,
+        (None, Some(_)) => format!(
+            "{} or (tcp[tcpflags] & (tcp-syn|tcp-ack) == tcp-syn)",
+            default_filter
+        ),
+        (Some(_), Some(_)) => format!(
+            "{} or (tcp.flags.syn == 1 and tcp.flags.ack == 0)",
+            default_filter
+        ),
+    };
+
+    // ESP directly over IP has no ports, WireGuard and encapsulated ESP are already captured as UDP
+    let default_filter = match (&args.infile, args.tunnels) {
+        (_, None) => default_filter,
+        (None, Some(_)) => format!("{} or ip proto 50", default_filter),
+        (Some(_), Some(_)) => format!("{} or esp", default_filter),
+    };
+
+    // VXLAN and GTP-U are already captured as UDP, and the display filter matches inner headers
+    let default_filter = match (&args.infile, args.decapsulate) {
+        (None, true) => format!("{} or ip proto 47", default_filter),
+        _ => default_filter,
+    };
+
+    // Protocols without ports are otherwise left out, along with TCP segments without data
+    let default_filter = match (&args.infile, args.all_protocols) {
+        (_, false) => default_filter,
+        (None, true) => format!(
+            "{} or ((ip or ip6) and not tcp and not udp)",
+            default_filter
+        ),
+        (Some(_), true) => format!(
+            "{} or ((ip or ipv6) and not tcp and not udp)",
+            default_filter
+        ),
+    };
+
+    // Resets and ICMP errors carry no data, but reference the flows that failed
+    let default_filter = match (&args.infile, args.flow_errors) {
+        (_, false) => default_filter,
+        (None, true) => format!(
+            "{} or (tcp[tcpflags] & tcp-rst != 0) or icmp[icmptype] == icmp-unreach or icmp[icmptype] == icmp-timxceed",
+            default_filter
+        ),
+        (Some(_), true) => format!(
+            "{} or tcp.flags.reset == 1 or icmp.type == 3 or icmp.type == 11",
+            default_filter
+        ),
+    };
+
+    let optional_filter = args.capture_filter.clone().or(args.display_filter.clone());
+    let supplied_filter = optional_filter.or(args.positional_filter.as_ref().map(|f| f.join(" ")));
 
-    let filter = match supplied_filter {
+    match supplied_filter {
         Some(filter) => format!("({}) and ({})", default_filter, filter),
         None => default_filter,
-    };
+    }
+}
+
+fn tshark_args(protocol: &Protocol, args: Args, fields: &[String]) -> Vec<String> {
+    let filter = tshark_filter(protocol, &args);
 
     let mut tshark_args = match &args.infile {
         Some(infile) => vec!["-r", infile, "-Y", &filter],
         None => vec!["-n", "-f", &filter],
     };
 
-    if let Some(interface) = &args.interface {
+    for interface in &args.interface {
         tshark_args.extend(vec!["-i", interface]);
     }
 
@@ -132,94 +1001,1016 @@ fn tshark_args(protocol: &Protocol, args: Args) -> Vec<String> {
         tshark_args.extend(vec!["-w", capture_outfile, "-P"]);
     }
 
-    tshark_args.extend(vec![
-        "-Q",
-        "-l",
-        "-T",
-        "fields",
-        "-e",
-        match args.time_format {
+    // RTP has no port of its own, so tshark only finds it by its heuristic
+    if args.rtp {
+        tshark_args.extend(vec!["--enable-heuristic", "rtp_udp"]);
+    }
+
+    tshark_args.extend(vec!["-Q", "-l", "-T", "fields"]);
+
+    tshark_args
+        .into_iter()
+        .map(str::to_string)
+        .chain(fields.iter().cloned())
+        .collect()
+}
+
+/// Whether to merge the frames of WLAN stations seen on several channels
+fn merge_channels(args: &Args) -> bool {
+    args.merge_channels || args.interface.len() > 1
+}
+
+/// The `-e` arguments of the parts of packets parsed for the protocol, in order, with the fields
+/// mapped by the user
+fn tshark_fields(protocol: &Protocol, args: &Args) -> Result<Vec<String>, BurstsharkError> {
+    let mut parts = vec![FieldPart::new(
+        "time",
+        &[match args.time_format {
             TimeFormat::Relative => "frame.time_relative",
             TimeFormat::Epoch => "frame.time_epoch",
-        },
-    ]);
+        }],
+    )];
 
-    tshark_args.extend(match protocol {
+    parts.extend(match protocol {
         Protocol::Ip => vec![
-            "-e",
-            "ip.src",
-            "-e",
-            "ip.dst",
-            "-e",
-            "udp.srcport",
-            "-e",
-            "tcp.srcport",
-            "-e",
-            "udp.dstport",
-            "-e",
-            "tcp.dstport",
-            "-e",
-            "data.len",
-            "-e",
-            "udp.length",
-            "-e",
-            "tcp.len",
+            FieldPart::new("src", &["ip.src"]),
+            FieldPart::new("dst", &["ip.dst"]),
+            FieldPart::new("src_port", &["udp.srcport", "tcp.srcport"]),
+            FieldPart::new("dst_port", &["udp.dstport", "tcp.dstport"]),
+            FieldPart::new("data_len", &["data.len", "udp.length", "tcp.len"]),
         ],
         Protocol::Wlan => vec![
-            "-e", "wlan.sa", "-e", "wlan.da", "-e", "data.len", "-e", "wlan.seq",
+            FieldPart::new("src", &["wlan.sa"]),
+            FieldPart::new("dst", &["wlan.da"]),
+            FieldPart::new("data_len", &["data.len"]),
+            FieldPart::new("seq_number", &["wlan.seq"]),
+            FieldPart::new("type_subtype", &["wlan.fc.type_subtype"]),
+            FieldPart::new("tid", &["wlan.qos.tid"]),
+        ],
+        Protocol::Eth => vec![
+            FieldPart::new("src", &["eth.src"]),
+            FieldPart::new("dst", &["eth.dst"]),
+            FieldPart::new("ethertype", &["eth.type"]),
+            FieldPart::new("data_len", &["frame.len"]),
         ],
     });
 
-    tshark_args.into_iter().map(str::to_string).collect()
+    if protocol == &Protocol::Wlan && args.airtime {
+        parts.push(FieldPart::new("data_rate", &["wlan_radio.data_rate"]));
+    }
+
+    if protocol == &Protocol::Wlan && merge_channels(args) {
+        parts.push(FieldPart::new("channel", &["wlan_radio.channel"]));
+    }
+
+    if protocol == &Protocol::Wlan && args.count_msdus {
+        parts.push(FieldPart::new(
+            "msdu_lengths",
+            &["wlan_aggregate.a_msdu.length"],
+        ));
+    }
+
+    if protocol == &Protocol::Ip && args.tunnels.is_some() {
+        parts.extend([
+            FieldPart::new("esp_spi", &["esp.spi"]),
+            FieldPart::new("wg_type", &["wg.type"]),
+            FieldPart::new("wg_receiver_index", &["wg.receiver_index"]),
+            FieldPart::new("ip_len", &["ip.len"]),
+            FieldPart::new("ip_hdr_len", &["ip.hdr_len"]),
+        ]);
+    }
+
+    if protocol == &Protocol::Ip && args.decapsulate {
+        parts.extend([
+            FieldPart::new("vxlan_vni", &["vxlan.vni"]),
+            FieldPart::new("gre_proto", &["gre.proto"]),
+            FieldPart::new("gre_key", &["gre.key"]),
+            FieldPart::new("gtp_teid", &["gtp.teid"]),
+            FieldPart::new("ip_len", &["ip.len"]),
+            FieldPart::new("ip_hdr_len", &["ip.hdr_len"]),
+        ]);
+    }
+
+    if protocol == &Protocol::Ip && args.flow_errors {
+        parts.extend([
+            FieldPart::new("tcp_reset", &["tcp.flags.reset"]),
+            FieldPart::new("icmp_type", &["icmp.type"]),
+        ]);
+    }
+
+    if protocol == &Protocol::Ip && args.quic {
+        parts.extend([
+            FieldPart::new("quic_dcid", &["quic.dcid"]),
+            FieldPart::new("quic_scid", &["quic.scid"]),
+        ]);
+    }
+
+    if protocol == &Protocol::Ip && args.rtp {
+        parts.extend([
+            FieldPart::new("rtp_ssrc", &["rtp.ssrc"]),
+            FieldPart::new("rtp_payload_type", &["rtp.p_type"]),
+            FieldPart::new("rtp_seq", &["rtp.seq"]),
+        ]);
+    }
+
+    if protocol == &Protocol::Ip && args.capture_qos {
+        parts.extend([
+            FieldPart::new("dscp", &["ip.dsfield.dscp", "ipv6.tclass.dscp"]),
+            FieldPart::new("flow_label", &["ipv6.flow"]),
+        ]);
+    }
+
+    if protocol == &Protocol::Ip && args.exclude_retransmissions {
+        parts.push(FieldPart::new("tcp_seq", &["tcp.seq"]));
+    }
+
+    if protocol == &Protocol::Ip && (args.eve_compat || args.all_protocols) {
+        parts.push(FieldPart::new("proto", &["ip.proto", "ipv6.nxt"]));
+    }
+
+    if protocol == &Protocol::Ip && args.all_protocols {
+        parts.extend([
+            FieldPart::new("ip_len", &["ip.len"]),
+            FieldPart::new("ip_hdr_len", &["ip.hdr_len"]),
+            FieldPart::new("ipv6_plen", &["ipv6.plen"]),
+        ]);
+    }
+
+    // Always the last field, as it is split off before the packet is parsed
+    if args.interface.len() > 1 {
+        parts.push(FieldPart::new("interface", &["frame.interface_id"]));
+    }
+
+    let mut mapping = match &args.tshark_fields_file {
+        Some(path) => FieldMapping::from_file(path)?,
+        None => FieldMapping::default(),
+    };
+    if let Some(fields) = &args.tshark_fields {
+        mapping.extend(FieldMapping::parse(fields)?);
+    }
+    mapping.apply(&mut parts)?;
+
+    Ok(tshark::field_args(&parts))
+}
+
+/// Parse the command line again with the options of the profile that it does not set
+fn with_profile(args: &Args, profile: &str, matches: &ArgMatches) -> Result<Args, BurstsharkError> {
+    Ok(Args::parse_from(profile_command_line(
+        args, profile, matches,
+    )?))
+}
+
+/// The command line with the options of the profile that it does not set
+fn profile_command_line(
+    args: &Args,
+    profile: &str,
+    matches: &ArgMatches,
+) -> Result<Vec<OsString>, BurstsharkError> {
+    let path = args
+        .config
+        .clone()
+        .or_else(config::default_path)
+        .ok_or_else(|| {
+            BurstsharkError::InvalidOptions(
+                "No --config given, and no home directory to find the config file in".to_string(),
+            )
+        })?;
+    let profile_args = config::profile_args(&path, profile, &Args::command(), matches)?;
+
+    let mut command_line = std::env::args_os();
+    let program = command_line.next();
+    Ok(program
+        .into_iter()
+        .chain(profile_args)
+        .chain(command_line)
+        .collect())
+}
+
+/// The settings of the capture that can be changed while it runs
+fn runtime_settings(args: &Args) -> RuntimeSettings {
+    RuntimeSettings {
+        filter: BurstFilter {
+            min_bytes: args.min_bytes,
+            max_bytes: args.max_bytes,
+            min_packets: args.min_packets,
+            max_packets: args.max_packets,
+        },
+        suppress: args.suppress,
+        // The positional filter is a display filter when reading files
+        capture_filter: match &args.infile {
+            Some(_) => None,
+            None => args
+                .capture_filter
+                .clone()
+                .or(args.positional_filter.as_ref().map(|f| f.join(" "))),
+        },
+        tshark_filter: None,
+    }
+}
+
+/// The command line and protocol of the capture, for the commands of the control socket
+struct ControlConfig {
+    args: Args,
+    matches: ArgMatches,
+    protocol: Protocol,
+}
+
+impl Configuration for ControlConfig {
+    fn reload(&self) -> Result<RuntimeSettings, BurstsharkError> {
+        let args = match &self.args.profile {
+            // Reported rather than exiting, as when the profile is read at start
+            Some(profile) => {
+                Args::try_parse_from(profile_command_line(&self.args, profile, &self.matches)?)
+                    .map_err(|e| {
+                        BurstsharkError::InvalidOptions(format!(
+                            "Invalid options of profile {profile}: {e}"
+                        ))
+                    })?
+            }
+            None => self.args.clone(),
+        };
+        Ok(runtime_settings(&args))
+    }
+
+    fn tshark_filter(&self, capture_filter: Option<&str>) -> Result<String, BurstsharkError> {
+        let args = &self.args;
+        if args.infile.is_some()
+            || args.stdin
+            || args.source_command.is_some()
+            || args.backend != Backend::Tshark
+        {
+            return Err(BurstsharkError::InvalidCommand(
+                "The capture filter can only be changed for live captures by tshark".to_string(),
+            ));
+        }
+        if args.time_format != TimeFormat::Epoch {
+            return Err(BurstsharkError::InvalidCommand("Changing the capture filter restarts tshark, which needs --time-format epoch to keep the times of packets".to_string()));
+        }
+        if args.capture_outfile.is_some() {
+            return Err(BurstsharkError::InvalidCommand("Changing the capture filter restarts tshark, which would overwrite the capture written with -w".to_string()));
+        }
+        if let Some(filter) = capture_filter {
+            tshark::check_capture_filter(filter).map_err(BurstsharkError::InvalidCommand)?;
+        }
+        Ok(tshark_filter(
+            &self.protocol,
+            &Args {
+                capture_filter: capture_filter.map(str::to_string),
+                positional_filter: None,
+                ..args.clone()
+            },
+        ))
+    }
+}
+
+/// Compare the flows of two burst logs, and print their differences
+fn run_diff(args: &DiffArgs) -> Result<(), BurstsharkError> {
+    let diffs = diff::diff(&burstlog::read(&args.a)?, &burstlog::read(&args.b)?);
+    let mut out = io::stdout().lock();
+    match args.jsonl {
+        true => {
+            for flow in &diffs {
+                serde_json::to_writer(&mut out, flow)?;
+                writeln!(out)?;
+            }
+        }
+        false => diff::write_table(&mut out, &diffs)?,
+    }
+    Ok(())
+}
+
+/// Find the bursts or flows of a database matching the filters, and print them
+#[cfg(feature = "sqlite")]
+fn run_query(args: &QueryArgs) -> Result<(), BurstsharkError> {
+    use burstshark::burstdb;
+
+    let conn = burstdb::open(&args.database)?;
+    let query = burstdb::BurstQuery {
+        src: args.src.clone(),
+        dst: args.dst.clone(),
+        host: args.host.clone(),
+        port: args.port,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        from: args.from,
+        to: args.to,
+        limit: args.limit,
+    };
+    let mut out = io::stdout().lock();
+    match (args.flows, args.jsonl) {
+        (true, true) => {
+            for flow in &burstdb::flows(&conn, &query)? {
+                serde_json::to_writer(&mut out, flow)?;
+                writeln!(out)?;
+            }
+        }
+        (true, false) => burstdb::write_flows(&mut out, &burstdb::flows(&conn, &query)?)?,
+        (false, true) => {
+            for burst in &burstdb::bursts(&conn, &query)? {
+                writeln!(out, "{}", burst.record)?;
+            }
+        }
+        (false, false) => burstdb::write_bursts(&mut out, &burstdb::bursts(&conn, &query)?)?,
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn run_query(_args: &QueryArgs) -> Result<(), BurstsharkError> {
+    Err(BurstsharkError::MissingFeature {
+        name: "SQLite",
+        feature: "sqlite",
+    })
+}
+
+/// Create bursts from generated traffic, and print how fast and whether they were as generated
+fn run_bench(args: &BenchArgs) -> Result<bool, BurstsharkError> {
+    if args.packet_rate <= 0.0 || args.duration <= 0.0 {
+        return Err(BurstsharkError::InvalidOptions(
+            "The packet rate and duration must be positive".to_string(),
+        ));
+    }
+    let traffic = SyntheticTraffic {
+        flows: args.flows,
+        packet_rate: args.packet_rate,
+        burst_packets: args.burst_packets,
+        burst_gap: args.burst_gap,
+        packet_size: args.packet_size,
+        duration: args.duration,
+    };
+    let capture = |opts| match args.capture_type {
+        Protocol::Ip => CaptureType::IPCapture {
+            opts,
+            ip: IpOptions::default(),
+        },
+        Protocol::Wlan => CaptureType::WLANCapture {
+            opts,
+            wlan: WlanOptions::default(),
+        },
+        Protocol::Eth => CaptureType::EthCapture { opts },
+    };
+    let report = bench::run(&traffic, args.inactive_time, args.workers as usize, capture)?;
+
+    let mut out = io::stdout().lock();
+    match args.json {
+        true => {
+            serde_json::to_writer(&mut out, &report)?;
+            writeln!(out)?;
+        }
+        false => {
+            writeln!(
+                out,
+                "Generated {} packets in {} bursts of {} flows",
+                report.packets, report.expected_bursts, args.flows
+            )?;
+            writeln!(
+                out,
+                "Created {} bursts in {:.3} s: {:.0} packets/s, {:.0} bursts/s",
+                report.bursts,
+                report.elapsed.as_secs_f64(),
+                report.packets_per_sec(),
+                report.bursts_per_sec()
+            )?;
+            match report.passed() {
+                true => writeln!(out, "All bursts are as generated")?,
+                false => writeln!(
+                    out,
+                    "Expected {} bursts, but {} were created with other sizes",
+                    report.expected_bursts, report.mismatched
+                )?,
+            }
+        }
+    }
+    Ok(report.passed())
 }
 
-fn main() {
-    let args: Args = Args::parse();
-    let protocol = match args.monitor_mode {
-        true => Protocol::Wlan,
-        false => Protocol::Ip,
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if let Some(profile) = &args.profile {
+        args = match with_profile(&args, profile, &matches) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+
+    if let Some(Command::Diff(diff_args)) = &args.command {
+        if let Err(e) = run_diff(diff_args) {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(Command::Query(query_args)) = &args.command {
+        if let Err(e) = run_query(query_args) {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        match run_bench(bench_args) {
+            Ok(true) => return ExitCode::SUCCESS,
+            Ok(false) => return ExitCode::FAILURE,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if args.self_test {
+        return match burstshark::selftest::run() {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        };
+    }
+
+    let protocol = match (&args.format, args.monitor_mode, args.l2_mode) {
+        (Some(format), _, _) => format.clone(),
+        (None, true, _) => Protocol::Wlan,
+        (None, false, true) => Protocol::Eth,
+        (None, false, false) => Protocol::Ip,
+    };
+
+    let line_source = args.stdin || args.source_command.is_some();
+    if (args.format.is_some() || args.live) && !line_source {
+        eprintln!("Error: --format and --live require --stdin or --command");
+        return ExitCode::FAILURE;
+    }
+
+    if args.eve_file.is_some() && args.time_format != TimeFormat::Epoch {
+        eprintln!("Error: --eve-file requires --time-format epoch");
+        return ExitCode::FAILURE;
+    }
+
+    if args.influx_url.is_some() && args.time_format != TimeFormat::Epoch {
+        eprintln!("Error: --influx-url requires --time-format epoch");
+        return ExitCode::FAILURE;
+    }
+
+    let alert_conditions = match args
+        .alert_on
+        .iter()
+        .map(|condition| condition.parse::<AlertCondition>())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(conditions) => conditions,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if !alert_conditions.is_empty() && args.alert_webhook.is_none() && args.alert_exec.is_none() {
+        eprintln!("Error: --alert-on requires --alert-webhook or --alert-exec");
+        return ExitCode::FAILURE;
+    }
+
+    if args.eve_compat {
+        if args.time_format != TimeFormat::Epoch {
+            eprintln!("Error: --eve-compat requires --time-format epoch");
+            return ExitCode::FAILURE;
+        }
+        // EVE flow ids are numbers, which the hashes are
+        match (&protocol, args.flow_id) {
+            (_, Some(FlowId::Key)) => {
+                eprintln!("Error: --eve-compat needs flow ids in the hash format");
+                return ExitCode::FAILURE;
+            }
+            (Protocol::Ip, None) => args.flow_id = Some(FlowId::Hash),
+            _ => (),
+        }
+        args.output_format = OutputFormat::Eve;
+    }
+
+    if args.compare_interface.is_some() && args.time_format != TimeFormat::Epoch {
+        eprintln!("Error: --compare-interface requires --time-format epoch");
+        return ExitCode::FAILURE;
+    }
+
+    if args.backend == Backend::Native && args.remote.is_some() {
+        eprintln!("Error: --remote runs tshark on the remote host, which needs the tshark backend");
+        return ExitCode::FAILURE;
+    }
+
+    if args.backend == Backend::Native
+        && (args.capture_filter.is_some()
+            || args.display_filter.is_some()
+            || args.positional_filter.is_some()
+            || args.capture_outfile.is_some())
+    {
+        eprintln!("Error: the native backend does not support filters or writing captures");
+        return ExitCode::FAILURE;
+    }
+
+    if args.backend == Backend::Native
+        && (args.tshark_fields.is_some()
+            || args.tshark_fields_file.is_some()
+            || args.dump_bad_lines.is_some()
+            || args.log_malformed)
+    {
+        eprintln!("Error: the native backend does not use tshark fields");
+        return ExitCode::FAILURE;
+    }
+
+    // The positional filter is a display filter when reading files, like -Y
+    let positional_filter = args
+        .positional_filter
+        .as_ref()
+        .map(|filter| filter.join(" "));
+    let (capture_filter, display_filter) = match &args.infile {
+        Some(_) => (None, args.display_filter.clone().or(positional_filter)),
+        None => (args.capture_filter.clone().or(positional_filter), None),
+    };
+    let filter_check = capture_filter
+        .as_deref()
+        .map_or(Ok(()), tshark::check_capture_filter)
+        .and(
+            display_filter
+                .as_deref()
+                .map_or(Ok(()), tshark::check_display_filter),
+        );
+    if let Err(e) = filter_check {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let fields = match tshark_fields(&protocol, &args) {
+        Ok(fields) => fields,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.interface.len() > 1 && args.compare_interface.is_some() {
+        eprintln!("Error: --compare-interface compares a single interface with another");
+        return ExitCode::FAILURE;
+    }
+
+    if args.backend == Backend::Native && args.interface.len() > 1 {
+        eprintln!("Error: the native backend captures on a single interface");
+        return ExitCode::FAILURE;
+    }
+
+    if args.no_guess && !args.monitor_mode && !args.rtp {
+        eprintln!("Error: --no-guess requires --monitor-mode or --rtp");
+        return ExitCode::FAILURE;
+    }
+
+    if args.backend == Backend::Native && args.rtp {
+        eprintln!("Error: --rtp needs the tshark backend, which finds RTP by its heuristic");
+        return ExitCode::FAILURE;
+    }
+
+    if args.backend == Backend::Native && args.quic {
+        eprintln!(
+            "Error: --quic needs the tshark backend, which tracks the lengths of connection ids"
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let adaptive_gap = args.adaptive_gap.map(|factor| AdaptiveGap {
+        factor,
+        min: args.min_gap,
+        max: args.max_gap.unwrap_or(args.inactive_time),
+    });
+    if adaptive_gap.is_some_and(|gap| !(gap.factor > 0.0 && 0.0 < gap.min && gap.min <= gap.max)) {
+        eprintln!("Error: --adaptive-gap and --min-gap must be positive, and at most --max-gap");
+        return ExitCode::FAILURE;
+    }
+
+    if args.rate_interval.is_some_and(|interval| interval <= 0.0) {
+        eprintln!("Error: --rate-interval must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    if args.merge_gap.is_some_and(|gap| gap <= 0.0) {
+        eprintln!("Error: --merge-gap must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    if args.privacy.is_some() && args.privacy_interval <= 0.0 {
+        eprintln!("Error: --privacy-interval must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    if args.anonymize_key.is_some() && args.anonymize.is_none() && args.anonymize_output.is_empty()
+    {
+        eprintln!("Error: --anonymize-key requires --anonymize or --anonymize-output");
+        return ExitCode::FAILURE;
+    }
+
+    if args.histogram.is_some_and(|interval| interval <= 0.0) {
+        eprintln!("Error: --histogram must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    if args.flow_timeout.is_some_and(|timeout| timeout <= 0.0) {
+        eprintln!("Error: --flow-timeout must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    if args.rotate_interval.is_some_and(|interval| interval <= 0.0) {
+        eprintln!("Error: --rotate-interval must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    if args.compress.is_some() && args.rotate_size.is_none() && args.rotate_interval.is_none() {
+        eprintln!("Error: --compress requires --rotate-size or --rotate-interval");
+        return ExitCode::FAILURE;
+    }
+
+    if matches!(args.output_format, OutputFormat::Csv | OutputFormat::Eve)
+        && (args.flow_events
+            || args.privacy.is_some()
+            || args.histogram.is_some()
+            || args.compare_interface.is_some()
+            || args.surge_threshold.is_some()
+            || args.concurrency_interval.is_some())
+    {
+        eprintln!(
+            "Error: CSV and EVE output only contain bursts, use JSON for flow events and alerts"
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let upload = match (&args.upload_endpoint, &args.upload_bucket) {
+        (Some(endpoint), Some(bucket)) => {
+            let (Some(access_key), Some(secret_key)) =
+                (&args.upload_access_key, &args.upload_secret_key)
+            else {
+                eprintln!("Error: uploading requires --upload-access-key and --upload-secret-key");
+                return ExitCode::FAILURE;
+            };
+            let store = ObjectStore {
+                endpoint: endpoint.clone(),
+                bucket: bucket.clone(),
+                prefix: args.upload_prefix.clone(),
+                region: args.upload_region.clone(),
+                access_key: access_key.clone(),
+                secret_key: secret_key.clone(),
+            };
+            if let Err(e) = store.check_endpoint() {
+                eprintln!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
+            Some(store)
+        }
+        _ => None,
+    };
+
+    let probe = match ProbeIdentity::new(args.probe_id.as_deref(), &args.tag) {
+        Ok(probe) => probe,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // After the options are checked, so invalid ones are reported by the original process, but
+    // before any threads are started
+    let mut daemon = match args.daemon {
+        true => match daemon::daemonize(args.pid_file.as_deref(), args.log_file.as_deref()) {
+            Ok(daemon) => Some(daemon),
+            Err(e) => {
+                eprintln!("Error starting daemon: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        false => None,
+    };
+
+    let profile = args
+        .profile_pipeline
+        .then(|| Arc::new(PipelineProfile::default()));
+    let mut profile_reporter = profile.clone().map(|profile| {
+        ProfileReporter::start(profile, Duration::from_secs_f64(args.profile_interval))
+    });
+
+    // Packets and records are counted apart, as each dropped record is a lost burst
+    let channels = || ChannelOptions {
+        capacity: args.channel_capacity.map(|capacity| capacity as usize),
+        overflow: match args.overflow {
+            OverflowPolicy::Block => Overflow::Block,
+            OverflowPolicy::DropOldest => Overflow::DropOldest,
+        },
+        dropped: Arc::default(),
+    };
+    let packet_channels = channels();
+    let record_channels = channels();
+
+    // Removed along with its socket when the capture ends
+    let controls = args
+        .control_socket
+        .as_ref()
+        .map(|_| Arc::new(Controls::new(runtime_settings(&args))));
+    let _control_socket = match (&args.control_socket, &controls) {
+        (Some(path), Some(controls)) => {
+            let config = ControlConfig {
+                args: args.clone(),
+                matches: matches.clone(),
+                protocol: protocol.clone(),
+            };
+            match ControlSocket::start(path, controls.clone(), config) {
+                Ok(socket) => Some(socket),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        _ => None,
     };
 
-    let mut output_writer = OutputWriter::new(
-        args.bursts_outfile.clone(),
-        args.suppress,
-        args.min_bytes,
-        args.max_bytes,
-        args.min_packets,
-        args.max_packets,
-    );
+    let mut output_writer = OutputWriter::new(OutputOptions {
+        outfile: args.bursts_outfile.clone(),
+        rotate: (args.rotate_size.is_some() || args.rotate_interval.is_some()).then(|| {
+            RotateOptions {
+                size: args.rotate_size,
+                interval: args.rotate_interval.map(Duration::from_secs_f64),
+                compression: args.compress,
+            }
+        }),
+        fifo_policy: match args.fifo_policy {
+            Fifo::Block => FifoPolicy::Block,
+            Fifo::Buffer => FifoPolicy::Buffer(args.fifo_buffer),
+            Fifo::Drop => FifoPolicy::Drop,
+        },
+        format: args.output_format,
+        suppress: args.suppress,
+        tui: args.tui,
+        dashboard: args.serve_http.clone(),
+        serve: args.serve.clone(),
+        export: args.export.clone(),
+        sqlite: args.sqlite.clone(),
+        split: args.split_output.as_ref().map(|dir| SplitOptions {
+            dir: dir.clone(),
+            max_open: args.split_max_open as usize,
+            rotate_size: args.split_rotate_size,
+        }),
+        probe,
+        merge_gap: args.merge_gap,
+        resolve: args.resolve,
+        geoip_db: args.geoip_db.clone(),
+        influx: match (&args.influx_url, &args.influx_bucket) {
+            (Some(url), Some(bucket)) => Some(InfluxOptions {
+                url: url.clone(),
+                bucket: bucket.clone(),
+                org: args.influx_org.clone(),
+                token: args.influx_token.clone(),
+            }),
+            _ => None,
+        },
+        alert: (!alert_conditions.is_empty()).then(|| AlertOptions {
+            conditions: alert_conditions,
+            webhook: args.alert_webhook.clone(),
+            exec: args.alert_exec.clone(),
+        }),
+        anonymize: match (args.anonymize, args.anonymize_output.is_empty()) {
+            (Some(_), true) => OutputSink::value_variants().to_vec(),
+            _ => args.anonymize_output.clone(),
+        },
+        anonymize_key: args.anonymize_key.clone(),
+        anonymize_mode: args.anonymize.unwrap_or_default(),
+        eve_file: args.eve_file.clone(),
+        eve_from_start: args.infile.is_some(),
+        port_profiles: args.port_profiles.clone(),
+        learn_time: args.learn_time,
+        max_response_time: args.response_latency.then_some(args.max_response_time),
+        classify: args.classify_flows,
+        signatures: args.signatures.clone(),
+        flow_stats: args.flow_stats,
+        stats_file: args.stats_file.clone(),
+        privacy: args.privacy,
+        privacy_interval: args.privacy_interval,
+        histogram: args.histogram,
+        histogram_only: args.histogram_only,
+        upload,
+        channels: record_channels.clone(),
+        sched: ThreadSched {
+            cpu: args.output_cpu,
+            nice: args.nice,
+        },
+        profile: profile.clone(),
+        controls: controls.clone(),
+    });
 
     let tx = match output_writer.start() {
         Ok(tx) => tx,
         Err(e) => {
             eprintln!("Error starting output writer: {}", e);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
-    let opts = CommonOptions {
-        tshark_args: tshark_args(&protocol, args.clone()),
-        inactive_time: args.inactive_time,
-        tx,
-    };
+    // The output has started, which is what fails on bad output options
+    if let Some(daemon) = &mut daemon {
+        if let Err(e) = daemon.started() {
+            eprintln!("Error starting daemon: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
 
-    let capture_result = match protocol {
-        Protocol::Ip => CaptureType::IPCapture {
-            opts,
-            ignore_ports: args.ignore_ports,
+    let evictions = Arc::new(FlowEvictions::default());
+
+    // A capture on the interface, sending its records to `tx`
+    let capture = |interface: Vec<String>, tx: Sender<Record>| {
+        let backend = match (&args.source_command, &args.backend) {
+            _ if args.stdin => CaptureBackend::Stdin,
+            (Some(command), _) => CaptureBackend::Exec {
+                command: command.clone(),
+            },
+            (None, Backend::Tshark) => {
+                let tshark_args = tshark_args(
+                    &protocol,
+                    Args {
+                        interface: interface.clone(),
+                        ..args.clone()
+                    },
+                    &fields,
+                );
+                match &args.remote {
+                    Some(destination) => CaptureBackend::Remote {
+                        host: RemoteHost {
+                            port: args.ssh_port,
+                            identity: args.ssh_identity.clone(),
+                            options: args.ssh_option.clone(),
+                            tshark: args.remote_tshark.clone(),
+                            ..RemoteHost::new(destination.clone())
+                        },
+                        args: tshark_args,
+                    },
+                    None => CaptureBackend::Tshark { args: tshark_args },
+                }
+            }
+            (None, Backend::Native) => CaptureBackend::Native {
+                source: match &args.infile {
+                    Some(infile) => NativeSource::File(infile.clone()),
+                    None => NativeSource::Interface(interface.first().cloned()),
+                },
+                epoch_time: args.time_format == TimeFormat::Epoch,
+            },
+        };
+
+        let opts = CommonOptions {
+            backend,
+            inactive_time: args.inactive_time,
+            offline: args.infile.is_some() || (line_source && !args.live),
+            flow_events: args.flow_events,
+            filter: BurstFilter {
+                min_bytes: args.min_bytes,
+                max_bytes: args.max_bytes,
+                min_packets: args.min_packets,
+                max_packets: args.max_packets,
+            },
+            max_burst_packets: args.max_burst_packets,
+            adaptive_gap,
+            flow_timeout: args.flow_timeout,
+            max_flows: args.max_flows.map(|max| max as usize),
+            evictions: evictions.clone(),
+            packet_timing: args.packet_timing.then_some(args.max_timing_packets),
+            detailed_bursts: args.detailed_bursts,
+            rate_interval: args.rate_interval,
+            workers: args.workers as usize,
+            channels: packet_channels.clone(),
+            bad_lines_file: args.dump_bad_lines.clone(),
+            log_malformed: args.log_malformed,
+            state_file: args.state_file.clone(),
+            interfaces: match interface.len() > 1 {
+                true => interface
+                    .iter()
+                    .map(|name| Arc::from(name.as_str()))
+                    .collect(),
+                false => Vec::new(),
+            },
+            tx,
+            reader_sched: ThreadSched {
+                cpu: args.reader_cpu,
+                nice: args.nice,
+            },
+            worker_sched: ThreadSched {
+                cpu: args.worker_cpu,
+                nice: args.nice,
+            },
+            profile: profile.clone(),
+            controls: controls.clone(),
+        };
+
+        match protocol {
+            Protocol::Ip => CaptureType::IPCapture {
+                opts,
+                ip: IpOptions {
+                    ignore_ports: args.ignore_ports,
+                    group_keys: args.multicast_groups,
+                    tunnels: args.tunnels.map(|tunnels| match tunnels {
+                        Tunnels::Session => TunnelMode::Session,
+                        Tunnels::Single => TunnelMode::Single,
+                    }),
+                    decapsulate: args.decapsulate,
+                    ssh_phases: args.ssh_phases,
+                    bidirectional: args.bidirectional,
+                    surge: args.surge_threshold.map(|threshold| SurgeOptions {
+                        threshold,
+                        window: args.surge_window,
+                    }),
+                    concurrency_interval: args.concurrency_interval,
+                    flow_ids: args.flow_id.map(|flow_id| match flow_id {
+                        FlowId::Key => FlowIdFormat::Key,
+                        FlowId::Hash => FlowIdFormat::Hash,
+                    }),
+                    flow_errors: args.flow_errors,
+                    quic: args.quic,
+                    rtp: args.rtp,
+                    no_guess: args.no_guess,
+                    qos: args.capture_qos,
+                    exclude_retransmissions: args.exclude_retransmissions,
+                    portless: args.all_protocols,
+                    protocols: args.eve_compat,
+                },
+            },
+            Protocol::Wlan => {
+                let window = SequenceWindow::symmetric(args.max_deviation);
+                CaptureType::WLANCapture {
+                    opts,
+                    wlan: WlanOptions {
+                        no_guess: args.no_guess,
+                        sequence: SequenceWindow {
+                            reorder_tolerance: args
+                                .reorder_tolerance
+                                .unwrap_or(window.reorder_tolerance),
+                            max_missed: args.max_missed.unwrap_or(window.max_missed),
+                            outliers: match args.outliers {
+                                Outliers::Skip => OutlierPolicy::Skip,
+                                Outliers::Ignore => OutlierPolicy::Ignore,
+                                Outliers::Resync => OutlierPolicy::Resync,
+                            },
+                        },
+                        airtime: args.airtime,
+                        channels: merge_channels(&args),
+                        no_tid: args.no_tid,
+                        msdus: args.count_msdus,
+                        correlate_macs: args.correlate_random_macs,
+                    },
+                }
+            }
+            Protocol::Eth => CaptureType::EthCapture { opts },
         }
-        .run(),
-        Protocol::Wlan => CaptureType::WLANCapture {
-            opts,
-            no_guess: args.no_guess,
-            max_deviation: args.max_deviation,
+    };
+
+    let capture_result = match &args.compare_interface {
+        None => capture(args.interface.clone(), tx).run(),
+        Some(compare_interface) => {
+            let (before_tx, after_tx, comparison) = BurstComparison::start(args.compare_window, tx);
+            let after = capture(vec![compare_interface.clone()], after_tx);
+            let after = thread::spawn(move || after.run());
+            let before = capture(args.interface.clone(), before_tx).run();
+            let after = match after.join() {
+                Ok(result) => result,
+                Err(_) => Err(BurstsharkError::Panicked("compared capture")),
+            };
+            comparison.stop();
+            before.and(after)
         }
-        .run(),
     };
 
-    output_writer.stop();
+    let output_result = output_writer.stop();
 
-    if let Err(e) = capture_result {
-        eprintln!("Error running capture: {}", e);
+    if let Some(reporter) = &mut profile_reporter {
+        reporter.stop();
+    }
+
+    if let Err(e) = &output_result {
+        eprintln!("Error writing output: {}", e);
+    }
+    if packet_channels.dropped() + record_channels.dropped() > 0 {
+        eprintln!(
+            "Dropped {} packets and {} records as their queues were full",
+            packet_channels.dropped(),
+            record_channels.dropped()
+        );
+    }
+    if evictions.idle() + evictions.over_limit() > 0 {
+        eprintln!(
+            "Evicted {} idle flows and {} flows beyond --max-flows",
+            evictions.idle(),
+            evictions.over_limit()
+        );
+    }
+    match capture_result {
+        // The capture stops when the output does, which is already reported
+        Err(BurstsharkError::ChannelClosed) if output_result.is_err() => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error running capture: {}", e);
+            ExitCode::FAILURE
+        }
+        Ok(()) if output_result.is_err() => ExitCode::FAILURE,
+        Ok(()) => ExitCode::SUCCESS,
     }
 }
@@ -0,0 +1,164 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+};
+
+use super::sink::BurstSink;
+use crate::{alert::AlertCondition, capture::Burst, BurstsharkError};
+
+/// Conditions on bursts to raise alerts for, and where to deliver the alerts
+#[derive(Clone, Debug)]
+pub struct AlertOptions {
+    /// Alerts are raised for bursts matching any of the conditions
+    pub conditions: Vec<AlertCondition>,
+    /// URL to POST the JSON of each alerted burst to
+    pub webhook: Option<String>,
+    /// Shell command run for each alerted burst, with its JSON on the standard input
+    pub exec: Option<String>,
+}
+
+/// A burst matching a condition, as JSON
+struct Alert {
+    condition: String,
+    burst: String,
+}
+
+/// Raises alerts for the bursts matching the conditions, delivered on a thread of its own in the
+/// order they are raised.
+///
+/// The webhook is sent the burst with the condition it matched in the `X-Burstshark-Alert` header,
+/// and the command is run with it in the `BURSTSHARK_ALERT` environment variable. Failures to
+/// deliver an alert are reported and the alert dropped.
+pub struct AlertSink {
+    conditions: Vec<AlertCondition>,
+    tx: Sender<Alert>,
+    handle: JoinHandle<()>,
+}
+
+impl AlertSink {
+    pub fn start(opts: AlertOptions) -> Self {
+        let (tx, rx) = mpsc::channel::<Alert>();
+
+        let AlertOptions {
+            conditions,
+            webhook,
+            exec,
+        } = opts;
+        let handle = thread::spawn(move || {
+            for alert in rx {
+                if let Some(url) = &webhook {
+                    if let Err(e) = post(url, &alert) {
+                        eprintln!("Error posting alert to {url}: {e}");
+                    }
+                }
+                if let Some(command) = &exec {
+                    if let Err(e) = run(command, &alert) {
+                        eprintln!("Error running alert command: {e}");
+                    }
+                }
+            }
+        });
+
+        AlertSink {
+            conditions,
+            tx,
+            handle,
+        }
+    }
+}
+
+impl BurstSink for AlertSink {
+    fn write(&mut self, burst: &Burst) {
+        let Some(condition) = self.conditions.iter().find(|c| c.matches(burst)) else {
+            return;
+        };
+        match serde_json::to_string(burst) {
+            Ok(json) => {
+                let _ = self.tx.send(Alert {
+                    condition: condition.to_string(),
+                    burst: json,
+                });
+            }
+            Err(e) => eprintln!("Error formatting alert: {e}"),
+        }
+    }
+
+    fn finish(self: Box<Self>) {
+        let AlertSink { tx, handle, .. } = *self;
+        drop(tx);
+        let _ = handle.join();
+    }
+}
+
+fn post(url: &str, alert: &Alert) -> Result<(), BurstsharkError> {
+    ureq::post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Burstshark-Alert", &alert.condition)
+        .send(&alert.burst)?;
+    Ok(())
+}
+
+fn run(command: &str, alert: &Alert) -> Result<(), BurstsharkError> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("BURSTSHARK_ALERT", &alert.condition)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may exit without reading the burst
+        let _ = writeln!(stdin, "{}", alert.burst);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(BurstsharkError::CommandFailed {
+            command: command.to_string(),
+            status,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::{AlertOptions, AlertSink};
+    use crate::{capture::Burst, output::sink::BurstSink};
+
+    #[test]
+    fn commands_are_run_for_bursts_matching_a_condition() {
+        let path = std::env::temp_dir().join(format!("burstshark-alert-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut sink = Box::new(AlertSink::start(AlertOptions {
+            conditions: vec![
+                "size>1MB".parse().unwrap(),
+                "src_port==443".parse().unwrap(),
+            ],
+            webhook: None,
+            exec: Some(format!(
+                "echo \"$BURSTSHARK_ALERT\" >> '{0}'; cat >> '{0}'",
+                path.display()
+            )),
+        }));
+        sink.write(&Burst::test("10.0.0.1:80", "10.0.0.2:50000", 1.0, 1.5, 100));
+        sink.write(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            2.0,
+            2.5,
+            2_000_000,
+        ));
+        sink.finish();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        // The first condition matching is the one alerted
+        assert_eq!(lines[0], "size>1MB");
+        assert!(lines[1].starts_with('{') && lines[1].contains("2000000"));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use sha1::{Digest, Sha1};
+
+use super::sink::BurstSink;
+use crate::{capture::Burst, BurstsharkError};
+
+/// The page of the dashboard, which charts the bursts it receives over the WebSocket
+const PAGE: &str = include_str!("dashboard.html");
+
+/// Appended to the key of a WebSocket handshake before hashing it, as defined by RFC 6455
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Longest time writing a message to a browser may take before it is disconnected, so that a slow
+/// browser can not hold up the others
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A web page charting the bursts of each flow live, for browsers pointed at the probe.
+///
+/// The page is served at `/`, and bursts are streamed to it as JSON over a WebSocket at `/bursts`.
+pub struct Dashboard {
+    tx: Sender<String>,
+}
+
+impl Dashboard {
+    /// Listen for browsers on the address, such as `0.0.0.0:8080`
+    pub fn start(addr: &str) -> Result<Self, BurstsharkError> {
+        let listener = TcpListener::bind(addr).map_err(|source| BurstsharkError::Listen {
+            what: "the dashboard",
+            addr: addr.to_string(),
+            source,
+        })?;
+        let (tx, rx) = mpsc::channel();
+        let (client_tx, client_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let client_tx = client_tx.clone();
+                thread::spawn(move || {
+                    if let Ok(Some(client)) = accept(stream) {
+                        let _ = client_tx.send(client);
+                    }
+                });
+            }
+        });
+        thread::spawn(move || broadcast(rx, client_rx));
+
+        Ok(Dashboard { tx })
+    }
+}
+
+impl BurstSink for Dashboard {
+    /// Send a burst to all connected browsers
+    fn write(&mut self, burst: &Burst) {
+        if let Ok(json) = serde_json::to_string(burst) {
+            let _ = self.tx.send(json);
+        }
+    }
+}
+
+/// Answer a request, and return the stream if it opened a WebSocket
+fn accept(mut stream: TcpStream) -> Result<Option<TcpStream>, BurstsharkError> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    let mut key = None;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    match (parts.next(), parts.next(), key) {
+        (Some("GET"), Some("/bursts"), Some(key)) => {
+            let mut hash = Sha1::new();
+            hash.update(key.as_bytes());
+            hash.update(WEBSOCKET_GUID.as_bytes());
+            write!(
+                stream,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                base64(&hash.finalize())
+            )?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            Ok(Some(stream))
+        }
+        (Some("GET"), Some("/"), _) => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{PAGE}",
+                PAGE.len()
+            )?;
+            Ok(None)
+        }
+        _ => {
+            write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+/// Send every message to all browsers, dropping those that disconnected or can not keep up
+fn broadcast(messages: Receiver<String>, clients: Receiver<TcpStream>) {
+    let mut connected: Vec<TcpStream> = Vec::new();
+    for message in messages {
+        connected.extend(clients.try_iter());
+        let frame = text_frame(&message);
+        connected.retain_mut(|client| client.write_all(&frame).is_ok());
+    }
+}
+
+/// An unmasked WebSocket frame holding a whole text message, as sent by servers
+fn text_frame(text: &str) -> Vec<u8> {
+    let len = text.len();
+    let mut frame = Vec::with_capacity(len + 10);
+    frame.push(0x81);
+    match len {
+        0..=125 => frame.push(len as u8),
+        126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use super::{accept, base64, text_frame};
+
+    /// The response to a request, and whether it opened a WebSocket
+    fn request(request: &str) -> (String, bool) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let server = thread::spawn(move || accept(stream).unwrap().is_some());
+        let upgraded = server.join().unwrap();
+
+        let mut response = vec![0; 4096];
+        let n = client.read(&mut response).unwrap();
+        (
+            String::from_utf8_lossy(&response[..n]).into_owned(),
+            upgraded,
+        )
+    }
+
+    #[test]
+    fn websocket_handshakes_are_accepted_as_in_rfc_6455() {
+        let (response, upgraded) = request(
+            "GET /bursts HTTP/1.1\r\nHost: probe\r\nUpgrade: websocket\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        );
+        assert!(upgraded);
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn the_page_is_served_at_the_root() {
+        let (response, upgraded) = request("GET / HTTP/1.1\r\n\r\n");
+        assert!(!upgraded);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let (response, _) = request("GET /other HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn messages_are_sent_as_text_frames_of_their_length() {
+        assert_eq!(text_frame("hi"), [0x81, 2, b'h', b'i']);
+        let frame = text_frame(&"x".repeat(300));
+        assert_eq!(frame[..4], [0x81, 126, 1, 44]);
+        assert_eq!(frame.len(), 304);
+        let frame = text_frame(&"x".repeat(70000));
+        assert_eq!(frame[..2], [0x81, 127]);
+        assert_eq!(frame[2..10], 70000u64.to_be_bytes());
+    }
+
+    #[test]
+    fn bytes_are_encoded_as_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::sink::BurstSink;
+use crate::{capture::Burst, BurstsharkError};
+
+/// Bytes of MessagePack buffered before they are written to the file
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// Binary formats bursts can be exported in, to analyze many of them offline
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ExportFormat {
+    /// A MessagePack map per burst, with the fields of JSON output
+    MessagePack,
+    /// Columns of bursts as in CSV output, typed and compressed
+    Parquet,
+}
+
+impl ExportFormat {
+    /// The format of an export file by its extension
+    fn of_path(path: &str) -> Result<Self, BurstsharkError> {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("msgpack" | "mpk") => Ok(ExportFormat::MessagePack),
+            Some("parquet") => Ok(ExportFormat::Parquet),
+            _ => Err(BurstsharkError::InvalidOptions(format!(
+                "Unknown export format of {path}, expected a .msgpack or .parquet file"
+            ))),
+        }
+    }
+}
+
+/// Create the export file, in the format of its extension
+pub(super) fn create(path: &str) -> Result<Box<dyn BurstSink>, BurstsharkError> {
+    match ExportFormat::of_path(path)? {
+        ExportFormat::MessagePack => Ok(Box::new(MessagePackSink::create(path)?)),
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => Ok(Box::new(super::parquet::ParquetSink::create(path)?)),
+        #[cfg(not(feature = "parquet"))]
+        ExportFormat::Parquet => Err(BurstsharkError::MissingFeature {
+            name: "Parquet",
+            feature: "parquet",
+        }),
+    }
+}
+
+/// Writes bursts to a file as a sequence of MessagePack maps, keyed by the names of their fields so
+/// that readers are not affected by fields that are added.
+struct MessagePackSink {
+    path: String,
+    file: Option<BufWriter<File>>,
+}
+
+impl MessagePackSink {
+    fn create(path: &str) -> Result<Self, BurstsharkError> {
+        let file = File::create(path).map_err(|source| BurstsharkError::File {
+            action: "create export",
+            path: path.to_string(),
+            source,
+        })?;
+        Ok(MessagePackSink {
+            path: path.to_string(),
+            file: Some(BufWriter::with_capacity(BUFFER_SIZE, file)),
+        })
+    }
+}
+
+impl BurstSink for MessagePackSink {
+    fn write(&mut self, burst: &Burst) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = rmp_serde::encode::write_named(file, burst) {
+                eprintln!("Error writing bursts to {}: {}", self.path, e);
+                self.file = None;
+            }
+        }
+    }
+
+    fn finish(mut self: Box<Self>) {
+        if let Some(file) = &mut self.file {
+            if let Err(e) = file.flush() {
+                eprintln!("Error writing bursts to {}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::{create, ExportFormat};
+    use crate::capture::Burst;
+
+    #[test]
+    fn formats_are_told_by_the_extension() {
+        assert_eq!(
+            ExportFormat::of_path("bursts.mpk").unwrap(),
+            ExportFormat::MessagePack
+        );
+        assert_eq!(
+            ExportFormat::of_path("/tmp/bursts.parquet").unwrap(),
+            ExportFormat::Parquet
+        );
+        assert!(ExportFormat::of_path("bursts.csv").is_err());
+        assert!(ExportFormat::of_path("bursts").is_err());
+    }
+
+    #[test]
+    fn bursts_are_exported_as_named_message_pack_maps() {
+        let path =
+            std::env::temp_dir().join(format!("burstshark-export-{}.msgpack", process::id()));
+        let mut sink = create(path.to_str().unwrap()).unwrap();
+        sink.write(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            1.0,
+            1.5,
+            1000,
+        ));
+        sink.write(&Burst::test("10.0.0.3:53", "10.0.0.2:50001", 2.0, 2.5, 300));
+        sink.finish();
+
+        let contents = fs::read(&path).unwrap();
+        let mut reader = &contents[..];
+        let mut bursts = Vec::new();
+        while !reader.is_empty() {
+            let burst: serde_json::Value = rmp_serde::from_read(&mut reader).unwrap();
+            bursts.push(burst);
+        }
+        assert_eq!(bursts.len(), 2);
+        assert_eq!(bursts[0]["src"], "10.0.0.1");
+        assert_eq!(bursts[0]["size"], 1000);
+        assert_eq!(bursts[1]["dst_port"], 50001);
+        let _ = fs::remove_file(&path);
+    }
+}
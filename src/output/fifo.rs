@@ -0,0 +1,223 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+};
+
+/// What to do with lines written to a named pipe while no reader is attached, or it is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FifoPolicy {
+    /// Wait for a reader, stalling the output
+    Block,
+    /// Keep up to this many lines until a reader attaches, dropping the oldest beyond it
+    Buffer(usize),
+    /// Drop the lines
+    Drop,
+}
+
+/// Writes whole lines to a named pipe, following the policy while no reader is attached.
+///
+/// Readers may detach and attach again at any time. Lines are never partly written to a reader,
+/// unless it detaches while reading one.
+pub struct FifoWriter {
+    path: String,
+    policy: FifoPolicy,
+    pipe: Option<File>,
+    /// The line being written, until its newline
+    partial: Vec<u8>,
+    /// Complete lines not yet written to a reader, and how much of the first one was written
+    queue: VecDeque<Vec<u8>>,
+    written: usize,
+    dropped: u64,
+}
+
+impl FifoWriter {
+    /// Open the named pipe, which waits for a reader to attach if the policy is to block.
+    pub fn open(path: &str, policy: FifoPolicy) -> io::Result<Self> {
+        let mut writer = FifoWriter {
+            path: path.to_string(),
+            policy,
+            pipe: None,
+            partial: Vec::new(),
+            queue: VecDeque::new(),
+            written: 0,
+            dropped: 0,
+        };
+        writer.connect()?;
+        Ok(writer)
+    }
+
+    /// Attach to a reader, if any. Only fails if the pipe cannot be opened for other reasons.
+    fn connect(&mut self) -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if self.policy != FifoPolicy::Block {
+            options.custom_flags(libc::O_NONBLOCK);
+        }
+
+        match options.open(&self.path) {
+            Ok(pipe) => self.pipe = Some(pipe),
+            // Opening a pipe without blocking fails when there is no reader
+            Err(err) if err.raw_os_error() == Some(libc::ENXIO) => self.pipe = None,
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Write as many of the queued lines as the reader takes, blocking only if that is the policy
+    fn send(&mut self) -> io::Result<()> {
+        while !self.queue.is_empty() {
+            if self.pipe.is_none() {
+                self.connect()?;
+            }
+            let (Some(pipe), Some(line)) = (&mut self.pipe, self.queue.front()) else {
+                return Ok(());
+            };
+
+            let len = line.len();
+            match pipe.write(&line[self.written..]) {
+                Ok(n) => {
+                    self.written += n;
+                    if self.written == len {
+                        self.queue.pop_front();
+                        self.written = 0;
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                    // The reader detached, and the next one should not start mid-line
+                    self.pipe = None;
+                    if self.written > 0 {
+                        self.queue.pop_front();
+                        self.written = 0;
+                        self.dropped += 1;
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the oldest lines beyond what the policy keeps, except the one being written
+    fn limit(&mut self) {
+        let capacity = match self.policy {
+            FifoPolicy::Block => return,
+            FifoPolicy::Buffer(lines) => lines,
+            FifoPolicy::Drop => 0,
+        };
+
+        let keep = capacity.max((self.written > 0) as usize);
+        while self.queue.len() > keep {
+            let index = (self.written > 0) as usize;
+            self.queue.remove(index);
+            self.dropped += 1;
+        }
+    }
+}
+
+impl Write for FifoWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&rest[..=end]);
+            self.queue.push_back(std::mem::take(&mut self.partial));
+            rest = &rest[end + 1..];
+
+            self.send()?;
+            self.limit();
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()?;
+        self.limit();
+        Ok(())
+    }
+}
+
+impl Drop for FifoWriter {
+    fn drop(&mut self) {
+        let dropped = self.dropped + self.queue.len() as u64;
+        if dropped > 0 {
+            eprintln!(
+                "Dropped {} lines written to {} while no reader was attached",
+                dropped, self.path
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File, OpenOptions},
+        io::{ErrorKind, Read, Write},
+        os::unix::fs::OpenOptionsExt,
+        process,
+    };
+
+    use nix::sys::stat::Mode;
+
+    use super::{FifoPolicy, FifoWriter};
+
+    /// A new named pipe for the test
+    fn fifo(test: &str) -> String {
+        let path = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        nix::unistd::mkfifo(&path, Mode::S_IRUSR | Mode::S_IWUSR).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Attach a reader that does not wait for lines
+    fn reader(path: &str) -> File {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .unwrap()
+    }
+
+    /// The lines written to the reader so far
+    fn available(reader: &mut File) -> String {
+        let mut buf = [0; 1024];
+        match reader.read(&mut buf) {
+            Ok(n) => String::from_utf8(buf[..n].to_vec()).unwrap(),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => String::new(),
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    #[test]
+    fn the_newest_lines_are_buffered_until_a_reader_attaches() {
+        let path = fifo("fifo-buffer");
+        let mut writer = FifoWriter::open(&path, FifoPolicy::Buffer(2)).unwrap();
+        writer.write_all(b"1\n2\n3\n").unwrap();
+        assert_eq!(writer.queue.len(), 2);
+        assert_eq!(writer.dropped, 1);
+
+        let mut reader = reader(&path);
+        writer.flush().unwrap();
+        assert_eq!(available(&mut reader), "2\n3\n");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn only_whole_lines_are_written() {
+        let path = fifo("fifo-drop");
+        let mut writer = FifoWriter::open(&path, FifoPolicy::Drop).unwrap();
+        writer.write_all(b"1\n").unwrap();
+        assert_eq!(writer.dropped, 1);
+
+        let mut reader = reader(&path);
+        writer.write_all(b"2\n3").unwrap();
+        assert_eq!(available(&mut reader), "2\n");
+        writer.write_all(b"4\n").unwrap();
+        assert_eq!(available(&mut reader), "34\n");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+use super::format::{Formatter, OutputFormat};
+use crate::{upload::ObjectStore, BurstsharkError};
+
+/// How rotated files are compressed
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// Gzip, to .gz files.
+    Gzip,
+
+    /// Zstandard, to .zst files.
+    Zstd,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// When the bursts file is rotated, by its size in bytes or its age, and how the rotated files
+/// are compressed
+#[derive(Clone, Debug)]
+pub struct RotateOptions {
+    pub size: Option<u64>,
+    pub interval: Option<Duration>,
+    pub compression: Option<Compression>,
+}
+
+/// Writes lines to a file that is rotated once it reaches the size or age to rotate at, by moving
+/// it to a numbered file such as `bursts.csv.1` and starting over with the header of the format.
+///
+/// Files are only rotated between lines, and numbered after those rotated by earlier runs. Rotated
+/// files are compressed in threads of their own so the output does not wait for them, replacing
+/// `bursts.csv.1` with `bursts.csv.1.gz`, and then uploaded when uploading the output.
+pub struct RotatingFile {
+    path: String,
+    opts: RotateOptions,
+    upload: Option<ObjectStore>,
+    header: Option<&'static str>,
+    file: BufWriter<File>,
+    /// Bytes written to the current file, and when it was created
+    size: u64,
+    created: Instant,
+    /// Number of the last rotated file
+    rotations: u32,
+    /// Threads compressing and uploading rotated files
+    finishing: Vec<JoinHandle<()>>,
+}
+
+impl RotatingFile {
+    pub fn create(
+        path: &str,
+        opts: RotateOptions,
+        format: OutputFormat,
+        upload: Option<ObjectStore>,
+    ) -> Result<Self, BurstsharkError> {
+        if format == OutputFormat::Json {
+            return Err(BurstsharkError::InvalidOptions(
+                "A rotated file can not hold a JSON array, use jsonl instead".to_string(),
+            ));
+        }
+        Ok(RotatingFile {
+            path: path.to_string(),
+            opts,
+            upload,
+            header: Formatter::new(format).header(),
+            file: BufWriter::new(File::create(path)?),
+            size: 0,
+            created: Instant::now(),
+            rotations: last_rotation(path),
+            finishing: Vec::new(),
+        })
+    }
+
+    fn rotation_due(&self) -> bool {
+        self.opts.size.is_some_and(|max| self.size >= max)
+            || self
+                .opts
+                .interval
+                .is_some_and(|interval| self.created.elapsed() >= interval)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.rotations += 1;
+        let rotated = format!("{}.{}", self.path, self.rotations);
+        fs::rename(&self.path, &rotated)?;
+
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.size = 0;
+        self.created = Instant::now();
+        if let Some(header) = self.header {
+            writeln!(self.file, "{header}")?;
+            self.size += header.len() as u64 + 1;
+        }
+
+        if self.opts.compression.is_some() || self.upload.is_some() {
+            let (compression, upload) = (self.opts.compression, self.upload.clone());
+            self.finishing.retain(|handle| !handle.is_finished());
+            self.finishing.push(thread::spawn(move || {
+                finish_rotated(rotated, compression, upload)
+            }));
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        if buf[..written].ends_with(b"\n") && self.rotation_due() {
+            self.rotate()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for RotatingFile {
+    /// Wait for the rotated files to be compressed and uploaded
+    fn drop(&mut self) {
+        for handle in self.finishing.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Compress a rotated file, and upload it once compressed. Files that fail to compress are
+/// uploaded as they are.
+fn finish_rotated(path: String, compression: Option<Compression>, upload: Option<ObjectStore>) {
+    let path = match compression.map(|compression| compress(&path, compression)) {
+        Some(Ok(compressed)) => compressed,
+        Some(Err(e)) => {
+            eprintln!("Error compressing {}: {}", path, e);
+            path
+        }
+        None => path,
+    };
+    if let Some(store) = upload {
+        if let Err(e) = store.upload_and_remove(&path) {
+            eprintln!("Error uploading output: {}", e);
+        }
+    }
+}
+
+/// Compress a rotated file to a file with the extension of the compression, and remove it,
+/// returning the path of the compressed file
+fn compress(path: &str, compression: Compression) -> io::Result<String> {
+    let mut input = File::open(path)?;
+    let compressed = format!("{path}.{}", compression.extension());
+    let output = File::create(&compressed)?;
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, 0)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+        }
+    }
+    fs::remove_file(path)?;
+    Ok(compressed)
+}
+
+/// The highest number of the files rotated from the file by earlier runs, or 0 without any
+fn last_rotation(path: &str) -> u32 {
+    let path = Path::new(path);
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return 0;
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let number = file_name.strip_prefix(name)?.strip_prefix('.')?;
+            number.split('.').next()?.parse::<u32>().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+        process,
+    };
+
+    use super::{last_rotation, Compression, RotateOptions, RotatingFile};
+    use crate::output::format::OutputFormat;
+
+    /// A path in an empty directory of its own for the test
+    fn bursts_path(test: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir.join("bursts.csv").to_string_lossy().into_owned()
+    }
+
+    fn options(size: u64, compression: Option<Compression>) -> RotateOptions {
+        RotateOptions {
+            size: Some(size),
+            interval: None,
+            compression,
+        }
+    }
+
+    #[test]
+    fn files_are_rotated_between_lines() {
+        let path = bursts_path("rotate");
+        let mut file =
+            RotatingFile::create(&path, options(30, None), OutputFormat::Csv, None).unwrap();
+        for _ in 0..3 {
+            // Written in parts, as a formatted line may be
+            file.write_all(b"1.0,10.0.0.1,").unwrap();
+            file.write_all(b"50000\n").unwrap();
+        }
+        drop(file);
+
+        let lines = |path: &str| -> Vec<String> {
+            let content = fs::read_to_string(path).unwrap();
+            content.lines().map(|line| line[..4].to_string()).collect()
+        };
+        assert_eq!(lines(&format!("{path}.1")), ["1.0,", "1.0,"]);
+        // Files after a rotation start with the header, which is over the size on its own
+        assert_eq!(lines(&format!("{path}.2")), ["comp", "1.0,"]);
+        assert_eq!(lines(&path), ["comp"]);
+        assert_eq!(last_rotation(&path), 2);
+    }
+
+    #[test]
+    fn rotations_are_numbered_after_earlier_runs() {
+        let path = bursts_path("numbering");
+        assert_eq!(last_rotation(&path), 0);
+        fs::write(format!("{path}.2.gz"), "").unwrap();
+        fs::write(format!("{path}.10.zst"), "").unwrap();
+        fs::write(format!("{path}.old"), "").unwrap();
+        assert_eq!(last_rotation(&path), 10);
+    }
+
+    #[test]
+    fn rotated_files_are_compressed() {
+        for (compression, extension) in [(Compression::Gzip, "gz"), (Compression::Zstd, "zst")] {
+            let path = bursts_path(&format!("compress-{extension}"));
+            let opts = options(1, Some(compression));
+            let mut file = RotatingFile::create(&path, opts, OutputFormat::Jsonl, None).unwrap();
+            file.write_all(b"{\"size\":4000}\n").unwrap();
+            drop(file);
+
+            assert!(!fs::exists(format!("{path}.1")).unwrap());
+            let compressed = fs::File::open(format!("{path}.1.{extension}")).unwrap();
+            let mut content = String::new();
+            match compression {
+                Compression::Gzip => flate2::read::GzDecoder::new(compressed)
+                    .read_to_string(&mut content)
+                    .unwrap(),
+                Compression::Zstd => zstd::Decoder::new(compressed)
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap(),
+            };
+            assert_eq!(content, "{\"size\":4000}\n");
+        }
+    }
+
+    #[test]
+    fn json_arrays_are_refused() {
+        let path = bursts_path("json");
+        let opts = options(1, None);
+        assert!(RotatingFile::create(&path, opts, OutputFormat::Json, None).is_err());
+    }
+}
@@ -0,0 +1,828 @@
+use std::{
+    fmt::{self, Write},
+    net::IpAddr,
+    sync::Arc,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    capture::{
+        Burst, FlowEvent, FlowEventKind, FlowSurge, HostConcurrency, IpProto, RateSample, Record,
+    },
+    classify::FlowClass,
+    compare::BurstDelta,
+    eve::EveAlert,
+    histogram::FlowHistogram,
+    privacy::BurstHistogram,
+    probe::{ProbeIdentity, Tags},
+    timestamp,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Aligned columns for reading in a terminal.
+    Text,
+
+    /// A JSON array of records.
+    Json,
+
+    /// A JSON object per line.
+    Jsonl,
+
+    /// Comma-separated values with a header, bursts only.
+    Csv,
+
+    /// Suricata EVE JSON entries of the burst event type, a JSON object per line, bursts only.
+    #[value(skip)]
+    Eve,
+}
+
+const CSV_HEADER: &str = "completion_time,src,src_port,dst,dst_port,start,end,num_packets,size,\
+    ethertype,dscp,flow_label,group,tunnel,tunnel_overhead,airtime,channels,retransmitted_bytes,ssh_phase,deviation,response_latency,class,class_confidence,label,direction,flow_id,errors,truncated,packets,alerts,probe_id,tags,proto,interface,src_name,dst_name,service,src_country,src_asn,dst_country,dst_asn,tid,ssrc,payload_type,lost_packets,device,min_iat,mean_iat,max_iat,jitter,peak_rate";
+
+/// Formats records as lines in one of the output formats
+pub struct Formatter {
+    format: OutputFormat,
+    /// Number of bursts formatted so far, which numbers the bursts of the text format
+    bursts: u64,
+    /// Whether any record is written, to know where the JSON array starts
+    started: bool,
+}
+
+impl Formatter {
+    pub fn new(format: OutputFormat) -> Self {
+        Formatter {
+            format,
+            bursts: 0,
+            started: false,
+        }
+    }
+
+    /// Line to write before any records.
+    pub fn header(&self) -> Option<&'static str> {
+        match self.format {
+            OutputFormat::Csv => Some(CSV_HEADER),
+            _ => None,
+        }
+    }
+
+    /// Line to write after all records.
+    pub fn footer(&self) -> Option<&'static str> {
+        match (self.format, self.started) {
+            (OutputFormat::Json, true) => Some("]"),
+            (OutputFormat::Json, false) => Some("[]"),
+            _ => None,
+        }
+    }
+
+    /// Format a record as a line. The line is left empty for records the format does not support.
+    pub fn write(&mut self, line: &mut String, record: &Record) -> fmt::Result {
+        if let Record::Burst(_) = record {
+            self.bursts += 1;
+        }
+
+        match (self.format, record) {
+            (OutputFormat::Text, Record::Burst(burst)) => write_burst(line, self.bursts, burst),
+            (OutputFormat::Text, Record::FlowSurge(surge)) => write_surge(line, surge),
+            (OutputFormat::Text, Record::FlowEvent(event)) => write_flow_event(line, event),
+            (OutputFormat::Text, Record::Concurrency(report)) => write_concurrency(line, report),
+            (OutputFormat::Text, Record::BurstDelta(delta)) => write_delta(line, delta),
+            (OutputFormat::Text, Record::BurstHistogram(histogram)) => {
+                write_histogram(line, histogram)
+            }
+            (OutputFormat::Text, Record::FlowHistogram(histogram)) => {
+                write_flow_histogram(line, histogram)
+            }
+            (OutputFormat::Text, Record::RateSample(sample)) => write_rate(line, sample),
+            (OutputFormat::Json, record) => {
+                // Each line starts with the opening bracket or separating comma of the array
+                line.push(if self.started { ',' } else { '[' });
+                self.started = true;
+                write_json(line, record)
+            }
+            (OutputFormat::Jsonl, record) => write_json(line, record),
+            (OutputFormat::Csv, Record::Burst(burst)) => write_csv(line, burst),
+            (OutputFormat::Csv, _) => Ok(()),
+            (OutputFormat::Eve, Record::Burst(burst)) => write_eve(line, burst),
+            (OutputFormat::Eve, _) => Ok(()),
+        }
+    }
+}
+
+fn write_json(line: &mut String, record: &Record) -> fmt::Result {
+    line.push_str(&serde_json::to_string(record).map_err(|_| fmt::Error)?);
+    Ok(())
+}
+
+/// Format a burst as aligned columns, followed by any annotations.
+fn write_burst(line: &mut String, count: u64, burst: &Burst) -> fmt::Result {
+    write!(
+        line,
+        "{:5} {:13.9} {:15} {:6} {:15} {:5} {:13.9} {:13.9} {:4} {}",
+        count,
+        burst.completion_time,
+        burst.src,
+        burst.src_port.map_or("".to_string(), |p| p.to_string()),
+        burst.dst,
+        burst.dst_port.map_or("".to_string(), |p| p.to_string()),
+        burst.start,
+        burst.end,
+        burst.num_packets,
+        burst.size,
+    )?;
+
+    if let Some(ethertype) = burst.ethertype {
+        write!(line, " 0x{:04x}", ethertype)?;
+    }
+
+    if let Some(proto) = burst.proto {
+        write!(line, " proto {}", proto)?;
+    }
+
+    if let Some(dscp) = burst.dscp {
+        write!(line, " dscp {}", dscp)?;
+    }
+
+    if let Some(flow_label) = burst.flow_label {
+        write!(line, " label 0x{:05x}", flow_label)?;
+    }
+
+    if let Some(group) = burst.group {
+        write!(line, " {}", group)?;
+    }
+
+    if let Some(tunnel) = burst.tunnel {
+        write!(
+            line,
+            " {} overhead {}",
+            tunnel,
+            burst.tunnel_overhead.unwrap_or(0)
+        )?;
+    }
+
+    if let Some(airtime) = burst.airtime {
+        write!(line, " airtime {:.6}", airtime)?;
+    }
+
+    if !burst.channels.is_empty() {
+        write!(line, " channels {}", join_channels(burst, ","))?;
+    }
+
+    if let Some(bytes) = burst.retransmitted_bytes.filter(|bytes| *bytes > 0) {
+        write!(line, " retransmitted {}", bytes)?;
+    }
+
+    if let Some(phase) = burst.ssh_phase {
+        write!(line, " {}", phase)?;
+    }
+
+    if let Some(deviation) = burst.deviation {
+        write!(line, " deviation {:.2}", deviation)?;
+    }
+
+    if let Some(latency) = burst.response_latency {
+        write!(line, " latency {:.6}", latency)?;
+    }
+
+    if let (Some(class), Some(confidence)) = (burst.class, burst.class_confidence) {
+        write!(line, " {} {:.2}", class, confidence)?;
+    }
+
+    if let Some(label) = &burst.label {
+        write!(line, " label {}", label)?;
+    }
+
+    if let Some(direction) = burst.direction {
+        write!(line, " {}", direction)?;
+    }
+
+    if let Some(flow_id) = &burst.flow_id {
+        write!(line, " flow {}", flow_id)?;
+    }
+
+    if let Some(interface) = &burst.interface {
+        write!(line, " on {}", interface)?;
+    }
+
+    if let Some(tid) = burst.tid {
+        write!(line, " tid {}", tid)?;
+    }
+
+    if let Some(device) = burst.device {
+        write!(line, " device {}", device)?;
+    }
+
+    if let (Some(ssrc), Some(payload_type)) = (burst.ssrc, burst.payload_type) {
+        write!(line, " ssrc 0x{:08x} pt {}", ssrc, payload_type)?;
+    }
+
+    if let Some(lost) = burst.lost_packets.filter(|lost| *lost > 0) {
+        write!(line, " lost {}", lost)?;
+    }
+
+    if burst.src_name.is_some() || burst.dst_name.is_some() {
+        write!(
+            line,
+            " names {} {}",
+            burst.src_name.as_deref().unwrap_or("-"),
+            burst.dst_name.as_deref().unwrap_or("-")
+        )?;
+    }
+
+    if let Some(service) = &burst.service {
+        write!(line, " service {}", service)?;
+    }
+
+    if burst.src_country.is_some()
+        || burst.src_asn.is_some()
+        || burst.dst_country.is_some()
+        || burst.dst_asn.is_some()
+    {
+        let location = |country: Option<&str>, asn: Option<u32>| {
+            format!(
+                "{}/{}",
+                country.unwrap_or("-"),
+                asn.map_or("-".to_string(), |asn| format!("AS{asn}"))
+            )
+        };
+        write!(
+            line,
+            " geo {} {}",
+            location(burst.src_country.as_deref(), burst.src_asn),
+            location(burst.dst_country.as_deref(), burst.dst_asn)
+        )?;
+    }
+
+    if !burst.errors.is_empty() {
+        write!(line, " errors {}", join_errors(burst))?;
+    }
+
+    if burst.truncated {
+        write!(line, " truncated")?;
+    }
+
+    if !burst.packets.is_empty() {
+        write!(line, " packets {}", join_packets(burst, " "))?;
+    }
+
+    if let Some(detail) = burst.detail {
+        if let (Some(min), Some(mean), Some(max)) =
+            (detail.min_iat, detail.mean_iat, detail.max_iat)
+        {
+            write!(line, " iat {:.6}/{:.6}/{:.6}", min, mean, max)?;
+        }
+        if let Some(jitter) = detail.jitter {
+            write!(line, " jitter {:.6}", jitter)?;
+        }
+        write!(line, " peak {:.0}", detail.peak_rate)?;
+    }
+
+    write_probe(line, &burst.probe)?;
+
+    for alert in &burst.alerts {
+        write!(line, " [{}] {}", alert.severity, alert.signature)?;
+    }
+
+    Ok(())
+}
+
+/// Format a flow surge alert, in place of the burst counter it starts with ALERT.
+fn write_surge(line: &mut String, surge: &FlowSurge) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {:10} {} new flows to {} hosts and {} ports within {} s",
+        "ALERT",
+        surge.time,
+        surge.src,
+        surge.kind,
+        surge.new_flows,
+        surge.distinct_dsts,
+        surge.distinct_ports,
+        surge.window,
+    )
+}
+
+/// Format the concurrency of a host, in place of the burst counter it starts with CONC.
+fn write_concurrency(line: &mut String, report: &HostConcurrency) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {} flows with concurrent bursts, {} at the end of the {} s interval",
+        "CONC", report.time, report.host, report.peak, report.current, report.interval,
+    )
+}
+
+/// Format how a burst changed through a middlebox, in place of the burst counter it starts with
+/// DELTA.
+///
+/// After the addresses follow the start of the burst before the middlebox, the delays of its start
+/// and end, and the bytes and packets added.
+fn write_delta(line: &mut String, delta: &BurstDelta) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {:6} {:15} {:5} {:13.9} {:+.6} {:+.6} {:+} {:+}",
+        "DELTA",
+        delta.time,
+        delta.src,
+        delta.src_port.map_or("".to_string(), |p| p.to_string()),
+        delta.dst,
+        delta.dst_port.map_or("".to_string(), |p| p.to_string()),
+        delta.start,
+        delta.latency,
+        delta.end_latency,
+        delta.size_change,
+        delta.packet_change,
+    )
+}
+
+/// Format the bursts of an anonymous flow over an interval, in place of the burst counter it starts
+/// with HIST.
+///
+/// After the addresses follow the number of bursts and their bytes, and the number of bursts of
+/// each size bucket as `size:count`.
+fn write_histogram(line: &mut String, histogram: &BurstHistogram) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {:6} {:15} {:5} {:4} {}",
+        "HIST",
+        histogram.time,
+        histogram.src,
+        histogram.src_port.map_or("".to_string(), |p| p.to_string()),
+        histogram.dst,
+        histogram.dst_port.map_or("".to_string(), |p| p.to_string()),
+        histogram.bursts,
+        histogram.bytes,
+    )?;
+
+    for (size, count) in &histogram.sizes {
+        write!(line, " {}:{}", size, count)?;
+    }
+
+    write_probe(line, &histogram.probe)
+}
+
+/// Format the bursts of a flow over an interval, in place of the burst counter it starts with HIST.
+///
+/// After the addresses follow the number of bursts and their bytes, the number of bursts of each
+/// size bucket as `size:count`, and after `gaps` the number of gaps of each bucket in seconds.
+fn write_flow_histogram(line: &mut String, histogram: &FlowHistogram) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {:6} {:15} {:5} {:4} {}",
+        "HIST",
+        histogram.time,
+        histogram.src,
+        histogram.src_port.map_or("".to_string(), |p| p.to_string()),
+        histogram.dst,
+        histogram.dst_port.map_or("".to_string(), |p| p.to_string()),
+        histogram.bursts,
+        histogram.bytes,
+    )?;
+
+    for (size, count) in &histogram.sizes {
+        write!(line, " {}:{}", size, count)?;
+    }
+    if !histogram.gaps_us.is_empty() {
+        line.push_str(" gaps");
+        for (micros, count) in &histogram.gaps_us {
+            write!(line, " {:.6}:{}", *micros as f64 / 1e6, count)?;
+        }
+    }
+
+    write_probe(line, &histogram.probe)
+}
+
+/// Append the id and tags of the probe of a record, if any
+fn write_probe(line: &mut String, probe: &ProbeIdentity) -> fmt::Result {
+    if let Some(probe_id) = &probe.probe_id {
+        write!(line, " probe {}", probe_id)?;
+    }
+    if probe.tags.is_some() {
+        write!(line, " tags {}", probe.join_tags(","))?;
+    }
+    Ok(())
+}
+
+/// Format a flow event, in place of the burst counter it starts with OPEN or CLOSE.
+///
+/// After the addresses follow the first and last time a packet was seen, and the number of packets,
+/// bytes and bursts of the whole flow.
+fn write_flow_event(line: &mut String, event: &FlowEvent) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {:6} {:15} {:5} {:13.9} {:13.9} {:4} {} {}",
+        match event.kind {
+            FlowEventKind::Open => "OPEN",
+            FlowEventKind::Close => "CLOSE",
+            FlowEventKind::Evict => "EVICT",
+        },
+        event.time,
+        event.src,
+        event.src_port.map_or("".to_string(), |p| p.to_string()),
+        event.dst,
+        event.dst_port.map_or("".to_string(), |p| p.to_string()),
+        event.totals.first_seen,
+        event.totals.last_seen,
+        event.totals.packets,
+        event.totals.bytes,
+        event.totals.bursts,
+    )?;
+
+    if let Some(flow_id) = &event.flow_id {
+        write!(line, " flow {}", flow_id)?;
+    }
+
+    if let Some(interface) = &event.interface {
+        write!(line, " on {}", interface)?;
+    }
+
+    if let Some(tid) = event.tid {
+        write!(line, " tid {}", tid)?;
+    }
+
+    if let Some(ssrc) = event.ssrc {
+        write!(line, " ssrc 0x{:08x}", ssrc)?;
+    }
+
+    Ok(())
+}
+
+/// Format the throughput of a flow during an interval, in place of the burst counter it starts with
+/// RATE.
+fn write_rate(line: &mut String, sample: &RateSample) -> fmt::Result {
+    write!(
+        line,
+        "{:>5} {:13.9} {:15} {:6} {:15} {:5} {:.0} B/s {:.3} bursts/s over {} s",
+        "RATE",
+        sample.time,
+        sample.src,
+        sample.src_port.map_or("".to_string(), |p| p.to_string()),
+        sample.dst,
+        sample.dst_port.map_or("".to_string(), |p| p.to_string()),
+        sample.bytes_per_sec,
+        sample.bursts_per_sec,
+        sample.interval,
+    )?;
+
+    if let Some(flow_id) = &sample.flow_id {
+        write!(line, " flow {}", flow_id)?;
+    }
+
+    if let Some(interface) = &sample.interface {
+        write!(line, " on {}", interface)?;
+    }
+
+    if let Some(tid) = sample.tid {
+        write!(line, " tid {}", tid)?;
+    }
+
+    if let Some(ssrc) = sample.ssrc {
+        write!(line, " ssrc 0x{:08x}", ssrc)?;
+    }
+
+    Ok(())
+}
+
+/// Format a burst as a row of the CSV header, leaving the annotations it lacks empty.
+fn write_csv(line: &mut String, burst: &Burst) -> fmt::Result {
+    fn optional<T: fmt::Display>(value: Option<T>) -> String {
+        value.map_or(String::new(), |value| value.to_string())
+    }
+
+    write!(
+        line,
+        "{:.9},{},{},{},{},{:.9},{:.9},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        burst.completion_time,
+        burst.src,
+        optional(burst.src_port),
+        burst.dst,
+        optional(burst.dst_port),
+        burst.start,
+        burst.end,
+        burst.num_packets,
+        burst.size,
+        optional(
+            burst
+                .ethertype
+                .map(|ethertype| format!("0x{:04x}", ethertype))
+        ),
+        optional(burst.dscp),
+        optional(
+            burst
+                .flow_label
+                .map(|flow_label| format!("0x{:05x}", flow_label))
+        ),
+        optional(burst.group),
+        optional(burst.tunnel),
+        optional(burst.tunnel_overhead),
+        optional(burst.airtime.map(|airtime| format!("{:.9}", airtime))),
+        join_channels(burst, ";"),
+        optional(burst.retransmitted_bytes),
+        optional(burst.ssh_phase),
+        optional(burst.deviation.map(|deviation| format!("{:.2}", deviation))),
+        optional(
+            burst
+                .response_latency
+                .map(|latency| format!("{:.9}", latency))
+        ),
+        optional(burst.class),
+        optional(
+            burst
+                .class_confidence
+                .map(|confidence| format!("{:.2}", confidence))
+        ),
+        csv_quote(burst.label.as_deref().unwrap_or("")),
+        optional(burst.direction),
+        optional(burst.flow_id.as_deref()),
+        join_errors(burst),
+        burst.truncated,
+        join_packets(burst, ";"),
+        csv_quote(&join_alerts(burst)),
+        csv_quote(burst.probe.probe_id.as_deref().unwrap_or("")),
+        csv_quote(&burst.probe.join_tags(";")),
+        optional(burst.proto),
+        csv_quote(burst.interface.as_deref().unwrap_or("")),
+        csv_quote(burst.src_name.as_deref().unwrap_or("")),
+        csv_quote(burst.dst_name.as_deref().unwrap_or("")),
+        csv_quote(burst.service.as_deref().unwrap_or("")),
+        optional(burst.src_country.as_deref()),
+        optional(burst.src_asn),
+        optional(burst.dst_country.as_deref()),
+        optional(burst.dst_asn),
+        optional(burst.tid),
+        optional(burst.ssrc.map(|ssrc| format!("0x{:08x}", ssrc))),
+        optional(burst.payload_type),
+        optional(burst.lost_packets),
+        optional(burst.device),
+        optional(burst.detail.and_then(|d| d.min_iat).map(|iat| format!("{:.9}", iat))),
+        optional(burst.detail.and_then(|d| d.mean_iat).map(|iat| format!("{:.9}", iat))),
+        optional(burst.detail.and_then(|d| d.max_iat).map(|iat| format!("{:.9}", iat))),
+        optional(burst.detail.and_then(|d| d.jitter).map(|jitter| format!("{:.9}", jitter))),
+        optional(burst.detail.map(|d| format!("{:.0}", d.peak_rate))),
+    )
+}
+
+/// Bits of flow ids kept in EVE entries, as Suricata does so that JSON parsers read them exactly
+const EVE_FLOW_ID_BITS: u32 = 51;
+
+/// A burst as an entry of Suricata's EVE JSON, to be read by the same pipelines as its alerts
+#[derive(Serialize)]
+struct EveEntry<'a> {
+    timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flow_id: Option<u64>,
+    /// The probe id, in the place of the sensor name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<&'a str>,
+    event_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    src_ip: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    src_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dest_ip: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dest_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proto: Option<IpProto>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_iface: Option<&'a str>,
+    /// The addresses of layer 2 bursts, which have no IP addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ether: Option<EveEther<'a>>,
+    burst: EveBurst<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a Tags>,
+}
+
+#[derive(Serialize)]
+struct EveEther<'a> {
+    src_mac: &'a str,
+    dest_mac: &'a str,
+}
+
+#[derive(Serialize)]
+struct EveBurst<'a> {
+    start: String,
+    end: String,
+    pkts: u16,
+    bytes: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<FlowClass>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_latency: Option<f64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    alerts: &'a [EveAlert],
+}
+
+/// Format a burst as an EVE entry timestamped at its completion, with the times of the burst in
+/// ISO-8601 as Suricata writes them. Times must be epoch seconds, and the flow id the hash format.
+fn write_eve(line: &mut String, burst: &Burst) -> fmt::Result {
+    let ip = burst.src.parse::<IpAddr>().is_ok();
+    let entry = EveEntry {
+        timestamp: timestamp::format_iso8601(burst.completion_time),
+        flow_id: burst
+            .flow_id
+            .as_deref()
+            .and_then(|flow_id| u64::from_str_radix(flow_id, 16).ok())
+            .map(|flow_id| flow_id & ((1 << EVE_FLOW_ID_BITS) - 1)),
+        host: burst.probe.probe_id.as_deref(),
+        event_type: "burst",
+        src_ip: ip.then_some(&*burst.src),
+        src_port: burst.src_port,
+        dest_ip: ip.then_some(&*burst.dst),
+        dest_port: burst.dst_port,
+        proto: burst.proto,
+        in_iface: burst.interface.as_deref(),
+        ether: (!ip).then_some(EveEther {
+            src_mac: &burst.src,
+            dest_mac: &burst.dst,
+        }),
+        burst: EveBurst {
+            start: timestamp::format_iso8601(burst.start),
+            end: timestamp::format_iso8601(burst.end),
+            pkts: burst.num_packets,
+            bytes: burst.size,
+            class: burst.class,
+            label: burst.label.as_ref(),
+            response_latency: burst.response_latency,
+            truncated: burst.truncated,
+            alerts: &burst.alerts,
+        },
+        tags: burst.probe.tags.as_deref(),
+    };
+    line.push_str(&serde_json::to_string(&entry).map_err(|_| fmt::Error)?);
+    Ok(())
+}
+
+/// The channels of a burst, such as 6,36
+pub(super) fn join_channels(burst: &Burst, separator: &str) -> String {
+    burst
+        .channels
+        .iter()
+        .map(|channel| channel.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// The errors of a burst, separated by semicolons
+pub(super) fn join_errors(burst: &Burst) -> String {
+    burst
+        .errors
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// The offsets and sizes of the packets of a burst, such as 0.000120:1448
+pub(super) fn join_packets(burst: &Burst, separator: &str) -> String {
+    burst
+        .packets
+        .iter()
+        .map(|packet| format!("{:.6}:{}", packet.offset, packet.size))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// The severities and signatures of the alerts of a burst, such as [2] ET POLICY, separated by
+/// semicolons
+pub(super) fn join_alerts(burst: &Burst) -> String {
+    burst
+        .alerts
+        .iter()
+        .map(|alert| format!("[{}] {}", alert.severity, alert.signature))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Quote a CSV field if it contains separators, quotes or line breaks.
+fn csv_quote(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_quote, Formatter, OutputFormat, CSV_HEADER};
+    use crate::capture::{Burst, IpProto, Record};
+
+    fn burst() -> Record {
+        Record::Burst(Burst {
+            num_packets: 3,
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", 1.0, 1.25, 4000)
+        })
+    }
+
+    fn lines(format: OutputFormat, records: &[Record]) -> Vec<String> {
+        let mut formatter = Formatter::new(format);
+        let mut lines: Vec<String> = formatter.header().map(String::from).into_iter().collect();
+        for record in records {
+            let mut line = String::new();
+            formatter.write(&mut line, record).unwrap();
+            lines.push(line);
+        }
+        lines.extend(formatter.footer().map(String::from));
+        lines
+    }
+
+    #[test]
+    fn text_lines_number_the_bursts() {
+        let lines = lines(OutputFormat::Text, &[burst(), burst()]);
+        let fields: Vec<&str> = lines[1].split_whitespace().collect();
+        assert_eq!(
+            fields,
+            [
+                "2",
+                "1.250000000",
+                "10.0.0.1",
+                "50000",
+                "10.0.0.2",
+                "443",
+                "1.000000000",
+                "1.250000000",
+                "3",
+                "4000"
+            ]
+        );
+    }
+
+    #[test]
+    fn json_lines_together_form_an_array() {
+        let json = lines(OutputFormat::Json, &[burst(), burst()]).concat();
+        let records: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(records.as_array().unwrap().len(), 2);
+        assert_eq!(records[0]["type"], "burst");
+        assert_eq!(records[1]["size"], 4000);
+        assert_eq!(lines(OutputFormat::Json, &[]), ["[]"]);
+    }
+
+    #[test]
+    fn json_lines_are_objects_of_their_own() {
+        for line in lines(OutputFormat::Jsonl, &[burst(), burst()]) {
+            let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(record["src_port"], 50000);
+            // Annotations that are not set are left out
+            assert!(record.get("airtime").is_none());
+        }
+    }
+
+    #[test]
+    fn csv_rows_have_a_field_per_column() {
+        let lines = lines(OutputFormat::Csv, &[burst()]);
+        assert_eq!(lines[0], CSV_HEADER);
+        let columns = CSV_HEADER.split(',').count();
+        assert_eq!(lines[1].split(',').count(), columns);
+        assert!(lines[1].starts_with("1.250000000,10.0.0.1,50000,10.0.0.2,443,1.000000000,"));
+    }
+
+    #[test]
+    fn eve_entries_have_suricata_fields() {
+        let burst = Record::Burst(Burst {
+            proto: Some(IpProto(6)),
+            flow_id: Some("07b318681e33fb88".into()),
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", 1.0, 1.25, 4000)
+        });
+        let entry: serde_json::Value =
+            serde_json::from_str(&lines(OutputFormat::Eve, &[burst])[0]).unwrap();
+        assert_eq!(entry["timestamp"], "1970-01-01T00:00:01.250000+0000");
+        assert_eq!(entry["event_type"], "burst");
+        assert_eq!(entry["flow_id"], 0x07b318681e33fb88_u64 & ((1 << 51) - 1));
+        assert_eq!(
+            (&entry["src_ip"], &entry["dest_port"], &entry["proto"]),
+            (&"10.0.0.1".into(), &443.into(), &"TCP".into())
+        );
+        assert_eq!(entry["burst"]["start"], "1970-01-01T00:00:01.000000+0000");
+        assert_eq!(entry["burst"]["bytes"], 4000);
+        assert!(entry.get("ether").is_none());
+    }
+
+    #[test]
+    fn eve_entries_of_layer_2_bursts_have_mac_addresses() {
+        let burst = Record::Burst(Burst {
+            src: "02:00:00:00:00:01".into(),
+            dst: "02:00:00:00:00:02".into(),
+            src_port: None,
+            dst_port: None,
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", 1.0, 1.25, 4000)
+        });
+        let entry: serde_json::Value =
+            serde_json::from_str(&lines(OutputFormat::Eve, &[burst])[0]).unwrap();
+        assert_eq!(entry["ether"]["src_mac"], "02:00:00:00:00:01");
+        assert!(entry.get("src_ip").is_none() && entry.get("src_port").is_none());
+    }
+
+    #[test]
+    fn csv_fields_with_separators_are_quoted() {
+        assert_eq!(csv_quote("plain"), "plain");
+        assert_eq!(csv_quote("a,b"), "\"a,b\"");
+        assert_eq!(csv_quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    fmt::Write,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::sink::BurstSink;
+use crate::{capture::Burst, upload::uri_encode};
+
+/// Bursts written in one request, unless the batch interval passes first
+const BATCH_LINES: usize = 5000;
+
+/// Longest time a burst is buffered before it is written
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Attempts to write a batch, waiting twice as long after each transient failure
+const ATTEMPTS: u32 = 5;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// An InfluxDB database to write bursts to, with the version 2 write API
+#[derive(Clone, Debug)]
+pub struct InfluxOptions {
+    /// Base URL of the server, such as `http://localhost:8086`
+    pub url: String,
+    pub bucket: String,
+    pub org: Option<String>,
+    pub token: Option<String>,
+}
+
+/// Writes bursts to InfluxDB in line protocol, batched on a thread of its own.
+///
+/// Each burst is a point of the `burst` measurement at its start, tagged with the addresses and
+/// ports of its flow, its interface when capturing on several, and the id and tags of the probe.
+pub struct InfluxSink {
+    tx: Sender<String>,
+    handle: JoinHandle<()>,
+}
+
+impl InfluxSink {
+    pub fn start(opts: InfluxOptions) -> Self {
+        let (tx, rx) = mpsc::channel::<String>();
+
+        let handle = thread::spawn(move || {
+            let mut batch = String::new();
+            let mut lines = 0;
+            let mut deadline = None;
+
+            loop {
+                let timeout = deadline.map_or(BATCH_INTERVAL, |deadline: Instant| {
+                    deadline.saturating_duration_since(Instant::now())
+                });
+                let finished = match rx.recv_timeout(timeout) {
+                    Ok(line) => {
+                        batch.push_str(&line);
+                        batch.push('\n');
+                        lines += 1;
+                        deadline.get_or_insert_with(|| Instant::now() + BATCH_INTERVAL);
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => true,
+                };
+
+                let due = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                if lines > 0 && (lines >= BATCH_LINES || due || finished) {
+                    if let Err(e) = write_batch(&opts, &batch) {
+                        eprintln!("Error writing {lines} bursts to InfluxDB: {e}");
+                    }
+                    batch.clear();
+                    lines = 0;
+                    deadline = None;
+                }
+
+                if finished {
+                    break;
+                }
+            }
+        });
+
+        InfluxSink { tx, handle }
+    }
+}
+
+impl BurstSink for InfluxSink {
+    fn write(&mut self, burst: &Burst) {
+        let mut line = String::new();
+        if write_line(&mut line, burst).is_ok() {
+            let _ = self.tx.send(line);
+        }
+    }
+
+    fn finish(self: Box<Self>) {
+        let InfluxSink { tx, handle } = *self;
+        drop(tx);
+        let _ = handle.join();
+    }
+}
+
+/// Format a burst as a line of the line protocol, with a timestamp in nanoseconds
+fn write_line(line: &mut String, burst: &Burst) -> std::fmt::Result {
+    write!(
+        line,
+        "burst,src={},dst={}",
+        escape_tag(&burst.src),
+        escape_tag(&burst.dst)
+    )?;
+    if let Some(port) = burst.src_port {
+        write!(line, ",src_port={port}")?;
+    }
+    if let Some(port) = burst.dst_port {
+        write!(line, ",dst_port={port}")?;
+    }
+    if let Some(interface) = &burst.interface {
+        write!(line, ",interface={}", escape_tag(interface))?;
+    }
+    if let Some(tid) = burst.tid {
+        write!(line, ",tid={tid}")?;
+    }
+    if let Some(device) = burst.device {
+        write!(line, ",device={device}")?;
+    }
+    if let Some(ssrc) = burst.ssrc {
+        write!(line, ",ssrc=0x{ssrc:08x}")?;
+    }
+    if let Some(probe_id) = &burst.probe.probe_id {
+        write!(line, ",probe={}", escape_tag(probe_id))?;
+    }
+    for (key, value) in burst.probe.tags.iter().flat_map(|tags| tags.iter()) {
+        write!(line, ",{}={}", escape_tag(key), escape_tag(value))?;
+    }
+    write!(
+        line,
+        " size={}i,packets={}i,duration={} {}",
+        burst.size,
+        burst.num_packets,
+        burst.end - burst.start,
+        (burst.start * 1e9).round() as i64
+    )
+}
+
+/// Escape the characters that separate the parts of a line
+fn escape_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Write a batch of lines, retrying while the server is unreachable or overloaded
+fn write_batch(opts: &InfluxOptions, batch: &str) -> Result<(), ureq::Error> {
+    let mut url = format!(
+        "{}/api/v2/write?bucket={}&precision=ns",
+        opts.url.trim_end_matches('/'),
+        uri_encode(&opts.bucket)
+    );
+    if let Some(org) = &opts.org {
+        url.push_str(&format!("&org={}", uri_encode(org)));
+    }
+
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let mut request = ureq::post(&url).header("Content-Type", "text/plain; charset=utf-8");
+        if let Some(token) = &opts.token {
+            request = request.header("Authorization", &format!("Token {token}"));
+        }
+
+        match request.send(batch) {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < ATTEMPTS && is_transient(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether a failed write may succeed when retried
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::StatusCode(status) => *status == 429 || *status >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::ConnectionFailed => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_tag, is_transient, write_line};
+    use crate::{capture::Burst, probe::ProbeIdentity};
+
+    #[test]
+    fn bursts_are_points_at_their_start() {
+        let burst = Burst {
+            num_packets: 3,
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", 1.5, 1.75, 4000)
+        };
+        let mut line = String::new();
+        write_line(&mut line, &burst).unwrap();
+        assert_eq!(
+            line,
+            "burst,src=10.0.0.1,dst=10.0.0.2,src_port=50000,dst_port=443 \
+             size=4000i,packets=3i,duration=0.25 1500000000"
+        );
+    }
+
+    #[test]
+    fn probe_tags_are_escaped() {
+        let tags = ["site=lab 1".to_string(), "rack=a,b".to_string()];
+        let burst = Burst {
+            probe: ProbeIdentity::new(Some("probe=1"), &tags).unwrap(),
+            ..Burst::test("10.0.0.1:50000", "10.0.0.2:443", 1.0, 1.0, 1)
+        };
+        let mut line = String::new();
+        write_line(&mut line, &burst).unwrap();
+        assert!(line.contains(r",probe=probe\=1,rack=a\,b,site=lab\ 1 "));
+        assert_eq!(escape_tag("plain"), "plain");
+    }
+
+    #[test]
+    fn overload_and_connection_failures_are_retried() {
+        assert!(is_transient(&ureq::Error::StatusCode(503)));
+        assert!(is_transient(&ureq::Error::StatusCode(429)));
+        assert!(is_transient(&ureq::Error::ConnectionFailed));
+        assert!(!is_transient(&ureq::Error::StatusCode(400)));
+        assert!(!is_transient(&ureq::Error::StatusCode(401)));
+    }
+}
@@ -0,0 +1,597 @@
+mod alert;
+mod dashboard;
+mod export;
+mod fifo;
+mod file;
+mod format;
+mod influx;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod serve;
+mod sink;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod tui;
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    os::unix::fs::FileTypeExt,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    anonymize::{AnonymizeMode, Anonymizer},
+    capture::Record,
+    channel::{self, ChannelOptions, RecvTimeoutError, Sender},
+    classify::FlowClassifier,
+    control::Controls,
+    detect::SignatureDetector,
+    eve::EveCorrelator,
+    geoip::GeoIp,
+    histogram::FlowHistograms,
+    latency::ResponseTracker,
+    learn::PortProfiles,
+    pipeline::{MergeGap, Pipeline},
+    privacy::{PrivacyHistograms, PrivacyMode},
+    probe::ProbeIdentity,
+    profile::{PipelineProfile, Stage},
+    resolve::NameResolver,
+    sched::ThreadSched,
+    stats::FlowStatistics,
+    upload::ObjectStore,
+    BurstsharkError,
+};
+pub use alert::AlertOptions;
+use alert::AlertSink;
+use dashboard::Dashboard;
+pub use fifo::FifoPolicy;
+use fifo::FifoWriter;
+use file::RotatingFile;
+pub use file::{Compression, RotateOptions};
+use format::Formatter;
+pub use format::OutputFormat;
+pub use influx::InfluxOptions;
+use influx::InfluxSink;
+use serve::StreamServer;
+pub use sink::{BurstSink, OutputSink};
+pub use split::SplitOptions;
+use split::SplitSink;
+use tui::Tui;
+
+/// Number of buffered lines that triggers a flush of the outputs.
+const FLUSH_LINES: usize = 256;
+
+/// Maximum time a written line may stay buffered before it is flushed.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+pub struct OutputOptions {
+    pub outfile: Option<String>,
+    /// When to rotate the output file, and how to compress the rotated files
+    pub rotate: Option<RotateOptions>,
+    /// What to do with output while no reader is attached, when the output file is a named pipe
+    pub fifo_policy: FifoPolicy,
+    pub format: OutputFormat,
+    pub suppress: bool,
+    /// Show the active flows in a terminal interface instead of the bursts on the standard output
+    pub tui: bool,
+    /// Address to serve the live dashboard of bursts on
+    pub dashboard: Option<String>,
+    /// Unix socket or TCP address to stream bursts to subscribers on
+    pub serve: Option<String>,
+    /// InfluxDB database to write bursts to
+    pub influx: Option<InfluxOptions>,
+    /// File to export bursts to in MessagePack or Parquet, by its extension
+    pub export: Option<String>,
+    /// SQLite database to insert bursts into
+    pub sqlite: Option<String>,
+    /// Directory to write the bursts of each flow to a file of its own in
+    pub split: Option<SplitOptions>,
+    /// Conditions to raise alerts for bursts on, and where to deliver them
+    pub alert: Option<AlertOptions>,
+    /// Id and tags of this probe to attach to bursts and statistics
+    pub probe: ProbeIdentity,
+    /// Merge consecutive bursts of a flow with less than this many seconds between them
+    pub merge_gap: Option<f64>,
+    /// Annotate bursts with the host names of their addresses and the service names of their ports
+    pub resolve: bool,
+    /// MaxMind databases to annotate bursts with the countries and autonomous systems of their
+    /// addresses from
+    pub geoip_db: Vec<String>,
+    /// Outputs to anonymize the addresses of records for, with the key file to anonymize them with
+    pub anonymize: Vec<OutputSink>,
+    pub anonymize_key: Option<String>,
+    /// How the addresses are anonymized
+    pub anonymize_mode: AnonymizeMode,
+    /// Suricata EVE file to correlate bursts with, and whether to read it from the start
+    pub eve_file: Option<String>,
+    pub eve_from_start: bool,
+    /// File of learned port profiles to score bursts against, and seconds to learn them for
+    pub port_profiles: Option<String>,
+    pub learn_time: Option<f64>,
+    /// Longest time between a burst and the response to it, when pairing responses
+    pub max_response_time: Option<f64>,
+    /// Label bursts with the class of traffic inferred for their flow
+    pub classify: bool,
+    /// File of burst signatures to label the bursts of matching flows with
+    pub signatures: Option<String>,
+    /// Print a summary of the bursts of each flow when finished, and write it as JSON to a file
+    pub flow_stats: bool,
+    pub stats_file: Option<String>,
+    /// Output histograms of the bursts of each anonymous flow instead of the bursts, with seconds
+    /// of bursts per histogram
+    pub privacy: Option<PrivacyMode>,
+    pub privacy_interval: f64,
+    /// Also output histograms of the burst sizes and gaps of each flow, with seconds of bursts per
+    /// histogram, and only the histograms rather than the bursts on the standard output and file
+    pub histogram: Option<f64>,
+    pub histogram_only: bool,
+    /// Object storage to upload the output file, and each file rotated from it, to once finished
+    pub upload: Option<ObjectStore>,
+    /// Capacity of the channel of records to the output, and what to do when it is full
+    pub channels: ChannelOptions,
+    pub sched: ThreadSched,
+    pub profile: Option<Arc<PipelineProfile>>,
+    /// Settings changed while the capture runs, of which the output follows the suppression
+    pub controls: Option<Arc<Controls>>,
+}
+
+pub struct OutputWriter {
+    opts: OutputOptions,
+    handle: Option<thread::JoinHandle<Result<(), BurstsharkError>>>,
+}
+
+impl OutputWriter {
+    pub fn new(opts: OutputOptions) -> Self {
+        OutputWriter { opts, handle: None }
+    }
+
+    pub fn start(&mut self) -> Result<Sender<Record>, BurstsharkError> {
+        let (tx, rx) = channel::channel::<Record>(&self.opts.channels);
+
+        let file: Option<Box<dyn Write + Send>> = match &self.opts.outfile {
+            Some(path) if is_fifo(path) => {
+                if self.opts.upload.is_some() {
+                    return Err(BurstsharkError::InvalidOptions(format!(
+                        "Cannot upload the named pipe {path}"
+                    )));
+                }
+                if self.opts.rotate.is_some() {
+                    return Err(BurstsharkError::InvalidOptions(format!(
+                        "Cannot rotate the named pipe {path}"
+                    )));
+                }
+                Some(Box::new(FifoWriter::open(path, self.opts.fifo_policy)?))
+            }
+            Some(path) => match &self.opts.rotate {
+                Some(rotate) => Some(Box::new(RotatingFile::create(
+                    path,
+                    rotate.clone(),
+                    self.opts.format,
+                    self.opts.upload.clone(),
+                )?)),
+                None => Some(Box::new(BufWriter::new(File::create(path)?))),
+            },
+            None => None,
+        };
+        let mut eve = self
+            .opts
+            .eve_file
+            .as_ref()
+            .map(|path| EveCorrelator::start(path, self.opts.eve_from_start))
+            .transpose()?;
+        let anonymize = |sink| self.opts.anonymize.contains(&sink);
+        let mut anonymizer = match (&self.opts.anonymize_key, self.opts.anonymize.is_empty()) {
+            (_, true) => None,
+            (Some(path), false) => Some(Anonymizer::from_key_file(path, self.opts.anonymize_mode)?),
+            (None, false) => Some(Anonymizer::random(self.opts.anonymize_mode)?),
+        };
+        // Each sink with whether it gets anonymized bursts
+        let mut sinks: Vec<(Box<dyn BurstSink>, bool)> = Vec::new();
+        if self.opts.tui {
+            sinks.push((Box::new(Tui::start()?), anonymize(OutputSink::Stdout)));
+        }
+        if let Some(addr) = &self.opts.dashboard {
+            sinks.push((
+                Box::new(Dashboard::start(addr)?),
+                anonymize(OutputSink::Dashboard),
+            ));
+        }
+        if let Some(addr) = &self.opts.serve {
+            sinks.push((
+                Box::new(StreamServer::start(addr)?),
+                anonymize(OutputSink::Serve),
+            ));
+        }
+        if let Some(influx) = &self.opts.influx {
+            sinks.push((
+                Box::new(InfluxSink::start(influx.clone())),
+                anonymize(OutputSink::Influx),
+            ));
+        }
+        if let Some(path) = &self.opts.export {
+            sinks.push((export::create(path)?, anonymize(OutputSink::Export)));
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(path) = &self.opts.sqlite {
+            sinks.push((
+                Box::new(sqlite::SqliteSink::create(path)?),
+                anonymize(OutputSink::Sqlite),
+            ));
+        }
+        #[cfg(not(feature = "sqlite"))]
+        if self.opts.sqlite.is_some() {
+            return Err(BurstsharkError::MissingFeature {
+                name: "SQLite",
+                feature: "sqlite",
+            });
+        }
+        if let Some(split) = &self.opts.split {
+            sinks.push((
+                Box::new(SplitSink::create(split.clone(), self.opts.format)?),
+                anonymize(OutputSink::Split),
+            ));
+        }
+        if let Some(alert) = &self.opts.alert {
+            sinks.push((
+                Box::new(AlertSink::start(alert.clone())),
+                anonymize(OutputSink::Alert),
+            ));
+        }
+        let mut lines = Lines::new(
+            self.opts.format,
+            anonymize(OutputSink::Stdout),
+            anonymize(OutputSink::File),
+        );
+        let mut detector = self
+            .opts
+            .signatures
+            .as_deref()
+            .map(SignatureDetector::load)
+            .transpose()?;
+        let mut geoip = match self.opts.geoip_db.is_empty() {
+            true => None,
+            false => Some(GeoIp::open(&self.opts.geoip_db)?),
+        };
+        let mut pipeline = Pipeline::default();
+        if let Some(gap) = self.opts.merge_gap {
+            pipeline.push(MergeGap::new(gap));
+        }
+        let mut port_profiles = self
+            .opts
+            .port_profiles
+            .as_ref()
+            .map(|path| PortProfiles::load(path, self.opts.learn_time))
+            .transpose()?;
+
+        let OutputOptions {
+            suppress,
+            tui,
+            outfile,
+            sched,
+            profile,
+            upload,
+            max_response_time,
+            classify,
+            flow_stats,
+            stats_file,
+            privacy,
+            privacy_interval,
+            histogram,
+            histogram_only,
+            probe,
+            resolve,
+            controls,
+            ..
+        } = self.opts.clone();
+
+        self.handle = Some(thread::spawn(move || -> Result<(), BurstsharkError> {
+            sched.apply_or_warn("output");
+
+            let mut resolver = resolve.then(NameResolver::start);
+            let mut responses = max_response_time.map(ResponseTracker::new);
+            let mut classifier = classify.then(FlowClassifier::default);
+            let mut statistics = (flow_stats || stats_file.is_some()).then(FlowStatistics::default);
+            let mut histograms = privacy.map(|mode| PrivacyHistograms::new(mode, privacy_interval));
+            let mut flow_histograms = histogram.map(FlowHistograms::new);
+            let mut stdout =
+                (!suppress && !tui).then(|| BufWriter::with_capacity(1 << 16, io::stdout().lock()));
+            let mut buffer = file;
+            let mut pending = 0;
+            let mut last_flush = Instant::now();
+            let mut bursts = Vec::new();
+            let mut seen = 0;
+
+            if let Some(header) = lines.formatter.header() {
+                write_line(&mut stdout, &mut buffer, header)?;
+            }
+
+            loop {
+                if let Some(settings) = controls.as_ref().and_then(|c| c.changed(&mut seen)) {
+                    if !tui && settings.suppress != stdout.is_none() {
+                        flush(&mut stdout, &mut buffer)?;
+                        stdout = (!settings.suppress)
+                            .then(|| BufWriter::with_capacity(1 << 16, io::stdout().lock()));
+                    }
+                }
+
+                // Flush in batches rather than issuing a write for every burst
+                if pending >= FLUSH_LINES || (pending > 0 && last_flush.elapsed() >= FLUSH_INTERVAL)
+                {
+                    flush(&mut stdout, &mut buffer)?;
+                    pending = 0;
+                    last_flush = Instant::now();
+                }
+
+                let record = match rx.recv_timeout(FLUSH_INTERVAL) {
+                    Ok(record) => Some(record),
+                    Err(RecvTimeoutError::Timeout) => {
+                        // Also hands lines kept for a named pipe to a reader that attached since
+                        flush(&mut stdout, &mut buffer)?;
+                        pending = 0;
+                        last_flush = Instant::now();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                };
+
+                // The bursts held back by the pipeline are output once the capture has ended
+                let finished = record.is_none();
+                match record {
+                    Some(Record::Burst(mut burst)) => {
+                        burst.probe = probe.clone();
+                        pipeline.process(burst, &mut bursts);
+                    }
+                    Some(record) => {
+                        let anonymized = anonymizer.as_mut().map(|a| a.record(&record));
+                        if lines.write(&mut stdout, &mut buffer, &record, anonymized.as_ref())? {
+                            pending += 1;
+                        }
+                        continue;
+                    }
+                    None => pipeline.finish(&mut bursts),
+                }
+
+                for mut burst in bursts.drain(..) {
+                    if let Some(resolver) = &mut resolver {
+                        resolver.annotate(&mut burst);
+                    }
+
+                    if let Some(geoip) = &mut geoip {
+                        geoip.annotate(&mut burst);
+                    }
+
+                    if let Some(responses) = &mut responses {
+                        responses.observe(&mut burst);
+                    }
+
+                    let start = profile.is_some().then(Instant::now);
+
+                    if let Some(eve) = &mut eve {
+                        eve.annotate(&mut burst);
+                    }
+
+                    if let Some(port_profiles) = &mut port_profiles {
+                        port_profiles.observe(&mut burst);
+                    }
+
+                    if let Some(classifier) = &mut classifier {
+                        classifier.observe(&mut burst);
+                    }
+
+                    if let Some(detector) = &mut detector {
+                        detector.observe(&mut burst);
+                    }
+
+                    if let Some(statistics) = &mut statistics {
+                        statistics.add(&burst);
+                    }
+
+                    if let Some(histograms) = &mut histograms {
+                        for histogram in histograms.add(&burst) {
+                            let record = Record::BurstHistogram(histogram);
+                            lines.write(&mut stdout, &mut buffer, &record, None)?;
+                            pending += 1;
+                        }
+                        continue;
+                    }
+
+                    if let Some(flow_histograms) = &mut flow_histograms {
+                        for histogram in flow_histograms.add(&burst) {
+                            let record = Record::FlowHistogram(histogram);
+                            let anonymized = anonymizer.as_mut().map(|a| a.record(&record));
+                            lines.write(&mut stdout, &mut buffer, &record, anonymized.as_ref())?;
+                            pending += 1;
+                        }
+                    }
+
+                    let anonymized = anonymizer.as_mut().map(|a| a.burst(&burst));
+                    for (sink, anonymize) in &mut sinks {
+                        match (&anonymized, anonymize) {
+                            (Some(anonymized), true) => sink.write(anonymized),
+                            _ => sink.write(&burst),
+                        }
+                    }
+
+                    if !histogram_only {
+                        let anonymized = anonymized.map(Record::Burst);
+                        let record = Record::Burst(burst);
+                        lines.write(&mut stdout, &mut buffer, &record, anonymized.as_ref())?;
+                        pending += 1;
+                    }
+
+                    if let (Some(profile), Some(start)) = (&profile, start) {
+                        profile.record(Stage::Output, start.elapsed());
+                    }
+                }
+
+                if finished {
+                    break;
+                }
+            }
+
+            if let Some(histograms) = &mut histograms {
+                for histogram in histograms.finish() {
+                    let record = Record::BurstHistogram(histogram);
+                    lines.write(&mut stdout, &mut buffer, &record, None)?;
+                }
+            }
+
+            if let Some(flow_histograms) = &mut flow_histograms {
+                for histogram in flow_histograms.finish() {
+                    let record = Record::FlowHistogram(histogram);
+                    let anonymized = anonymizer.as_mut().map(|a| a.record(&record));
+                    lines.write(&mut stdout, &mut buffer, &record, anonymized.as_ref())?;
+                }
+            }
+
+            if let Some(footer) = lines.formatter.footer() {
+                write_line(&mut stdout, &mut buffer, footer)?;
+            }
+
+            flush(&mut stdout, &mut buffer)?;
+            drop(buffer);
+
+            for (sink, _) in sinks {
+                sink.finish();
+            }
+
+            if let Some(port_profiles) = &port_profiles {
+                if let Err(e) = port_profiles.save() {
+                    eprintln!("Error saving port profiles: {}", e);
+                }
+            }
+
+            if let Some(statistics) = &mut statistics {
+                if let Err(e) = statistics.report(flow_stats, stats_file.as_deref()) {
+                    eprintln!("Error writing flow statistics: {}", e);
+                }
+            }
+
+            if let (Some(store), Some(path)) = (upload, outfile) {
+                if let Err(e) = store.upload_and_remove(&path) {
+                    eprintln!("Error uploading output: {}", e);
+                }
+            }
+            Ok(())
+        }));
+
+        Ok(tx)
+    }
+
+    /// Wait for the output to be written, and return the error it stopped at, if any
+    pub fn stop(&mut self) -> Result<(), BurstsharkError> {
+        match self.handle.take().map(|handle| handle.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(BurstsharkError::Panicked("output")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Formats records as lines for stdout and the file output, from the anonymized records for those
+/// that are anonymized.
+struct Lines {
+    formatter: Formatter,
+    line: String,
+    /// Formats the anonymized records, when stdout or the file output is anonymized
+    anonymized: Option<(Formatter, String)>,
+    anonymize_stdout: bool,
+    anonymize_file: bool,
+}
+
+impl Lines {
+    fn new(format: OutputFormat, anonymize_stdout: bool, anonymize_file: bool) -> Self {
+        Lines {
+            formatter: Formatter::new(format),
+            line: String::with_capacity(256),
+            anonymized: (anonymize_stdout || anonymize_file)
+                .then(|| (Formatter::new(format), String::with_capacity(256))),
+            anonymize_stdout,
+            anonymize_file,
+        }
+    }
+
+    /// Write a record, or its anonymized copy, to stdout and the file output. Returns false if the
+    /// format does not support the record.
+    fn write<A: Write, B: Write>(
+        &mut self,
+        stdout: &mut Option<A>,
+        file: &mut Option<B>,
+        record: &Record,
+        anonymized: Option<&Record>,
+    ) -> Result<bool, BurstsharkError> {
+        self.line.clear();
+        self.formatter.write(&mut self.line, record)?;
+        let anonymized_line = match &mut self.anonymized {
+            Some((formatter, line)) => {
+                // Formats every record to keep track of the JSON array, even the ones already
+                // anonymous
+                line.clear();
+                formatter.write(line, anonymized.unwrap_or(record))?;
+                &*line
+            }
+            None => &self.line,
+        };
+
+        if self.line.is_empty() {
+            return Ok(false);
+        }
+
+        if let Some(stdout) = stdout {
+            let line = if self.anonymize_stdout {
+                anonymized_line
+            } else {
+                &self.line
+            };
+            writeln!(stdout, "{}", line)?;
+        }
+
+        if let Some(file) = file {
+            let line = if self.anonymize_file {
+                anonymized_line
+            } else {
+                &self.line
+            };
+            writeln!(file, "{}", line)?;
+        }
+
+        Ok(true)
+    }
+}
+
+fn is_fifo(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+/// Write a line to stdout and the file output, if enabled.
+fn write_line<A: Write, B: Write>(
+    stdout: &mut Option<A>,
+    file: &mut Option<B>,
+    line: &str,
+) -> io::Result<()> {
+    if let Some(stdout) = stdout {
+        writeln!(stdout, "{}", line)?;
+    }
+
+    if let Some(file) = file {
+        writeln!(file, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Flush the buffered stdout and file output.
+fn flush<A: Write, B: Write>(stdout: &mut Option<A>, file: &mut Option<B>) -> io::Result<()> {
+    if let Some(stdout) = stdout {
+        stdout.flush()?;
+    }
+
+    if let Some(file) = file {
+        file.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,319 @@
+use std::{fs::File, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+    UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties, format::KeyValue,
+};
+
+use super::{
+    format::{join_alerts, join_channels, join_errors, join_packets},
+    sink::BurstSink,
+};
+use crate::{capture::Burst, BurstsharkError};
+
+/// Version of the columns of the file, kept in its metadata. Columns are only ever added at the
+/// end, and the version is increased if any is renamed, removed or changes its type.
+const SCHEMA_VERSION: u32 = 1;
+
+/// Bursts per row group, which are written to the file at once
+const ROW_GROUP_BURSTS: usize = 65536;
+
+/// Columns of the bursts, with the names of the CSV header, in the order they were added
+fn schema() -> Schema {
+    let column = |name, data_type, nullable| Field::new(name, data_type, nullable);
+    Schema::new(vec![
+        column("completion_time", DataType::Float64, false),
+        column("src", DataType::Utf8, false),
+        column("src_port", DataType::UInt16, true),
+        column("dst", DataType::Utf8, false),
+        column("dst_port", DataType::UInt16, true),
+        column("start", DataType::Float64, false),
+        column("end", DataType::Float64, false),
+        column("num_packets", DataType::UInt16, false),
+        column("size", DataType::UInt32, false),
+        column("ethertype", DataType::UInt16, true),
+        column("dscp", DataType::UInt8, true),
+        column("flow_label", DataType::UInt32, true),
+        column("group", DataType::Utf8, true),
+        column("tunnel", DataType::Utf8, true),
+        column("tunnel_overhead", DataType::UInt32, true),
+        column("airtime", DataType::Float64, true),
+        column("retransmitted_bytes", DataType::UInt32, true),
+        column("ssh_phase", DataType::Utf8, true),
+        column("deviation", DataType::Float64, true),
+        column("response_latency", DataType::Float64, true),
+        column("class", DataType::Utf8, true),
+        column("class_confidence", DataType::Float64, true),
+        column("label", DataType::Utf8, true),
+        column("direction", DataType::Utf8, true),
+        column("flow_id", DataType::Utf8, true),
+        column("errors", DataType::Utf8, true),
+        column("truncated", DataType::Boolean, false),
+        column("packets", DataType::Utf8, true),
+        column("alerts", DataType::Utf8, true),
+        column("channels", DataType::Utf8, true),
+        column("probe_id", DataType::Utf8, true),
+        column("tags", DataType::Utf8, true),
+        column("proto", DataType::Utf8, true),
+        column("interface", DataType::Utf8, true),
+        column("src_name", DataType::Utf8, true),
+        column("dst_name", DataType::Utf8, true),
+        column("service", DataType::Utf8, true),
+        column("src_country", DataType::Utf8, true),
+        column("src_asn", DataType::UInt32, true),
+        column("dst_country", DataType::Utf8, true),
+        column("dst_asn", DataType::UInt32, true),
+        column("tid", DataType::UInt8, true),
+        column("ssrc", DataType::UInt32, true),
+        column("payload_type", DataType::UInt8, true),
+        column("lost_packets", DataType::UInt16, true),
+        column("device", DataType::UInt32, true),
+        column("min_iat", DataType::Float64, true),
+        column("mean_iat", DataType::Float64, true),
+        column("max_iat", DataType::Float64, true),
+        column("jitter", DataType::Float64, true),
+        column("peak_rate", DataType::Float64, true),
+    ])
+}
+
+/// Writes bursts to a Parquet file, a row group of columns at a time.
+///
+/// The footer of the file is written when the capture ends, so the file can not be read before.
+pub struct ParquetSink {
+    path: String,
+    schema: SchemaRef,
+    writer: Option<ArrowWriter<File>>,
+    /// Bursts of the row group being collected
+    bursts: Vec<Burst>,
+}
+
+impl ParquetSink {
+    pub fn create(path: &str) -> Result<Self, BurstsharkError> {
+        let schema = Arc::new(schema());
+        let file = File::create(path).map_err(|source| BurstsharkError::File {
+            action: "create export",
+            path: path.to_string(),
+            source,
+        })?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_BURSTS)
+            .set_key_value_metadata(Some(vec![KeyValue::new(
+                "burstshark.schema_version".to_string(),
+                SCHEMA_VERSION.to_string(),
+            )]))
+            .build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+
+        Ok(ParquetSink {
+            path: path.to_string(),
+            schema,
+            writer: Some(writer),
+            bursts: Vec::with_capacity(ROW_GROUP_BURSTS),
+        })
+    }
+
+    /// Write the collected bursts as a row group
+    fn write_row_group(&mut self) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        let result = batch(&self.schema, &self.bursts)
+            .and_then(|batch| Ok(writer.write(&batch).and_then(|_| writer.flush())?));
+        if let Err(e) = result {
+            eprintln!("Error writing bursts to {}: {}", self.path, e);
+            self.writer = None;
+        }
+        self.bursts.clear();
+    }
+}
+
+impl BurstSink for ParquetSink {
+    fn write(&mut self, burst: &Burst) {
+        if self.writer.is_none() {
+            return;
+        }
+        self.bursts.push(burst.clone());
+        if self.bursts.len() >= ROW_GROUP_BURSTS {
+            self.write_row_group();
+        }
+    }
+
+    fn finish(mut self: Box<Self>) {
+        if !self.bursts.is_empty() {
+            self.write_row_group();
+        }
+        if let Some(writer) = self.writer.take() {
+            if let Err(e) = writer.close() {
+                eprintln!("Error writing bursts to {}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+/// The columns of bursts, in the order of the schema
+fn batch(schema: &SchemaRef, bursts: &[Burst]) -> Result<RecordBatch, BurstsharkError> {
+    fn strings<T: ToString>(values: impl Iterator<Item = Option<T>>) -> ArrayRef {
+        Arc::new(StringArray::from_iter(
+            values.map(|value| value.map(|value| value.to_string())),
+        ))
+    }
+    fn non_empty(value: String) -> Option<String> {
+        (!value.is_empty()).then_some(value)
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Float64Array::from_iter_values(
+            bursts.iter().map(|b| b.completion_time),
+        )),
+        strings(bursts.iter().map(|b| Some(&b.src))),
+        Arc::new(UInt16Array::from_iter(bursts.iter().map(|b| b.src_port))),
+        strings(bursts.iter().map(|b| Some(&b.dst))),
+        Arc::new(UInt16Array::from_iter(bursts.iter().map(|b| b.dst_port))),
+        Arc::new(Float64Array::from_iter_values(
+            bursts.iter().map(|b| b.start),
+        )),
+        Arc::new(Float64Array::from_iter_values(bursts.iter().map(|b| b.end))),
+        Arc::new(UInt16Array::from_iter_values(
+            bursts.iter().map(|b| b.num_packets),
+        )),
+        Arc::new(UInt32Array::from_iter_values(bursts.iter().map(|b| b.size))),
+        Arc::new(UInt16Array::from_iter(bursts.iter().map(|b| b.ethertype))),
+        Arc::new(UInt8Array::from_iter(bursts.iter().map(|b| b.dscp))),
+        Arc::new(UInt32Array::from_iter(bursts.iter().map(|b| b.flow_label))),
+        strings(bursts.iter().map(|b| b.group)),
+        strings(bursts.iter().map(|b| b.tunnel)),
+        Arc::new(UInt32Array::from_iter(
+            bursts.iter().map(|b| b.tunnel_overhead),
+        )),
+        Arc::new(Float64Array::from_iter(bursts.iter().map(|b| b.airtime))),
+        Arc::new(UInt32Array::from_iter(
+            bursts.iter().map(|b| b.retransmitted_bytes),
+        )),
+        strings(bursts.iter().map(|b| b.ssh_phase)),
+        Arc::new(Float64Array::from_iter(bursts.iter().map(|b| b.deviation))),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.response_latency),
+        )),
+        strings(bursts.iter().map(|b| b.class)),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.class_confidence),
+        )),
+        strings(bursts.iter().map(|b| b.label.as_deref())),
+        strings(bursts.iter().map(|b| b.direction)),
+        strings(bursts.iter().map(|b| b.flow_id.as_deref())),
+        strings(bursts.iter().map(|b| non_empty(join_errors(b)))),
+        Arc::new(BooleanArray::from_iter(
+            bursts.iter().map(|b| Some(b.truncated)),
+        )),
+        strings(bursts.iter().map(|b| non_empty(join_packets(b, ";")))),
+        strings(bursts.iter().map(|b| non_empty(join_alerts(b)))),
+        strings(bursts.iter().map(|b| non_empty(join_channels(b, ";")))),
+        strings(bursts.iter().map(|b| b.probe.probe_id.as_deref())),
+        strings(bursts.iter().map(|b| non_empty(b.probe.join_tags(";")))),
+        strings(bursts.iter().map(|b| b.proto)),
+        strings(bursts.iter().map(|b| b.interface.as_deref())),
+        strings(bursts.iter().map(|b| b.src_name.as_deref())),
+        strings(bursts.iter().map(|b| b.dst_name.as_deref())),
+        strings(bursts.iter().map(|b| b.service.as_deref())),
+        strings(bursts.iter().map(|b| b.src_country.as_deref())),
+        Arc::new(UInt32Array::from_iter(bursts.iter().map(|b| b.src_asn))),
+        strings(bursts.iter().map(|b| b.dst_country.as_deref())),
+        Arc::new(UInt32Array::from_iter(bursts.iter().map(|b| b.dst_asn))),
+        Arc::new(UInt8Array::from_iter(bursts.iter().map(|b| b.tid))),
+        Arc::new(UInt32Array::from_iter(bursts.iter().map(|b| b.ssrc))),
+        Arc::new(UInt8Array::from_iter(bursts.iter().map(|b| b.payload_type))),
+        Arc::new(UInt16Array::from_iter(
+            bursts.iter().map(|b| b.lost_packets),
+        )),
+        Arc::new(UInt32Array::from_iter(bursts.iter().map(|b| b.device))),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.detail.and_then(|d| d.min_iat)),
+        )),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.detail.and_then(|d| d.mean_iat)),
+        )),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.detail.and_then(|d| d.max_iat)),
+        )),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.detail.and_then(|d| d.jitter)),
+        )),
+        Arc::new(Float64Array::from_iter(
+            bursts.iter().map(|b| b.detail.map(|d| d.peak_rate)),
+        )),
+    ];
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, process};
+
+    use arrow_array::{Array, Float64Array, StringArray, UInt32Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::{ParquetSink, SCHEMA_VERSION};
+    use crate::{capture::Burst, output::sink::BurstSink};
+
+    #[test]
+    fn bursts_are_exported_as_typed_columns() {
+        let path =
+            std::env::temp_dir().join(format!("burstshark-export-{}.parquet", process::id()));
+        let mut sink = Box::new(ParquetSink::create(path.to_str().unwrap()).unwrap());
+        sink.write(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            1.0,
+            1.5,
+            1000,
+        ));
+        sink.write(&Burst {
+            label: Some("video".into()),
+            ..Burst::test("10.0.0.3:53", "10.0.0.2:50001", 2.0, 2.5, 300)
+        });
+        sink.finish();
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        let metadata = builder
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap();
+        assert!(metadata
+            .iter()
+            .any(|kv| kv.value.as_deref() == Some(&*SCHEMA_VERSION.to_string())));
+        let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let column = |name| batch.column_by_name(name).unwrap();
+        let src = column("src")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!((src.value(0), src.value(1)), ("10.0.0.1", "10.0.0.3"));
+        let size = column("size")
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .unwrap();
+        assert_eq!(size.values(), &[1000, 300]);
+        let end = column("end")
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(end.values(), &[1.5, 2.5]);
+        let label = column("label")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(label.is_null(0));
+        assert_eq!(label.value(1), "video");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::net::UnixStream,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use super::sink::BurstSink;
+use crate::{capture::Burst, socket, BurstsharkError};
+
+/// Lines queued for a subscriber before it is disconnected for not keeping up
+const SUBSCRIBER_QUEUE: usize = 4096;
+
+/// Time a write to a subscriber may block before it is disconnected for not reading
+const SUBSCRIBER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Streams bursts as NDJSON, a JSON object per line, to every process connected to a Unix socket
+/// or TCP address, such as collectors running on the same host.
+///
+/// Subscribers get the bursts completed after they connected. Those that can not keep up are
+/// disconnected, rather than holding up the capture or the other subscribers. Unix sockets are
+/// only accessible to the user running burstshark, as bursts carry the addresses of their flows.
+pub struct StreamServer {
+    tx: Sender<Arc<str>>,
+    /// Path of the Unix socket, which is removed when finished
+    socket: Option<String>,
+}
+
+impl StreamServer {
+    /// Listen on a Unix socket if the address is a path, such as `/run/burstshark.sock`, or on a
+    /// TCP address otherwise, such as `127.0.0.1:9000`
+    pub fn start(addr: &str) -> Result<Self, BurstsharkError> {
+        let listen_error = |source| BurstsharkError::Listen {
+            what: "subscribers",
+            addr: addr.to_string(),
+            source,
+        };
+        let (subscriber_tx, subscriber_rx) = mpsc::channel();
+        let socket = match addr.contains('/') {
+            true => {
+                let listener = socket::bind_private(addr).map_err(listen_error)?;
+                thread::spawn(move || accept(listener.incoming(), subscriber_tx));
+                Some(addr.to_string())
+            }
+            false => {
+                let listener = TcpListener::bind(addr).map_err(listen_error)?;
+                thread::spawn(move || accept(listener.incoming(), subscriber_tx));
+                None
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || broadcast(rx, subscriber_rx));
+        Ok(StreamServer { tx, socket })
+    }
+}
+
+impl BurstSink for StreamServer {
+    fn write(&mut self, burst: &Burst) {
+        if let Ok(json) = serde_json::to_string(burst) {
+            let _ = self.tx.send(Arc::from(json));
+        }
+    }
+
+    fn finish(self: Box<Self>) {
+        if let Some(path) = &self.socket {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Connections of subscribers
+trait Subscriber: Write + Send + 'static {
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Subscriber for UnixStream {
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl Subscriber for TcpStream {
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+/// Hand each connecting subscriber a queue of lines, written to it by a thread of its own
+fn accept<S: Subscriber>(
+    streams: impl Iterator<Item = io::Result<S>>,
+    subscribers: Sender<SyncSender<Arc<str>>>,
+) {
+    for stream in streams.flatten() {
+        if stream.set_write_timeout(Some(SUBSCRIBER_TIMEOUT)).is_err() {
+            continue;
+        }
+        let (tx, rx) = mpsc::sync_channel(SUBSCRIBER_QUEUE);
+        if subscribers.send(tx).is_err() {
+            return;
+        }
+        thread::spawn(move || subscribe(stream, rx));
+    }
+}
+
+/// Write the queued lines to a subscriber until it disconnects, stops reading, or is disconnected
+fn subscribe(stream: impl Write, lines: Receiver<Arc<str>>) -> io::Result<()> {
+    let mut writer = BufWriter::new(stream);
+    while let Ok(line) = lines.recv() {
+        writeln!(writer, "{line}")?;
+        // Lines queued meanwhile are written at once
+        for line in lines.try_iter() {
+            writeln!(writer, "{line}")?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Queue every line for all subscribers, dropping those that disconnected or whose queue is full
+fn broadcast(lines: Receiver<Arc<str>>, subscribers: Receiver<SyncSender<Arc<str>>>) {
+    let mut connected: Vec<SyncSender<Arc<str>>> = Vec::new();
+    for line in lines {
+        connected.extend(subscribers.try_iter());
+        connected.retain(|subscriber| subscriber.try_send(line.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        os::unix::net::UnixStream,
+        sync::{mpsc, Arc},
+        time::Duration,
+    };
+
+    use super::{broadcast, subscribe};
+
+    #[test]
+    fn subscribers_that_stop_reading_are_disconnected() {
+        let (stream, _unread) = UnixStream::pair().unwrap();
+        stream
+            .set_write_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        let (tx, rx) = mpsc::channel();
+        let line: Arc<str> = Arc::from("x".repeat(1024));
+        // Far more than the socket buffers
+        for _ in 0..16384 {
+            tx.send(line.clone()).unwrap();
+        }
+        drop(tx);
+        let err = subscribe(stream, rx).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+    }
+
+    #[test]
+    fn subscribers_that_can_not_keep_up_are_dropped() {
+        let (lines_tx, lines_rx) = mpsc::channel();
+        let (subscribers_tx, subscribers_rx) = mpsc::channel();
+        let (slow_tx, slow_rx) = mpsc::sync_channel(1);
+        let (fast_tx, fast_rx) = mpsc::sync_channel(3);
+        subscribers_tx.send(slow_tx).unwrap();
+        subscribers_tx.send(fast_tx).unwrap();
+        for line in ["a", "b", "c"] {
+            lines_tx.send(Arc::from(line)).unwrap();
+        }
+        drop(lines_tx);
+        broadcast(lines_rx, subscribers_rx);
+
+        assert_eq!(slow_rx.iter().collect::<Vec<_>>(), [Arc::from("a")]);
+        let lines: Vec<Arc<str>> = fast_rx.iter().collect();
+        assert_eq!(lines, [Arc::from("a"), Arc::from("b"), Arc::from("c")]);
+    }
+}
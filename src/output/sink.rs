@@ -0,0 +1,46 @@
+use clap::ValueEnum;
+
+use crate::capture::Burst;
+
+/// A destination that bursts are sent to besides the standard output and the bursts file, once
+/// they are annotated.
+///
+/// Sinks are called from the output thread, so those that may block, such as on the network, should
+/// hand the bursts over to threads of their own.
+pub trait BurstSink: Send {
+    fn write(&mut self, burst: &Burst);
+
+    /// Deliver any bursts still buffered, as the capture ended
+    fn finish(self: Box<Self>) {}
+}
+
+/// An output that records are written to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputSink {
+    /// The standard output, or the terminal interface.
+    Stdout,
+
+    /// The bursts file.
+    File,
+
+    /// The live dashboard.
+    Dashboard,
+
+    /// The InfluxDB database.
+    Influx,
+
+    /// The MessagePack or Parquet export.
+    Export,
+
+    /// The SQLite database.
+    Sqlite,
+
+    /// The files of each flow.
+    Split,
+
+    /// The bursts streamed to subscribers.
+    Serve,
+
+    /// The bursts sent with alerts.
+    Alert,
+}
@@ -0,0 +1,375 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use super::{
+    format::{Formatter, OutputFormat},
+    sink::BurstSink,
+};
+use crate::{
+    capture::{Burst, Record},
+    BurstsharkError,
+};
+
+/// Where the bursts of flows are written to their own files, with how many may be open at once
+/// and the size they are rotated at
+#[derive(Clone, Debug)]
+pub struct SplitOptions {
+    pub dir: String,
+    pub max_open: usize,
+    pub rotate_size: Option<u64>,
+}
+
+/// Source and destination address and port of a burst, its interface when capturing on several,
+/// its TID when WLAN flows are kept apart per TID, and its SSRC when keying on RTP streams
+type FlowKey = (
+    Arc<str>,
+    Option<u16>,
+    Arc<str>,
+    Option<u16>,
+    Option<Arc<str>>,
+    Option<u8>,
+    Option<u32>,
+);
+
+/// The file of a flow, which stays known while it is closed to reopen it where it left off
+struct FlowFile {
+    /// Name without the rotation number and extension
+    stem: String,
+    /// Number of files rotated away so far, which numbers the current one
+    rotations: u32,
+    /// Bytes written to the current file
+    size: u64,
+    formatter: Formatter,
+    writer: Option<BufWriter<File>>,
+    /// When the file was last written to, in bursts written to any file
+    last_use: u64,
+}
+
+impl FlowFile {
+    fn path(&self, dir: &str, extension: &str) -> PathBuf {
+        let name = match self.rotations {
+            0 => format!("{}.{extension}", self.stem),
+            n => format!("{}.{n}.{extension}", self.stem),
+        };
+        PathBuf::from(dir).join(name)
+    }
+}
+
+/// Writes the bursts of each flow to a file of its own in a directory, named after the flow such
+/// as `10.0.0.1_443-10.0.0.2_51514.txt`, or `eth0_10.0.0.1_443-10.0.0.2_51514.txt` when capturing
+/// on several interfaces.
+///
+/// Only the most recently used files are kept open, and the others are reopened to append to when
+/// their flows have bursts again. Files that reach the rotation size are continued in files
+/// numbered from 1, such as `10.0.0.1_443-10.0.0.2_51514.1.txt`.
+pub struct SplitSink {
+    opts: SplitOptions,
+    format: OutputFormat,
+    flows: HashMap<FlowKey, FlowFile>,
+    /// Flows with open files by when they were last used, to close the least recently used
+    open: BTreeMap<u64, FlowKey>,
+    bursts: u64,
+    line: String,
+}
+
+impl SplitSink {
+    pub fn create(opts: SplitOptions, format: OutputFormat) -> Result<Self, BurstsharkError> {
+        if format == OutputFormat::Json {
+            return Err(BurstsharkError::InvalidOptions(
+                "Files of flows are closed and reopened, so they can not hold a JSON array, use jsonl instead"
+                    .to_string(),
+            ));
+        }
+        fs::create_dir_all(&opts.dir).map_err(|source| BurstsharkError::File {
+            action: "create directory",
+            path: opts.dir.clone(),
+            source,
+        })?;
+        Ok(SplitSink {
+            opts,
+            format,
+            flows: HashMap::new(),
+            open: BTreeMap::new(),
+            bursts: 0,
+            line: String::with_capacity(256),
+        })
+    }
+
+    fn extension(&self) -> &'static str {
+        match self.format {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Eve => "json",
+        }
+    }
+
+    /// Close the least recently used file
+    fn close_oldest(&mut self) {
+        let Some((_, key)) = self.open.pop_first() else {
+            return;
+        };
+        if let Some(flow) = self.flows.get_mut(&key) {
+            if let Some(mut writer) = flow.writer.take() {
+                if let Err(e) = writer.flush() {
+                    eprintln!("Error writing to {}: {}", flow.stem, e);
+                }
+            }
+        }
+    }
+
+    fn write_burst(&mut self, burst: &Burst) -> Result<(), BurstsharkError> {
+        self.bursts += 1;
+        let key = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+            burst.interface.clone(),
+            burst.tid,
+            burst.ssrc,
+        );
+
+        match self.flows.get(&key) {
+            Some(flow) if flow.writer.is_some() => {
+                self.open.remove(&flow.last_use);
+            }
+            _ => {
+                if self.open.len() >= self.opts.max_open {
+                    self.close_oldest();
+                }
+            }
+        }
+
+        let extension = self.extension();
+        let format = self.format;
+        let flow = self.flows.entry(key.clone()).or_insert_with(|| FlowFile {
+            stem: file_stem(burst),
+            rotations: 0,
+            size: 0,
+            formatter: Formatter::new(format),
+            writer: None,
+            last_use: 0,
+        });
+        flow.last_use = self.bursts;
+
+        if self.opts.rotate_size.is_some_and(|max| flow.size >= max) {
+            if let Some(mut writer) = flow.writer.take() {
+                writer.flush()?;
+            }
+            flow.rotations += 1;
+            flow.size = 0;
+            flow.formatter = Formatter::new(format);
+        }
+
+        let writer = match &mut flow.writer {
+            Some(writer) => writer,
+            None => {
+                let path = flow.path(&self.opts.dir, extension);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|source| BurstsharkError::File {
+                        action: "open",
+                        path: path.display().to_string(),
+                        source,
+                    })?;
+                // Files of flows from before a restart are appended to without another header
+                flow.size = file.metadata()?.len();
+                let mut writer = BufWriter::new(file);
+                if let Some(header) = flow.formatter.header().filter(|_| flow.size == 0) {
+                    writeln!(writer, "{header}")?;
+                    flow.size += header.len() as u64 + 1;
+                }
+                flow.writer.insert(writer)
+            }
+        };
+        self.open.insert(self.bursts, key);
+
+        self.line.clear();
+        flow.formatter
+            .write(&mut self.line, &Record::Burst(burst.clone()))?;
+        writeln!(writer, "{}", self.line)?;
+        flow.size += self.line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+impl BurstSink for SplitSink {
+    fn write(&mut self, burst: &Burst) {
+        if let Err(e) = self.write_burst(burst) {
+            eprintln!("Error writing burst to {}: {}", self.opts.dir, e);
+        }
+    }
+
+    fn finish(mut self: Box<Self>) {
+        for flow in self.flows.values_mut() {
+            if let Some(mut writer) = flow.writer.take() {
+                if let Err(e) = writer.flush() {
+                    eprintln!("Error writing to {}: {}", flow.stem, e);
+                }
+            }
+        }
+    }
+}
+
+/// Name of the file of a flow, starting with the interface when capturing on several and ending
+/// with the TID of WLAN flows kept apart per TID, with the characters that are awkward in file
+/// names, such as the colons of IPv6 and MAC addresses, replaced by dots
+fn file_stem(burst: &Burst) -> String {
+    let sanitize = |name: &str| -> String {
+        name.chars()
+            .map(|c| match c.is_ascii_alphanumeric() || c == '.' {
+                true => c,
+                false => '.',
+            })
+            .collect()
+    };
+    let endpoint = |addr: &str, port: Option<u16>| match port {
+        Some(port) => format!("{}_{port}", sanitize(addr)),
+        None => sanitize(addr),
+    };
+    let mut flow = format!(
+        "{}-{}",
+        endpoint(&burst.src, burst.src_port),
+        endpoint(&burst.dst, burst.dst_port)
+    );
+    if let Some(tid) = burst.tid {
+        flow.push_str(&format!("_tid{tid}"));
+    }
+    if let Some(ssrc) = burst.ssrc {
+        flow.push_str(&format!("_ssrc{ssrc:08x}"));
+    }
+    match &burst.interface {
+        Some(interface) => format!("{}_{flow}", sanitize(interface)),
+        None => flow,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::{file_stem, SplitOptions, SplitSink};
+    use crate::{
+        capture::Burst,
+        output::{format::OutputFormat, sink::BurstSink},
+    };
+
+    /// Options splitting into an empty directory of its own for the test
+    fn options(test: &str, max_open: usize, rotate_size: Option<u64>) -> SplitOptions {
+        let dir = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SplitOptions {
+            dir: dir.to_string_lossy().into_owned(),
+            max_open,
+            rotate_size,
+        }
+    }
+
+    /// Lines of each file in the directory, by file name
+    fn files(dir: &str) -> Vec<(String, usize)> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let lines = fs::read_to_string(&path).unwrap().lines().count();
+                (
+                    path.file_name().unwrap().to_string_lossy().into_owned(),
+                    lines,
+                )
+            })
+            .collect();
+        files.sort();
+        let _ = fs::remove_dir_all(dir);
+        files
+    }
+
+    #[test]
+    fn files_are_named_after_their_flow() {
+        let burst = Burst::test("10.0.0.1:443", "10.0.0.2:51514", 1.0, 1.5, 1000);
+        assert_eq!(file_stem(&burst), "10.0.0.1_443-10.0.0.2_51514");
+
+        let burst = Burst {
+            interface: Some("eth0".into()),
+            tid: Some(5),
+            ..Burst::test("[2001:db8::1]:443", "[2001:db8::2]:51514", 1.0, 1.5, 1000)
+        };
+        assert_eq!(
+            file_stem(&burst),
+            "eth0_2001.db8..1_443-2001.db8..2_51514_tid5"
+        );
+    }
+
+    #[test]
+    fn reopened_files_are_appended_to() {
+        let opts = options("split-reopen", 1, None);
+        let dir = opts.dir.clone();
+        let mut sink = Box::new(SplitSink::create(opts, OutputFormat::Csv).unwrap());
+        for i in 0..3 {
+            let start = i as f64;
+            sink.write(&Burst::test(
+                "10.0.0.1:443",
+                "10.0.0.2:50000",
+                start,
+                start,
+                1000,
+            ));
+            sink.write(&Burst::test(
+                "10.0.0.1:443",
+                "10.0.0.3:50000",
+                start,
+                start,
+                1000,
+            ));
+        }
+        sink.finish();
+        // A header and three bursts each
+        assert_eq!(
+            files(&dir),
+            [
+                ("10.0.0.1_443-10.0.0.2_50000.csv".to_string(), 4),
+                ("10.0.0.1_443-10.0.0.3_50000.csv".to_string(), 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn large_files_are_rotated() {
+        let opts = options("split-rotate", 16, Some(1));
+        let dir = opts.dir.clone();
+        let mut sink = Box::new(SplitSink::create(opts, OutputFormat::Jsonl).unwrap());
+        for i in 0..3 {
+            let start = i as f64;
+            sink.write(&Burst::test(
+                "10.0.0.1:443",
+                "10.0.0.2:50000",
+                start,
+                start,
+                1000,
+            ));
+        }
+        sink.finish();
+        assert_eq!(
+            files(&dir),
+            [
+                ("10.0.0.1_443-10.0.0.2_50000.1.jsonl".to_string(), 1),
+                ("10.0.0.1_443-10.0.0.2_50000.2.jsonl".to_string(), 1),
+                ("10.0.0.1_443-10.0.0.2_50000.jsonl".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn json_arrays_are_refused() {
+        let opts = options("split-json", 16, None);
+        assert!(SplitSink::create(opts, OutputFormat::Json).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use super::sink::BurstSink;
+use crate::{burstdb, capture::Burst, BurstsharkError};
+
+/// Bursts inserted in one transaction, unless the commit interval passes first
+const TRANSACTION_BURSTS: usize = 1000;
+
+/// Longest time since the transaction began after which it is committed with the next burst
+const COMMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A burst as the record of a JSON line, so stored bursts can be read as burst logs
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StoredRecord<'a> {
+    Burst(&'a Burst),
+}
+
+/// Inserts bursts into the table of a SQLite database.
+///
+/// Bursts are inserted in transactions, which are committed after a thousand bursts, with the
+/// first burst a second after they began, and when the capture ends. Bursts of an open transaction
+/// can not be queried yet.
+pub struct SqliteSink {
+    path: String,
+    conn: Option<Connection>,
+    /// When the open transaction began, with its number of bursts
+    transaction: Option<(Instant, usize)>,
+}
+
+impl SqliteSink {
+    pub fn create(path: &str) -> Result<Self, BurstsharkError> {
+        Ok(SqliteSink {
+            path: path.to_string(),
+            conn: Some(burstdb::create(path)?),
+            transaction: None,
+        })
+    }
+
+    fn insert(&mut self, burst: &Burst) -> Result<(), BurstsharkError> {
+        let Some(conn) = &self.conn else {
+            return Ok(());
+        };
+        let (began, bursts) = match self.transaction {
+            Some(transaction) => transaction,
+            None => {
+                conn.execute_batch("BEGIN")?;
+                (Instant::now(), 0)
+            }
+        };
+        conn.prepare_cached(burstdb::INSERT)?.execute(params![
+            burst.completion_time,
+            &*burst.src,
+            burst.src_port,
+            &*burst.dst,
+            burst.dst_port,
+            burst.start,
+            burst.end,
+            burst.num_packets,
+            burst.size,
+            burst.proto.map(|proto| proto.to_string()),
+            burst.interface.as_deref(),
+            burst.probe.probe_id.as_deref(),
+            serde_json::to_string(&StoredRecord::Burst(burst))?,
+        ])?;
+
+        self.transaction = Some((began, bursts + 1));
+        if bursts + 1 >= TRANSACTION_BURSTS || began.elapsed() >= COMMIT_INTERVAL {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), BurstsharkError> {
+        if let (Some(conn), Some(_)) = (&self.conn, self.transaction.take()) {
+            conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+}
+
+impl BurstSink for SqliteSink {
+    fn write(&mut self, burst: &Burst) {
+        if let Err(e) = self.insert(burst) {
+            eprintln!("Error writing bursts to {}: {}", self.path, e);
+            self.conn = None;
+        }
+    }
+
+    fn finish(mut self: Box<Self>) {
+        if let Err(e) = self.commit() {
+            eprintln!("Error writing bursts to {}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::SqliteSink;
+    use crate::{
+        burstdb::{self, BurstQuery},
+        capture::Burst,
+        output::sink::BurstSink,
+    };
+
+    #[test]
+    fn bursts_are_stored_with_their_json_record() {
+        let path = std::env::temp_dir().join(format!("burstshark-sqlite-{}.db", process::id()));
+        let path = path.to_string_lossy().into_owned();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{path}{suffix}"));
+        }
+
+        let mut sink = Box::new(SqliteSink::create(&path).unwrap());
+        sink.write(&Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            1.0,
+            1.5,
+            1000,
+        ));
+        // Uncommitted bursts can not be queried yet
+        let conn = burstdb::open(&path).unwrap();
+        assert!(burstdb::bursts(&conn, &BurstQuery::default())
+            .unwrap()
+            .is_empty());
+        sink.write(&Burst::test(
+            "[2001:db8::1]:443",
+            "[2001:db8::2]:50000",
+            2.0,
+            2.5,
+            500,
+        ));
+        sink.finish();
+
+        let stored = burstdb::bursts(&conn, &BurstQuery::default()).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            (&*stored[0].src, stored[0].src_port),
+            ("10.0.0.1", Some(443))
+        );
+        assert_eq!((stored[1].start, stored[1].size), (2.0, 500));
+        // Records can be read as lines of burst logs
+        let record: serde_json::Value = serde_json::from_str(&stored[0].record).unwrap();
+        assert_eq!(record["type"], "burst");
+        assert_eq!(record["size"], 1000);
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, IsTerminal},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use nix::sys::signal::{self, Signal};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Cell, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+
+use super::sink::BurstSink;
+use crate::{capture::Burst, BurstsharkError};
+
+/// Longest time between redraws, and between checks for pressed keys
+const TICK: Duration = Duration::from_millis(250);
+
+/// Seconds of the capture the throughput of flows is averaged over
+const THROUGHPUT_WINDOW: f64 = 10.0;
+
+/// Seconds of the capture after their last burst that flows are no longer shown
+const ACTIVE_TIME: f64 = 60.0;
+
+/// Recent bursts drawn in the sparkline of each flow
+const SPARKLINE_BURSTS: usize = 24;
+
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A live table of the active flows in the terminal, in place of the bursts on the standard output.
+///
+/// The interface runs on a thread of its own, which draws the bursts it receives and handles the
+/// keys. Quitting interrupts the capture as ctrl-c would.
+pub struct Tui {
+    tx: Sender<Burst>,
+    handle: JoinHandle<()>,
+}
+
+impl Tui {
+    pub fn start() -> Result<Self, BurstsharkError> {
+        if !io::stdout().is_terminal() {
+            return Err(BurstsharkError::InvalidOptions(
+                "The terminal interface needs the standard output to be a terminal".to_string(),
+            ));
+        }
+        let terminal = ratatui::try_init()?;
+        let (tx, rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let result = FlowView::default().run(terminal, rx);
+            ratatui::restore();
+            if let Err(e) = result {
+                eprintln!("Error in the terminal interface: {}", e);
+            }
+        });
+
+        Ok(Tui { tx, handle })
+    }
+}
+
+impl BurstSink for Tui {
+    fn write(&mut self, burst: &Burst) {
+        let _ = self.tx.send(burst.clone());
+    }
+
+    /// Keep showing the flows after the capture ended, until the interface is quit
+    fn finish(self: Box<Self>) {
+        let Tui { tx, handle } = *self;
+        drop(tx);
+        let _ = handle.join();
+    }
+}
+
+/// Flows are identified by their addresses and ports
+type FlowKey = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+struct FlowState {
+    bursts: u64,
+    bytes: u64,
+    last_time: f64,
+    /// Completion time and size of the bursts within the throughput window
+    window: VecDeque<(f64, u32)>,
+    /// Sizes of the last bursts, for the sparkline
+    sizes: VecDeque<u32>,
+}
+
+impl FlowState {
+    fn throughput(&self) -> f64 {
+        self.window
+            .iter()
+            .map(|(_, size)| *size as f64)
+            .sum::<f64>()
+            / THROUGHPUT_WINDOW
+    }
+}
+
+/// What the interface shows, and the state of its keys
+#[derive(Default)]
+struct FlowView {
+    flows: HashMap<FlowKey, FlowState>,
+    bursts: u64,
+    /// Completion time of the latest burst, which is the time of the capture
+    now: f64,
+    /// Show the same rows until resumed, while bursts keep being counted
+    paused: bool,
+    rows: Vec<(FlowKey, f64)>,
+    /// Only show flows with an address or port containing the filter
+    filter: String,
+    /// Whether keys are typed into the filter
+    editing: bool,
+    /// Whether the capture ended
+    ended: bool,
+    table: TableState,
+}
+
+impl FlowView {
+    fn run(mut self, mut terminal: DefaultTerminal, rx: Receiver<Burst>) -> io::Result<()> {
+        loop {
+            while !self.ended {
+                match rx.try_recv() {
+                    Ok(burst) => self.add(burst),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => self.ended = true,
+                }
+            }
+
+            if !self.paused {
+                self.update_rows();
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    let interrupt = key.modifiers.contains(KeyModifiers::CONTROL)
+                        && key.code == KeyCode::Char('c');
+                    if self.editing && !interrupt {
+                        self.edit_filter(key.code);
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => break,
+                        _ if interrupt => break,
+                        KeyCode::Char('p') | KeyCode::Char(' ') => self.paused = !self.paused,
+                        KeyCode::Char('/') => self.editing = true,
+                        KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+                        KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // Stop the capture as ctrl-c would, which the raw terminal no longer turns into a signal
+        if !self.ended {
+            let _ = signal::raise(Signal::SIGINT);
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, burst: Burst) {
+        self.bursts += 1;
+        self.now = self.now.max(burst.completion_time);
+        let flow = self
+            .flows
+            .entry((burst.src, burst.src_port, burst.dst, burst.dst_port))
+            .or_insert_with(|| FlowState {
+                bursts: 0,
+                bytes: 0,
+                last_time: burst.completion_time,
+                window: VecDeque::new(),
+                sizes: VecDeque::new(),
+            });
+        flow.bursts += 1;
+        flow.bytes += burst.size as u64;
+        flow.last_time = burst.completion_time;
+        flow.window.push_back((burst.completion_time, burst.size));
+        flow.sizes.push_back(burst.size);
+        if flow.sizes.len() > SPARKLINE_BURSTS {
+            flow.sizes.pop_front();
+        }
+    }
+
+    /// Drop the flows that are no longer active, and sort the rest by throughput
+    fn update_rows(&mut self) {
+        let now = self.now;
+        self.flows
+            .retain(|_, flow| now - flow.last_time <= ACTIVE_TIME);
+        for flow in self.flows.values_mut() {
+            while flow
+                .window
+                .front()
+                .is_some_and(|(time, _)| now - time > THROUGHPUT_WINDOW)
+            {
+                flow.window.pop_front();
+            }
+        }
+
+        self.rows = self
+            .flows
+            .iter()
+            .filter(|(key, _)| self.matches(key))
+            .map(|(key, flow)| (key.clone(), flow.throughput()))
+            .collect();
+        self.rows.sort_by(|a, b| b.1.total_cmp(&a.1));
+    }
+
+    fn matches(&self, (src, src_port, dst, dst_port): &FlowKey) -> bool {
+        let port = |port: &Option<u16>| port.is_some_and(|p| p.to_string().contains(&self.filter));
+        self.filter.is_empty()
+            || src.contains(&self.filter)
+            || dst.contains(&self.filter)
+            || port(src_port)
+            || port(dst_port)
+    }
+
+    fn edit_filter(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Enter => self.editing = false,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.editing = false;
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+            }
+            KeyCode::Char(c) => self.filter.push(c),
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, table, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let mut status = format!(
+            " BurstShark  {:.3} s  {} flows  {} bursts",
+            self.now,
+            self.flows.len(),
+            self.bursts
+        );
+        if self.paused {
+            status.push_str("  [paused]");
+        }
+        if self.ended {
+            status.push_str("  [capture ended]");
+        }
+        frame.render_widget(
+            Paragraph::new(status).style(Style::new().add_modifier(Modifier::REVERSED)),
+            header,
+        );
+
+        let rows = self.rows.iter().filter_map(|(key, throughput)| {
+            let flow = self.flows.get(key)?;
+            let (src, src_port, dst, dst_port) = key;
+            Some(Row::new(vec![
+                Cell::from(endpoint(src, *src_port)),
+                Cell::from(endpoint(dst, *dst_port)),
+                Cell::from(flow.bursts.to_string()),
+                Cell::from(flow.bytes.to_string()),
+                Cell::from(format!("{:.0}", throughput)),
+                Cell::from(sparkline(&flow.sizes)),
+            ]))
+        });
+        let widths = [
+            Constraint::Min(22),
+            Constraint::Min(22),
+            Constraint::Length(8),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(SPARKLINE_BURSTS as u16),
+        ];
+        let table_widget = Table::new(rows, widths)
+            .header(
+                Row::new(vec![
+                    "Source",
+                    "Destination",
+                    "Bursts",
+                    "Bytes",
+                    "Bytes/s",
+                    "Recent bursts",
+                ])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+            )
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .block(Block::bordered());
+        frame.render_stateful_widget(table_widget, table, &mut self.table);
+
+        let help = match self.editing {
+            true => format!(" Filter: {}_  (enter to apply, esc to clear)", self.filter),
+            false if !self.filter.is_empty() => format!(
+                " Filter: {}  q quit  p pause  / filter  up/down select",
+                self.filter
+            ),
+            false => " q quit  p pause  / filter  up/down select".to_string(),
+        };
+        frame.render_widget(Line::from(help), footer);
+    }
+}
+
+fn endpoint(addr: &str, port: Option<u16>) -> String {
+    match port {
+        Some(port) => format!("{addr} {port}"),
+        None => addr.to_string(),
+    }
+}
+
+/// Bars of the sizes relative to the largest
+fn sparkline(sizes: &VecDeque<u32>) -> String {
+    let max = sizes.iter().copied().max().unwrap_or(0).max(1) as u64;
+    sizes
+        .iter()
+        .map(|&size| SPARKLINE_BARS[(size as u64 * 7 / max) as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use ratatui::crossterm::event::KeyCode;
+
+    use super::{endpoint, sparkline, FlowView};
+    use crate::capture::Burst;
+
+    /// The source address and port of each row
+    fn rows(view: &FlowView) -> Vec<(String, Option<u16>)> {
+        view.rows
+            .iter()
+            .map(|((src, src_port, _, _), _)| (src.to_string(), *src_port))
+            .collect()
+    }
+
+    #[test]
+    fn active_flows_are_sorted_by_throughput() {
+        let mut view = FlowView::default();
+        view.add(Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            0.0,
+            0.5,
+            1000,
+        ));
+        view.add(Burst::test("10.0.0.3:53", "10.0.0.2:50001", 1.0, 1.5, 300));
+        view.add(Burst::test("10.0.0.3:53", "10.0.0.2:50001", 2.0, 2.5, 900));
+        view.update_rows();
+        assert_eq!(
+            rows(&view),
+            [
+                ("10.0.0.3".to_string(), Some(53)),
+                ("10.0.0.1".to_string(), Some(443))
+            ]
+        );
+        assert_eq!(view.rows[0].1, 120.0);
+        assert_eq!(view.bursts, 3);
+
+        // Flows without bursts for a minute are dropped, and old bursts leave the throughput
+        view.add(Burst::test(
+            "10.0.0.3:53",
+            "10.0.0.2:50001",
+            61.0,
+            61.0,
+            100,
+        ));
+        view.update_rows();
+        assert_eq!(rows(&view), [("10.0.0.3".to_string(), Some(53))]);
+        assert_eq!(view.rows[0].1, 10.0);
+    }
+
+    #[test]
+    fn flows_are_filtered_by_address_or_port() {
+        let mut view = FlowView::default();
+        view.add(Burst::test(
+            "10.0.0.1:443",
+            "10.0.0.2:50000",
+            0.0,
+            0.5,
+            1000,
+        ));
+        view.add(Burst::test("10.0.0.3:53", "10.0.0.2:50001", 1.0, 1.5, 300));
+        for c in "4433".chars() {
+            view.edit_filter(KeyCode::Char(c));
+        }
+        view.edit_filter(KeyCode::Backspace);
+        view.update_rows();
+        assert_eq!(rows(&view), [("10.0.0.1".to_string(), Some(443))]);
+
+        view.edit_filter(KeyCode::Esc);
+        assert!(view.filter.is_empty() && !view.editing);
+        view.update_rows();
+        assert_eq!(view.rows.len(), 2);
+    }
+
+    #[test]
+    fn sizes_are_drawn_relative_to_the_largest() {
+        assert_eq!(sparkline(&VecDeque::from([0, 50, 100])), "▁▄█");
+        assert_eq!(sparkline(&VecDeque::new()), "");
+        assert_eq!(endpoint("10.0.0.1", Some(443)), "10.0.0.1 443");
+        assert_eq!(endpoint("02:00:00:00:00:01", None), "02:00:00:00:00:01");
+    }
+}
@@ -0,0 +1,276 @@
+//! Stages that rework the stream of completed bursts before they are annotated and output.
+//!
+//! Unlike the annotations, a stage may hold bursts back, merge them or release several at once,
+//! so each takes bursts in and hands out those that are ready. Stages are chained in a
+//! [`Pipeline`], where the bursts handed out by one are taken in by the next.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    sync::Arc,
+};
+
+use crate::capture::Burst;
+
+/// A stage of the pipeline
+pub trait BurstStage: Send {
+    /// Take in a completed burst, and hand out the bursts that are ready to `out`
+    fn process(&mut self, burst: Burst, out: &mut Vec<Burst>);
+
+    /// Hand out the bursts still held back, as the capture has ended
+    fn finish(&mut self, out: &mut Vec<Burst>);
+}
+
+/// Stages run in order on each burst
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn BurstStage>>,
+    /// Bursts handed out by the previous stage, to reuse its allocation
+    scratch: Vec<Burst>,
+}
+
+impl Pipeline {
+    pub fn push(&mut self, stage: impl BurstStage + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    /// Run a burst through the stages, handing out the bursts that come out of the last one
+    pub fn process(&mut self, burst: Burst, out: &mut Vec<Burst>) {
+        out.push(burst);
+        for stage in &mut self.stages {
+            std::mem::swap(out, &mut self.scratch);
+            for burst in self.scratch.drain(..) {
+                stage.process(burst, out);
+            }
+        }
+    }
+
+    /// Hand out the bursts held back by each stage, running them through the stages after it
+    pub fn finish(&mut self, out: &mut Vec<Burst>) {
+        for stage in &mut self.stages {
+            std::mem::swap(out, &mut self.scratch);
+            for burst in self.scratch.drain(..) {
+                stage.process(burst, out);
+            }
+            stage.finish(out);
+        }
+    }
+}
+
+/// Source and destination address and port of a burst, its interface and its TID
+type FlowKey = (
+    Arc<str>,
+    Option<u16>,
+    Arc<str>,
+    Option<u16>,
+    Option<Arc<str>>,
+    Option<u8>,
+);
+
+/// Merges consecutive bursts of a flow with less than the merge gap between them, such as a burst
+/// split in two by a packet straggling just past the inactive time.
+///
+/// The last burst of each flow is held back until the next one of the flow arrives, or until the
+/// capture is the merge gap past the completion of the burst without one. A following burst still
+/// in progress by then is not merged with it.
+pub struct MergeGap {
+    gap: f64,
+    held: HashMap<FlowKey, Burst>,
+    /// When each held burst is due to be released, in the order they were held
+    releases: VecDeque<(f64, FlowKey)>,
+    /// Latest completion time of the bursts so far
+    now: f64,
+}
+
+impl MergeGap {
+    pub fn new(gap: f64) -> Self {
+        MergeGap {
+            gap,
+            held: HashMap::new(),
+            releases: VecDeque::new(),
+            now: f64::MIN,
+        }
+    }
+
+    /// Release the held bursts whose merge gap has passed
+    fn release(&mut self, out: &mut Vec<Burst>) {
+        while let Some((due, _)) = self.releases.front() {
+            if *due > self.now {
+                break;
+            }
+            let (_, key) = self.releases.pop_front().unwrap();
+            // Bursts merged since they were queued are due later, and queued again
+            if let Entry::Occupied(held) = self.held.entry(key) {
+                if held.get().completion_time + self.gap <= self.now {
+                    out.push(held.remove());
+                }
+            }
+        }
+    }
+}
+
+impl BurstStage for MergeGap {
+    fn process(&mut self, burst: Burst, out: &mut Vec<Burst>) {
+        self.now = self.now.max(burst.completion_time);
+        let key = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+            burst.interface.clone(),
+            burst.tid,
+        );
+        let due = burst.completion_time + self.gap;
+        match self.held.entry(key.clone()) {
+            Entry::Occupied(mut held)
+                if burst.start - held.get().end < self.gap && fits(held.get(), &burst) =>
+            {
+                merge(held.get_mut(), burst)
+            }
+            Entry::Occupied(mut held) => out.push(std::mem::replace(held.get_mut(), burst)),
+            Entry::Vacant(entry) => {
+                entry.insert(burst);
+            }
+        }
+        self.releases.push_back((due, key));
+        self.release(out);
+    }
+
+    fn finish(&mut self, out: &mut Vec<Burst>) {
+        let mut held: Vec<Burst> = self.held.drain().map(|(_, burst)| burst).collect();
+        held.sort_by(|a, b| {
+            a.completion_time
+                .total_cmp(&b.completion_time)
+                .then(a.start.total_cmp(&b.start))
+        });
+        out.extend(held);
+        self.releases.clear();
+    }
+}
+
+/// Whether the packets of a burst and the one following it fit in a burst
+fn fits(burst: &Burst, next: &Burst) -> bool {
+    burst.num_packets.checked_add(next.num_packets).is_some()
+        && burst.size.checked_add(next.size).is_some()
+}
+
+/// Extend a burst with the one following it
+fn merge(burst: &mut Burst, next: Burst) {
+    let sum = |a: Option<u32>, b: Option<u32>| match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_add(b)),
+        (a, b) => a.or(b),
+    };
+
+    burst.detail = match (burst.detail, next.detail) {
+        (Some(a), Some(b)) => Some(a.merge(
+            burst.num_packets,
+            &b,
+            next.num_packets,
+            next.start - burst.end,
+        )),
+        (a, b) => a.or(b),
+    };
+    let offset = next.start - burst.start;
+    burst
+        .packets
+        .extend(next.packets.into_iter().map(|mut packet| {
+            packet.offset = ((packet.offset + offset) * 1e9).round() / 1e9;
+            packet
+        }));
+    burst.num_packets += next.num_packets;
+    burst.size += next.size;
+    burst.end = next.end;
+    burst.completion_time = next.completion_time;
+    burst.tunnel_overhead = sum(burst.tunnel_overhead, next.tunnel_overhead);
+    burst.retransmitted_bytes = sum(burst.retransmitted_bytes, next.retransmitted_bytes);
+    burst.airtime = match (burst.airtime, next.airtime) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    };
+    for channel in next.channels {
+        if let Err(i) = burst.channels.binary_search(&channel) {
+            burst.channels.insert(i, channel);
+        }
+    }
+    burst.errors.extend(next.errors);
+    burst.alerts.extend(next.alerts);
+    burst.truncated = next.truncated;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BurstStage, MergeGap, Pipeline};
+    use crate::capture::Burst;
+
+    fn a(start: f64, end: f64) -> Burst {
+        Burst::test("10.0.0.1:50000", "10.0.0.2:443", start, end, 1000)
+    }
+
+    fn b(start: f64, end: f64) -> Burst {
+        Burst::test("10.0.0.3:50000", "10.0.0.2:443", start, end, 1000)
+    }
+
+    /// Start, end and size of bursts
+    type Times = Vec<(f64, f64, u32)>;
+
+    /// Run the bursts through a merge gap of 0.2 seconds, returning the times of those handed out
+    /// before the capture ended, and of those handed out after
+    fn merged(bursts: Vec<Burst>) -> (Times, Times) {
+        let mut pipeline = Pipeline::default();
+        pipeline.push(MergeGap::new(0.2));
+        let times = |bursts: Vec<Burst>| {
+            bursts
+                .into_iter()
+                .map(|burst| (burst.start, burst.end, burst.size))
+                .collect()
+        };
+        let mut out = Vec::new();
+        for burst in bursts {
+            pipeline.process(burst, &mut out);
+        }
+        let released = times(std::mem::take(&mut out));
+        pipeline.finish(&mut out);
+        (released, times(out))
+    }
+
+    #[test]
+    fn bursts_within_the_gap_are_merged() {
+        let (released, finished) = merged(vec![a(0.0, 1.0), a(1.1, 2.0), b(2.4, 2.5)]);
+        assert_eq!(released, [(0.0, 2.0, 2000)]);
+        assert_eq!(finished, [(2.4, 2.5, 1000)]);
+    }
+
+    #[test]
+    fn bursts_beyond_the_gap_are_not_merged() {
+        let (released, finished) = merged(vec![a(0.0, 1.0), a(1.5, 2.0)]);
+        assert_eq!(released, [(0.0, 1.0, 1000)]);
+        assert_eq!(finished, [(1.5, 2.0, 1000)]);
+    }
+
+    #[test]
+    fn flows_are_merged_apart() {
+        let (released, finished) = merged(vec![a(0.0, 1.0), b(1.05, 1.1), a(1.15, 1.2)]);
+        assert_eq!(released, []);
+        assert_eq!(finished, [(1.05, 1.1, 1000), (0.0, 1.2, 2000)]);
+    }
+
+    #[test]
+    fn merged_bursts_add_up_their_annotations() {
+        let mut merge_gap = MergeGap::new(0.2);
+        let mut out = Vec::new();
+        for (start, channels) in [(0.0, vec![6, 36]), (1.1, vec![1, 36])] {
+            let burst = Burst {
+                num_packets: 2,
+                retransmitted_bytes: Some(100),
+                channels,
+                ..a(start, start + 1.0)
+            };
+            merge_gap.process(burst, &mut out);
+        }
+        merge_gap.finish(&mut out);
+        let burst = &out[0];
+        assert_eq!((burst.num_packets, burst.size), (4, 2000));
+        assert_eq!(burst.retransmitted_bytes, Some(200));
+        assert_eq!(burst.channels, [1, 6, 36]);
+        assert_eq!(burst.completion_time, 2.1);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    hash::BuildHasher,
+    net::IpAddr,
+};
+
+use clap::ValueEnum;
+use macaddr::MacAddr;
+use serde::Serialize;
+
+use crate::{capture::Burst, probe::ProbeIdentity};
+
+/// How addresses are made anonymous in privacy mode
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum PrivacyMode {
+    /// A hash keyed anew for every run, which tells hosts apart but not who they are.
+    Hash,
+
+    /// The network only, the /24 of IPv4, the /48 of IPv6 and the vendor of MAC addresses.
+    Truncate,
+}
+
+/// Flow of anonymous addresses and ports
+type FlowKey = (String, Option<u16>, String, Option<u16>);
+
+/// Aggregated bursts of an anonymous flow over an interval, shared instead of the bursts
+#[derive(Debug, Clone, Serialize)]
+pub struct BurstHistogram {
+    /// End of the interval, which started `interval` seconds earlier
+    pub time: f64,
+    pub interval: f64,
+    pub src: String,
+    pub src_port: Option<u16>,
+    pub dst: String,
+    pub dst_port: Option<u16>,
+    pub bursts: u64,
+    pub bytes: u64,
+    /// Number of bursts by the smallest size of their bucket, with buckets of sizes up to twice that
+    pub sizes: BTreeMap<u32, u64>,
+    /// Id and tags of the probe of the bursts
+    #[serde(flatten)]
+    pub probe: ProbeIdentity,
+}
+
+/// Replaces the bursts of each flow with histograms of their sizes per interval of time, with the
+/// addresses of the flow made anonymous.
+pub struct PrivacyHistograms {
+    mode: PrivacyMode,
+    keys: RandomState,
+    interval: f64,
+    /// End of the current interval, set by the first burst
+    end: Option<f64>,
+    flows: HashMap<FlowKey, BurstHistogram>,
+}
+
+impl PrivacyHistograms {
+    pub fn new(mode: PrivacyMode, interval: f64) -> Self {
+        PrivacyHistograms {
+            mode,
+            keys: RandomState::new(),
+            interval,
+            end: None,
+            flows: HashMap::new(),
+        }
+    }
+
+    /// Add a burst, returning the histograms of the intervals it completed after
+    pub fn add(&mut self, burst: &Burst) -> Vec<BurstHistogram> {
+        let mut finished = Vec::new();
+        let end = *self
+            .end
+            .get_or_insert(burst.completion_time + self.interval);
+        if burst.completion_time >= end {
+            finished = self.take(end);
+            // Skip the intervals without bursts
+            let skipped = ((burst.completion_time - end) / self.interval).floor();
+            self.end = Some(end + (skipped + 1.0) * self.interval);
+        }
+
+        let src = self.anonymize(&burst.src);
+        let dst = self.anonymize(&burst.dst);
+        let end = self.end.unwrap();
+        let histogram = self
+            .flows
+            .entry((src.clone(), burst.src_port, dst.clone(), burst.dst_port))
+            .or_insert_with(|| BurstHistogram {
+                time: end,
+                interval: self.interval,
+                src,
+                src_port: burst.src_port,
+                dst,
+                dst_port: burst.dst_port,
+                bursts: 0,
+                bytes: 0,
+                sizes: BTreeMap::new(),
+                probe: burst.probe.clone(),
+            });
+        histogram.bursts += 1;
+        histogram.bytes += burst.size as u64;
+        *histogram.sizes.entry(size_bucket(burst.size)).or_default() += 1;
+
+        finished
+    }
+
+    /// The histograms of the last interval, as the capture ended
+    pub fn finish(&mut self) -> Vec<BurstHistogram> {
+        match self.end {
+            Some(end) => self.take(end),
+            None => Vec::new(),
+        }
+    }
+
+    /// Take the histograms of the interval ending at the time, ordered by flow
+    fn take(&mut self, end: f64) -> Vec<BurstHistogram> {
+        let mut histograms: Vec<_> = self
+            .flows
+            .drain()
+            .map(|(_, mut histogram)| {
+                histogram.time = end;
+                histogram
+            })
+            .collect();
+        histograms.sort_by(|a, b| {
+            (&a.src, a.src_port, &a.dst, a.dst_port).cmp(&(&b.src, b.src_port, &b.dst, b.dst_port))
+        });
+        histograms
+    }
+
+    fn anonymize(&self, addr: &str) -> String {
+        match (self.mode, addr.parse::<IpAddr>(), addr.parse::<MacAddr>()) {
+            (PrivacyMode::Truncate, Ok(IpAddr::V4(ip)), _) => {
+                let [a, b, c, _] = ip.octets();
+                format!("{a}.{b}.{c}.0/24")
+            }
+            (PrivacyMode::Truncate, Ok(IpAddr::V6(ip)), _) => {
+                let segments = ip.segments();
+                format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+            }
+            (PrivacyMode::Truncate, _, Ok(MacAddr::V6(mac))) => {
+                let [a, b, c, ..] = mac.into_array();
+                format!("{a:02x}:{b:02x}:{c:02x}:00:00:00")
+            }
+            _ => format!("{:016x}", self.keys.hash_one(addr)),
+        }
+    }
+}
+
+/// Smallest size of the power of two bucket of a burst size
+fn size_bucket(size: u32) -> u32 {
+    match size {
+        0 => 0,
+        size => 1 << size.ilog2(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{size_bucket, PrivacyHistograms, PrivacyMode};
+    use crate::capture::Burst;
+
+    fn burst(completion_time: f64, size: u32) -> Burst {
+        Burst::test(
+            "192.168.1.23:50000",
+            "10.0.0.2:443",
+            completion_time,
+            completion_time,
+            size,
+        )
+    }
+
+    #[test]
+    fn sizes_are_bucketed_by_powers_of_two() {
+        let buckets: Vec<u32> = [0, 1, 2, 3, 1000, 1024, 2047].map(size_bucket).to_vec();
+        assert_eq!(buckets, [0, 1, 2, 2, 512, 1024, 1024]);
+    }
+
+    #[test]
+    fn truncated_addresses_keep_only_the_network() {
+        let histograms = PrivacyHistograms::new(PrivacyMode::Truncate, 1.0);
+        assert_eq!(histograms.anonymize("192.168.1.23"), "192.168.1.0/24");
+        assert_eq!(
+            histograms.anonymize("2001:db8:1234:5678::1"),
+            "2001:db8:1234::/48"
+        );
+        assert_eq!(
+            histograms.anonymize("00:1b:63:84:45:e6"),
+            "00:1b:63:00:00:00"
+        );
+    }
+
+    #[test]
+    fn hashed_addresses_still_tell_hosts_apart() {
+        let histograms = PrivacyHistograms::new(PrivacyMode::Hash, 1.0);
+        let host = histograms.anonymize("192.168.1.23");
+        assert_eq!(host, histograms.anonymize("192.168.1.23"));
+        assert_ne!(host, histograms.anonymize("192.168.1.24"));
+        assert!(!host.contains("192.168"));
+    }
+
+    #[test]
+    fn bursts_are_replaced_by_histograms_per_interval() {
+        let mut histograms = PrivacyHistograms::new(PrivacyMode::Truncate, 1.0);
+        assert!(histograms.add(&burst(0.0, 100)).is_empty());
+        assert!(histograms.add(&burst(0.5, 120)).is_empty());
+        assert!(histograms.add(&burst(0.9, 3000)).is_empty());
+
+        let finished = histograms.add(&burst(3.2, 50));
+        assert_eq!(finished.len(), 1);
+        let histogram = &finished[0];
+        assert_eq!(
+            (histogram.time, histogram.bursts, histogram.bytes),
+            (1.0, 3, 3220)
+        );
+        assert_eq!(histogram.src, "192.168.1.0/24");
+        assert_eq!(
+            histogram.sizes.iter().collect::<Vec<_>>(),
+            [(&64, &2), (&2048, &1)]
+        );
+
+        // The empty intervals are skipped
+        let last = histograms.finish();
+        assert_eq!((last[0].time, last[0].bursts), (4.0, 1));
+    }
+}
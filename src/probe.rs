@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::Serialize;
+
+use crate::BurstsharkError;
+
+/// Tags of a probe by their keys
+pub type Tags = BTreeMap<String, String>;
+
+/// Id and tags of this instance of BurstShark, attached to the records it outputs so that
+/// collectors of many probes can tell them apart and group them.
+///
+/// Both are shared by all records, so attaching them does not allocate.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProbeIdentity {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe_id: Option<Arc<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Arc<Tags>>,
+}
+
+impl ProbeIdentity {
+    /// An identity with an optional id and tags given as `key=value`
+    pub fn new(probe_id: Option<&str>, tags: &[String]) -> Result<Self, BurstsharkError> {
+        let mut parsed = Tags::new();
+        for tag in tags {
+            let Some((key, value)) = tag.split_once('=').filter(|(key, _)| !key.is_empty()) else {
+                return Err(BurstsharkError::InvalidOptions(format!(
+                    "Invalid tag {tag}, expected key=value"
+                )));
+            };
+            if parsed.insert(key.to_string(), value.to_string()).is_some() {
+                return Err(BurstsharkError::InvalidOptions(format!(
+                    "Tag {key} is given more than once"
+                )));
+            }
+        }
+
+        Ok(ProbeIdentity {
+            probe_id: probe_id.map(Arc::from),
+            tags: (!parsed.is_empty()).then(|| Arc::new(parsed)),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probe_id.is_none() && self.tags.is_none()
+    }
+
+    /// The tags as `key=value`, separated by `separator`
+    pub fn join_tags(&self, separator: &str) -> String {
+        self.tags
+            .iter()
+            .flat_map(|tags| tags.iter())
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProbeIdentity;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn tags_are_sorted_by_key() {
+        let identity =
+            ProbeIdentity::new(Some("ap-3"), &tags(&["site=lab", "floor=2", "note=a=b"])).unwrap();
+        assert_eq!(identity.probe_id.as_deref(), Some("ap-3"));
+        assert_eq!(identity.join_tags(","), "floor=2,note=a=b,site=lab");
+        assert_eq!(
+            serde_json::to_string(&identity).unwrap(),
+            r#"{"probe_id":"ap-3","tags":{"floor":"2","note":"a=b","site":"lab"}}"#
+        );
+    }
+
+    #[test]
+    fn identities_without_id_or_tags_are_empty() {
+        let identity = ProbeIdentity::new(None, &[]).unwrap();
+        assert!(identity.is_empty());
+        assert_eq!(identity.join_tags(","), "");
+        assert_eq!(serde_json::to_string(&identity).unwrap(), "{}");
+    }
+
+    #[test]
+    fn invalid_tags_are_refused() {
+        assert!(ProbeIdentity::new(None, &tags(&["site"])).is_err());
+        assert!(ProbeIdentity::new(None, &tags(&["=lab"])).is_err());
+        assert!(ProbeIdentity::new(None, &tags(&["site=lab", "site=home"])).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A stage of the capture pipeline
+#[derive(Clone, Copy, Debug)]
+pub enum Stage {
+    /// Parsing tshark lines into packets
+    Parse,
+
+    /// Handing packets over to the burst thread
+    Enqueue,
+
+    /// Adding packets to flows and creating bursts
+    Burstify,
+
+    /// Filtering and writing bursts
+    Output,
+}
+
+const STAGES: [(Stage, &str); 4] = [
+    (Stage::Parse, "parse"),
+    (Stage::Enqueue, "enqueue"),
+    (Stage::Burstify, "burstify"),
+    (Stage::Output, "output"),
+];
+
+#[derive(Default)]
+struct StageStats {
+    count: AtomicU64,
+    nanos: AtomicU64,
+    max_nanos: AtomicU64,
+
+    /// Maximum latency since the last periodic summary
+    interval_max_nanos: AtomicU64,
+}
+
+/// Snapshot of the cumulative counters of a stage
+#[derive(Clone, Copy, Default)]
+struct Snapshot {
+    count: u64,
+    nanos: u64,
+}
+
+/// Latency and throughput measurements of each pipeline stage
+#[derive(Default)]
+pub struct PipelineProfile {
+    stages: [StageStats; 4],
+}
+
+impl PipelineProfile {
+    /// Record that one item passed through a stage in the given time
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let stats = &self.stages[stage as usize];
+        let nanos = elapsed.as_nanos() as u64;
+        stats.count.fetch_add(1, Ordering::Relaxed);
+        stats.nanos.fetch_add(nanos, Ordering::Relaxed);
+        stats.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        stats.interval_max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> [Snapshot; 4] {
+        let mut snapshot = [Snapshot::default(); 4];
+        for (stats, snap) in self.stages.iter().zip(snapshot.iter_mut()) {
+            snap.count = stats.count.load(Ordering::Relaxed);
+            snap.nanos = stats.nanos.load(Ordering::Relaxed);
+        }
+        snapshot
+    }
+
+    /// Print a summary of the stages between two snapshots to stderr
+    fn print_summary(&self, total: bool, prev: &[Snapshot; 4], now: &[Snapshot; 4], secs: f64) {
+        let title = if total { "total" } else { "interval" };
+        eprintln!("[profile] {title} ({secs:.1} s)");
+        for ((_, name), (stats, (prev, now))) in STAGES
+            .iter()
+            .zip(self.stages.iter().zip(prev.iter().zip(now.iter())))
+        {
+            let count = now.count - prev.count;
+            let nanos = now.nanos - prev.nanos;
+            let mean_us = if count > 0 {
+                nanos as f64 / count as f64 / 1000.0
+            } else {
+                0.0
+            };
+            let max_nanos = if total {
+                stats.max_nanos.load(Ordering::Relaxed)
+            } else {
+                stats.interval_max_nanos.swap(0, Ordering::Relaxed)
+            };
+            let max_us = max_nanos as f64 / 1000.0;
+            let busy = nanos as f64 / 1e9 / secs.max(f64::EPSILON) * 100.0;
+            eprintln!(
+                "[profile] {:9} {:10} items {:12.1} items/s  mean {:9.3} us  max {:9.3} us  busy {:5.1}%",
+                name,
+                count,
+                count as f64 / secs.max(f64::EPSILON),
+                mean_us,
+                max_us,
+                busy,
+            );
+        }
+    }
+}
+
+/// Time a closure as one item of a stage, if profiling is enabled
+pub fn time<T>(profile: &Option<Arc<PipelineProfile>>, stage: Stage, f: impl FnOnce() -> T) -> T {
+    match profile {
+        Some(profile) => {
+            let start = Instant::now();
+            let result = f();
+            profile.record(stage, start.elapsed());
+            result
+        }
+        None => f(),
+    }
+}
+
+/// Periodically prints summaries of a pipeline profile, and a total summary when stopped
+pub struct ProfileReporter {
+    stop_tx: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ProfileReporter {
+    pub fn start(profile: Arc<PipelineProfile>, interval: Duration) -> Self {
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let first = profile.snapshot();
+            let mut prev = first;
+            let mut prev_time = start;
+
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let now = profile.snapshot();
+                let secs = prev_time.elapsed().as_secs_f64();
+                profile.print_summary(false, &prev, &now, secs);
+                prev = now;
+                prev_time = Instant::now();
+            }
+
+            let now = profile.snapshot();
+            profile.print_summary(true, &first, &now, start.elapsed().as_secs_f64());
+        });
+
+        ProfileReporter {
+            stop_tx: Some(stop_tx),
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the periodic summaries and print the total summary
+    pub fn stop(&mut self) {
+        self.stop_tx.take();
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+
+    use super::{time, PipelineProfile, Stage};
+
+    #[test]
+    fn items_are_counted_per_stage() {
+        let profile = PipelineProfile::default();
+        profile.record(Stage::Parse, Duration::from_micros(3));
+        profile.record(Stage::Parse, Duration::from_micros(5));
+        profile.record(Stage::Output, Duration::from_micros(1));
+
+        let snapshot = profile.snapshot();
+        assert_eq!((snapshot[0].count, snapshot[0].nanos), (2, 8000));
+        assert_eq!(snapshot[1].count, 0);
+        assert_eq!(snapshot[3].count, 1);
+        let parse = &profile.stages[Stage::Parse as usize];
+        assert_eq!(parse.max_nanos.load(Ordering::Relaxed), 5000);
+    }
+
+    #[test]
+    fn closures_are_only_timed_when_profiling() {
+        assert_eq!(time(&None, Stage::Burstify, || 7), 7);
+        let profile = Arc::new(PipelineProfile::default());
+        assert_eq!(time(&Some(profile.clone()), Stage::Burstify, || 7), 7);
+        assert_eq!(profile.snapshot()[2].count, 1);
+    }
+}
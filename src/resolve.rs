@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::IpAddr,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::capture::Burst;
+
+/// Most addresses whose names are kept, forgetting the least recently used
+const CACHE_SIZE: usize = 4096;
+
+/// Most addresses waiting to be resolved. Further addresses are skipped until the resolver catches
+/// up, and asked for again by later bursts.
+const QUEUE_SIZE: usize = 256;
+
+/// File of the system naming the services of ports
+const SERVICES_FILE: &str = "/etc/services";
+
+/// Names of common services, used when the system has no services file
+const DEFAULT_SERVICES: &[(u16, &str)] = &[
+    (20, "ftp-data"),
+    (21, "ftp"),
+    (22, "ssh"),
+    (23, "telnet"),
+    (25, "smtp"),
+    (53, "domain"),
+    (67, "bootps"),
+    (68, "bootpc"),
+    (80, "http"),
+    (110, "pop3"),
+    (123, "ntp"),
+    (143, "imap2"),
+    (161, "snmp"),
+    (389, "ldap"),
+    (443, "https"),
+    (465, "submissions"),
+    (514, "syslog"),
+    (587, "submission"),
+    (853, "domain-s"),
+    (993, "imaps"),
+    (995, "pop3s"),
+    (1194, "openvpn"),
+    (1883, "mqtt"),
+    (3306, "mysql"),
+    (3478, "stun"),
+    (5060, "sip"),
+    (5353, "mdns"),
+    (5432, "postgresql"),
+    (8080, "http-alt"),
+];
+
+/// Host names of addresses, by when they were last used to forget the least recently used.
+///
+/// Addresses are added without a name when they are queued, so they are only resolved once, and
+/// keep no name if they have none.
+#[derive(Default)]
+struct NameCache {
+    names: HashMap<IpAddr, (Option<Arc<str>>, u64)>,
+    by_use: BTreeMap<u64, IpAddr>,
+    uses: u64,
+}
+
+impl NameCache {
+    /// The name of an address, or None and false if it is not in the cache
+    fn get(&mut self, addr: IpAddr) -> (Option<Arc<str>>, bool) {
+        self.uses += 1;
+        let Some((name, last_use)) = self.names.get_mut(&addr) else {
+            return (None, false);
+        };
+        self.by_use.remove(last_use);
+        self.by_use.insert(self.uses, addr);
+        *last_use = self.uses;
+        (name.clone(), true)
+    }
+
+    fn insert(&mut self, addr: IpAddr, name: Option<Arc<str>>) {
+        self.uses += 1;
+        if let Some((_, last_use)) = self.names.insert(addr, (name, self.uses)) {
+            self.by_use.remove(&last_use);
+        }
+        self.by_use.insert(self.uses, addr);
+
+        while self.names.len() > CACHE_SIZE {
+            let Some((_, oldest)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.names.remove(&oldest);
+        }
+    }
+}
+
+/// Annotates bursts with the host names of their addresses and the service names of their ports.
+///
+/// Addresses are resolved by reverse DNS in a thread of their own, so the output never waits for
+/// them. Bursts are annotated with the names resolved so far, so the first bursts of a host are
+/// often without its name.
+pub struct NameResolver {
+    cache: Arc<Mutex<NameCache>>,
+    queue: SyncSender<IpAddr>,
+    services: HashMap<u16, Arc<str>>,
+}
+
+impl NameResolver {
+    pub fn start() -> Self {
+        let cache = Arc::new(Mutex::new(NameCache::default()));
+        let (queue, addrs) = mpsc::sync_channel(QUEUE_SIZE);
+        let thread_cache = cache.clone();
+        thread::spawn(move || resolve(addrs, thread_cache));
+
+        NameResolver {
+            cache,
+            queue,
+            services: load_services(),
+        }
+    }
+
+    pub fn annotate(&mut self, burst: &mut Burst) {
+        burst.src_name = self.name(&burst.src);
+        burst.dst_name = self.name(&burst.dst);
+
+        // The server is more likely at the lower port
+        let mut ports: Vec<u16> = burst.src_port.into_iter().chain(burst.dst_port).collect();
+        ports.sort_unstable();
+        burst.service = ports
+            .iter()
+            .find_map(|port| self.services.get(port))
+            .cloned();
+    }
+
+    /// The name of an address if it is resolved, queueing it to be resolved otherwise
+    fn name(&self, addr: &str) -> Option<Arc<str>> {
+        // Layer 2 flows have MAC addresses, which have no names
+        let addr = addr.parse::<IpAddr>().ok()?;
+        let mut cache = self.cache.lock().unwrap();
+        let (name, cached) = cache.get(addr);
+        if !cached && self.queue.try_send(addr).is_ok() {
+            cache.insert(addr, None);
+        }
+        name
+    }
+}
+
+/// Resolve queued addresses until the resolver is dropped
+fn resolve(addrs: Receiver<IpAddr>, cache: Arc<Mutex<NameCache>>) {
+    for addr in addrs {
+        let name = dns_lookup::lookup_addr(&addr).ok().map(Arc::from);
+        cache.lock().unwrap().insert(addr, name);
+    }
+}
+
+/// Names of services by port, from the services file of the system or the defaults without one.
+///
+/// Each line of the file names a service and its port and protocol, such as `https 443/tcp`. The
+/// first name of a port is kept, whatever the protocol.
+fn load_services() -> HashMap<u16, Arc<str>> {
+    match fs::read_to_string(SERVICES_FILE) {
+        Ok(contents) => parse_services(&contents),
+        Err(_) => DEFAULT_SERVICES
+            .iter()
+            .map(|(port, name)| (*port, Arc::from(*name)))
+            .collect(),
+    }
+}
+
+/// Names of services by port from the contents of a services file
+fn parse_services(contents: &str) -> HashMap<u16, Arc<str>> {
+    let mut services = HashMap::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(port)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Some(Ok(port)) = port.split_once('/').map(|(port, _)| port.parse::<u16>()) else {
+            continue;
+        };
+        services.entry(port).or_insert_with(|| Arc::from(name));
+    }
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::IpAddr,
+        sync::{mpsc, Arc, Mutex},
+    };
+
+    use super::{parse_services, NameCache, NameResolver, CACHE_SIZE};
+    use crate::capture::Burst;
+
+    fn addr(i: usize) -> IpAddr {
+        IpAddr::from([10, 0, (i >> 8) as u8, i as u8])
+    }
+
+    #[test]
+    fn the_least_recently_used_names_are_forgotten() {
+        let mut cache = NameCache::default();
+        for i in 0..CACHE_SIZE {
+            cache.insert(addr(i), None);
+        }
+        cache.insert(addr(0), Some("first.example".into()));
+        cache.insert(addr(CACHE_SIZE), None);
+        assert_eq!(cache.get(addr(0)), (Some("first.example".into()), true));
+        assert_eq!(cache.get(addr(1)), (None, false));
+        assert_eq!(cache.names.len(), CACHE_SIZE);
+    }
+
+    #[test]
+    fn the_first_name_of_a_port_is_kept() {
+        let services = parse_services(
+            "# Network services\n\
+             http 80/tcp www # WorldWideWeb HTTP\n\
+             http 80/udp\n\
+             www-alt 80/sctp\n\
+             broken port/tcp\n\
+             \n\
+             https 443/tcp\n",
+        );
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[&80].as_ref(), "http");
+        assert_eq!(services[&443].as_ref(), "https");
+    }
+
+    #[test]
+    fn addresses_are_queued_once_and_named_when_resolved() {
+        let (queue, addrs) = mpsc::sync_channel(16);
+        let mut resolver = NameResolver {
+            cache: Arc::new(Mutex::new(NameCache::default())),
+            queue,
+            services: HashMap::from([(443, "https".into()), (8080, "http-alt".into())]),
+        };
+        let mut burst = Burst::test("10.0.0.1:8080", "10.0.0.2:443", 1.0, 1.5, 1000);
+        resolver.annotate(&mut burst);
+        resolver.annotate(&mut burst);
+        assert_eq!(addrs.try_iter().count(), 2);
+        assert_eq!((burst.src_name, burst.dst_name), (None, None));
+        // The server is taken to be at the lower port
+        assert_eq!(burst.service.as_deref(), Some("https"));
+
+        let mut resolved = Burst::test("10.0.0.1:8080", "10.0.0.2:443", 1.0, 1.5, 1000);
+        let name = Some(Arc::from("server.example"));
+        resolver.cache.lock().unwrap().insert(addr(2), name.clone());
+        resolver.annotate(&mut resolved);
+        assert_eq!(resolved.dst_name, name);
+    }
+}
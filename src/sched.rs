@@ -0,0 +1,52 @@
+use nix::{
+    sched::{sched_setaffinity, CpuSet},
+    unistd::Pid,
+};
+
+use crate::BurstsharkError;
+
+/// CPU affinity and scheduling priority for one of the pipeline threads
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadSched {
+    /// The core to pin the thread to
+    pub cpu: Option<usize>,
+
+    /// Niceness of the thread, negative values raise the priority
+    pub nice: Option<i32>,
+}
+
+impl ThreadSched {
+    /// Apply the settings to the calling thread
+    pub fn apply(&self) -> Result<(), BurstsharkError> {
+        if let Some(cpu) = self.cpu {
+            let mut cpu_set = CpuSet::new();
+            cpu_set.set(cpu)?;
+            sched_setaffinity(Pid::from_raw(0), &cpu_set).map_err(|err| {
+                BurstsharkError::Sched {
+                    setting: format!("pin thread to core {cpu}"),
+                    source: err.into(),
+                }
+            })?;
+        }
+
+        if let Some(nice) = self.nice {
+            // On Linux the niceness is a per-thread attribute, addressed by the thread id
+            let tid = nix::unistd::gettid().as_raw() as libc::id_t;
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } != 0 {
+                return Err(BurstsharkError::Sched {
+                    setting: format!("set thread niceness to {nice}"),
+                    source: std::io::Error::last_os_error(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the settings to the calling thread, only reporting failures
+    pub fn apply_or_warn(&self, thread: &str) {
+        if let Err(e) = self.apply() {
+            eprintln!("Error configuring {} thread: {}", thread, e);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{env, fs, process};
+
+use crate::{
+    capture::{
+        Burst, CaptureBackend, CaptureType, CommonOptions, IpOptions, NativeSource, WlanOptions,
+    },
+    stream::BurstStream,
+};
+
+/// Packets of a fixture, in the input format of one of the backends
+enum Input {
+    /// Fields as printed by tshark
+    Lines(&'static str),
+    /// A pcap file, read by the native backend
+    Pcap(&'static [u8]),
+}
+
+/// A bundled capture and the bursts expected from it
+struct Case {
+    name: &'static str,
+    input: Input,
+    capture: fn(CommonOptions) -> CaptureType,
+    expected: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "ip-fields",
+        input: Input::Lines(include_str!("fixtures/ip.txt")),
+        capture: ip,
+        expected: include_str!("fixtures/ip.expected"),
+    },
+    Case {
+        name: "wlan-fields",
+        input: Input::Lines(include_str!("fixtures/wlan.txt")),
+        capture: wlan,
+        expected: include_str!("fixtures/wlan.expected"),
+    },
+    Case {
+        name: "eth-fields",
+        input: Input::Lines(include_str!("fixtures/eth.txt")),
+        capture: eth,
+        expected: include_str!("fixtures/eth.expected"),
+    },
+    Case {
+        name: "ip-pcap",
+        input: Input::Pcap(include_bytes!("fixtures/ip.pcap")),
+        capture: ip,
+        expected: include_str!("fixtures/ip-pcap.expected"),
+    },
+    Case {
+        name: "wlan-pcap",
+        input: Input::Pcap(include_bytes!("fixtures/wlan.pcap")),
+        capture: wlan,
+        expected: include_str!("fixtures/wlan-pcap.expected"),
+    },
+    Case {
+        name: "eth-pcap",
+        input: Input::Pcap(include_bytes!("fixtures/ip.pcap")),
+        capture: eth,
+        expected: include_str!("fixtures/eth-pcap.expected"),
+    },
+];
+
+fn ip(opts: CommonOptions) -> CaptureType {
+    CaptureType::IPCapture {
+        opts,
+        ip: IpOptions::default(),
+    }
+}
+
+fn wlan(opts: CommonOptions) -> CaptureType {
+    CaptureType::WLANCapture {
+        opts,
+        wlan: WlanOptions::default(),
+    }
+}
+
+fn eth(opts: CommonOptions) -> CaptureType {
+    CaptureType::EthCapture { opts }
+}
+
+/// Run the full pipeline over each bundled capture and compare the bursts with the expected ones.
+///
+/// Prints the result of each case, and returns whether all of them passed.
+pub fn run() -> bool {
+    let mut failed = 0;
+    for case in CASES {
+        match run_case(case) {
+            Ok(bursts) if bursts == case.expected => println!("test {} ... ok", case.name),
+            Ok(bursts) => {
+                println!("test {} ... FAILED", case.name);
+                println!("expected:\n{}got:\n{}", case.expected, bursts);
+                failed += 1;
+            }
+            Err(err) => {
+                println!("test {} ... FAILED", case.name);
+                println!("{err}");
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "\nself-test result: {}. {} passed; {} failed",
+        if failed == 0 { "ok" } else { "FAILED" },
+        CASES.len() - failed,
+        failed
+    );
+    failed == 0
+}
+
+/// Run the pipeline over the input of a case, and return its bursts formatted a line each
+fn run_case(case: &Case) -> Result<String, String> {
+    let pcap = match case.input {
+        Input::Pcap(data) => {
+            let path = env::temp_dir().join(format!(
+                "burstshark-selftest-{}-{}.pcap",
+                process::id(),
+                case.name
+            ));
+            fs::write(&path, data)
+                .map_err(|err| format!("Failed to write {}: {err}", path.display()))?;
+            Some(path)
+        }
+        Input::Lines(_) => None,
+    };
+
+    let backend = match (&case.input, &pcap) {
+        (Input::Lines(lines), _) => CaptureBackend::Lines {
+            lines: lines.to_string(),
+        },
+        (Input::Pcap(_), Some(path)) => CaptureBackend::Native {
+            source: NativeSource::File(path.to_string_lossy().into_owned()),
+            epoch_time: false,
+        },
+        (Input::Pcap(_), None) => unreachable!("The pcap is written above"),
+    };
+
+    let mut stream = BurstStream::start(|tx| (case.capture)(CommonOptions::new(backend, tx)));
+    let bursts: String = stream.by_ref().map(|burst| format_burst(&burst)).collect();
+    let result = stream.finish().map_err(|err| err.to_string());
+
+    if let Some(path) = pcap {
+        let _ = fs::remove_file(path);
+    }
+
+    result.map(|_| bursts)
+}
+
+/// The address, time and size columns of a burst, which the expected bursts are written in
+fn format_burst(burst: &Burst) -> String {
+    format!(
+        "{} {} {} {} {:.6} {:.6} {:.6} {} {}\n",
+        burst.src,
+        burst.src_port.map_or("-".to_string(), |p| p.to_string()),
+        burst.dst,
+        burst.dst_port.map_or("-".to_string(), |p| p.to_string()),
+        burst.completion_time,
+        burst.start,
+        burst.end,
+        burst.num_packets,
+        burst.size,
+    )
+}
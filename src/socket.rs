@@ -0,0 +1,95 @@
+use std::{
+    fs::{self, DirBuilder},
+    io,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    process,
+};
+
+/// Listen on a Unix socket that only the user running burstshark can connect to.
+///
+/// The socket is bound in a directory only accessible to the user, restricted, and then linked
+/// into place, so no one else can connect before it is restricted. A socket left behind by an
+/// earlier run is replaced, while one that is still listened on is refused.
+pub(crate) fn bind_private(path: &str) -> io::Result<UnixListener> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another process is listening on it",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let path = Path::new(path);
+    let name = path.file_name().ok_or(io::ErrorKind::InvalidInput)?;
+    let dir = path.with_file_name(format!(".{}.{}", name.to_string_lossy(), process::id()));
+    DirBuilder::new().mode(0o700).create(&dir)?;
+    let private = dir.join("socket");
+    let listener = UnixListener::bind(&private).and_then(|listener| {
+        fs::set_permissions(&private, fs::Permissions::from_mode(0o600))?;
+        // Unlike renaming, linking fails rather than replacing a file at the path
+        fs::hard_link(&private, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&private);
+    let _ = fs::remove_dir(&dir);
+    listener
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        os::unix::{fs::PermissionsExt, net::UnixListener},
+        process,
+    };
+
+    use super::bind_private;
+
+    /// A path in a directory of its own for the test
+    fn socket_path(test: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("burstshark-{test}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir.join("socket").to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn sockets_are_only_accessible_to_the_user() {
+        let path = socket_path("private");
+        let _listener = bind_private(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // The private directory the socket was bound in is removed
+        let dir = fs::read_dir(std::path::Path::new(&path).parent().unwrap()).unwrap();
+        assert_eq!(dir.count(), 1);
+    }
+
+    #[test]
+    fn sockets_left_behind_are_replaced() {
+        let path = socket_path("stale");
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(bind_private(&path).is_ok());
+    }
+
+    #[test]
+    fn sockets_listened_on_are_refused() {
+        let path = socket_path("live");
+        let _listener = UnixListener::bind(&path).unwrap();
+        let err = bind_private(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn other_files_are_not_replaced() {
+        let path = socket_path("file");
+        fs::write(&path, "bursts").unwrap();
+        assert!(bind_private(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "bursts");
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::{capture::Burst, probe::ProbeIdentity, BurstsharkError};
+
+/// Source and destination address and port of a flow
+type FlowKey = (Arc<str>, Option<u16>, Arc<str>, Option<u16>);
+
+/// Mean, median and 95th percentile of a set of values
+#[derive(Debug, Clone, Copy, Serialize)]
+struct Summary {
+    mean: f64,
+    median: f64,
+    p95: f64,
+}
+
+impl Summary {
+    fn of(values: &mut [f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+
+        // Nearest-rank percentiles
+        let percentile = |p: f64| values[((p * values.len() as f64).ceil() as usize).max(1) - 1];
+        Some(Summary {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median: percentile(0.5),
+            p95: percentile(0.95),
+        })
+    }
+}
+
+/// Bursts of a flow seen so far
+#[derive(Default)]
+struct FlowBursts {
+    /// Id and tags of the probe of the bursts
+    probe: ProbeIdentity,
+    sizes: Vec<f64>,
+    /// Seconds between the starts of consecutive bursts
+    inter_arrivals: Vec<f64>,
+    bytes: u64,
+    last_start: Option<f64>,
+}
+
+/// Statistics of the bursts of a flow
+#[derive(Debug, Clone, Serialize)]
+struct FlowStats {
+    src: Arc<str>,
+    src_port: Option<u16>,
+    dst: Arc<str>,
+    dst_port: Option<u16>,
+    bursts: usize,
+    bytes: u64,
+    size: Option<Summary>,
+    inter_arrival: Option<Summary>,
+    #[serde(flatten)]
+    probe: ProbeIdentity,
+}
+
+/// Accumulates the bursts of each flow, to summarize them when the capture ends.
+#[derive(Default)]
+pub struct FlowStatistics {
+    flows: HashMap<FlowKey, FlowBursts>,
+}
+
+impl FlowStatistics {
+    pub fn add(&mut self, burst: &Burst) {
+        let key = (
+            burst.src.clone(),
+            burst.src_port,
+            burst.dst.clone(),
+            burst.dst_port,
+        );
+        let flow = self.flows.entry(key).or_insert_with(|| FlowBursts {
+            probe: burst.probe.clone(),
+            ..Default::default()
+        });
+
+        flow.sizes.push(burst.size as f64);
+        flow.bytes += burst.size as u64;
+        if let Some(last_start) = flow.last_start {
+            flow.inter_arrivals.push(burst.start - last_start);
+        }
+        flow.last_start = Some(burst.start);
+    }
+
+    /// Statistics of all flows, with the most bytes first and ties in address order
+    fn summarize(&mut self) -> Vec<FlowStats> {
+        let mut stats: Vec<FlowStats> = self
+            .flows
+            .iter_mut()
+            .map(|((src, src_port, dst, dst_port), flow)| FlowStats {
+                src: src.clone(),
+                src_port: *src_port,
+                dst: dst.clone(),
+                dst_port: *dst_port,
+                bursts: flow.sizes.len(),
+                bytes: flow.bytes,
+                size: Summary::of(&mut flow.sizes),
+                inter_arrival: Summary::of(&mut flow.inter_arrivals),
+                probe: flow.probe.clone(),
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            b.bytes.cmp(&a.bytes).then_with(|| {
+                (&a.src, a.src_port, &a.dst, a.dst_port)
+                    .cmp(&(&b.src, b.src_port, &b.dst, b.dst_port))
+            })
+        });
+        stats
+    }
+
+    /// Print a table of the statistics to stderr, and write them to the JSON file if set.
+    pub fn report(&mut self, print: bool, path: Option<&str>) -> Result<(), BurstsharkError> {
+        let stats = self.summarize();
+
+        if print {
+            write_table(&mut io::stderr().lock(), &stats)?;
+        }
+
+        if let Some(path) = path {
+            fs::write(path, serde_json::to_string_pretty(&stats)?)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_table(out: &mut impl Write, stats: &[FlowStats]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:15} {:6} {:15} {:5} {:>6} {:>12} {:>10} {:>10} {:>10} {:>9} {:>9} {:>9}",
+        "src",
+        "port",
+        "dst",
+        "port",
+        "bursts",
+        "bytes",
+        "size mean",
+        "median",
+        "p95",
+        "iat mean",
+        "median",
+        "p95",
+    )?;
+
+    for flow in stats {
+        write!(
+            out,
+            "{:15} {:6} {:15} {:5} {:6} {:12}",
+            flow.src,
+            flow.src_port.map_or("".to_string(), |p| p.to_string()),
+            flow.dst,
+            flow.dst_port.map_or("".to_string(), |p| p.to_string()),
+            flow.bursts,
+            flow.bytes,
+        )?;
+        if let Some(size) = flow.size {
+            write!(
+                out,
+                " {:10.1} {:10.0} {:10.0}",
+                size.mean, size.median, size.p95
+            )?;
+        }
+        if let Some(iat) = flow.inter_arrival {
+            write!(out, " {:9.3} {:9.3} {:9.3}", iat.mean, iat.median, iat.p95)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlowStatistics, Summary};
+    use crate::capture::Burst;
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        let mut values: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        let summary = Summary::of(&mut values).unwrap();
+        assert_eq!(
+            (summary.mean, summary.median, summary.p95),
+            (10.5, 10.0, 19.0)
+        );
+
+        let single = Summary::of(&mut [3.0]).unwrap();
+        assert_eq!((single.mean, single.median, single.p95), (3.0, 3.0, 3.0));
+        assert!(Summary::of(&mut []).is_none());
+    }
+
+    #[test]
+    fn flows_are_summarized_with_the_most_bytes_first() {
+        let mut stats = FlowStatistics::default();
+        for (i, size) in [100, 300, 200].into_iter().enumerate() {
+            let start = i as f64 * 0.5;
+            stats.add(&Burst::test(
+                "10.0.0.1:50000",
+                "10.0.0.2:443",
+                start,
+                start + 0.1,
+                size,
+            ));
+        }
+        stats.add(&Burst::test(
+            "10.0.0.2:443",
+            "10.0.0.1:50000",
+            0.2,
+            0.3,
+            5000,
+        ));
+
+        let flows = stats.summarize();
+        assert_eq!(flows.len(), 2);
+        assert_eq!(
+            (&*flows[0].src, flows[0].bursts, flows[0].bytes),
+            ("10.0.0.2", 1, 5000)
+        );
+        // A single burst has no time between bursts
+        assert!(flows[0].inter_arrival.is_none());
+
+        let upload = &flows[1];
+        assert_eq!((upload.bursts, upload.bytes), (3, 600));
+        assert_eq!(upload.size.unwrap().median, 200.0);
+        assert_eq!(upload.inter_arrival.unwrap().mean, 0.5);
+    }
+}
@@ -0,0 +1,46 @@
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    capture::{Burst, CaptureType, Record},
+    channel::{self, Receiver, Sender},
+    BurstsharkError,
+};
+
+/// The bursts of a capture running in the background, in the order they are completed.
+///
+/// Other records, such as flow events, are skipped. The iterator ends when the capture does, after
+/// which [`BurstStream::finish`] tells whether it ended because of an error.
+pub struct BurstStream {
+    records: Receiver<Record>,
+    capture: JoinHandle<Result<(), BurstsharkError>>,
+}
+
+impl BurstStream {
+    /// Start the capture created by `capture`, which must send its records to the given sender.
+    pub fn start(capture: impl FnOnce(Sender<Record>) -> CaptureType) -> Self {
+        let (tx, records) = channel::unbounded();
+        let capture = capture(tx);
+        let capture = thread::spawn(move || capture.run());
+
+        BurstStream { records, capture }
+    }
+
+    /// Wait for the capture to end, and return the error it ended with, if any.
+    pub fn finish(self) -> Result<(), BurstsharkError> {
+        match self.capture.join() {
+            Ok(result) => result,
+            Err(_) => Err(BurstsharkError::Panicked("capture")),
+        }
+    }
+}
+
+impl Iterator for BurstStream {
+    type Item = Burst;
+
+    fn next(&mut self) -> Option<Burst> {
+        self.records.iter().find_map(|record| match record {
+            Record::Burst(burst) => Some(burst),
+            _ => None,
+        })
+    }
+}
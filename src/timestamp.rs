@@ -0,0 +1,140 @@
+//! Conversions between UNIX epoch seconds and ISO-8601 timestamps
+
+/// Days since 1970-01-01 for a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Date in the proleptic Gregorian calendar of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// UTC date and time of day (year, month, day, hour, minute, second) of whole epoch seconds
+pub fn utc_parts(secs: i64) -> (i64, u32, u32, u32, u32, u32) {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400) as u32;
+    (year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+/// Format epoch seconds as an ISO-8601 timestamp in UTC with microseconds, as Suricata does, such
+/// as `2023-04-01T10:34:56.123456+0000`
+pub fn format_iso8601(secs: f64) -> String {
+    let micros = (secs * 1e6).round() as i64;
+    let (year, month, day, hour, minute, second) = utc_parts(micros.div_euclid(1_000_000));
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{:06}+0000",
+        micros.rem_euclid(1_000_000)
+    )
+}
+
+/// Parse an ISO-8601 timestamp, such as `2023-04-01T12:34:56.123456+0200`, into epoch seconds
+pub fn parse_iso8601(timestamp: &str) -> Option<f64> {
+    let (date, time) = timestamp.split_once(['T', ' '])?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year = date_parts.next()?.parse::<i64>().ok()?;
+    let month = date_parts.next()?.parse::<i64>().ok()?;
+    let day = date_parts.next()?.parse::<i64>().ok()?;
+
+    // Split off the UTC offset, if any
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(idx) => time.split_at(idx),
+        None => (time, ""),
+    };
+
+    let mut time_parts = time.splitn(3, ':');
+    let hour = time_parts.next()?.parse::<i64>().ok()?;
+    let minute = time_parts.next()?.parse::<i64>().ok()?;
+    let second = time_parts.next()?.parse::<f64>().ok()?;
+
+    let offset_secs = match offset {
+        "" | "Z" => 0,
+        _ => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let digits: String = offset[1..].chars().filter(|c| *c != ':').collect();
+            if digits.len() != 4 {
+                return None;
+            }
+            let hours = digits[..2].parse::<i64>().ok()?;
+            let minutes = digits[2..].parse::<i64>().ok()?;
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + hour * 3600 + minute * 60 - offset_secs;
+    Some(secs as f64 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_iso8601, parse_iso8601, utc_parts};
+
+    #[test]
+    fn epoch_seconds_are_split_into_utc_dates() {
+        assert_eq!(utc_parts(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(utc_parts(1369353600), (2013, 5, 24, 0, 0, 0));
+        assert_eq!(utc_parts(1709251199), (2024, 2, 29, 23, 59, 59));
+        assert_eq!(utc_parts(-1), (1969, 12, 31, 23, 59, 59));
+    }
+
+    #[test]
+    fn timestamps_are_formatted_as_suricata_does() {
+        assert_eq!(
+            format_iso8601(1680345296.123456),
+            "2023-04-01T10:34:56.123456+0000"
+        );
+        assert_eq!(format_iso8601(0.0), "1970-01-01T00:00:00.000000+0000");
+    }
+
+    #[test]
+    fn utc_offsets_are_applied() {
+        let utc = 1680345296.123456;
+        for timestamp in [
+            "2023-04-01T10:34:56.123456Z",
+            "2023-04-01T10:34:56.123456",
+            "2023-04-01T12:34:56.123456+0200",
+            "2023-04-01T12:34:56.123456+02:00",
+            "2023-04-01 05:04:56.123456-05:30",
+        ] {
+            let secs = parse_iso8601(timestamp).unwrap();
+            assert!((secs - utc).abs() < 1e-6, "{timestamp}");
+        }
+    }
+
+    #[test]
+    fn formatted_timestamps_parse_back() {
+        for secs in [0.0, 951782400.5, 1680345296.123456, 4102444799.999999] {
+            let parsed = parse_iso8601(&format_iso8601(secs)).unwrap();
+            assert!((parsed - secs).abs() < 1e-6, "{secs}");
+        }
+    }
+
+    #[test]
+    fn malformed_timestamps_are_rejected() {
+        for timestamp in [
+            "2023-04-01",
+            "2023-04T10:34:56",
+            "2023-04-01T10:34",
+            "2023-04-01T10:34:56+2",
+            "yesterday",
+        ] {
+            assert_eq!(parse_iso8601(timestamp), None, "{timestamp}");
+        }
+    }
+}
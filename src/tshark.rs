@@ -0,0 +1,323 @@
+//! Fields and filters passed to tshark.
+//!
+//! Packets are parsed from the fields tshark prints for each of its `-e` arguments, in order. Each
+//! part of a packet, such as its source port, is printed by one or more fields of which the first
+//! present is used. The fields can be remapped by name, for profiles or dissectors that name them
+//! differently, and the arguments are still built in the order the packets are parsed.
+
+use std::fs;
+
+use crate::BurstsharkError;
+
+/// A part of the packets, and the fields tshark prints it with
+#[derive(Debug, Clone)]
+pub struct FieldPart {
+    pub name: &'static str,
+    pub fields: Vec<String>,
+}
+
+impl FieldPart {
+    pub fn new(name: &'static str, fields: &[&str]) -> Self {
+        FieldPart {
+            name,
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        }
+    }
+}
+
+/// The `-e` arguments printing the parts, in order
+pub fn field_args(parts: &[FieldPart]) -> Vec<String> {
+    parts
+        .iter()
+        .flat_map(|part| &part.fields)
+        .flat_map(|field| ["-e".to_string(), field.clone()])
+        .collect()
+}
+
+/// Fields printing parts of the packets in place of the default ones, such as `src=ipv6.src`.
+///
+/// Alternatives are separated by `|`, of which the first present is used, as with
+/// `src_port=udp.srcport|sctp.srcport`.
+#[derive(Debug, Default, Clone)]
+pub struct FieldMapping(Vec<(String, Vec<String>)>);
+
+impl FieldMapping {
+    /// Parse mappings of the form `name=field`
+    pub fn parse<S: AsRef<str>>(mappings: &[S]) -> Result<Self, BurstsharkError> {
+        let mut mapping = FieldMapping::default();
+        for entry in mappings {
+            mapping.add(entry.as_ref())?;
+        }
+        Ok(mapping)
+    }
+
+    /// Read a mapping per line of the file, ignoring empty lines and those starting with #
+    pub fn from_file(path: &str) -> Result<Self, BurstsharkError> {
+        let content = fs::read_to_string(path).map_err(|source| BurstsharkError::File {
+            action: "read tshark fields file",
+            path: path.to_string(),
+            source,
+        })?;
+        let mut mapping = FieldMapping::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            mapping
+                .add(line)
+                .map_err(|e| BurstsharkError::InvalidFile {
+                    path: path.to_string(),
+                    reason: format!("line {}: {e}", number + 1),
+                })?;
+        }
+        Ok(mapping)
+    }
+
+    /// The mappings of both, where those of `other` take precedence
+    pub fn extend(&mut self, other: FieldMapping) {
+        self.0.extend(other.0);
+    }
+
+    /// Print the parts with the mapped fields. Parts parsed from several fields keep printing as
+    /// many, repeating the last mapped field, so that the parts after them stay in place.
+    pub fn apply(&self, parts: &mut [FieldPart]) -> Result<(), BurstsharkError> {
+        for (name, fields) in &self.0 {
+            let Some(part) = parts.iter_mut().find(|part| part.name == name) else {
+                let names: Vec<_> = parts.iter().map(|part| part.name).collect();
+                return Err(BurstsharkError::InvalidOptions(format!(
+                    "Unknown tshark field part '{name}', expected one of: {}",
+                    names.join(", ")
+                )));
+            };
+            let width = part.fields.len();
+            if fields.len() > width {
+                let allowed = match width {
+                    1 => "a single field".to_string(),
+                    width => format!("at most {width} alternative fields"),
+                };
+                return Err(BurstsharkError::InvalidOptions(format!(
+                    "Tshark field part '{name}' takes {allowed}"
+                )));
+            }
+            part.fields = fields.clone();
+            let last = fields[fields.len() - 1].clone();
+            part.fields.resize(width, last);
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, entry: &str) -> Result<(), BurstsharkError> {
+        let Some((name, fields)) = entry.split_once('=') else {
+            return Err(BurstsharkError::InvalidOptions(format!(
+                "Expected a tshark field mapping as name=field, got '{entry}'"
+            )));
+        };
+        let fields: Vec<String> = fields
+            .split('|')
+            .map(|field| field.trim().to_string())
+            .collect();
+        if let Some(field) = fields.iter().find(|field| !valid_field(field)) {
+            return Err(BurstsharkError::InvalidOptions(format!(
+                "Invalid tshark field name '{field}'"
+            )));
+        }
+        self.0.push((name.trim().to_string(), fields));
+        Ok(())
+    }
+}
+
+/// Whether the name is that of a tshark field, such as `ipv6.src`
+fn valid_field(field: &str) -> bool {
+    field.starts_with(|c: char| c.is_ascii_alphabetic())
+        && field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Check a capture filter for mistakes before tshark gets it, in particular a display filter
+/// passed as one.
+pub fn check_capture_filter(filter: &str) -> Result<(), String> {
+    let tokens = check_balanced(filter)?;
+    let display_field = tokens.iter().enumerate().find_map(|(i, token)| {
+        // Operators may be written without spaces, as in tcp.port==80
+        let (field, rest) = token.split_at(token.find(['=', '!', '~']).unwrap_or(token.len()));
+        let operator = match rest {
+            "" => tokens.get(i + 1).copied().unwrap_or(""),
+            rest => rest,
+        };
+        let compares = ["==", "!=", "~"].iter().any(|op| operator.starts_with(op))
+            || matches!(operator, "eq" | "ne" | "contains" | "matches");
+        (is_dotted_field(field) && compares).then_some(field)
+    });
+    match display_field {
+        Some(field) => Err(format!(
+            "Capture filter '{filter}' compares the field {field} as a display filter, which needs -Y with -r"
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Check a display filter for mistakes before tshark gets it, in particular a capture filter
+/// passed as one.
+pub fn check_display_filter(filter: &str) -> Result<(), String> {
+    let tokens = check_balanced(filter)?;
+    match tokens
+        .iter()
+        .find(|token| matches!(**token, "host" | "port" | "net" | "portrange"))
+    {
+        Some(token) => Err(format!(
+            "Display filter '{filter}' uses the capture filter primitive '{token}', try a field such as ip.addr or tcp.port"
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Check that the filter is not empty and its parentheses and quotes are balanced, returning its
+/// tokens outside of quotes
+fn check_balanced(filter: &str) -> Result<Vec<&str>, String> {
+    if filter.trim().is_empty() {
+        return Err("Filters can not be empty".to_string());
+    }
+
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in filter.char_indices() {
+        let separates = quoted || c == '"' || c == '(' || c == ')' || c.is_whitespace();
+        if let (true, Some(begin)) = (separates, start) {
+            tokens.push(&filter[begin..i]);
+            start = None;
+        }
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                return Err(format!(
+                    "Filter '{filter}' closes a parenthesis it never opened"
+                ))
+            }
+            ')' => depth -= 1,
+            _ if !separates && start.is_none() => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push(&filter[begin..]);
+    }
+
+    match (quoted, depth) {
+        (true, _) => Err(format!("Filter '{filter}' has an unterminated string")),
+        (false, 0) => Ok(tokens),
+        (false, _) => Err(format!("Filter '{filter}' has an unclosed parenthesis")),
+    }
+}
+
+/// Whether the token is a field of a protocol, such as `tcp.port`, rather than a host name or
+/// address
+fn is_dotted_field(token: &str) -> bool {
+    token.contains('.')
+        && token.split('.').all(|label| {
+            label.starts_with(|c: char| c.is_ascii_lowercase())
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::{check_capture_filter, check_display_filter, field_args, FieldMapping, FieldPart};
+
+    fn parts() -> Vec<FieldPart> {
+        vec![
+            FieldPart::new("src", &["ip.src", "ipv6.src"]),
+            FieldPart::new("src_port", &["udp.srcport"]),
+            FieldPart::new("len", &["frame.len"]),
+        ]
+    }
+
+    #[test]
+    fn mapped_parts_keep_their_place() {
+        let mut parts = parts();
+        let mapping = FieldMapping::parse(&["src=ipv6.src", "src_port = udp.srcport"]).unwrap();
+        mapping.apply(&mut parts).unwrap();
+        assert_eq!(
+            field_args(&parts),
+            [
+                "-e",
+                "ipv6.src",
+                "-e",
+                "ipv6.src",
+                "-e",
+                "udp.srcport",
+                "-e",
+                "frame.len"
+            ]
+        );
+    }
+
+    #[test]
+    fn later_mappings_take_precedence() {
+        let mut parts = parts();
+        let mut mapping = FieldMapping::parse(&["len=frame.cap_len"]).unwrap();
+        mapping.extend(FieldMapping::parse(&["len=ip.len"]).unwrap());
+        mapping.apply(&mut parts).unwrap();
+        assert_eq!(parts[2].fields, ["ip.len"]);
+    }
+
+    #[test]
+    fn invalid_mappings_are_refused() {
+        assert!(FieldMapping::parse(&["src"]).is_err());
+        assert!(FieldMapping::parse(&["src=ip src"]).is_err());
+        assert!(FieldMapping::parse(&["src=1ip.src"]).is_err());
+
+        let unknown = FieldMapping::parse(&["dscp=ip.dsfield.dscp"]).unwrap();
+        assert!(unknown.apply(&mut parts()).is_err());
+        let too_many = FieldMapping::parse(&["len=frame.len|ip.len"]).unwrap();
+        assert!(too_many.apply(&mut parts()).is_err());
+    }
+
+    #[test]
+    fn mapping_files_skip_comments() {
+        let path = std::env::temp_dir().join(format!("burstshark-fields-{}", process::id()));
+        fs::write(&path, "# Fields of IPv6 captures\n\nsrc=ipv6.src|ip.src\n").unwrap();
+        let mapping = FieldMapping::from_file(path.to_str().unwrap()).unwrap();
+        let mut parts = parts();
+        mapping.apply(&mut parts).unwrap();
+        assert_eq!(parts[0].fields, ["ipv6.src", "ip.src"]);
+
+        fs::write(&path, "src=ipv6.src\nsrc\n").unwrap();
+        let err = FieldMapping::from_file(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn display_filters_are_refused_as_capture_filters() {
+        assert!(check_capture_filter("udp port 443 and host example.com").is_ok());
+        assert!(check_capture_filter("tcp.port == 80").is_err());
+        assert!(check_capture_filter("tcp.port==80").is_err());
+        assert!(check_capture_filter("(ip.src eq 10.0.0.1)").is_err());
+    }
+
+    #[test]
+    fn capture_filters_are_refused_as_display_filters() {
+        assert!(check_display_filter("tcp.port == 80 && ip.addr == 10.0.0.1").is_ok());
+        assert!(check_display_filter("host 10.0.0.1").is_err());
+        // Quoted strings are not looked into
+        assert!(check_display_filter("http.host contains \"port\"").is_ok());
+    }
+
+    #[test]
+    fn unbalanced_filters_are_refused() {
+        assert!(check_display_filter(" ").is_err());
+        assert!(check_display_filter("(udp").is_err());
+        assert!(check_display_filter("udp)").is_err());
+        assert!(check_display_filter("http.host == \"example").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    fmt::Write,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use ureq::http::Uri;
+
+use crate::{timestamp, BurstsharkError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// An S3-compatible object storage bucket that finished output files are uploaded to
+#[derive(Clone, Debug)]
+pub struct ObjectStore {
+    /// Base URL of the service, such as `https://s3.eu-north-1.amazonaws.com`
+    pub endpoint: String,
+    pub bucket: String,
+
+    /// Prepended to the file name to form the object key
+    pub prefix: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl ObjectStore {
+    /// Check the endpoint, so an invalid one is reported before anything is captured
+    pub fn check_endpoint(&self) -> Result<(), BurstsharkError> {
+        endpoint_host(&self.endpoint)
+            .map(|_| ())
+            .map_err(BurstsharkError::InvalidOptions)
+    }
+
+    /// Upload a file, and delete the local copy once the upload succeeded
+    pub fn upload_and_remove(&self, path: &str) -> Result<(), BurstsharkError> {
+        let failed = |reason| BurstsharkError::Upload {
+            path: path.to_string(),
+            reason,
+        };
+        let body = fs::read(path).map_err(|source| BurstsharkError::File {
+            action: "read",
+            path: path.to_string(),
+            source,
+        })?;
+        let file_name = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| failed("invalid file name".to_string()))?;
+        let key = format!("{}{}", self.prefix, file_name);
+
+        self.put(&key, &body)
+            .map_err(|err| failed(format!("to {}/{key}: {err}", self.bucket)))?;
+        fs::remove_file(path).map_err(|source| BurstsharkError::File {
+            action: "remove",
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    /// PUT an object using a path-style URL signed with AWS signature version 4
+    fn put(&self, key: &str, body: &[u8]) -> Result<(), String> {
+        let (scheme, host) = endpoint_host(&self.endpoint)?;
+        let path = format!("/{}/{}", uri_encode(&self.bucket), uri_encode(key));
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| err.to_string())?
+            .as_secs() as i64;
+        let (year, month, day, hour, minute, second) = timestamp::utc_parts(now);
+        let amz_date = format!("{year:04}{month:02}{day:02}T{hour:02}{minute:02}{second:02}Z");
+        let payload_hash = hex(&Sha256::digest(body));
+
+        let headers = [
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        let (canonical_request, signed_headers) =
+            canonical_request("PUT", &path, &headers, &payload_hash);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={signed_headers}, Signature={}",
+            self.access_key,
+            scope(&amz_date, &self.region),
+            sign(
+                &self.secret_key,
+                &self.region,
+                &amz_date,
+                &canonical_request
+            )
+        );
+
+        // The URL is rebuilt from the signed host so the Host header sent matches it
+        ureq::put(format!("{scheme}://{host}{path}"))
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header("Authorization", &authorization)
+            .send(body)
+            .map_err(|err| err.to_string())?;
+        Ok(())
+    }
+}
+
+/// The scheme of an endpoint and its host header, lowercase and with the port only when it is not
+/// the default of the scheme
+fn endpoint_host(endpoint: &str) -> Result<(String, String), String> {
+    let uri: Uri = endpoint
+        .parse()
+        .map_err(|err| format!("Invalid endpoint {endpoint}: {err}"))?;
+    let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+        return Err(format!(
+            "Invalid endpoint {endpoint}: expected http(s)://host[:port]"
+        ));
+    };
+    let default_port = match scheme {
+        "http" => 80,
+        "https" => 443,
+        _ => {
+            return Err(format!(
+                "Invalid endpoint {endpoint}: unsupported scheme {scheme}"
+            ))
+        }
+    };
+    if uri.path() != "/" || uri.query().is_some() {
+        return Err(format!(
+            "Invalid endpoint {endpoint}: it can not have a path"
+        ));
+    }
+
+    let host = authority.host().to_ascii_lowercase();
+    let host = match authority.port_u16() {
+        Some(port) if port != default_port => format!("{host}:{port}"),
+        _ => host,
+    };
+    Ok((scheme.to_string(), host))
+}
+
+/// The canonical request of a request without a query string, and its signed header list. The
+/// headers must have lowercase names and be sorted by them.
+fn canonical_request(
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> (String, String) {
+    let canonical_headers = headers.iter().fold(String::new(), |mut s, (name, value)| {
+        let _ = writeln!(s, "{name}:{}", value.trim());
+        s
+    });
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let request =
+        format!("{method}\n{path}\n\n{canonical_headers}\n{signed_headers}\n{payload_hash}");
+    (request, signed_headers)
+}
+
+/// The credential scope of a request made at an `amz_date` such as `20130524T000000Z`
+fn scope(amz_date: &str, region: &str) -> String {
+    format!("{}/{region}/s3/aws4_request", &amz_date[..8])
+}
+
+/// The signature of a canonical request
+fn sign(secret_key: &str, region: &str, amz_date: &str, canonical_request: &str) -> String {
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{}\n{}",
+        scope(amz_date, region),
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let mut signing_key = format!("AWS4{secret_key}").into_bytes();
+    for part in [&amz_date[..8], region, "s3", "aws4_request"] {
+        signing_key = hmac(&signing_key, part.as_bytes());
+    }
+    hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+/// Percent-encode everything except unreserved characters and path separators
+pub(crate) fn uri_encode(s: &str) -> String {
+    s.bytes().fold(String::new(), |mut encoded, b| {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{b:02X}");
+        }
+        encoded
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::{canonical_request, endpoint_host, hex, sign, uri_encode};
+
+    // The example requests of the AWS S3 documentation on signing requests with the
+    // Authorization header, "Signature Calculations for the Authorization Header"
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const AMZ_DATE: &str = "20130524T000000Z";
+    const HOST: &str = "examplebucket.s3.amazonaws.com";
+
+    #[test]
+    fn get_object_matches_the_aws_example() {
+        let payload_hash = hex(&Sha256::digest(b""));
+        let headers = [
+            ("host", HOST),
+            ("range", "bytes=0-9"),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", AMZ_DATE),
+        ];
+        let (request, signed_headers) =
+            canonical_request("GET", "/test.txt", &headers, &payload_hash);
+        assert_eq!(signed_headers, "host;range;x-amz-content-sha256;x-amz-date");
+        assert_eq!(
+            sign(SECRET_KEY, "us-east-1", AMZ_DATE, &request),
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+    }
+
+    #[test]
+    fn put_object_matches_the_aws_example() {
+        let payload_hash = hex(&Sha256::digest(b"Welcome to Amazon S3."));
+        let headers = [
+            ("date", "Fri, 24 May 2013 00:00:00 GMT"),
+            ("host", HOST),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", AMZ_DATE),
+            ("x-amz-storage-class", "REDUCED_REDUNDANCY"),
+        ];
+        let path = format!("/{}", uri_encode("test$file.text"));
+        let (request, _) = canonical_request("PUT", &path, &headers, &payload_hash);
+        assert_eq!(
+            sign(SECRET_KEY, "us-east-1", AMZ_DATE, &request),
+            "98ad721746da40c64f1a55b78f14c238d841ea1380cd77a1b5971af0ece108bd"
+        );
+    }
+
+    #[test]
+    fn object_keys_are_percent_encoded() {
+        assert_eq!(uri_encode("test$file.text"), "test%24file.text");
+        assert_eq!(uri_encode("bursts/a b+c.csv"), "bursts/a%20b%2Bc.csv");
+    }
+
+    #[test]
+    fn default_ports_are_left_out_of_the_host() {
+        let host = |endpoint| endpoint_host(endpoint).unwrap();
+        assert_eq!(
+            host("https://S3.eu-north-1.amazonaws.com/"),
+            ("https".into(), "s3.eu-north-1.amazonaws.com".into())
+        );
+        assert_eq!(
+            host("https://s3.example.com:443"),
+            ("https".into(), "s3.example.com".into())
+        );
+        assert_eq!(host("http://minio:80"), ("http".into(), "minio".into()));
+        assert_eq!(
+            host("http://127.0.0.1:9000"),
+            ("http".into(), "127.0.0.1:9000".into())
+        );
+        assert_eq!(
+            host("https://user@[::1]:8443"),
+            ("https".into(), "[::1]:8443".into())
+        );
+    }
+
+    #[test]
+    fn endpoints_without_a_host_or_with_a_path_are_refused() {
+        assert!(endpoint_host("s3.example.com").is_err());
+        assert!(endpoint_host("ftp://s3.example.com").is_err());
+        assert!(endpoint_host("https://s3.example.com/bucket").is_err());
+        assert!(endpoint_host("https://s3.example.com/?list").is_err());
+    }
+}
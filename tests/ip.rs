@@ -0,0 +1,106 @@
+//! IP bursts of packets run through the capture as tshark would print them.
+
+use std::sync::Arc;
+
+use burstshark::{
+    capture::{FlowEvictions, IpOptions, SurgeOptions},
+    Burst, BurstStream, CaptureBackend, CaptureType, CommonOptions,
+};
+
+/// The bursts of UDP packets from one port to another, given as their time and payload length
+fn bursts(packets: &[(f64, u32)], ip: IpOptions) -> Vec<Burst> {
+    let packets: Vec<_> = packets
+        .iter()
+        .map(|(time, len)| (*time, 5000, *len))
+        .collect();
+    capture(&packets, ip, |_| {})
+}
+
+/// The bursts of UDP packets from several ports to one, given as their time, source port and
+/// payload length, with the options changed by `configure`
+fn capture(
+    packets: &[(f64, u16, u32)],
+    ip: IpOptions,
+    configure: impl FnOnce(&mut CommonOptions),
+) -> Vec<Burst> {
+    let lines: String = packets
+        .iter()
+        .map(|(time, port, len)| {
+            format!("{time:.6}\t10.0.0.1\t10.0.0.2\t{port}\t\t6000\t\t{len}\t\t\n")
+        })
+        .collect();
+    let mut stream = BurstStream::start(|tx| {
+        let mut opts = CommonOptions::new(CaptureBackend::Lines { lines }, tx);
+        opts.inactive_time = 0.5;
+        configure(&mut opts);
+        CaptureType::IPCapture { opts, ip }
+    });
+    let bursts = stream.by_ref().collect();
+    stream.finish().unwrap();
+    bursts
+}
+
+/// The packets and bytes of each burst
+fn sizes(bursts: &[Burst]) -> Vec<(u16, u32)> {
+    bursts
+        .iter()
+        .map(|burst| (burst.num_packets, burst.size))
+        .collect()
+}
+
+#[test]
+fn packets_without_payload_count_towards_bursts() {
+    let packets = [(0.0, 1000), (0.01, 0), (0.02, 500)];
+    assert_eq!(sizes(&bursts(&packets, IpOptions::default())), [(3, 1500)]);
+}
+
+#[test]
+fn packets_without_payload_are_only_used_to_detect_surges() {
+    let packets = [(0.0, 1000), (0.01, 0), (0.02, 500)];
+    let ip = IpOptions {
+        surge: Some(SurgeOptions {
+            threshold: 100,
+            window: 10.0,
+        }),
+        ..IpOptions::default()
+    };
+    assert_eq!(sizes(&bursts(&packets, ip)), [(2, 1500)]);
+}
+
+#[test]
+fn idle_flows_are_evicted_with_their_burst() {
+    let evictions = Arc::new(FlowEvictions::default());
+    let packets = [(0.0, 5000, 1000), (1.5, 5001, 500), (3.0, 5001, 700)];
+    let bursts = capture(&packets, IpOptions::default(), |opts| {
+        // Longer than the flow timeout, so only the eviction completes the first burst
+        opts.inactive_time = 5.0;
+        opts.flow_timeout = Some(2.0);
+        opts.evictions = evictions.clone();
+    });
+    assert_eq!(sizes(&bursts), [(1, 1000), (2, 1200)]);
+    assert_eq!(bursts[0].completion_time, 3.0);
+    assert_eq!((evictions.idle(), evictions.over_limit()), (1, 0));
+}
+
+#[test]
+fn the_least_recently_active_flows_are_evicted_beyond_the_most_flows() {
+    let evictions = Arc::new(FlowEvictions::default());
+    let packets = [
+        (0.0, 5000, 100),
+        (0.1, 5001, 200),
+        (0.2, 5000, 100),
+        (0.3, 5002, 300),
+        (0.4, 5000, 100),
+    ];
+    let bursts = capture(&packets, IpOptions::default(), |opts| {
+        opts.max_flows = Some(2);
+        opts.evictions = evictions.clone();
+    });
+    // Flows are only evicted before the packets after the one going over the limit
+    assert_eq!(
+        (bursts[0].src_port, bursts[0].completion_time),
+        (Some(5001), 0.4)
+    );
+    assert_eq!(sizes(&bursts), [(1, 200), (1, 300), (3, 300)]);
+    assert_eq!((evictions.idle(), evictions.over_limit()), (0, 1));
+}
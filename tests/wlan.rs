@@ -0,0 +1,174 @@
+//! WLAN bursts of frames with out-of-order, repeated and missed sequence numbers, run through the
+//! capture as tshark would print the frames.
+
+use burstshark::{
+    capture::{SequenceWindow, WlanOptions},
+    Burst, BurstStream, CaptureBackend, CaptureType, CommonOptions,
+};
+
+/// The bursts of QoS data frames from one station to another, given as their time, sequence number
+/// and length
+fn bursts(frames: &[(f64, u16, u32)], wlan: WlanOptions) -> Vec<Burst> {
+    let frames: Vec<_> = frames
+        .iter()
+        .map(|(time, seq, len)| (*time, *seq, *len, 0))
+        .collect();
+    tid_bursts(&frames, wlan)
+}
+
+/// The bursts of QoS data frames from one station to another, given as their time, sequence
+/// number, length and TID
+fn tid_bursts(frames: &[(f64, u16, u32, u8)], wlan: WlanOptions) -> Vec<Burst> {
+    let lines: String = frames
+        .iter()
+        .map(|(time, seq, len, tid)| {
+            format!(
+                "{time:.6}\taa:aa:aa:aa:aa:aa\tbb:bb:bb:bb:bb:bb\t{len}\t{seq}\t0x0028\t{tid}\n"
+            )
+        })
+        .collect();
+    let mut stream = BurstStream::start(|tx| {
+        let mut opts = CommonOptions::new(CaptureBackend::Lines { lines }, tx);
+        opts.inactive_time = 0.5;
+        CaptureType::WLANCapture { opts, wlan }
+    });
+    let bursts = stream.by_ref().collect();
+    stream.finish().unwrap();
+    bursts
+}
+
+/// The packets and bytes of each burst
+fn sizes(bursts: &[Burst]) -> Vec<(u16, u32)> {
+    bursts
+        .iter()
+        .map(|burst| (burst.num_packets, burst.size))
+        .collect()
+}
+
+fn window(max_deviation: u16) -> WlanOptions {
+    WlanOptions {
+        sequence: SequenceWindow::symmetric(max_deviation),
+        ..WlanOptions::default()
+    }
+}
+
+#[test]
+fn sequence_numbers_wrap_around_after_4095() {
+    let frames = [
+        (0.0, 4094, 1000),
+        (0.01, 4095, 1000),
+        (0.02, 0, 1000),
+        (0.03, 1, 1000),
+    ];
+    assert_eq!(sizes(&bursts(&frames, WlanOptions::default())), [(4, 4000)]);
+}
+
+#[test]
+fn missed_frames_are_counted_across_the_wraparound() {
+    let frames = [(0.0, 4094, 1000), (0.01, 2, 1000)];
+    assert_eq!(sizes(&bursts(&frames, WlanOptions::default())), [(5, 5000)]);
+}
+
+#[test]
+fn retransmissions_are_not_counted() {
+    let frames = [
+        (0.0, 1, 1000),
+        (0.01, 2, 1000),
+        (0.02, 2, 1000),
+        (0.03, 3, 1000),
+    ];
+    let bursts = bursts(&frames, WlanOptions::default());
+    assert_eq!(sizes(&bursts), [(3, 3000)]);
+    assert_eq!(bursts[0].end, 0.03);
+}
+
+#[test]
+fn missed_frames_are_guessed_from_their_neighbours() {
+    let frames = [(0.0, 1, 1000), (0.01, 4, 2000)];
+    assert_eq!(sizes(&bursts(&frames, WlanOptions::default())), [(4, 6000)]);
+}
+
+#[test]
+fn no_guess_only_counts_captured_frames() {
+    let frames = [(0.0, 1, 1000), (0.01, 4, 2000)];
+    let wlan = WlanOptions {
+        no_guess: true,
+        ..WlanOptions::default()
+    };
+    assert_eq!(sizes(&bursts(&frames, wlan)), [(2, 3000)]);
+}
+
+#[test]
+fn frames_beyond_the_max_deviation_ahead_are_outliers() {
+    // The frame after the outlier is then a retransmission
+    let frames = [(0.0, 1, 1000), (0.01, 10, 1000), (0.02, 2, 1000)];
+    assert_eq!(sizes(&bursts(&frames, window(50))), [(10, 10000)]);
+    assert_eq!(sizes(&bursts(&frames, window(5))), [(1, 1000)]);
+}
+
+#[test]
+fn frames_beyond_the_max_deviation_behind_are_outliers() {
+    // Within the deviation the old frame is a retransmission, beyond it an outlier that takes the
+    // place of the expected frame, making the next one a retransmission
+    let frames = [(0.0, 10, 1000), (0.01, 5, 1000), (0.02, 11, 1000)];
+    assert_eq!(sizes(&bursts(&frames, window(50))), [(2, 2000)]);
+    assert_eq!(sizes(&bursts(&frames, window(5))), [(1, 1000)]);
+}
+
+#[test]
+fn outliers_do_not_keep_bursts_open() {
+    // The burst ends with its last counted frame, so the next frames after the inactive time are a
+    // burst of their own
+    let frames = [
+        (0.0, 1, 1000),
+        (0.01, 2, 1000),
+        (0.3, 100, 1000),
+        (1.0, 3, 1000),
+        (1.01, 4, 1000),
+    ];
+    let bursts = bursts(&frames, window(5));
+    assert_eq!(sizes(&bursts), [(2, 2000), (2, 2000)]);
+    assert_eq!((bursts[0].start, bursts[0].end), (0.0, 0.01));
+}
+
+#[test]
+fn bursts_are_split_by_the_inactive_time() {
+    let frames = [
+        (0.0, 1, 1000),
+        (0.01, 2, 1000),
+        (1.0, 3, 1000),
+        (1.01, 4, 1000),
+    ];
+    let bursts = bursts(&frames, WlanOptions::default());
+    assert_eq!(sizes(&bursts), [(2, 2000), (2, 2000)]);
+    assert_eq!((bursts[1].start, bursts[1].end), (1.0, 1.01));
+}
+
+#[test]
+fn sequence_numbers_are_counted_per_tid() {
+    // Best effort and video frames interleaved, each TID with sequence numbers of its own
+    let frames = [
+        (0.00, 10, 100, 0),
+        (0.01, 500, 1000, 5),
+        (0.02, 11, 100, 0),
+        (0.03, 501, 1000, 5),
+    ];
+    let bursts = tid_bursts(&frames, WlanOptions::default());
+    let tids: Vec<_> = bursts
+        .iter()
+        .map(|burst| (burst.tid, burst.num_packets, burst.size))
+        .collect();
+    assert_eq!(tids, [(Some(0), 2, 200), (Some(5), 2, 2000)]);
+
+    // Merged into one flow, whose sequence numbers are still counted per TID
+    let no_tid = WlanOptions {
+        no_tid: true,
+        ..WlanOptions::default()
+    };
+    let bursts = tid_bursts(&frames, no_tid);
+    let merged: Vec<_> = bursts
+        .iter()
+        .map(|burst| (burst.tid, burst.num_packets, burst.size))
+        .collect();
+    assert_eq!(merged, [(None, 4, 2200)]);
+}